@@ -1,12 +1,12 @@
 use futures::StreamExt;
 
 use nanocl_error::io::IoResult;
-use nanocld_client::stubs::system::Event;
+use nanocld_client::stubs::system::{Event, EventWatchQuery};
 
 use crate::{
   utils,
   config::CliConfig,
-  models::{EventArg, EventRow, EventCommand},
+  models::{EventArg, EventRow, EventCommand, EventWatchOpts},
 };
 
 use super::GenericList;
@@ -27,9 +27,15 @@ impl GenericList for EventArg {
 
 /// Function that execute when running `nanocl events`
 /// Will print the events emitted by the daemon
-pub async fn watch_event(cli_conf: &CliConfig) -> IoResult<()> {
+pub async fn watch_event(
+  cli_conf: &CliConfig,
+  opts: &EventWatchOpts,
+) -> IoResult<()> {
   let client = &cli_conf.client;
-  let mut stream = client.watch_events(None).await?;
+  let query = EventWatchQuery {
+    since: opts.since.clone(),
+  };
+  let mut stream = client.watch_events(None, Some(&query)).await?;
   while let Some(event) = stream.next().await {
     let event = event?;
     utils::print::display_format(&cli_conf.user_config.display_format, event)?;
@@ -43,6 +49,6 @@ pub async fn exec_event(cli_conf: &CliConfig, args: &EventArg) -> IoResult<()> {
     EventCommand::List(opts) => {
       EventArg::exec_ls(&cli_conf.client, args, opts).await
     }
-    EventCommand::Watch => watch_event(cli_conf).await,
+    EventCommand::Watch(opts) => watch_event(cli_conf, opts).await,
   }
 }
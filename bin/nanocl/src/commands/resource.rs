@@ -1,4 +1,6 @@
-use nanocl_error::io::{IoResult, FromIo};
+use nanocl_error::io::{IoError, IoResult, FromIo};
+
+use nanocld_client::stubs::resource::ResourcePartial;
 
 use crate::{
   utils,
@@ -44,6 +46,27 @@ async fn exec_resource_rm(
   Ok(())
 }
 
+/// Function that execute when running `nanocl resource inspect --validate`
+async fn exec_resource_validate(
+  cli_conf: &CliConfig,
+  resource: ResourcePartial,
+) -> IoResult<()> {
+  let validation = cli_conf.client.validate_resource(&resource).await?;
+  if let Some(config) = &validation.config {
+    println!("{config}");
+  }
+  if validation.errors.is_empty() {
+    return Ok(());
+  }
+  for error in &validation.errors {
+    eprintln!("{error}");
+  }
+  Err(IoError::invalid_data(
+    "Resource",
+    &format!("{} is invalid", resource.name),
+  ))
+}
+
 /// Function that execute when running `nanocl resource inspect`
 async fn exec_resource_inspect(
   cli_conf: &CliConfig,
@@ -51,6 +74,9 @@ async fn exec_resource_inspect(
 ) -> IoResult<()> {
   let client = &cli_conf.client;
   let resource = client.inspect_resource(&opts.name).await?;
+  if opts.validate {
+    return exec_resource_validate(cli_conf, resource.into()).await;
+  }
   let display = opts
     .display
     .clone()
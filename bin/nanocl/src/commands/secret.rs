@@ -1,11 +1,13 @@
 use nanocl_error::io::{IoResult, FromIo};
 
+use nanocld_client::stubs::secret::SecretInspectQuery;
+
 use crate::{
   utils,
   config::CliConfig,
   models::{
     SecretArg, SecretCommand, SecretCreateOpts, SecretInspectOpts,
-    SecretRemoveOpts, SecretRow,
+    SecretRemoveOpts, SecretRow, SecretHistoryOpts, SecretRevertOpts,
   },
 };
 
@@ -47,7 +49,10 @@ async fn exec_secret_inspect(
   opts: &SecretInspectOpts,
 ) -> IoResult<()> {
   let client = &cli_conf.client;
-  let secret = client.inspect_secret(&opts.key).await?;
+  let query = SecretInspectQuery {
+    reveal: Some(opts.reveal),
+  };
+  let secret = client.inspect_secret(&opts.key, Some(&query)).await?;
   let _ = utils::print::display_format(
     &opts.display.clone().unwrap_or_default(),
     secret,
@@ -64,6 +69,31 @@ async fn exec_secret_create(
   Ok(())
 }
 
+/// Function that execute when running `nanocl secret history`
+async fn exec_secret_history(
+  cli_conf: &CliConfig,
+  opts: &SecretHistoryOpts,
+) -> IoResult<()> {
+  let client = &cli_conf.client;
+  let query = SecretInspectQuery {
+    reveal: Some(opts.reveal),
+  };
+  let histories = client.list_history_secret(&opts.key, Some(&query)).await?;
+  utils::print::print_yml(histories)?;
+  Ok(())
+}
+
+/// Function that execute when running `nanocl secret revert`
+async fn exec_secret_revert(
+  cli_conf: &CliConfig,
+  opts: &SecretRevertOpts,
+) -> IoResult<()> {
+  let client = &cli_conf.client;
+  let secret = client.revert_secret(&opts.key, &opts.history_id).await?;
+  utils::print::print_yml(secret)?;
+  Ok(())
+}
+
 /// Function that execute when running `nanocl secret`
 pub async fn exec_secret(
   cli_conf: &CliConfig,
@@ -76,5 +106,7 @@ pub async fn exec_secret(
     SecretCommand::Remove(opts) => exec_secret_rm(cli_conf, opts).await,
     SecretCommand::Inspect(opts) => exec_secret_inspect(cli_conf, opts).await,
     SecretCommand::Create(opts) => exec_secret_create(cli_conf, opts).await,
+    SecretCommand::History(opts) => exec_secret_history(cli_conf, opts).await,
+    SecretCommand::Revert(opts) => exec_secret_revert(cli_conf, opts).await,
   }
 }
@@ -1,5 +1,5 @@
 use std::{
-  collections::HashMap,
+  collections::{HashMap, HashSet},
   env::{consts, vars_os},
   fs,
   path::{Path, PathBuf},
@@ -35,8 +35,13 @@ use nanocld_client::{
     cargo_spec::CargoSpecPartial,
     vm_spec::{VmSpecPartial, VmSpecUpdate},
     resource::{ResourcePartial, ResourceUpdate},
-    secret::{SecretUpdate, SecretPartial},
-    cargo::CargoDeleteQuery,
+    secret::{Secret, SecretUpdate, SecretPartial, SecretInspectQuery},
+    cargo::{CargoDeleteQuery, CargoSummary},
+    vm::VmSummary,
+    job::JobSummary,
+    namespace::NamespaceSummary,
+    resource::Resource,
+    generic::{GenericFilter, GenericListNspQuery},
     system::NativeEventAction,
   },
 };
@@ -47,7 +52,8 @@ use crate::{
   config::CliConfig,
   models::{
     Context, DisplayFormat, StateApplyOpts, StateArg, StateCommand,
-    StateLogsOpts, StateRef, StateRemoveOpts, StateRoot,
+    StateDiffKind, StateDiffOpts, StateLogsOpts, StateObjectDiff, StateRef,
+    StateRemoveOpts, StateRoot,
   },
 };
 
@@ -93,7 +99,7 @@ async fn wait_job_instance_and_log(
   instance: &Process,
   query: &ProcessLogQuery,
 ) {
-  let Ok(mut stream) = client.watch_events(None).await else {
+  let Ok(mut stream) = client.watch_events(None, None).await else {
     return;
   };
   while let Some(event) = stream.next().await {
@@ -439,6 +445,86 @@ async fn read_state_file(
   read_from_file(&path, format)
 }
 
+/// Metadata key used to store the group of the Statefile declaring an object
+const STATE_GROUP_KEY: &str = "io.nanocl.state.group";
+
+/// Get the group of a Statefile default to `{name_of_directory}.{name_of_file}`
+fn state_group(state_ref: &StateRef<Statefile>) -> String {
+  if let Some(group) = &state_ref.data.group {
+    return group.clone();
+  }
+  let mut paths = state_ref
+    .location
+    .trim_end_matches('/')
+    .rsplit('/')
+    .filter(|path| !path.is_empty());
+  let file = paths.next().unwrap_or_default();
+  let file = match file.rsplit_once('.') {
+    Some((name, _)) if !name.is_empty() => name,
+    _ => file,
+  };
+  match paths.next() {
+    Some(directory) => format!("{directory}.{file}"),
+    None => file.to_owned(),
+  }
+}
+
+/// Set the group of the Statefile in the metadata of an object
+/// Metadata that are not an object are left untouched
+fn tag_group(metadata: Option<Value>, group: &str) -> Option<Value> {
+  match metadata {
+    None => tag_group(Some(Value::Object(Map::new())), group),
+    Some(Value::Object(mut metadata)) => {
+      metadata.insert(STATE_GROUP_KEY.to_owned(), Value::String(group.into()));
+      Some(Value::Object(metadata))
+    }
+    Some(metadata) => Some(metadata),
+  }
+}
+
+/// Get the group of an object from his metadata
+fn get_group(metadata: &Option<Value>) -> Option<&str> {
+  metadata.as_ref()?.get(STATE_GROUP_KEY)?.as_str()
+}
+
+/// Give the group of the wanted metadata to the current metadata without one
+/// before comparing them, the objects created before the groups existed
+/// aren't updated only to be tagged.
+fn ignore_missing_group(
+  current: Option<Value>,
+  wanted: &Option<Value>,
+) -> Option<Value> {
+  if get_group(&current).is_some() {
+    return current;
+  }
+  match get_group(wanted) {
+    Some(group) => tag_group(current, group),
+    None => current,
+  }
+}
+
+/// Tag every object of a Statefile with his group
+fn tag_state_group(state_ref: &mut StateRef<Statefile>) {
+  let group = state_group(state_ref);
+  let data = &mut state_ref.data;
+  for cargo in data.cargoes.iter_mut().flatten() {
+    cargo.metadata = tag_group(cargo.metadata.take(), &group);
+  }
+  for vm in data.virtual_machines.iter_mut().flatten() {
+    vm.metadata = tag_group(vm.metadata.take(), &group);
+  }
+  for job in data.jobs.iter_mut().flatten() {
+    job.metadata = tag_group(job.metadata.take(), &group);
+  }
+  for secret in data.secrets.iter_mut().flatten() {
+    secret.metadata = tag_group(secret.metadata.take(), &group);
+  }
+  for resource in data.resources.iter_mut().flatten() {
+    resource.metadata = tag_group(resource.metadata.take(), &group);
+  }
+  data.group = Some(group);
+}
+
 async fn render_template(
   state_ref: &StateRef<Statefile>,
   args: &serde_json::Value,
@@ -450,9 +536,6 @@ async fn render_template(
     None => "global".to_owned(),
   };
   namespace = inject_namespace(&namespace, args)?;
-  if client.inspect_namespace(&namespace).await.is_err() {
-    client.create_namespace(&namespace).await?;
-  }
   let mut state_ref =
     inject_data(state_ref, args, &cli_conf.context, client).await?;
   state_ref.data.namespace = Some(namespace);
@@ -460,6 +543,7 @@ async fn render_template(
     let hooked_cargoes = hook_cargoes(cargoes)?;
     state_ref.data.cargoes = Some(hooked_cargoes);
   }
+  tag_state_group(&mut state_ref);
   Ok(state_ref)
 }
 
@@ -470,13 +554,16 @@ async fn wait_process_object(
   client: &NanocldClient,
 ) -> IoResult<rt::JoinHandle<IoResult<()>>> {
   let mut stream = client
-    .watch_events(Some(vec![EventCondition {
-      actor_key: Some(key.to_owned()),
-      actor_kind: Some(kind.clone()),
-      kind: vec![EventKind::Normal, EventKind::Error],
-      action,
-      ..Default::default()
-    }]))
+    .watch_events(
+      Some(vec![EventCondition {
+        actor_key: Some(key.to_owned()),
+        actor_kind: Some(kind.clone()),
+        kind: vec![EventKind::Normal, EventKind::Error],
+        action,
+        ..Default::default()
+      }]),
+      None,
+    )
     .await?;
   let fut = rt::spawn(async move {
     while let Some(event) = stream.next().await {
@@ -603,17 +690,22 @@ async fn state_apply(
 ) -> IoResult<()> {
   let client = &cli_conf.client;
   let namespace = state_file.data.namespace.clone().unwrap_or("global".into());
+  if client.inspect_namespace(&namespace).await.is_err() {
+    client.create_namespace(&namespace).await?;
+  }
   let pg_style = utils::progress::create_spinner_style("green");
   if let Some(secrets) = &state_file.data.secrets {
     for secret in secrets {
       let token = format!("secret/{}", secret.name);
       let pg = utils::progress::create_progress(&token, &pg_style);
-      match client.inspect_secret(&secret.name).await {
+      let query = SecretInspectQuery { reveal: Some(true) };
+      match client.inspect_secret(&secret.name, Some(&query)).await {
         Err(_) => {
           client.create_secret(secret).await?;
         }
         Ok(inspect) => {
-          let cmp: SecretPartial = inspect.into();
+          let mut cmp: SecretPartial = inspect.into();
+          cmp.metadata = ignore_missing_group(cmp.metadata, &secret.metadata);
           if cmp != *secret {
             let update: SecretUpdate = secret.clone().into();
             client.patch_secret(&secret.name, &update).await?;
@@ -664,7 +756,8 @@ async fn state_apply(
             pg.finish();
             continue;
           }
-          let cmp: CargoSpecPartial = inspect.spec.into();
+          let mut cmp: CargoSpecPartial = inspect.spec.into();
+          cmp.metadata = ignore_missing_group(cmp.metadata, &cargo.metadata);
           if cmp != *cargo || opts.reload {
             client
               .put_cargo(&cargo.name, cargo, Some(&namespace))
@@ -699,7 +792,8 @@ async fn state_apply(
             pg.finish();
             continue;
           }
-          let cmp: VmSpecPartial = inspect.spec.into();
+          let mut cmp: VmSpecPartial = inspect.spec.into();
+          cmp.metadata = ignore_missing_group(cmp.metadata, &vm.metadata);
           if cmp != *vm {
             let update: VmSpecUpdate = vm.clone().into();
             client.patch_vm(&vm.name, &update, Some(&namespace)).await?;
@@ -729,7 +823,8 @@ async fn state_apply(
           client.create_resource(resource).await?;
         }
         Ok(inspect) => {
-          let cmp: ResourcePartial = inspect.into();
+          let mut cmp: ResourcePartial = inspect.into();
+          cmp.metadata = ignore_missing_group(cmp.metadata, &resource.metadata);
           if cmp != *resource {
             let update: ResourceUpdate = resource.clone().into();
             client.put_resource(&resource.name, &update).await?;
@@ -742,6 +837,288 @@ async fn state_apply(
   Ok(())
 }
 
+/// Compare an object of a Statefile with his current specification
+fn gen_object_diff<T>(
+  kind: &str,
+  name: &str,
+  namespace: Option<&str>,
+  current: Option<T>,
+  wanted: &T,
+) -> IoResult<StateObjectDiff>
+where
+  T: serde::Serialize,
+{
+  let diff = match current {
+    None => StateDiffKind::Create,
+    Some(current) => {
+      let fields = utils::diff::diff(&current, wanted)?;
+      if fields.is_empty() {
+        StateDiffKind::Unchanged
+      } else {
+        StateDiffKind::Update(fields)
+      }
+    }
+  };
+  Ok(StateObjectDiff {
+    kind: kind.to_owned(),
+    name: name.to_owned(),
+    namespace: namespace.map(str::to_owned),
+    diff,
+  })
+}
+
+/// Compute the changes a Statefile would apply to the current state
+async fn state_diff(
+  cli_conf: &CliConfig,
+  state_file: &StateRef<Statefile>,
+) -> IoResult<Vec<StateObjectDiff>> {
+  let client = &cli_conf.client;
+  let namespace = state_file.data.namespace.clone().unwrap_or("global".into());
+  let mut diffs = Vec::new();
+  let query = SecretInspectQuery { reveal: Some(true) };
+  for secret in state_file.data.secrets.iter().flatten() {
+    let current = client
+      .inspect_secret(&secret.name, Some(&query))
+      .await
+      .ok()
+      .map(|inspect| {
+        let mut current = SecretPartial::from(inspect);
+        current.metadata =
+          ignore_missing_group(current.metadata, &secret.metadata);
+        current
+      });
+    let mut diff =
+      gen_object_diff("secret", &secret.name, None, current, secret)?;
+    // Never print the values of a secret
+    if let StateDiffKind::Update(fields) = diff.diff {
+      diff.diff = StateDiffKind::Update(
+        fields.into_iter().map(|field| field.redact()).collect(),
+      );
+    }
+    diffs.push(diff);
+  }
+  for job in state_file.data.jobs.iter().flatten() {
+    let current = client.inspect_job(&job.name).await.ok().map(|inspect| {
+      let mut current = JobPartial::from(inspect);
+      current.metadata = ignore_missing_group(current.metadata, &job.metadata);
+      current
+    });
+    diffs.push(gen_object_diff("job", &job.name, None, current, job)?);
+  }
+  for cargo in state_file.data.cargoes.iter().flatten() {
+    let current = client
+      .inspect_cargo(&cargo.name, Some(&namespace))
+      .await
+      .ok()
+      .map(|inspect| {
+        let mut current = CargoSpecPartial::from(inspect.spec);
+        current.metadata =
+          ignore_missing_group(current.metadata, &cargo.metadata);
+        current
+      });
+    diffs.push(gen_object_diff(
+      "cargo",
+      &cargo.name,
+      Some(&namespace),
+      current,
+      cargo,
+    )?);
+  }
+  for vm in state_file.data.virtual_machines.iter().flatten() {
+    let current = client
+      .inspect_vm(&vm.name, Some(&namespace))
+      .await
+      .ok()
+      .map(|inspect| {
+        let mut current = VmSpecPartial::from(inspect.spec);
+        current.metadata = ignore_missing_group(current.metadata, &vm.metadata);
+        current
+      });
+    diffs.push(gen_object_diff(
+      "vm",
+      &vm.name,
+      Some(&namespace),
+      current,
+      vm,
+    )?);
+  }
+  for resource in state_file.data.resources.iter().flatten() {
+    let current =
+      client
+        .inspect_resource(&resource.name)
+        .await
+        .ok()
+        .map(|inspect| {
+          let mut current = ResourcePartial::from(inspect);
+          current.metadata =
+            ignore_missing_group(current.metadata, &resource.metadata);
+          current
+        });
+    diffs.push(gen_object_diff(
+      "resource",
+      &resource.name,
+      None,
+      current,
+      resource,
+    )?);
+  }
+  Ok(diffs)
+}
+
+/// Number of objects requested per page when listing every object
+const LIST_PAGE_SIZE: usize = 100;
+
+/// List every object of a kind page by page, the lists of the daemon are capped
+async fn list_all<T>(
+  client: &NanocldClient,
+  path: &str,
+  namespace: Option<&str>,
+) -> IoResult<Vec<T>>
+where
+  T: serde::de::DeserializeOwned + Send + 'static,
+{
+  let mut items = Vec::new();
+  loop {
+    let filter = GenericFilter::new()
+      .limit(LIST_PAGE_SIZE)
+      .offset(items.len());
+    let query = GenericListNspQuery::try_from(filter)
+      .map_err(|err| err.map_err_context(|| "StatePrune"))?
+      .with_namespace(namespace);
+    let res = client.send_get(path, Some(query)).await?;
+    let page = NanocldClient::res_json::<Vec<T>>(res).await?;
+    let len = page.len();
+    items.extend(page);
+    if len < LIST_PAGE_SIZE {
+      return Ok(items);
+    }
+  }
+}
+
+/// List the objects belonging to the groups of the Statefiles
+/// that are no longer declared by them
+async fn list_prunable(
+  cli_conf: &CliConfig,
+  states: &[StateRef<Statefile>],
+) -> IoResult<Vec<StateObjectDiff>> {
+  let client = &cli_conf.client;
+  let groups = states
+    .iter()
+    .filter_map(|state| state.data.group.clone())
+    .collect::<HashSet<_>>();
+  let mut declared = HashSet::new();
+  for state in states {
+    let data = &state.data;
+    let namespace = data.namespace.clone().unwrap_or("global".into());
+    for cargo in data.cargoes.iter().flatten() {
+      declared.insert(("cargo", cargo.name.clone(), Some(namespace.clone())));
+    }
+    for vm in data.virtual_machines.iter().flatten() {
+      declared.insert(("vm", vm.name.clone(), Some(namespace.clone())));
+    }
+    for job in data.jobs.iter().flatten() {
+      declared.insert(("job", job.name.clone(), None));
+    }
+    for secret in data.secrets.iter().flatten() {
+      declared.insert(("secret", secret.name.clone(), None));
+    }
+    for resource in data.resources.iter().flatten() {
+      declared.insert(("resource", resource.name.clone(), None));
+    }
+  }
+  // Every existing object with his kind, name, namespace and metadata
+  let mut objects = Vec::new();
+  let namespaces =
+    list_all::<NamespaceSummary>(client, "/namespaces", None).await?;
+  for namespace in namespaces {
+    let namespace = Some(namespace.name.as_str());
+    for cargo in list_all::<CargoSummary>(client, "/cargoes", namespace).await?
+    {
+      let namespace = Some(cargo.namespace_name);
+      objects.push(("cargo", cargo.spec.name, namespace, cargo.spec.metadata));
+    }
+    for vm in list_all::<VmSummary>(client, "/vms", namespace).await? {
+      let namespace = Some(vm.namespace_name);
+      objects.push(("vm", vm.spec.name, namespace, vm.spec.metadata));
+    }
+  }
+  for job in list_all::<JobSummary>(client, "/jobs", None).await? {
+    objects.push(("job", job.spec.name, None, job.spec.metadata));
+  }
+  for secret in list_all::<Secret>(client, "/secrets", None).await? {
+    objects.push(("secret", secret.name, None, secret.metadata));
+  }
+  for resource in list_all::<Resource>(client, "/resources", None).await? {
+    let spec = resource.spec;
+    objects.push(("resource", spec.resource_key, None, spec.metadata));
+  }
+  let prunable = objects
+    .into_iter()
+    .filter(|(kind, name, namespace, metadata)| {
+      let Some(group) = get_group(metadata) else {
+        return false;
+      };
+      groups.contains(group)
+        && !declared.contains(&(*kind, name.clone(), namespace.clone()))
+    })
+    .map(|(kind, name, namespace, _)| StateObjectDiff {
+      kind: kind.to_owned(),
+      name,
+      namespace,
+      diff: StateDiffKind::Delete,
+    })
+    .collect();
+  Ok(prunable)
+}
+
+/// Print the changes of the Statefiles with a summary
+fn print_diffs(diffs: &[StateObjectDiff]) {
+  let (mut create, mut update, mut unchanged, mut delete) = (0, 0, 0, 0);
+  for diff in diffs {
+    match diff.diff {
+      StateDiffKind::Create => create += 1,
+      StateDiffKind::Update(_) => update += 1,
+      StateDiffKind::Unchanged => unchanged += 1,
+      StateDiffKind::Delete => delete += 1,
+    }
+    println!("{diff}");
+  }
+  println!(
+    "\n{create} to create, {update} to update, {delete} to delete, {unchanged} unchanged"
+  );
+}
+
+/// Compute the changes of the parsed Statefiles
+/// including the objects that would be pruned when `prune` is set
+async fn gen_state_diffs(
+  cli_conf: &CliConfig,
+  states: &[StateRef<Statefile>],
+  prune: bool,
+) -> IoResult<Vec<StateObjectDiff>> {
+  let mut diffs = Vec::new();
+  for state in states {
+    diffs.append(&mut state_diff(cli_conf, state).await?);
+  }
+  if prune {
+    diffs.append(&mut list_prunable(cli_conf, states).await?);
+  }
+  Ok(diffs)
+}
+
+/// Function called when running `nanocl state diff`
+async fn exec_state_diff(
+  cli_conf: &CliConfig,
+  opts: &StateDiffOpts,
+) -> IoResult<()> {
+  let format = cli_conf.user_config.display_format.clone();
+  let state_file = read_state_file(&opts.state_location, &format).await?;
+  let args = parse_build_args(&state_file.data, &opts.args)?;
+  let states = parse_state_file_recurr(cli_conf, &state_file, &args).await?;
+  let diffs = gen_state_diffs(cli_conf, &states, opts.prune).await?;
+  print_diffs(&diffs);
+  Ok(())
+}
+
 fn print_states(states: &[StateRef<Statefile>]) {
   let raw = states.iter().fold(String::new(), |init, state| {
     format!("{init}{}\n", state.raw)
@@ -749,6 +1126,42 @@ fn print_states(states: &[StateRef<Statefile>]) {
   println!("{raw}");
 }
 
+/// Validate the resources of the states with their controllers
+/// before anything is applied.
+/// A resource whose kind isn't created yet is skipped.
+async fn validate_resources(
+  cli_conf: &CliConfig,
+  states: &[StateRef<Statefile>],
+) -> IoResult<()> {
+  let client = &cli_conf.client;
+  let mut invalid = 0;
+  for state in states {
+    for resource in state.data.resources.iter().flatten() {
+      let validation = match client.validate_resource(resource).await {
+        Ok(validation) => validation,
+        Err(err) => {
+          eprintln!("resource/{} not validated: {err}", resource.name);
+          continue;
+        }
+      };
+      if validation.errors.is_empty() {
+        continue;
+      }
+      invalid += 1;
+      for error in &validation.errors {
+        eprintln!("resource/{}: {error}", resource.name);
+      }
+    }
+  }
+  if invalid > 0 {
+    return Err(IoError::invalid_data(
+      "StateApply",
+      &format!("{invalid} resource(s) are invalid"),
+    ));
+  }
+  Ok(())
+}
+
 /// Function called when running `nanocl state apply`
 async fn exec_state_apply(
   cli_conf: &CliConfig,
@@ -758,14 +1171,38 @@ async fn exec_state_apply(
   let state_file = read_state_file(&opts.state_location, &format).await?;
   let args = parse_build_args(&state_file.data, &opts.args)?;
   let states = parse_state_file_recurr(cli_conf, &state_file, &args).await?;
+  if opts.validate {
+    validate_resources(cli_conf, &states).await?;
+  }
+  if opts.dry_run {
+    let diffs = gen_state_diffs(cli_conf, &states, opts.prune).await?;
+    print_diffs(&diffs);
+    return Ok(());
+  }
+  let prunable = if opts.prune {
+    list_prunable(cli_conf, &states).await?
+  } else {
+    Vec::new()
+  };
   if !opts.skip_confirm {
     print_states(&states);
     utils::dialog::confirm("Are you sure to apply this state ?")
       .map_err(|err| err.map_err_context(|| "StateApply"))?;
   }
+  // The pruned objects are always listed, even when the confirmation is skipped
+  if !prunable.is_empty() {
+    for object in &prunable {
+      println!("{object}");
+    }
+    if !opts.skip_confirm {
+      utils::dialog::confirm("Are you sure to prune these objects ?")
+        .map_err(|err| err.map_err_context(|| "StatePrune"))?;
+    }
+  }
   for state in &states {
     state_apply(cli_conf, opts, state).await?;
   }
+  state_prune(cli_conf, &prunable).await?;
   if opts.follow {
     states
       .iter()
@@ -932,7 +1369,7 @@ async fn state_remove(
     for secret in secrets {
       let token = format!("secret/{}", secret.name);
       let pg = utils::progress::create_progress(&token, &pg_style);
-      if client.inspect_secret(&secret.name).await.is_ok() {
+      if client.inspect_secret(&secret.name, None).await.is_ok() {
         client.delete_secret(&secret.name).await?;
       }
       pg.finish();
@@ -941,6 +1378,72 @@ async fn state_remove(
   Ok(())
 }
 
+/// Remove the objects no longer declared in the Statefile group
+async fn state_prune(
+  cli_conf: &CliConfig,
+  prunable: &[StateObjectDiff],
+) -> IoResult<()> {
+  let client = &cli_conf.client;
+  let pg_style = utils::progress::create_spinner_style("red");
+  for object in prunable {
+    let token = format!("{}/{}", object.kind, object.name);
+    let pg = utils::progress::create_progress(&token, &pg_style);
+    let namespace = object.namespace.as_deref().unwrap_or("global");
+    match object.kind.as_str() {
+      "job" => {
+        let waiter = wait_process_object(
+          &object.name,
+          EventActorKind::Job,
+          vec![NativeEventAction::Destroy],
+          client,
+        )
+        .await?;
+        client.delete_job(&object.name).await?;
+        waiter.await??;
+      }
+      "cargo" => {
+        let waiter = wait_process_object(
+          &format!("{}.{namespace}", object.name),
+          EventActorKind::Cargo,
+          vec![NativeEventAction::Destroy],
+          client,
+        )
+        .await?;
+        client
+          .delete_cargo(
+            &object.name,
+            Some(&CargoDeleteQuery {
+              namespace: object.namespace.clone(),
+              force: Some(true),
+            }),
+          )
+          .await?;
+        waiter.await??;
+      }
+      "vm" => {
+        let waiter = wait_process_object(
+          &format!("{}.{namespace}", object.name),
+          EventActorKind::Vm,
+          vec![NativeEventAction::Destroy],
+          client,
+        )
+        .await?;
+        client.delete_vm(&object.name, Some(namespace)).await?;
+        waiter.await??;
+      }
+      "resource" => {
+        client.delete_resource(&object.name).await?;
+      }
+      "secret" => {
+        client.delete_secret(&object.name).await?;
+      }
+      _ => {}
+    }
+    pg.finish();
+  }
+  Ok(())
+}
+
 /// Function called when running `nanocl state rm`
 async fn exec_state_remove(
   cli_conf: &CliConfig,
@@ -966,6 +1469,7 @@ async fn exec_state_remove(
 pub async fn exec_state(cli_conf: &CliConfig, args: &StateArg) -> IoResult<()> {
   match &args.command {
     StateCommand::Apply(opts) => exec_state_apply(cli_conf, opts).await,
+    StateCommand::Diff(opts) => exec_state_diff(cli_conf, opts).await,
     StateCommand::Remove(opts) => exec_state_remove(cli_conf, opts).await,
     StateCommand::Logs(opts) => exec_state_logs(cli_conf, opts).await,
   }
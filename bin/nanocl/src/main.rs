@@ -281,6 +281,7 @@ mod tests {
     );
     // History
     assert_cli_ok!("resource", "history", "deploy-example.com");
+    assert_cli_ok!("resource", "inspect", "--validate", "deploy-example.com");
     let client = get_test_client();
     let history = client
       .list_history_resource("deploy-example.com")
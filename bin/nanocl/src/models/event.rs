@@ -19,7 +19,15 @@ pub enum EventCommand {
   #[clap(alias("ls"))]
   List(GenericListOpts),
   /// Watch for new events in real time
-  Watch,
+  Watch(EventWatchOpts),
+}
+
+/// `nanocl event watch` available options
+#[derive(Clone, Parser)]
+pub struct EventWatchOpts {
+  /// Replay the events after this event key or RFC 3339 date before the new ones
+  #[clap(long)]
+  pub since: Option<String>,
 }
 
 #[derive(Clone, Tabled)]
@@ -86,6 +86,9 @@ pub struct ResourceInspectOpts {
   /// Display format
   #[clap(long)]
   pub display: Option<DisplayFormat>,
+  /// Validate the resource with its controller and print the generated config
+  #[clap(long)]
+  pub validate: bool,
   /// The name of the resource to inspect
   pub name: String,
 }
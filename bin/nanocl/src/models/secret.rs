@@ -21,6 +21,10 @@ pub enum SecretCommand {
   Inspect(SecretInspectOpts),
   /// Create a new secret
   Create(SecretCreateOpts),
+  /// List secret history
+  History(SecretHistoryOpts),
+  /// Revert secret to a specific history
+  Revert(SecretRevertOpts),
 }
 
 /// `nanocl secret` available arguments
@@ -39,6 +43,14 @@ pub struct EnvCreateOpts {
   pub values: Vec<String>,
 }
 
+/// Create a new nanocl.io/htpasswd secret
+#[derive(Clone, Parser)]
+pub struct HtpasswdCreateOpts {
+  /// List of users in the form of `user:hash` as generated by `htpasswd -nB`
+  #[clap(required = true)]
+  pub users: Vec<String>,
+}
+
 /// Create a new nanocl.io/tls secret
 #[derive(Clone, Parser, Serialize)]
 pub struct TlsCreateOpts {
@@ -87,6 +99,9 @@ impl TryFrom<SecretCreateOpts> for SecretPartial {
         "nanocl.io/container-registry",
         serde_json::to_value(container_registry)?,
       ),
+      SecretKindCreateCommand::Htpasswd(htpasswd) => {
+        ("nanocl.io/htpasswd", serde_json::to_value(&htpasswd.users)?)
+      }
     };
     Ok(Self {
       name: opts.name,
@@ -103,6 +118,7 @@ pub enum SecretKindCreateCommand {
   Env(EnvCreateOpts),
   Tls(TlsCreateOpts),
   ContainerRegistry(ContainerRegistryCreateOpts),
+  Htpasswd(HtpasswdCreateOpts),
 }
 
 /// `nanocl secret create` available options
@@ -133,6 +149,28 @@ pub struct SecretInspectOpts {
   /// Display format
   #[clap(long)]
   pub display: Option<DisplayFormat>,
+  /// Show the data of the secret instead of redacting it
+  #[clap(long)]
+  pub reveal: bool,
+}
+
+/// `nanocl secret history` available options
+#[derive(Clone, Parser)]
+pub struct SecretHistoryOpts {
+  /// Name of secret to browse history
+  pub key: String,
+  /// Show the data of the secret instead of redacting it
+  #[clap(long)]
+  pub reveal: bool,
+}
+
+/// `nanocl secret revert` available options
+#[derive(Clone, Parser)]
+pub struct SecretRevertOpts {
+  /// Name of secret to revert
+  pub key: String,
+  /// Revert to a specific historic
+  pub history_id: String,
 }
 
 /// A row of the secret table
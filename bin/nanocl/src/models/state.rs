@@ -4,6 +4,7 @@ use std::{
 };
 
 use clap::{Parser, Subcommand};
+use serde_json::Value;
 
 use super::DisplayFormat;
 
@@ -22,6 +23,29 @@ pub struct StateApplyOpts {
   /// Perform an apply even if state didn't changed
   #[clap(long, short = 'r')]
   pub reload: bool,
+  /// Only print the changes that would be applied
+  #[clap(long)]
+  pub dry_run: bool,
+  /// Remove objects of the Statefile group no longer declared
+  #[clap(long)]
+  pub prune: bool,
+  /// Validate the resources with their controllers before applying
+  #[clap(long)]
+  pub validate: bool,
+  /// Additional arguments to pass to the file
+  #[clap(last = true, raw = true)]
+  pub args: Vec<String>,
+}
+
+/// `nanocl state diff` available options
+#[derive(Parser, Clone)]
+pub struct StateDiffOpts {
+  /// Path or Url to the Statefile
+  #[clap(long, short = 's')]
+  pub state_location: Option<String>,
+  /// Include objects of the Statefile group no longer declared
+  #[clap(long)]
+  pub prune: bool,
   /// Additional arguments to pass to the file
   #[clap(last = true, raw = true)]
   pub args: Vec<String>,
@@ -72,6 +96,8 @@ pub struct StateRemoveOpts {
 pub enum StateCommand {
   /// Create or Update elements from a Statefile
   Apply(StateApplyOpts),
+  /// Show the changes a Statefile would apply
+  Diff(StateDiffOpts),
   /// Logs elements from a Statefile
   Logs(StateLogsOpts),
   /// Remove elements from a Statefile
@@ -123,3 +149,99 @@ where
   /// Path to the Statefile
   pub location: String,
 }
+
+/// A change of a single field between the current and the wanted object
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldDiff {
+  /// The field is only defined in the wanted object
+  Added { path: String, value: Value },
+  /// The field is only defined in the current object
+  Removed { path: String, value: Value },
+  /// The field is defined in both objects with a different value
+  Changed {
+    path: String,
+    old: Value,
+    new: Value,
+  },
+}
+
+impl FieldDiff {
+  /// Hide the values of the field, used for sensitive objects like secrets
+  pub fn redact(self) -> Self {
+    let hidden = Value::String("<redacted>".to_owned());
+    match self {
+      Self::Added { path, .. } => Self::Added {
+        path,
+        value: hidden,
+      },
+      Self::Removed { path, .. } => Self::Removed {
+        path,
+        value: hidden,
+      },
+      Self::Changed { path, .. } => Self::Changed {
+        path,
+        old: hidden.clone(),
+        new: hidden,
+      },
+    }
+  }
+}
+
+impl Display for FieldDiff {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Added { path, value } => write!(f, "+ {path}: {value}"),
+      Self::Removed { path, value } => write!(f, "- {path}: {value}"),
+      Self::Changed { path, old, new } => {
+        write!(f, "~ {path}: {old} -> {new}")
+      }
+    }
+  }
+}
+
+/// Change planned for an object declared in a Statefile
+#[derive(Debug, Clone, PartialEq)]
+pub enum StateDiffKind {
+  /// The object doesn't exist yet
+  Create,
+  /// The object exists with a different specification
+  Update(Vec<FieldDiff>),
+  /// The object exists with the same specification
+  Unchanged,
+  /// The object belongs to the Statefile group but is no longer declared
+  Delete,
+}
+
+/// Planned change of an object of a Statefile
+#[derive(Debug, Clone)]
+pub struct StateObjectDiff {
+  /// Kind of the object (cargo, vm, job, secret, resource)
+  pub kind: String,
+  /// Name of the object
+  pub name: String,
+  /// Namespace of the object if namespaced
+  pub namespace: Option<String>,
+  /// The planned change
+  pub diff: StateDiffKind,
+}
+
+impl Display for StateObjectDiff {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    let sign = match &self.diff {
+      StateDiffKind::Create => "+",
+      StateDiffKind::Update(_) => "~",
+      StateDiffKind::Unchanged => "=",
+      StateDiffKind::Delete => "-",
+    };
+    write!(f, "{sign} {}/{}", self.kind, self.name)?;
+    if let Some(namespace) = &self.namespace {
+      write!(f, ".{namespace}")?;
+    }
+    if let StateDiffKind::Update(fields) = &self.diff {
+      for field in fields {
+        write!(f, "\n    {field}")?;
+      }
+    }
+    Ok(())
+  }
+}
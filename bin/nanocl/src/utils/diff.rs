@@ -0,0 +1,133 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use nanocl_error::io::{FromIo, IoResult};
+
+use crate::models::FieldDiff;
+
+fn join_path(path: &str, key: &str) -> String {
+  if path.is_empty() {
+    return key.to_owned();
+  }
+  format!("{path}.{key}")
+}
+
+/// Recursively compare two json values and push the field differences.
+/// Null values are considered as undefined.
+pub fn diff_value(
+  path: &str,
+  current: &Value,
+  wanted: &Value,
+  diffs: &mut Vec<FieldDiff>,
+) {
+  match (current, wanted) {
+    (current, wanted) if current == wanted => {}
+    (Value::Null, wanted) => diffs.push(FieldDiff::Added {
+      path: path.to_owned(),
+      value: wanted.clone(),
+    }),
+    (current, Value::Null) => diffs.push(FieldDiff::Removed {
+      path: path.to_owned(),
+      value: current.clone(),
+    }),
+    (Value::Object(current), Value::Object(wanted)) => {
+      let mut keys = current.keys().chain(wanted.keys()).collect::<Vec<_>>();
+      keys.sort();
+      keys.dedup();
+      for key in keys {
+        diff_value(
+          &join_path(path, key),
+          current.get(key).unwrap_or(&Value::Null),
+          wanted.get(key).unwrap_or(&Value::Null),
+          diffs,
+        );
+      }
+    }
+    (Value::Array(current), Value::Array(wanted)) => {
+      for index in 0..current.len().max(wanted.len()) {
+        diff_value(
+          &format!("{path}[{index}]"),
+          current.get(index).unwrap_or(&Value::Null),
+          wanted.get(index).unwrap_or(&Value::Null),
+          diffs,
+        );
+      }
+    }
+    (current, wanted) => diffs.push(FieldDiff::Changed {
+      path: path.to_owned(),
+      old: current.clone(),
+      new: wanted.clone(),
+    }),
+  }
+}
+
+/// Compute the field level differences between two serializable objects
+pub fn diff<T>(current: &T, wanted: &T) -> IoResult<Vec<FieldDiff>>
+where
+  T: Serialize,
+{
+  let current = serde_json::to_value(current)
+    .map_err(|err| err.map_err_context(|| "Diff current"))?;
+  let wanted = serde_json::to_value(wanted)
+    .map_err(|err| err.map_err_context(|| "Diff wanted"))?;
+  let mut diffs = Vec::new();
+  diff_value("", &current, &wanted, &mut diffs);
+  Ok(diffs)
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  #[test]
+  fn diff_objects() {
+    let current = json!({
+      "Name": "api",
+      "Container": { "Image": "api:1.0", "Env": ["A=1", "B=2"] },
+      "Metadata": { "Team": "core" },
+    });
+    let wanted = json!({
+      "Name": "api",
+      "Container": { "Image": "api:1.1", "Env": ["A=1"], "Cmd": ["run"] },
+    });
+    let diffs = diff(&current, &wanted).unwrap();
+    assert_eq!(
+      diffs,
+      vec![
+        FieldDiff::Added {
+          path: "Container.Cmd".to_owned(),
+          value: json!(["run"]),
+        },
+        FieldDiff::Removed {
+          path: "Container.Env[1]".to_owned(),
+          value: json!("B=2"),
+        },
+        FieldDiff::Changed {
+          path: "Container.Image".to_owned(),
+          old: json!("api:1.0"),
+          new: json!("api:1.1"),
+        },
+        FieldDiff::Removed {
+          path: "Metadata".to_owned(),
+          value: json!({ "Team": "core" }),
+        },
+      ]
+    );
+    assert!(diff(&current, &current).unwrap().is_empty());
+  }
+
+  #[test]
+  fn redact_diff() {
+    let field = FieldDiff::Changed {
+      path: "Data.Password".to_owned(),
+      old: json!("old"),
+      new: json!("new"),
+    };
+    assert_eq!(
+      field.redact().to_string(),
+      "~ Data.Password: \"<redacted>\" -> \"<redacted>\""
+    );
+  }
+}
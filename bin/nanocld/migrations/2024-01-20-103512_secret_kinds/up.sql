@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS "secret_kinds" (
+  "name" VARCHAR NOT NULL UNIQUE PRIMARY KEY,
+  "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  "schema" JSON NOT NULL,
+  "metadata" JSON
+);
@@ -0,0 +1,25 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS "webhooks" (
+  "name" VARCHAR NOT NULL UNIQUE PRIMARY KEY,
+  "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  "updated_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  "url" VARCHAR NOT NULL,
+  "conditions" JSON,
+  "secret" JSON,
+  "retry" JSON NOT NULL,
+  "metadata" JSON
+);
+
+CREATE TABLE IF NOT EXISTS "webhook_deliveries" (
+  "key" UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+  "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  "updated_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  "webhook_name" VARCHAR NOT NULL REFERENCES webhooks("name") ON DELETE CASCADE,
+  "event_key" UUID NOT NULL,
+  "node_name" VARCHAR NOT NULL,
+  "status" VARCHAR NOT NULL,
+  "attempts" INT NOT NULL DEFAULT 0,
+  "status_code" INT,
+  "error" VARCHAR,
+  "next_attempt_at" TIMESTAMPTZ
+);
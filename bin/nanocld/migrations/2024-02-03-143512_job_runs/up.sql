@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS "job_runs" (
+  "key" UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+  "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  "updated_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  "job_name" VARCHAR NOT NULL REFERENCES jobs("key") ON DELETE CASCADE,
+  "scheduled_at" TIMESTAMPTZ,
+  "started_at" TIMESTAMPTZ,
+  "ended_at" TIMESTAMPTZ,
+  "status" VARCHAR NOT NULL,
+  "reason" VARCHAR
+);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "job_runs" DROP COLUMN IF EXISTS "attempt";
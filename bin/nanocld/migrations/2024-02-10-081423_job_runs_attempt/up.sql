@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE "job_runs" ADD COLUMN IF NOT EXISTS "attempt" INT NOT NULL DEFAULT 1;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+DELETE FROM "job_runs" WHERE "key" IN (
+  SELECT "key" FROM (
+    SELECT "key", ROW_NUMBER() OVER (
+      PARTITION BY "job_name", "scheduled_at", "attempt" ORDER BY "created_at"
+    ) AS "position"
+    FROM "job_runs"
+    WHERE "scheduled_at" IS NOT NULL
+  ) AS "runs"
+  WHERE "position" > 1
+);
+CREATE UNIQUE INDEX IF NOT EXISTS "job_runs_schedule_unique"
+  ON "job_runs" ("job_name", "scheduled_at", "attempt");
@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 use nanocl_stubs::system::SslConfig;
 
@@ -39,6 +39,24 @@ pub struct Cli {
   /// Optional ssl options
   #[clap(flatten)]
   pub ssl: Option<SslConfig>,
+  /// Run a maintenance command instead of starting the daemon
+  #[clap(subcommand)]
+  pub command: Option<Command>,
+}
+
+/// Maintenance commands of the daemon
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+  /// Manage the encryption of the secrets
+  #[clap(subcommand)]
+  Secret(SecretCommand),
+}
+
+/// `nanocld secret` available commands
+#[derive(Debug, Clone, Subcommand)]
+pub enum SecretCommand {
+  /// Generate a new master key and re-encrypt every secret with it
+  RotateKey,
 }
 
 impl Default for Cli {
@@ -54,6 +72,7 @@ impl Default for Cli {
       advertise_addr: None,
       gid: 0,
       ssl: None,
+      command: None,
     }
   }
 }
@@ -95,4 +114,14 @@ mod tests {
     assert_eq!(args.state_dir, Some(String::from("/var/lib/nanocl")));
     assert_eq!(args.conf_dir, String::from("/etc/nanocl"));
   }
+
+  /// Test the secret rotate-key command
+  #[test]
+  fn cli_with_rotate_key() {
+    let args = Cli::parse_from(["nanocl", "secret", "rotate-key"]);
+    assert!(matches!(
+      args.command,
+      Some(Command::Secret(SecretCommand::RotateKey))
+    ));
+  }
 }
@@ -39,6 +39,15 @@ async fn main() -> std::io::Result<()> {
     }
     Ok(config) => config,
   };
+  // Run the maintenance command if any instead of the daemon
+  if let Some(cli::Command::Secret(cli::SecretCommand::RotateKey)) =
+    &args.command
+  {
+    if let Err(err) = utils::secret::rotate_key(&config).await {
+      err.print_and_exit();
+    }
+    return Ok(());
+  }
   // Boot internal dependencies (database, event bus, etc...)
   let daemon_state = match system::init(&config).await {
     Err(err) => {
@@ -1,6 +1,12 @@
+use std::str::FromStr;
+
 use diesel::prelude::*;
 
-use crate::schema::jobs;
+use nanocl_error::io::IoError;
+
+use nanocl_stubs::job::{JobRun, JobRunStatus};
+
+use crate::schema::{jobs, job_runs};
 
 /// This structure represent a job to run.
 /// It will create and run a list of containers.
@@ -29,3 +35,111 @@ pub struct JobDb {
 pub struct JobUpdateDb {
   pub updated_at: Option<chrono::NaiveDateTime>,
 }
+
+/// This structure represent a run of a job in the database.
+/// The runs are kept as the history of the job.
+#[derive(Clone, Debug, Queryable, Identifiable, Insertable)]
+#[diesel(primary_key(key))]
+#[diesel(table_name = job_runs)]
+pub struct JobRunDb {
+  /// Unique identifier of the run
+  pub key: uuid::Uuid,
+  /// When the run have been created
+  pub created_at: chrono::NaiveDateTime,
+  /// When the run have been updated
+  pub updated_at: chrono::NaiveDateTime,
+  /// Name of the job
+  pub job_name: String,
+  /// When the run was due, none when started manually
+  pub scheduled_at: Option<chrono::NaiveDateTime>,
+  /// When the steps started
+  pub started_at: Option<chrono::NaiveDateTime>,
+  /// When the run ended
+  pub ended_at: Option<chrono::NaiveDateTime>,
+  /// Status of the run
+  pub status: String,
+  /// Why the run failed or was not started
+  pub reason: Option<String>,
+  /// Attempt of the run, starting at 1
+  pub attempt: i64,
+}
+
+impl JobRunDb {
+  /// Create a new run of a job with the given status
+  pub fn new(
+    job_name: &str,
+    scheduled_at: Option<chrono::NaiveDateTime>,
+    status: JobRunStatus,
+    reason: Option<String>,
+  ) -> Self {
+    let now = chrono::Utc::now().naive_utc();
+    let ended_at = match status {
+      JobRunStatus::Pending | JobRunStatus::Running => None,
+      _ => Some(now),
+    };
+    let started_at = match status {
+      JobRunStatus::Running => Some(now),
+      _ => None,
+    };
+    Self {
+      key: uuid::Uuid::new_v4(),
+      created_at: now,
+      updated_at: now,
+      job_name: job_name.to_owned(),
+      scheduled_at,
+      started_at,
+      ended_at,
+      status: status.to_string(),
+      reason,
+      attempt: 1,
+    }
+  }
+
+  /// Create the running run of the next attempt
+  pub fn next_attempt(&self) -> Self {
+    Self {
+      attempt: self.attempt + 1,
+      ..Self::new(
+        &self.job_name,
+        self.scheduled_at,
+        JobRunStatus::Running,
+        None,
+      )
+    }
+  }
+}
+
+impl TryFrom<JobRunDb> for JobRun {
+  type Error = IoError;
+
+  fn try_from(db: JobRunDb) -> Result<Self, Self::Error> {
+    Ok(Self {
+      key: db.key,
+      created_at: db.created_at,
+      updated_at: db.updated_at,
+      job_name: db.job_name,
+      scheduled_at: db.scheduled_at,
+      started_at: db.started_at,
+      ended_at: db.ended_at,
+      attempt: db.attempt as u32,
+      status: JobRunStatus::from_str(&db.status)?,
+      reason: db.reason,
+    })
+  }
+}
+
+/// This structure is used to update the status of a run
+#[derive(Debug, Default, AsChangeset)]
+#[diesel(table_name = job_runs)]
+pub struct JobRunUpdateDb {
+  /// When the run have been updated
+  pub updated_at: Option<chrono::NaiveDateTime>,
+  /// When the steps started
+  pub started_at: Option<Option<chrono::NaiveDateTime>>,
+  /// When the run ended
+  pub ended_at: Option<Option<chrono::NaiveDateTime>>,
+  /// Status of the run
+  pub status: Option<String>,
+  /// Why the run failed or was not started
+  pub reason: Option<Option<String>>,
+}
@@ -1,10 +1,15 @@
+use std::{
+  collections::BTreeMap,
+  sync::{Arc, Mutex, MutexGuard},
+};
+
 use uuid::Uuid;
 use diesel::prelude::*;
 use serde::{Serialize, Deserialize};
 
 use nanocl_error::io::IoResult;
 
-use nanocl_stubs::metric::MetricPartial;
+use nanocl_stubs::metric::{MetricPartial, HttpMetric, StreamMetric};
 
 use crate::{utils, schema::metrics};
 
@@ -74,3 +79,98 @@ impl From<&MetricNodePartial> for MetricDb {
     }
   }
 }
+
+/// Upper bounds in seconds of the buckets of the request duration histogram
+pub const HTTP_DURATION_BUCKETS: [f64; 11] = [
+  0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Counters of the http requests proxied to an upstream
+#[derive(Clone, Debug, Default)]
+pub struct HttpUpstreamStats {
+  /// Number of requests by status code
+  pub statuses: BTreeMap<i64, u64>,
+  /// Number of requests by bucket of `HTTP_DURATION_BUCKETS`, not cumulated
+  pub buckets: [u64; HTTP_DURATION_BUCKETS.len()],
+  /// Number of requests
+  pub count: u64,
+  /// Sum of the request times in seconds
+  pub duration_sum: f64,
+  /// Bytes sent to the clients
+  pub bytes_sent: u64,
+}
+
+/// Counters of the tcp and udp sessions proxied to an upstream
+#[derive(Clone, Debug, Default)]
+pub struct StreamUpstreamStats {
+  /// Number of sessions by status code
+  pub statuses: BTreeMap<i64, u64>,
+  /// Bytes sent to the clients
+  pub bytes_sent: u64,
+  /// Bytes received from the clients
+  pub bytes_received: u64,
+}
+
+/// Counters of the proxy by upstream
+#[derive(Clone, Debug, Default)]
+pub struct ProxyMetricsInner {
+  pub http: BTreeMap<String, HttpUpstreamStats>,
+  pub streams: BTreeMap<String, StreamUpstreamStats>,
+}
+
+/// Proxy metrics aggregated in memory when ncproxy save them.
+/// They only count what happened since the daemon started,
+/// prometheus handles the reset of the counters on restart.
+#[derive(Clone, Default)]
+pub struct ProxyMetrics {
+  inner: Arc<Mutex<ProxyMetricsInner>>,
+}
+
+impl ProxyMetrics {
+  fn lock(&self) -> MutexGuard<'_, ProxyMetricsInner> {
+    match self.inner.lock() {
+      Ok(inner) => inner,
+      Err(err) => err.into_inner(),
+    }
+  }
+
+  /// Count a metric saved by ncproxy, other kinds are ignored
+  pub fn observe(&self, kind: &str, data: &serde_json::Value) -> IoResult<()> {
+    match kind {
+      "ncproxy.io/http" => {
+        let metric = serde_json::from_value::<HttpMetric>(data.clone())?;
+        let upstream = metric
+          .proxy_host
+          .or(metric.upstream_addr)
+          .unwrap_or_default();
+        let mut inner = self.lock();
+        let stats = inner.http.entry(upstream).or_default();
+        *stats.statuses.entry(metric.status).or_default() += 1;
+        if let Some(index) = HTTP_DURATION_BUCKETS
+          .iter()
+          .position(|bound| metric.request_time <= *bound)
+        {
+          stats.buckets[index] += 1;
+        }
+        stats.count += 1;
+        stats.duration_sum += metric.request_time;
+        stats.bytes_sent += metric.bytes_sent.max(0) as u64;
+      }
+      "ncproxy.io/stream" => {
+        let metric = serde_json::from_value::<StreamMetric>(data.clone())?;
+        let mut inner = self.lock();
+        let stats = inner.streams.entry(metric.upstream_addr).or_default();
+        *stats.statuses.entry(metric.status).or_default() += 1;
+        stats.bytes_sent += metric.bytes_sent.max(0) as u64;
+        stats.bytes_received += metric.bytes_received.max(0) as u64;
+      }
+      _ => {}
+    }
+    Ok(())
+  }
+
+  /// Copy of the counters
+  pub fn snapshot(&self) -> ProxyMetricsInner {
+    self.lock().clone()
+  }
+}
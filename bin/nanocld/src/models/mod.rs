@@ -36,6 +36,12 @@ pub use resource_kind::*;
 mod secret;
 pub use secret::*;
 
+mod secret_kind;
+pub use secret_kind::*;
+
+mod webhook;
+pub use webhook::*;
+
 mod job;
 pub use job::*;
 
@@ -146,6 +152,51 @@ macro_rules! gen_where4uuid {
         let uuid = uuid::Uuid::parse_str(&val).unwrap_or_default();
         $query = $query.filter($column.eq(uuid));
       }
+      nanocl_stubs::generic::GenericClause::In(items) => {
+        let uuids = items
+          .iter()
+          .map(|item| uuid::Uuid::parse_str(item).unwrap_or_default())
+          .collect::<Vec<_>>();
+        $query = $query.filter($column.eq_any(uuids));
+      }
+      _ => {
+        // Ignore unsupported clause
+      }
+    }
+  };
+}
+
+/// Generate a where clause for a timestamp column, the value is a RFC 3339 date
+#[macro_export]
+macro_rules! gen_where4date {
+  ($query: expr, $column: expr, $value: expr) => {
+    let parse = |val: &str| {
+      chrono::DateTime::parse_from_rfc3339(val)
+        .map(|date| date.naive_utc())
+        .unwrap_or_default()
+    };
+    match $value {
+      nanocl_stubs::generic::GenericClause::Eq(val) => {
+        $query = $query.filter($column.eq(parse(val)));
+      }
+      nanocl_stubs::generic::GenericClause::Gt(val) => {
+        $query = $query.filter($column.gt(parse(val)));
+      }
+      nanocl_stubs::generic::GenericClause::Lt(val) => {
+        $query = $query.filter($column.lt(parse(val)));
+      }
+      nanocl_stubs::generic::GenericClause::Ge(val) => {
+        $query = $query.filter($column.ge(parse(val)));
+      }
+      nanocl_stubs::generic::GenericClause::Le(val) => {
+        $query = $query.filter($column.le(parse(val)));
+      }
+      nanocl_stubs::generic::GenericClause::IsNull => {
+        $query = $query.filter($column.is_null());
+      }
+      nanocl_stubs::generic::GenericClause::IsNotNull => {
+        $query = $query.filter($column.is_not_null());
+      }
       _ => {
         // Ignore unsupported clause
       }
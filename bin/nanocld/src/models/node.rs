@@ -1,7 +1,7 @@
 use diesel::prelude::*;
 use serde::{Serialize, Deserialize};
 
-use crate::schema::nodes;
+use crate::schema::{nodes, node_group_links};
 
 /// This structure represent a node in the database.
 /// A node is a machine that is connected to nanocl network.
@@ -19,3 +19,18 @@ pub struct NodeDb {
   /// The created at date
   pub created_at: chrono::NaiveDateTime,
 }
+
+/// This structure represent a link between a node and a node group.
+/// It's used by the replication controller to resolve node groups.
+#[derive(Debug, Clone, Queryable, Identifiable, Serialize, Deserialize)]
+#[diesel(primary_key(rowid))]
+#[diesel(table_name = node_group_links)]
+#[serde(rename_all = "PascalCase")]
+pub struct NodeGroupLinkDb {
+  /// The name of the node
+  pub node_name: String,
+  /// The name of the node group
+  pub node_group_name: String,
+  /// The internal row id
+  pub rowid: i64,
+}
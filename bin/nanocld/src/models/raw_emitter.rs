@@ -1,16 +1,22 @@
 use std::{
   pin::Pin,
   time::Duration,
+  rc::Rc,
+  cell::{Cell, RefCell},
+  collections::HashSet,
   sync::{Arc, Mutex},
   task::{Poll, Context},
 };
 
-use futures::Stream;
+use futures::{Stream, StreamExt, future, stream};
 
 use ntex::{rt, time, web, util::Bytes};
-use tokio::sync::mpsc::{Sender, Receiver, channel};
+use tokio::sync::mpsc::{Sender, Receiver, channel, error::TrySendError};
 
-use nanocl_error::io::{IoResult, IoError};
+use nanocl_error::{
+  io::{IoResult, IoError},
+  http::HttpError,
+};
 
 use nanocl_stubs::system::{Event, EventCondition};
 
@@ -33,6 +39,57 @@ impl Stream for RawEventReceiver {
   }
 }
 
+impl RawEventReceiver {
+  /// Stream persisted events before the live ones of this receiver.
+  /// The receiver must subscribe before the events are read from the store,
+  /// the live events already replayed are skipped so none is sent twice.
+  /// Like the live events, the stream ends on the first event matching a condition.
+  /// A receiver lagging too far behind during the replay is disconnected,
+  /// the client can resume from the key of the last event it got.
+  pub fn replay<S>(
+    self,
+    events: S,
+    condition: Option<Vec<EventCondition>>,
+  ) -> impl Stream<Item = Result<Bytes, web::Error>> + Unpin
+  where
+    S: Stream<Item = IoResult<Event>> + 'static,
+  {
+    let conditions = condition.unwrap_or_default();
+    let keys = Rc::new(RefCell::new(HashSet::new()));
+    let done = Rc::new(Cell::new(false));
+    let replay = {
+      let keys = Rc::clone(&keys);
+      let done = Rc::clone(&done);
+      events
+        .take_while({
+          let done = Rc::clone(&done);
+          move |_| future::ready(!done.get())
+        })
+        .map(move |event| {
+          let event = event.map_err(HttpError::from)?;
+          if conditions.iter().any(|c| c == &event) {
+            done.set(true);
+          }
+          keys.borrow_mut().insert(event.key);
+          Ok(event.try_to_bytes().map_err(HttpError::from)?)
+        })
+    };
+    let live = stream::once(async move { (!done.get()).then_some(self) })
+      .filter_map(future::ready)
+      .flatten()
+      .filter(move |item| {
+        let replayed = match item {
+          Ok(bytes) => serde_json::from_slice::<Event>(bytes)
+            .map(|event| keys.borrow_mut().remove(&event.key))
+            .unwrap_or_default(),
+          Err(_) => false,
+        };
+        future::ready(!replayed)
+      });
+    replay.chain(live).boxed_local()
+  }
+}
+
 #[derive(Clone)]
 pub struct RawEventSender(
   pub Sender<Bytes>,
@@ -128,7 +185,16 @@ impl RawEventEmitter {
     let mut new_clients = Vec::new();
     let msg = e.try_to_bytes()?;
     for mut client in clients {
-      let _ = client.0.try_send(msg.clone());
+      match client.0.try_send(msg.clone()) {
+        Ok(_) => {}
+        // Drop the clients not reading fast enough instead of losing events,
+        // their stream ends and they can resume from their last event
+        Err(TrySendError::Full(_)) => {
+          log::warn!("raw_emitter::emit: client lagging behind disconnected");
+          continue;
+        }
+        Err(TrySendError::Closed(_)) => continue,
+      }
       let conditions = client.1.clone().unwrap_or_default();
       if conditions.is_empty() {
         new_clients.push(client);
@@ -144,6 +210,11 @@ impl RawEventEmitter {
     Ok(())
   }
 
+  /// Number of clients subscribed to the events
+  pub fn subscriber_count(&self) -> IoResult<usize> {
+    Ok(self.inner.lock()?.clients.len())
+  }
+
   /// Subscribe to events
   pub async fn subscribe(
     &self,
@@ -159,3 +230,98 @@ impl RawEventEmitter {
     Ok(rx)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use nanocl_stubs::system::{
+    EventActor, EventActorKind, EventKind, NativeEventAction,
+  };
+
+  fn event(actor: &str, action: NativeEventAction) -> Event {
+    let now = chrono::Utc::now().naive_utc();
+    Event {
+      key: uuid::Uuid::new_v4(),
+      created_at: now,
+      expires_at: now,
+      reporting_node: "test".to_owned(),
+      reporting_controller: "test".to_owned(),
+      kind: EventKind::Normal,
+      action: action.to_string(),
+      reason: "test".to_owned(),
+      note: None,
+      actor: Some(EventActor {
+        key: Some(actor.to_owned()),
+        kind: EventActorKind::Cargo,
+        attributes: None,
+      }),
+      related: None,
+      metadata: None,
+    }
+  }
+
+  async fn keys(
+    stream: impl Stream<Item = Result<Bytes, web::Error>>,
+  ) -> Vec<uuid::Uuid> {
+    stream
+      .map(|item| serde_json::from_slice::<Event>(&item.unwrap()).unwrap().key)
+      .collect()
+      .await
+  }
+
+  #[ntex::test]
+  async fn replay() {
+    let events = [
+      event("a", NativeEventAction::Create),
+      event("b", NativeEventAction::Create),
+      event("c", NativeEventAction::Start),
+    ];
+    // The live events include an already replayed one
+    let (tx, rx) = RawEventSender::new(None);
+    tx.0.try_send(events[1].try_to_bytes().unwrap()).unwrap();
+    tx.0.try_send(events[2].try_to_bytes().unwrap()).unwrap();
+    drop(tx);
+    let replayed =
+      stream::iter(events[..2].iter().cloned().map(Ok).collect::<Vec<_>>());
+    let stream = rx.replay(replayed, None);
+    assert_eq!(
+      keys(stream).await,
+      events.iter().map(|e| e.key).collect::<Vec<_>>()
+    );
+    // The stream ends on a replayed event matching a condition
+    let (tx, rx) = RawEventSender::new(None);
+    tx.0.try_send(events[2].try_to_bytes().unwrap()).unwrap();
+    let condition = EventCondition {
+      actor_key: Some("a".to_owned()),
+      actor_kind: Some(EventActorKind::Cargo),
+      kind: vec![EventKind::Normal],
+      action: vec![NativeEventAction::Create],
+      ..Default::default()
+    };
+    let replayed =
+      stream::iter(events.iter().cloned().map(Ok).collect::<Vec<_>>());
+    let stream = rx.replay(replayed, Some(vec![condition]));
+    assert_eq!(keys(stream).await, vec![events[0].key]);
+  }
+
+  #[test]
+  fn emit_lagging() {
+    let emitter = RawEventEmitter::default();
+    let (tx, mut rx) = RawEventSender::new(None);
+    emitter.inner.lock().unwrap().clients.push(tx);
+    let e = event("a", NativeEventAction::Create);
+    for _ in 0..100 {
+      emitter.emit(&e).unwrap();
+    }
+    assert_eq!(emitter.subscriber_count().unwrap(), 1);
+    emitter.emit(&e).unwrap();
+    assert_eq!(emitter.subscriber_count().unwrap(), 0);
+    // The buffered events are still received before the stream ends
+    let mut count = 0;
+    while rx.0.try_recv().is_ok() {
+      count += 1;
+    }
+    assert_eq!(count, 100);
+  }
+}
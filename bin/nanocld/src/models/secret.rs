@@ -5,11 +5,13 @@ use nanocl_error::io::IoError;
 
 use nanocl_stubs::secret::{Secret, SecretPartial, SecretUpdate};
 
-use crate::schema::secrets;
+use crate::{utils, schema::secrets};
 
 /// This structure represent the secret in the database.
 /// A secret is a key/value pair that can be used by the user to store
 /// sensitive data. It is stored as a json object in the database.
+/// The data is encrypted by a per-secret data key wrapped by the master key,
+/// see `utils::secret`.
 #[derive(
   Clone, Serialize, Deserialize, Queryable, Identifiable, Insertable,
 )]
@@ -27,7 +29,7 @@ pub struct SecretDb {
   pub kind: String,
   /// The secret cannot be updated
   pub immutable: bool,
-  /// The secret data
+  /// The secret data encrypted
   pub data: serde_json::Value,
   // The metadata (user defined)
   #[serde(skip_serializing_if = "Option::is_none")]
@@ -58,7 +60,7 @@ impl TryFrom<SecretDb> for Secret {
       updated_at: db.updated_at,
       kind: db.kind,
       immutable: db.immutable,
-      data: db.data,
+      data: utils::secret::decrypt(&db.data)?,
       metadata: db.metadata,
     })
   }
@@ -0,0 +1,43 @@
+use diesel::prelude::*;
+
+use nanocl_stubs::secret_kind::{SecretKind, SecretKindPartial};
+
+use crate::schema::secret_kinds;
+
+/// This structure represent a custom secret kind in the database.
+/// It holds the JSONSchema used to validate the data of the secrets.
+#[derive(Clone, Debug, Queryable, Identifiable, Insertable)]
+#[diesel(primary_key(name))]
+#[diesel(table_name = secret_kinds)]
+pub struct SecretKindDb {
+  /// Name of the kind
+  pub name: String,
+  /// When the kind have been created
+  pub created_at: chrono::NaiveDateTime,
+  /// The JSONSchema of the secret data
+  pub schema: serde_json::Value,
+  /// The metadata (user defined)
+  pub metadata: Option<serde_json::Value>,
+}
+
+impl From<&SecretKindPartial> for SecretKindDb {
+  fn from(kind: &SecretKindPartial) -> Self {
+    Self {
+      name: kind.name.clone(),
+      created_at: chrono::Utc::now().naive_utc(),
+      schema: kind.schema.clone(),
+      metadata: kind.metadata.clone(),
+    }
+  }
+}
+
+impl From<SecretKindDb> for SecretKind {
+  fn from(db: SecretKindDb) -> Self {
+    Self {
+      name: db.name,
+      created_at: db.created_at,
+      schema: db.schema,
+      metadata: db.metadata,
+    }
+  }
+}
@@ -23,3 +23,12 @@ pub struct SpecDb {
   /// Metadata (user defined) of the resource kind version
   pub metadata: Option<serde_json::Value>,
 }
+
+/// This structure is used to update a specification in the database.
+/// Only used to re-encrypt secret histories when the master key is rotated.
+#[derive(Debug, Default, AsChangeset)]
+#[diesel(table_name = specs)]
+pub struct SpecUpdateDb {
+  /// Config of the resource kind version
+  pub data: Option<serde_json::Value>,
+}
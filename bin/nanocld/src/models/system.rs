@@ -5,7 +5,7 @@ use futures::channel::mpsc;
 
 use nanocl_stubs::{config::DaemonConfig, system::Event};
 
-use super::{Pool, RawEventEmitter, TaskManager};
+use super::{Pool, RawEventEmitter, TaskManager, ProxyMetrics};
 
 /// This structure represent the state of the system.
 /// Used to share the state between the different handlers.
@@ -19,6 +19,8 @@ pub struct SystemStateInner {
   pub config: DaemonConfig,
   /// Manager of the tasks
   pub task_manager: TaskManager,
+  /// Counters of the proxy exposed to prometheus
+  pub proxy_metrics: ProxyMetrics,
   /// Latest version of the daemon
   pub version: String,
   /// Event emitter
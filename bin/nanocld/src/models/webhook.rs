@@ -0,0 +1,179 @@
+use std::str::FromStr;
+
+use diesel::prelude::*;
+
+use nanocl_error::io::IoError;
+
+use nanocl_stubs::webhook::{
+  Webhook, WebhookDelivery, WebhookDeliveryStatus, WebhookPartial,
+};
+
+use crate::{
+  utils,
+  schema::{webhooks, webhook_deliveries},
+};
+
+/// This structure represent a webhook in the database.
+/// The signing secret is encrypted with the master key, see `utils::secret`.
+#[derive(Clone, Debug, Queryable, Identifiable, Insertable)]
+#[diesel(primary_key(name))]
+#[diesel(table_name = webhooks)]
+pub struct WebhookDb {
+  /// Name of the webhook
+  pub name: String,
+  /// When the webhook have been created
+  pub created_at: chrono::NaiveDateTime,
+  /// When the webhook have been updated
+  pub updated_at: chrono::NaiveDateTime,
+  /// The url where the events are posted
+  pub url: String,
+  /// The conditions the events must match
+  pub conditions: Option<serde_json::Value>,
+  /// The signing secret encrypted
+  pub secret: Option<serde_json::Value>,
+  /// The retry policy
+  pub retry: serde_json::Value,
+  /// The metadata (user defined)
+  pub metadata: Option<serde_json::Value>,
+}
+
+impl TryFrom<&WebhookPartial> for WebhookDb {
+  type Error = IoError;
+
+  fn try_from(webhook: &WebhookPartial) -> Result<Self, Self::Error> {
+    let secret = webhook
+      .secret
+      .as_ref()
+      .map(|secret| {
+        utils::secret::encrypt(&serde_json::Value::String(secret.clone()))
+      })
+      .transpose()?;
+    Ok(Self {
+      name: webhook.name.clone(),
+      created_at: chrono::Utc::now().naive_utc(),
+      updated_at: chrono::Utc::now().naive_utc(),
+      url: webhook.url.clone(),
+      conditions: webhook
+        .conditions
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()?,
+      secret,
+      retry: serde_json::to_value(webhook.retry.clone().unwrap_or_default())?,
+      metadata: webhook.metadata.clone(),
+    })
+  }
+}
+
+impl TryFrom<WebhookDb> for Webhook {
+  type Error = IoError;
+
+  fn try_from(db: WebhookDb) -> Result<Self, Self::Error> {
+    Ok(Self {
+      name: db.name,
+      created_at: db.created_at,
+      updated_at: db.updated_at,
+      url: db.url,
+      conditions: db.conditions.map(serde_json::from_value).transpose()?,
+      signed: db.secret.is_some(),
+      retry: serde_json::from_value(db.retry)?,
+      metadata: db.metadata,
+    })
+  }
+}
+
+/// This structure is used to update a webhook
+#[derive(Debug, Default, AsChangeset)]
+#[diesel(table_name = webhooks)]
+pub struct WebhookUpdateDb {
+  /// The signing secret encrypted
+  pub secret: Option<Option<serde_json::Value>>,
+}
+
+/// This structure represent the delivery of an event to a webhook.
+/// It is kept once delivered or dead-lettered so failures can be inspected.
+#[derive(Clone, Debug, Queryable, Identifiable, Insertable)]
+#[diesel(primary_key(key))]
+#[diesel(table_name = webhook_deliveries)]
+pub struct WebhookDeliveryDb {
+  /// Unique identifier of the delivery
+  pub key: uuid::Uuid,
+  /// When the delivery have been created
+  pub created_at: chrono::NaiveDateTime,
+  /// When the delivery have been updated
+  pub updated_at: chrono::NaiveDateTime,
+  /// Name of the webhook
+  pub webhook_name: String,
+  /// Key of the delivered event
+  pub event_key: uuid::Uuid,
+  /// Name of the node delivering the event
+  pub node_name: String,
+  /// Status of the delivery
+  pub status: String,
+  /// Number of attempts made
+  pub attempts: i64,
+  /// Status code of the last response
+  pub status_code: Option<i64>,
+  /// Error of the last failed attempt
+  pub error: Option<String>,
+  /// When the next attempt is scheduled
+  pub next_attempt_at: Option<chrono::NaiveDateTime>,
+}
+
+impl WebhookDeliveryDb {
+  /// Create a new pending delivery of an event
+  pub fn new(webhook_name: &str, event_key: &uuid::Uuid, node: &str) -> Self {
+    let now = chrono::Utc::now().naive_utc();
+    Self {
+      key: uuid::Uuid::new_v4(),
+      created_at: now,
+      updated_at: now,
+      webhook_name: webhook_name.to_owned(),
+      event_key: *event_key,
+      node_name: node.to_owned(),
+      status: WebhookDeliveryStatus::Pending.to_string(),
+      attempts: 0,
+      status_code: None,
+      error: None,
+      next_attempt_at: Some(now),
+    }
+  }
+}
+
+impl TryFrom<WebhookDeliveryDb> for WebhookDelivery {
+  type Error = IoError;
+
+  fn try_from(db: WebhookDeliveryDb) -> Result<Self, Self::Error> {
+    Ok(Self {
+      key: db.key,
+      created_at: db.created_at,
+      updated_at: db.updated_at,
+      webhook_name: db.webhook_name,
+      event_key: db.event_key,
+      node_name: db.node_name,
+      status: WebhookDeliveryStatus::from_str(&db.status)?,
+      attempts: db.attempts as u32,
+      status_code: db.status_code.map(|code| code as u16),
+      error: db.error,
+      next_attempt_at: db.next_attempt_at,
+    })
+  }
+}
+
+/// This structure is used to record the result of a delivery attempt
+#[derive(Debug, Default, AsChangeset)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct WebhookDeliveryUpdateDb {
+  /// When the delivery have been updated
+  pub updated_at: Option<chrono::NaiveDateTime>,
+  /// Status of the delivery
+  pub status: Option<String>,
+  /// Number of attempts made
+  pub attempts: Option<i64>,
+  /// Status code of the last response
+  pub status_code: Option<Option<i64>>,
+  /// Error of the last failed attempt
+  pub error: Option<Option<String>>,
+  /// When the next attempt is scheduled
+  pub next_attempt_at: Option<Option<chrono::NaiveDateTime>>,
+}
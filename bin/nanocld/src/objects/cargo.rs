@@ -34,6 +34,15 @@ impl ObjCreate for CargoDb {
     if obj.spec.name.contains('.') {
       return Err(HttpError::bad_request("Cargo name cannot contain '.'"));
     }
+    if let Some(autoscale) = &obj.spec.autoscale {
+      utils::autoscale::validate(autoscale)?;
+    }
+    if let Some(strategy) = &obj.spec.update_strategy {
+      utils::update::validate(strategy)?;
+    }
+    if let Some(mounts) = &obj.spec.secret_mounts {
+      utils::secret_mount::validate(mounts)?;
+    }
     let key = utils::key::gen_key(&obj.namespace, &obj.spec.name);
     let new_spec =
       SpecDb::try_from_cargo_partial(&key, &obj.version, &obj.spec)?;
@@ -112,6 +121,15 @@ impl ObjPutByPk for CargoDb {
     obj: &Self::ObjPutIn,
     state: &SystemState,
   ) -> HttpResult<Self::ObjPutOut> {
+    if let Some(autoscale) = &obj.spec.autoscale {
+      utils::autoscale::validate(autoscale)?;
+    }
+    if let Some(strategy) = &obj.spec.update_strategy {
+      utils::update::validate(strategy)?;
+    }
+    if let Some(mounts) = &obj.spec.secret_mounts {
+      utils::secret_mount::validate(mounts)?;
+    }
     let status = ObjPsStatusDb::read_by_pk(pk, &state.inner.pool).await?;
     let new_status = ObjPsStatusUpdate {
       wanted: Some(ObjPsStatusKind::Start.to_string()),
@@ -221,11 +239,26 @@ impl ObjPatchByPk for CargoDb {
         cargo.spec.init_container
       },
       replication: obj.spec.replication.clone(),
+      autoscale: if obj.spec.autoscale.is_some() {
+        obj.spec.autoscale.clone()
+      } else {
+        cargo.spec.autoscale
+      },
+      update_strategy: if obj.spec.update_strategy.is_some() {
+        obj.spec.update_strategy.clone()
+      } else {
+        cargo.spec.update_strategy
+      },
       secrets: if obj.spec.secrets.is_some() {
         obj.spec.secrets.clone()
       } else {
         cargo.spec.secrets
       },
+      secret_mounts: if obj.spec.secret_mounts.is_some() {
+        obj.spec.secret_mounts.clone()
+      } else {
+        cargo.spec.secret_mounts
+      },
       metadata: if obj.spec.metadata.is_some() {
         obj.spec.metadata.clone()
       } else {
@@ -1,5 +1,6 @@
 use nanocl_error::http::{HttpError, HttpResult};
 use nanocl_stubs::{
+  generic::{GenericClause, GenericFilter},
   job::{Job, JobPartial, JobInspect},
   system::{ObjPsStatusPartial, ObjPsStatusKind, NativeEventAction},
 };
@@ -7,7 +8,7 @@ use nanocl_stubs::{
 use crate::{
   utils,
   repositories::generic::*,
-  models::{JobDb, ObjPsStatusDb, ObjPsStatusUpdate, ProcessDb},
+  models::{JobDb, JobRunDb, ObjPsStatusDb, ObjPsStatusUpdate, ProcessDb},
 };
 
 use super::generic::*;
@@ -20,6 +21,8 @@ impl ObjCreate for JobDb {
     obj: &Self::ObjCreateIn,
     state: &crate::models::SystemState,
   ) -> HttpResult<Self::ObjCreateOut> {
+    utils::job::validate(obj)?;
+    utils::job::validate_dependencies(obj, state).await?;
     let db_model = JobDb::try_from_partial(obj)?;
     let status = ObjPsStatusPartial {
       key: obj.name.clone(),
@@ -32,9 +35,6 @@ impl ObjCreate for JobDb {
     let job = JobDb::create_from(db_model, &state.inner.pool)
       .await?
       .try_to_spec(&status)?;
-    if let Some(schedule) = &job.schedule {
-      utils::cron::add_cron_rule(&job, schedule, state).await?;
-    }
     Ok(job)
   }
 }
@@ -78,6 +78,10 @@ impl ObjInspectByPk for JobDb {
       utils::container::count_status(&instances);
     let status =
       ObjPsStatusDb::read_by_pk(&job.name, &state.inner.pool).await?;
+    let filter = GenericFilter::new()
+      .r#where("job_name", GenericClause::Eq(job.name.clone()))
+      .limit(10);
+    let runs = JobRunDb::transform_read_by(&filter, &state.inner.pool).await?;
     let job_inspect = JobInspect {
       status: status
         .try_into()
@@ -88,6 +92,7 @@ impl ObjInspectByPk for JobDb {
       instance_running,
       instance_failed,
       instances,
+      runs,
     };
     Ok(job_inspect)
   }
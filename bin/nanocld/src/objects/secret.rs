@@ -1,3 +1,5 @@
+use ntex::rt;
+
 use nanocl_error::http::HttpResult;
 use nanocl_stubs::{
   secret::{Secret, SecretPartial, SecretUpdate},
@@ -5,8 +7,9 @@ use nanocl_stubs::{
 };
 
 use crate::{
+  utils,
   repositories::generic::*,
-  models::{SecretDb, SystemState},
+  models::{SecretDb, SecretUpdateDb, SpecDb, SystemState},
 };
 
 use super::generic::*;
@@ -19,7 +22,12 @@ impl ObjCreate for SecretDb {
     obj: &Self::ObjCreateIn,
     state: &SystemState,
   ) -> HttpResult<Self::ObjCreateOut> {
-    let secret = SecretDb::create_from(obj, &state.inner.pool).await?;
+    utils::secret::validate(&obj.kind, &obj.data, &state.inner.pool).await?;
+    let mut secret = SecretDb::from(obj);
+    secret.data = utils::secret::encrypt(&secret.data)?;
+    let secret = SecretDb::create_from(secret, &state.inner.pool).await?;
+    SpecDb::create_from(SpecDb::from_secret(&secret), &state.inner.pool)
+      .await?;
     let secret: Secret = secret.try_into()?;
     Ok(secret)
   }
@@ -36,6 +44,7 @@ impl ObjDelByPk for SecretDb {
   ) -> HttpResult<Self::ObjDelOut> {
     let secret = SecretDb::transform_read_by_pk(pk, &state.inner.pool).await?;
     SecretDb::del_by_pk(pk, &state.inner.pool).await?;
+    SpecDb::del_secret_histories(pk, &state.inner.pool).await?;
     Ok(secret)
   }
 }
@@ -53,9 +62,26 @@ impl ObjPatchByPk for SecretDb {
     obj: &Self::ObjPatchIn,
     state: &SystemState,
   ) -> HttpResult<Self::ObjPatchOut> {
-    let secret = SecretDb::update_pk(pk, obj, &state.inner.pool)
-      .await?
-      .try_into()?;
+    let current = SecretDb::read_by_pk(pk, &state.inner.pool).await?;
+    utils::secret::validate(&current.kind, &obj.data, &state.inner.pool)
+      .await?;
+    let mut update = SecretUpdateDb::from(obj);
+    update.data = update
+      .data
+      .map(|data| utils::secret::encrypt(&data))
+      .transpose()?;
+    let secret = SecretDb::update_pk(pk, update, &state.inner.pool).await?;
+    SpecDb::create_from(SpecDb::from_secret(&secret), &state.inner.pool)
+      .await?;
+    let secret: Secret = secret.try_into()?;
+    let key = pk.to_owned();
+    let state = state.clone();
+    rt::spawn(async move {
+      if let Err(err) = utils::secret_mount::sync(&key, &state).await
+      {
+        log::warn!("secret::patch: unable to sync mounts of {key}: {err}");
+      }
+    });
     Ok(secret)
   }
 }
@@ -1,10 +1,14 @@
+use std::sync::Arc;
+
 use crate::{
-  gen_multiple, gen_where4uuid, gen_where4string, models::EventDb,
+  utils, gen_multiple, gen_where4uuid, gen_where4string,
+  models::{Pool, EventDb},
   schema::events,
 };
 
 use diesel::prelude::*;
-use nanocl_error::io::IoResult;
+use futures::{Stream, StreamExt, stream};
+use nanocl_error::io::{IoError, IoResult};
 use nanocl_stubs::system::Event;
 
 use super::generic::*;
@@ -62,3 +66,95 @@ impl RepositoryReadByTransform for EventDb {
     Self::NewOutput::try_from(input)
   }
 }
+
+/// Number of events read at once when replaying the store
+const REPLAY_PAGE_SIZE: i64 = 100;
+
+/// Position in the events ordered by creation date then key.
+/// Without a key every event created at the date is after the cursor.
+pub type EventCursor = (chrono::NaiveDateTime, Option<uuid::Uuid>);
+
+impl EventDb {
+  /// Parse a cursor, the key of an event or a RFC 3339 date
+  pub async fn parse_cursor(since: &str, pool: &Pool) -> IoResult<EventCursor> {
+    match uuid::Uuid::parse_str(since) {
+      Ok(key) => {
+        let event = EventDb::read_by_pk(&key, pool).await?;
+        Ok((event.created_at, Some(key)))
+      }
+      Err(_) => {
+        let date = chrono::DateTime::parse_from_rfc3339(since)
+          .map_err(|err| {
+            IoError::invalid_input(
+              "Since",
+              &format!("{since} is not an event key or a date: {err}"),
+            )
+          })?
+          .naive_utc();
+        Ok((date, None))
+      }
+    }
+  }
+
+  /// Read at most `limit` persisted events after a cursor
+  /// ordered by creation date then key
+  pub async fn read_page(
+    cursor: &EventCursor,
+    limit: i64,
+    pool: &Pool,
+  ) -> IoResult<Vec<Event>> {
+    let (date, key) = *cursor;
+    let pool = Arc::clone(pool);
+    let items = ntex::rt::spawn_blocking(move || {
+      let mut conn = utils::store::get_pool_conn(&pool)?;
+      let mut query = events::table
+        .order((events::created_at.asc(), events::key.asc()))
+        .limit(limit)
+        .into_boxed();
+      query = match key {
+        None => query.filter(events::created_at.ge(date)),
+        Some(key) => query.filter(
+          events::created_at
+            .gt(date)
+            .or(events::created_at.eq(date).and(events::key.gt(key))),
+        ),
+      };
+      let items = query
+        .get_results::<EventDb>(&mut conn)
+        .map_err(Self::map_err)?;
+      Ok::<_, IoError>(items)
+    })
+    .await??;
+    items.into_iter().map(Event::try_from).collect()
+  }
+
+  /// Stream the persisted events after a cursor page by page
+  /// ordered by creation date then key
+  pub fn stream_since(
+    cursor: EventCursor,
+    pool: &Pool,
+  ) -> impl Stream<Item = IoResult<Event>> {
+    let pool = Arc::clone(pool);
+    stream::unfold(Some(cursor), move |cursor| {
+      let pool = Arc::clone(&pool);
+      async move {
+        let cursor = cursor?;
+        let (events, next) =
+          match EventDb::read_page(&cursor, REPLAY_PAGE_SIZE, &pool).await {
+            Err(err) => (vec![Err(err)], None),
+            Ok(events) => {
+              let next = match events.last() {
+                Some(last) if events.len() as i64 == REPLAY_PAGE_SIZE => {
+                  Some((last.created_at, Some(last.key)))
+                }
+                _ => None,
+              };
+              (events.into_iter().map(Ok).collect(), next)
+            }
+          };
+        Some((stream::iter(events), next))
+      }
+    })
+    .flatten()
+  }
+}
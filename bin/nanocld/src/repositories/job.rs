@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use diesel::prelude::*;
 
 use futures_util::StreamExt;
@@ -8,15 +10,19 @@ use nanocl_error::{
   io::IoResult,
 };
 use nanocl_stubs::{
-  generic::GenericFilter,
-  job::{Job, JobPartial, JobSummary},
+  generic::{GenericClause, GenericFilter},
+  job::{Job, JobPartial, JobRun, JobRunStatus, JobSummary},
 };
 
 use crate::{
   utils,
-  schema::jobs,
-  gen_multiple, gen_where4json, gen_where4string,
-  models::{JobDb, JobUpdateDb, ObjPsStatusDb, Pool, ProcessDb, SystemState},
+  schema::{jobs, job_runs},
+  gen_multiple, gen_where4date, gen_where4json, gen_where4string,
+  gen_where4uuid,
+  models::{
+    JobDb, JobRunDb, JobRunUpdateDb, JobUpdateDb, ObjPsStatusDb, Pool,
+    ProcessDb, SystemState,
+  },
 };
 
 use super::generic::*;
@@ -104,19 +110,29 @@ impl JobDb {
       metadata: self.metadata.clone(),
       secrets: p.secrets.clone(),
       schedule: p.schedule.clone(),
+      time_zone: p.time_zone.clone(),
+      concurrency_policy: p.concurrency_policy.clone(),
+      starting_deadline_seconds: p.starting_deadline_seconds,
+      backoff_limit: p.backoff_limit,
+      backoff_seconds: p.backoff_seconds,
+      max_backoff_seconds: p.max_backoff_seconds,
+      active_deadline_seconds: p.active_deadline_seconds,
       ttl: p.ttl,
       status: status.clone().try_into()?,
       containers: p.containers.clone(),
       image_pull_secret: p.image_pull_secret.clone(),
       image_pull_policy: p.image_pull_policy.clone(),
+      depends_on: p.depends_on.clone(),
+      steps: p.steps.clone(),
     })
   }
 
-  /// List all jobs
-  pub async fn list(state: &SystemState) -> HttpResult<Vec<JobSummary>> {
-    let jobs =
-      JobDb::transform_read_by(&GenericFilter::default(), &state.inner.pool)
-        .await?;
+  /// List the jobs matching the filter
+  pub async fn list(
+    filter: &GenericFilter,
+    state: &SystemState,
+  ) -> HttpResult<Vec<JobSummary>> {
+    let jobs = JobDb::transform_read_by(filter, &state.inner.pool).await?;
     let job_summaries = jobs
       .iter()
       .map(|job| async {
@@ -144,3 +160,184 @@ impl JobDb {
     Ok(job_summaries)
   }
 }
+
+impl RepositoryBase for JobRunDb {}
+
+impl RepositoryCreate for JobRunDb {}
+
+impl RepositoryUpdate for JobRunDb {
+  type UpdateItem = JobRunUpdateDb;
+}
+
+impl RepositoryReadBy for JobRunDb {
+  type Output = JobRunDb;
+
+  fn get_pk() -> &'static str {
+    "key"
+  }
+
+  fn gen_read_query(
+    filter: &GenericFilter,
+    is_multiple: bool,
+  ) -> impl diesel::query_dsl::methods::LoadQuery<
+    'static,
+    diesel::PgConnection,
+    Self::Output,
+  > {
+    let r#where = filter.r#where.clone().unwrap_or_default();
+    let mut query = job_runs::table.into_boxed();
+    if let Some(key) = r#where.get("key") {
+      gen_where4uuid!(query, job_runs::key, key);
+    }
+    if let Some(job_name) = r#where.get("job_name") {
+      gen_where4string!(query, job_runs::job_name, job_name);
+    }
+    if let Some(status) = r#where.get("status") {
+      gen_where4string!(query, job_runs::status, status);
+    }
+    if let Some(scheduled_at) = r#where.get("scheduled_at") {
+      gen_where4date!(query, job_runs::scheduled_at, scheduled_at);
+    }
+    if let Some(ended_at) = r#where.get("ended_at") {
+      gen_where4date!(query, job_runs::ended_at, ended_at);
+    }
+    if is_multiple {
+      gen_multiple!(query, job_runs::created_at, filter);
+    }
+    query
+  }
+}
+
+impl RepositoryReadByTransform for JobRunDb {
+  type NewOutput = JobRun;
+
+  fn transform(input: Self::Output) -> IoResult<Self::NewOutput> {
+    input.try_into()
+  }
+}
+
+impl JobRunDb {
+  /// Read the runs of a job with the given status, the newest first
+  pub async fn read_by_status(
+    job_name: &str,
+    status: &JobRunStatus,
+    pool: &Pool,
+  ) -> IoResult<Vec<JobRunDb>> {
+    let filter = GenericFilter::new()
+      .r#where("job_name", GenericClause::Eq(job_name.to_owned()))
+      .r#where("status", GenericClause::Eq(status.to_string()));
+    JobRunDb::read_by(&filter, pool).await
+  }
+
+  /// Read the runs of a job that ended with the given status after a date
+  pub async fn read_ended_since(
+    job_name: &str,
+    status: &JobRunStatus,
+    since: &chrono::NaiveDateTime,
+    pool: &Pool,
+  ) -> IoResult<Vec<JobRunDb>> {
+    let since = since.and_utc().to_rfc3339();
+    let filter = GenericFilter::new()
+      .r#where("job_name", GenericClause::Eq(job_name.to_owned()))
+      .r#where("status", GenericClause::Eq(status.to_string()))
+      .r#where("ended_at", GenericClause::Gt(since));
+    JobRunDb::read_by(&filter, pool).await
+  }
+
+  /// Return if a job has a pending or a running run
+  pub async fn is_active(job_name: &str, pool: &Pool) -> IoResult<bool> {
+    for status in [JobRunStatus::Pending, JobRunStatus::Running] {
+      if !JobRunDb::read_by_status(job_name, &status, pool)
+        .await?
+        .is_empty()
+      {
+        return Ok(true);
+      }
+    }
+    Ok(false)
+  }
+
+  /// Get when the last scheduled run of a job was due
+  pub async fn read_last_scheduled(
+    job_name: &str,
+    pool: &Pool,
+  ) -> IoResult<Option<chrono::NaiveDateTime>> {
+    let filter = GenericFilter::new()
+      .r#where("job_name", GenericClause::Eq(job_name.to_owned()))
+      .r#where("scheduled_at", GenericClause::IsNotNull)
+      .limit(1);
+    let runs = JobRunDb::read_by(&filter, pool).await?;
+    Ok(runs.first().and_then(|run| run.scheduled_at))
+  }
+
+  /// Insert a scheduled run unless a node already recorded one
+  /// for the same job and due time, return if it was inserted.
+  /// Every node runs the scheduler so only the node claiming a due run handles it.
+  pub async fn claim(run: JobRunDb, pool: &Pool) -> IoResult<bool> {
+    let pool = Arc::clone(pool);
+    ntex::rt::spawn_blocking(move || {
+      let mut conn = utils::store::get_pool_conn(&pool)?;
+      let count = diesel::insert_into(job_runs::table)
+        .values(&run)
+        .on_conflict_do_nothing()
+        .execute(&mut conn)
+        .map_err(Self::map_err)?;
+      Ok(count == 1)
+    })
+    .await?
+  }
+
+  /// Mark the oldest pending run of a job as running.
+  /// Each start records its pending run and the start tasks are run in order
+  /// so every task take its own run.
+  /// A new run is created for the starts that didn't record one.
+  pub async fn start(job_name: &str, pool: &Pool) -> IoResult<JobRunDb> {
+    let pending =
+      JobRunDb::read_by_status(job_name, &JobRunStatus::Pending, pool).await?;
+    let Some(run) = pending.last() else {
+      let run = JobRunDb::new(job_name, None, JobRunStatus::Running, None);
+      return JobRunDb::create_from(run, pool).await;
+    };
+    let now = chrono::Utc::now().naive_utc();
+    let update = JobRunUpdateDb {
+      updated_at: Some(now),
+      started_at: Some(Some(now)),
+      status: Some(JobRunStatus::Running.to_string()),
+      ..Default::default()
+    };
+    JobRunDb::update_pk(&run.key, update, pool).await
+  }
+
+  /// Record the end of a run
+  pub async fn end(
+    key: &uuid::Uuid,
+    status: &JobRunStatus,
+    reason: Option<String>,
+    pool: &Pool,
+  ) -> IoResult<JobRunDb> {
+    let now = chrono::Utc::now().naive_utc();
+    let update = JobRunUpdateDb {
+      updated_at: Some(now),
+      ended_at: Some(Some(now)),
+      status: Some(status.to_string()),
+      reason: Some(reason),
+      ..Default::default()
+    };
+    JobRunDb::update_pk(key, update, pool).await
+  }
+
+  /// Record the end of the running runs of a job
+  pub async fn end_running(
+    job_name: &str,
+    status: &JobRunStatus,
+    reason: &str,
+    pool: &Pool,
+  ) -> IoResult<()> {
+    let runs =
+      JobRunDb::read_by_status(job_name, &JobRunStatus::Running, pool).await?;
+    for run in runs {
+      JobRunDb::end(&run.key, status, Some(reason.to_owned()), pool).await?;
+    }
+    Ok(())
+  }
+}
@@ -1,9 +1,16 @@
+use std::sync::Arc;
+
 use diesel::prelude::*;
+
+use nanocl_error::io::IoResult;
+
 use nanocl_stubs::generic::GenericFilter;
 
 use crate::{
-  gen_multiple, gen_where4json, gen_where4uuid, gen_where4string,
-  models::MetricDb, schema::metrics,
+  utils, gen_multiple, gen_where4date, gen_where4json, gen_where4uuid,
+  gen_where4string,
+  models::{Pool, MetricDb},
+  schema::metrics,
 };
 
 use super::generic::*;
@@ -12,6 +19,38 @@ impl RepositoryBase for MetricDb {}
 
 impl RepositoryCreate for MetricDb {}
 
+impl RepositoryDelBy for MetricDb {
+  fn gen_del_query(
+    filter: &GenericFilter,
+  ) -> diesel::query_builder::BoxedDeleteStatement<
+    'static,
+    diesel::pg::Pg,
+    <Self as diesel::associations::HasTable>::Table,
+  >
+  where
+    Self: diesel::associations::HasTable,
+  {
+    let r#where = filter.r#where.to_owned().unwrap_or_default();
+    let mut query = diesel::delete(metrics::table).into_boxed();
+    if let Some(key) = r#where.get("key") {
+      gen_where4uuid!(query, metrics::key, key);
+    }
+    if let Some(node_name) = r#where.get("node_name") {
+      gen_where4string!(query, metrics::node_name, node_name);
+    }
+    if let Some(kind) = r#where.get("kind") {
+      gen_where4string!(query, metrics::kind, kind);
+    }
+    if let Some(created_at) = r#where.get("created_at") {
+      gen_where4date!(query, metrics::created_at, created_at);
+    }
+    if let Some(expires_at) = r#where.get("expires_at") {
+      gen_where4date!(query, metrics::expires_at, expires_at);
+    }
+    query
+  }
+}
+
 impl RepositoryReadBy for MetricDb {
   type Output = MetricDb;
 
@@ -27,6 +66,19 @@ impl RepositoryReadBy for MetricDb {
     diesel::pg::PgConnection,
     Self::Output,
   > {
+    let mut query = Self::gen_filter_query(filter);
+    if is_multiple {
+      gen_multiple!(query, metrics::dsl::created_at, filter);
+    }
+    query
+  }
+}
+
+impl MetricDb {
+  /// Query of the metrics matching the where clause of a filter
+  fn gen_filter_query(
+    filter: &GenericFilter,
+  ) -> metrics::BoxedQuery<'static, diesel::pg::Pg> {
     let r#where = filter.r#where.clone().unwrap_or_default();
     let mut query = metrics::table.into_boxed();
     if let Some(key) = r#where.get("key") {
@@ -41,9 +93,62 @@ impl RepositoryReadBy for MetricDb {
     if let Some(data) = r#where.get("data") {
       gen_where4json!(query, metrics::data, data);
     }
-    if is_multiple {
-      gen_multiple!(query, metrics::dsl::created_at, filter);
+    if let Some(created_at) = r#where.get("created_at") {
+      gen_where4date!(query, metrics::created_at, created_at);
     }
     query
   }
+
+  /// Read the metrics matching a filter created between `since` and `until`
+  pub async fn read_range(
+    filter: &GenericFilter,
+    since: Option<chrono::NaiveDateTime>,
+    until: Option<chrono::NaiveDateTime>,
+    pool: &Pool,
+  ) -> IoResult<Vec<MetricDb>> {
+    let pool = Arc::clone(pool);
+    let filter = filter.clone();
+    log::trace!("MetricDb::read_range {since:?} {until:?} {filter:#?}");
+    ntex::rt::spawn_blocking(move || {
+      let mut conn = utils::store::get_pool_conn(&pool)?;
+      let mut query = Self::gen_filter_query(&filter);
+      if let Some(since) = since {
+        query = query.filter(metrics::created_at.ge(since));
+      }
+      if let Some(until) = until {
+        query = query.filter(metrics::created_at.lt(until));
+      }
+      gen_multiple!(query, metrics::dsl::created_at, filter);
+      let items = query
+        .get_results::<MetricDb>(&mut conn)
+        .map_err(Self::map_err)?;
+      Ok(items)
+    })
+    .await?
+  }
+
+  /// Insert the summaries of metrics and delete the metrics in a transaction,
+  /// on failure the metrics are kept and summarized again on the next run
+  pub async fn replace(
+    summaries: Vec<MetricDb>,
+    keys: Vec<uuid::Uuid>,
+    pool: &Pool,
+  ) -> IoResult<()> {
+    let pool = Arc::clone(pool);
+    ntex::rt::spawn_blocking(move || {
+      let mut conn = utils::store::get_pool_conn(&pool)?;
+      conn
+        .transaction(|conn| {
+          diesel::insert_into(metrics::table)
+            .values(&summaries)
+            .execute(conn)?;
+          diesel::delete(metrics::table.filter(metrics::key.eq_any(keys)))
+            .execute(conn)?;
+          Ok::<_, diesel::result::Error>(())
+        })
+        .map_err(Self::map_err)?;
+      Ok(())
+    })
+    .await?
+  }
 }
@@ -1,6 +1,7 @@
 mod node;
 mod namespace;
 mod secret;
+mod secret_kind;
 mod process;
 mod spec;
 mod job;
@@ -12,5 +13,6 @@ mod vm;
 mod vm_image;
 mod event;
 mod object_process_status;
+mod webhook;
 
 pub mod generic;
@@ -2,12 +2,12 @@ use diesel::prelude::*;
 
 use nanocl_error::io::IoResult;
 
-use nanocl_stubs::generic::GenericFilter;
+use nanocl_stubs::generic::{GenericFilter, GenericClause};
 
 use crate::{
   gen_multiple, gen_where4string,
-  models::{NodeDb, Pool, SystemState},
-  schema::nodes,
+  models::{NodeDb, NodeGroupLinkDb, Pool, SystemState},
+  schema::{nodes, node_group_links},
 };
 
 use super::generic::*;
@@ -45,7 +45,73 @@ impl RepositoryReadBy for NodeDb {
   }
 }
 
+impl RepositoryBase for NodeGroupLinkDb {}
+
+impl RepositoryReadBy for NodeGroupLinkDb {
+  type Output = NodeGroupLinkDb;
+
+  fn get_pk() -> &'static str {
+    "rowid"
+  }
+
+  fn gen_read_query(
+    filter: &GenericFilter,
+    is_multiple: bool,
+  ) -> impl diesel::query_dsl::methods::LoadQuery<
+    'static,
+    diesel::pg::PgConnection,
+    Self::Output,
+  > {
+    let r#where = filter.r#where.clone().unwrap_or_default();
+    let mut query = node_group_links::table.into_boxed();
+    if let Some(node_name) = r#where.get("node_name") {
+      gen_where4string!(query, node_group_links::node_name, node_name);
+    }
+    if let Some(group_name) = r#where.get("node_group_name") {
+      gen_where4string!(query, node_group_links::node_group_name, group_name);
+    }
+    if is_multiple {
+      gen_multiple!(query, node_group_links::rowid, filter);
+    }
+    query
+  }
+}
+
+impl NodeGroupLinkDb {
+  /// Read the links of the given node groups
+  pub async fn read_by_groups(
+    groups: &[String],
+    pool: &Pool,
+  ) -> IoResult<Vec<NodeGroupLinkDb>> {
+    let filter = GenericFilter::new()
+      .r#where("node_group_name", GenericClause::In(groups.to_vec()));
+    NodeGroupLinkDb::read_by(&filter, pool).await
+  }
+}
+
+/// Number of nodes read per page when listing the whole cluster
+const NODE_PAGE_SIZE: usize = 100;
+
 impl NodeDb {
+  /// Read the names of every node of the cluster sorted by name,
+  /// the nodes are read page by page since the reads are capped
+  pub async fn read_names(pool: &Pool) -> IoResult<Vec<String>> {
+    let mut names = Vec::new();
+    loop {
+      let filter = GenericFilter::new()
+        .limit(NODE_PAGE_SIZE)
+        .offset(names.len());
+      let nodes = NodeDb::read_by(&filter, pool).await?;
+      let len = nodes.len();
+      names.extend(nodes.into_iter().map(|node| node.name));
+      if len < NODE_PAGE_SIZE {
+        break;
+      }
+    }
+    names.sort();
+    Ok(names)
+  }
+
   pub async fn create_if_not_exists(
     node: &NodeDb,
     pool: &Pool,
@@ -8,7 +8,7 @@ use nanocl_error::{
 
 use nanocl_stubs::{
   generic::GenericFilter,
-  resource::{Resource, ResourcePartial},
+  resource::{Resource, ResourcePartial, ResourceValidation},
   resource_kind::ResourceKind,
 };
 
@@ -175,9 +175,10 @@ impl ResourceDb {
     let mut resource = resource.clone();
     let (kind, version) = ResourceDb::parse_kind(&resource.kind, pool).await?;
     log::trace!("hook_create_resource kind: {kind} {version}");
-    let kind: ResourceKind = SpecDb::get_version(&kind, &version, pool)
-      .await?
-      .try_into()?;
+    let kind: ResourceKind =
+      SpecDb::get_version("ResourceKind", &kind, &version, pool)
+        .await?
+        .try_into()?;
     if let Some(schema) = &kind.data.schema {
       let schema: JSONSchema = JSONSchema::options()
         .with_draft(Draft::Draft7)
@@ -203,14 +204,53 @@ impl ResourceDb {
     Ok(resource)
   }
 
+  /// Validate a resource without creating it.
+  /// The schema errors or the validation of the controller are returned,
+  /// the errors are empty when the resource is valid.
+  pub async fn hook_validate(
+    resource: &ResourcePartial,
+    pool: &Pool,
+  ) -> HttpResult<ResourceValidation> {
+    let (kind, version) = ResourceDb::parse_kind(&resource.kind, pool).await?;
+    log::trace!("hook_validate_resource kind: {kind} {version}");
+    let kind: ResourceKind =
+      SpecDb::get_version("ResourceKind", &kind, &version, pool)
+        .await?
+        .try_into()?;
+    if let Some(schema) = &kind.data.schema {
+      let schema: JSONSchema = JSONSchema::options()
+        .with_draft(Draft::Draft7)
+        .compile(schema)
+        .map_err(|err| {
+          HttpError::bad_request(format!("Invalid schema {}", err))
+        })?;
+      let res = schema.validate(&resource.data);
+      if let Err(err) = res {
+        return Ok(ResourceValidation {
+          errors: err.map(|error| error.to_string()).collect(),
+          ..Default::default()
+        });
+      }
+    }
+    if let Some(url) = &kind.data.url {
+      let ctrl_client = utils::ctrl_client::CtrlClient::new(&kind.name, url);
+      let validation = ctrl_client
+        .validate_rule(&version, &resource.name, &resource.data)
+        .await?;
+      return Ok(validation);
+    }
+    Ok(ResourceValidation::default())
+  }
+
   /// This hook is called when a resource is deleted.
   /// It call a custom controller at a specific url.
   /// If the resource is a Kind Kind, it will delete the resource Kind with an associated version.
   pub async fn hook_delete(resource: &Resource, pool: &Pool) -> HttpResult<()> {
     let (kind, version) = ResourceDb::parse_kind(&resource.kind, pool).await?;
-    let kind: ResourceKind = SpecDb::get_version(&kind, &version, pool)
-      .await?
-      .try_into()?;
+    let kind: ResourceKind =
+      SpecDb::get_version("ResourceKind", &kind, &version, pool)
+        .await?
+        .try_into()?;
     log::debug!("hook_delete_resource kind: {kind:?}");
     if let Some(url) = &kind.data.url {
       let ctrl_client = utils::ctrl_client::CtrlClient::new(&kind.name, url);
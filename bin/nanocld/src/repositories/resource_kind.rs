@@ -90,7 +90,7 @@ impl ResourceKindDb {
     item: &ResourceKindPartial,
     pool: &Pool,
   ) -> HttpResult<ResourceKind> {
-    if SpecDb::get_version(&item.name, &item.version, pool)
+    if SpecDb::get_version("ResourceKind", &item.name, &item.version, pool)
       .await
       .is_ok()
     {
@@ -0,0 +1,52 @@
+use diesel::prelude::*;
+
+use nanocl_error::io::IoResult;
+
+use nanocl_stubs::{generic::GenericFilter, secret_kind::SecretKind};
+
+use crate::{
+  gen_multiple, gen_where4string, models::SecretKindDb, schema::secret_kinds,
+};
+
+use super::generic::*;
+
+impl RepositoryBase for SecretKindDb {}
+
+impl RepositoryCreate for SecretKindDb {}
+
+impl RepositoryDelByPk for SecretKindDb {}
+
+impl RepositoryReadBy for SecretKindDb {
+  type Output = SecretKindDb;
+
+  fn get_pk() -> &'static str {
+    "name"
+  }
+
+  fn gen_read_query(
+    filter: &GenericFilter,
+    is_multiple: bool,
+  ) -> impl diesel::query_dsl::methods::LoadQuery<
+    'static,
+    diesel::pg::PgConnection,
+    Self::Output,
+  > {
+    let r#where = filter.r#where.clone().unwrap_or_default();
+    let mut query = secret_kinds::table.into_boxed();
+    if let Some(name) = r#where.get("name") {
+      gen_where4string!(query, secret_kinds::name, name);
+    }
+    if is_multiple {
+      gen_multiple!(query, secret_kinds::created_at, filter);
+    }
+    query
+  }
+}
+
+impl RepositoryReadByTransform for SecretKindDb {
+  type NewOutput = SecretKind;
+
+  fn transform(input: Self::Output) -> IoResult<Self::NewOutput> {
+    Ok(input.into())
+  }
+}
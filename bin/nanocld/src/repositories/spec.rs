@@ -6,11 +6,12 @@ use nanocl_stubs::{
   generic::{GenericFilter, GenericClause},
   cargo_spec::{CargoSpecPartial, CargoSpec},
   vm_spec::{VmSpec, VmSpecPartial},
+  secret::SecretHistory,
 };
 
 use crate::{
-  gen_multiple, gen_where4uuid, gen_where4string,
-  models::{Pool, SpecDb},
+  utils, vars, gen_multiple, gen_where4uuid, gen_where4string,
+  models::{Pool, SecretDb, SpecDb, SpecUpdateDb},
   schema::specs,
 };
 
@@ -20,6 +21,10 @@ impl RepositoryBase for SpecDb {}
 
 impl RepositoryCreate for SpecDb {}
 
+impl RepositoryUpdate for SpecDb {
+  type UpdateItem = SpecUpdateDb;
+}
+
 impl RepositoryDelBy for SpecDb {
   fn gen_del_query(
     filter: &GenericFilter,
@@ -33,6 +38,9 @@ impl RepositoryDelBy for SpecDb {
   {
     let r#where = filter.r#where.to_owned().unwrap_or_default();
     let mut query = diesel::delete(specs::table).into_boxed();
+    if let Some(value) = r#where.get("kind_name") {
+      gen_where4string!(query, specs::kind_name, value);
+    }
     if let Some(value) = r#where.get("kind_key") {
       gen_where4string!(query, specs::kind_key, value);
     }
@@ -63,6 +71,9 @@ impl RepositoryReadBy for SpecDb {
     if let Some(key) = r#where.get("key") {
       gen_where4uuid!(query, specs::key, key);
     }
+    if let Some(kind_name) = r#where.get("kind_name") {
+      gen_where4string!(query, specs::kind_name, kind_name);
+    }
     if let Some(kind_key) = r#where.get("kind_key") {
       gen_where4string!(query, specs::kind_key, kind_key);
     }
@@ -77,32 +88,55 @@ impl RepositoryReadBy for SpecDb {
 }
 
 impl SpecDb {
-  pub async fn del_by_kind_key(key: &str, pool: &Pool) -> IoResult<()> {
-    let filter = GenericFilter::new()
-      .r#where("kind_key", GenericClause::Eq(key.to_owned()));
+  /// Filter the histories of an object.
+  /// The kind name is required because the keys of different kinds can collide.
+  fn kind_filter(kind_name: &str, key: &str) -> GenericFilter {
+    GenericFilter::new()
+      .r#where("kind_name", GenericClause::Eq(kind_name.to_owned()))
+      .r#where("kind_key", GenericClause::Eq(key.to_owned()))
+  }
+
+  pub async fn del_by_kind_key(
+    kind_name: &str,
+    key: &str,
+    pool: &Pool,
+  ) -> IoResult<()> {
+    let filter = Self::kind_filter(kind_name, key);
     SpecDb::del_by(&filter, pool).await
   }
 
   pub async fn get_version(
+    kind_name: &str,
     name: &str,
     version: &str,
     pool: &Pool,
   ) -> IoResult<SpecDb> {
-    let filter = GenericFilter::new()
-      .r#where("kind_key", GenericClause::Eq(name.to_owned()))
+    let filter = Self::kind_filter(kind_name, name)
       .r#where("version", GenericClause::Eq(version.to_owned()));
     SpecDb::read_one_by(&filter, pool).await
   }
 
   pub async fn read_by_kind_key(
+    kind_name: &str,
     key: &str,
     pool: &Pool,
   ) -> IoResult<Vec<SpecDb>> {
-    let filter = GenericFilter::new()
-      .r#where("kind_key", GenericClause::Eq(key.to_owned()));
+    let filter = Self::kind_filter(kind_name, key);
     SpecDb::read_by(&filter, pool).await
   }
 
+  /// Read a history by its id and ensure it belongs to the given object
+  pub async fn read_by_kind_pk(
+    kind_name: &str,
+    key: &str,
+    pk: &uuid::Uuid,
+    pool: &Pool,
+  ) -> IoResult<SpecDb> {
+    let filter = Self::kind_filter(kind_name, key)
+      .r#where("key", GenericClause::Eq(pk.to_string()));
+    SpecDb::read_one_by(&filter, pool).await
+  }
+
   pub fn try_from_cargo_partial(
     key: &str,
     version: &str,
@@ -135,6 +169,47 @@ impl SpecDb {
     })
   }
 
+  /// Record the current data of a secret, the data is kept encrypted
+  pub fn from_secret(secret: &SecretDb) -> Self {
+    Self {
+      key: uuid::Uuid::new_v4(),
+      created_at: chrono::Utc::now().naive_utc(),
+      kind_name: "Secret".to_owned(),
+      kind_key: secret.key.clone(),
+      version: vars::VERSION.to_owned(),
+      data: secret.data.clone(),
+      metadata: secret.metadata.clone(),
+    }
+  }
+
+  /// Filter of the histories of a secret
+  fn secret_filter(key: &str) -> GenericFilter {
+    Self::kind_filter("Secret", key)
+  }
+
+  /// List the histories of a secret ordered by creation date
+  pub async fn read_secret_histories(
+    key: &str,
+    pool: &Pool,
+  ) -> IoResult<Vec<SpecDb>> {
+    SpecDb::read_by(&Self::secret_filter(key), pool).await
+  }
+
+  /// Delete the histories of a secret
+  pub async fn del_secret_histories(key: &str, pool: &Pool) -> IoResult<()> {
+    SpecDb::del_by(&Self::secret_filter(key), pool).await
+  }
+
+  pub fn try_to_secret_history(&self) -> IoResult<SecretHistory> {
+    Ok(SecretHistory {
+      key: self.key,
+      secret_key: self.kind_key.clone(),
+      created_at: self.created_at,
+      metadata: self.metadata.clone(),
+      data: utils::secret::decrypt(&self.data)?,
+    })
+  }
+
   pub fn try_to_cargo_spec(&self) -> IoResult<CargoSpec> {
     let p = serde_json::from_value::<CargoSpecPartial>(self.data.clone())?;
     let spec = CargoSpec {
@@ -146,8 +221,11 @@ impl SpecDb {
       metadata: self.metadata.clone(),
       init_container: p.init_container,
       secrets: p.secrets,
+      secret_mounts: p.secret_mounts,
       container: p.container,
       replication: p.replication,
+      autoscale: p.autoscale,
+      update_strategy: p.update_strategy,
       image_pull_secret: p.image_pull_secret,
       image_pull_policy: p.image_pull_policy,
     };
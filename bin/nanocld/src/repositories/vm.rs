@@ -120,13 +120,17 @@ impl VmDb {
     VmDb::transform_read_by(&filter, pool).await
   }
 
-  /// List VMs by namespace
+  /// List VMs by namespace matching the filter
   pub async fn list_by_namespace(
     nsp: &str,
+    filter: &GenericFilter,
     pool: &Pool,
   ) -> HttpResult<Vec<VmSummary>> {
     let namespace = NamespaceDb::read_by_pk(nsp, pool).await?;
-    let vmes = VmDb::read_by_namespace(&namespace.name, pool).await?;
+    let filter = filter
+      .clone()
+      .r#where("namespace_name", GenericClause::Eq(namespace.name));
+    let vmes = VmDb::transform_read_by(&filter, pool).await?;
     let mut vm_summaries = Vec::new();
     for vm in vmes {
       let spec = SpecDb::read_by_pk(&vm.spec.key, pool)
@@ -149,7 +153,7 @@ impl VmDb {
 
   pub async fn clear_by_pk(pk: &str, pool: &Pool) -> IoResult<()> {
     VmDb::del_by_pk(pk, pool).await?;
-    SpecDb::del_by_kind_key(pk, pool).await?;
+    SpecDb::del_by_kind_key("Vm", pk, pool).await?;
     ObjPsStatusDb::del_by_pk(pk, pool).await?;
     Ok(())
   }
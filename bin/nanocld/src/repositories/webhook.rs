@@ -0,0 +1,121 @@
+use diesel::prelude::*;
+
+use nanocl_error::io::IoResult;
+
+use nanocl_stubs::{
+  generic::GenericFilter,
+  webhook::{Webhook, WebhookDelivery},
+};
+
+use crate::{
+  gen_multiple, gen_where4string, gen_where4uuid,
+  models::{
+    WebhookDb, WebhookUpdateDb, WebhookDeliveryDb, WebhookDeliveryUpdateDb,
+  },
+  schema::{webhooks, webhook_deliveries},
+};
+
+use super::generic::*;
+
+impl RepositoryBase for WebhookDb {}
+
+impl RepositoryCreate for WebhookDb {}
+
+impl RepositoryDelByPk for WebhookDb {}
+
+impl RepositoryUpdate for WebhookDb {
+  type UpdateItem = WebhookUpdateDb;
+}
+
+impl RepositoryReadBy for WebhookDb {
+  type Output = WebhookDb;
+
+  fn get_pk() -> &'static str {
+    "name"
+  }
+
+  fn gen_read_query(
+    filter: &GenericFilter,
+    is_multiple: bool,
+  ) -> impl diesel::query_dsl::methods::LoadQuery<
+    'static,
+    diesel::pg::PgConnection,
+    Self::Output,
+  > {
+    let r#where = filter.r#where.clone().unwrap_or_default();
+    let mut query = webhooks::table.into_boxed();
+    if let Some(name) = r#where.get("name") {
+      gen_where4string!(query, webhooks::name, name);
+    }
+    if let Some(url) = r#where.get("url") {
+      gen_where4string!(query, webhooks::url, url);
+    }
+    if is_multiple {
+      gen_multiple!(query, webhooks::created_at, filter);
+    }
+    query
+  }
+}
+
+impl RepositoryReadByTransform for WebhookDb {
+  type NewOutput = Webhook;
+
+  fn transform(input: Self::Output) -> IoResult<Self::NewOutput> {
+    input.try_into()
+  }
+}
+
+impl RepositoryBase for WebhookDeliveryDb {}
+
+impl RepositoryCreate for WebhookDeliveryDb {}
+
+impl RepositoryUpdate for WebhookDeliveryDb {
+  type UpdateItem = WebhookDeliveryUpdateDb;
+}
+
+impl RepositoryReadBy for WebhookDeliveryDb {
+  type Output = WebhookDeliveryDb;
+
+  fn get_pk() -> &'static str {
+    "key"
+  }
+
+  fn gen_read_query(
+    filter: &GenericFilter,
+    is_multiple: bool,
+  ) -> impl diesel::query_dsl::methods::LoadQuery<
+    'static,
+    diesel::pg::PgConnection,
+    Self::Output,
+  > {
+    let r#where = filter.r#where.clone().unwrap_or_default();
+    let mut query = webhook_deliveries::table.into_boxed();
+    if let Some(key) = r#where.get("key") {
+      gen_where4uuid!(query, webhook_deliveries::key, key);
+    }
+    if let Some(webhook_name) = r#where.get("webhook_name") {
+      gen_where4string!(query, webhook_deliveries::webhook_name, webhook_name);
+    }
+    if let Some(event_key) = r#where.get("event_key") {
+      gen_where4uuid!(query, webhook_deliveries::event_key, event_key);
+    }
+    if let Some(node_name) = r#where.get("node_name") {
+      gen_where4string!(query, webhook_deliveries::node_name, node_name);
+    }
+    if let Some(status) = r#where.get("status") {
+      gen_where4string!(query, webhook_deliveries::status, status);
+    }
+    if is_multiple {
+      gen_multiple!(query, webhook_deliveries::created_at, filter);
+    }
+    query
+  }
+}
+
+impl RepositoryReadByTransform for WebhookDeliveryDb {
+  type NewOutput = WebhookDelivery;
+
+  fn transform(input: Self::Output) -> IoResult<Self::NewOutput> {
+    input.try_into()
+  }
+}
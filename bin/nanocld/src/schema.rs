@@ -28,6 +28,21 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    job_runs (key) {
+        key -> Uuid,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        job_name -> Varchar,
+        scheduled_at -> Nullable<Timestamptz>,
+        started_at -> Nullable<Timestamptz>,
+        ended_at -> Nullable<Timestamptz>,
+        status -> Varchar,
+        reason -> Nullable<Varchar>,
+        attempt -> Int8,
+    }
+}
+
 diesel::table! {
     jobs (key) {
         key -> Varchar,
@@ -122,6 +137,15 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    secret_kinds (name) {
+        name -> Varchar,
+        created_at -> Timestamptz,
+        schema -> Jsonb,
+        metadata -> Nullable<Jsonb>,
+    }
+}
+
 diesel::table! {
     secrets (key) {
         key -> Varchar,
@@ -170,9 +194,39 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    webhook_deliveries (key) {
+        key -> Uuid,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        webhook_name -> Varchar,
+        event_key -> Uuid,
+        node_name -> Varchar,
+        status -> Varchar,
+        attempts -> Int8,
+        status_code -> Nullable<Int8>,
+        error -> Nullable<Varchar>,
+        next_attempt_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    webhooks (name) {
+        name -> Varchar,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        url -> Varchar,
+        conditions -> Nullable<Jsonb>,
+        secret -> Nullable<Jsonb>,
+        retry -> Jsonb,
+        metadata -> Nullable<Jsonb>,
+    }
+}
+
 diesel::joinable!(cargoes -> namespaces (namespace_name));
 diesel::joinable!(cargoes -> object_process_statuses (status_key));
 diesel::joinable!(cargoes -> specs (spec_key));
+diesel::joinable!(job_runs -> jobs (job_name));
 diesel::joinable!(jobs -> object_process_statuses (status_key));
 diesel::joinable!(node_group_links -> node_groups (node_group_name));
 diesel::joinable!(node_group_links -> nodes (node_name));
@@ -181,10 +235,12 @@ diesel::joinable!(resources -> specs (spec_key));
 diesel::joinable!(vms -> namespaces (namespace_name));
 diesel::joinable!(vms -> object_process_statuses (status_key));
 diesel::joinable!(vms -> specs (spec_key));
+diesel::joinable!(webhook_deliveries -> webhooks (webhook_name));
 
 diesel::allow_tables_to_appear_in_same_query!(
   cargoes,
   events,
+  job_runs,
   jobs,
   metrics,
   namespaces,
@@ -195,8 +251,11 @@ diesel::allow_tables_to_appear_in_same_query!(
   processes,
   resource_kinds,
   resources,
+  secret_kinds,
   secrets,
   specs,
   vm_images,
   vms,
+  webhook_deliveries,
+  webhooks,
 );
@@ -11,7 +11,6 @@ use nanocl_stubs::{
 use crate::{
   utils,
   objects::generic::*,
-  repositories::generic::*,
   models::{
     SystemState, SpecDb, CargoObjCreateIn, CargoDb, CargoObjPutIn,
     CargoObjPatchIn,
@@ -209,7 +208,7 @@ pub async fn list_cargo_history(
 ) -> HttpResult<web::HttpResponse> {
   let namespace = utils::key::resolve_nsp(&qs.namespace);
   let key = utils::key::gen_key(&namespace, &path.1);
-  let histories = SpecDb::read_by_kind_key(&key, &state.inner.pool)
+  let histories = SpecDb::read_by_kind_key("Cargo", &key, &state.inner.pool)
     .await?
     .into_iter()
     .map(|e| e.try_to_cargo_spec())
@@ -240,9 +239,10 @@ pub async fn revert_cargo(
 ) -> HttpResult<web::HttpResponse> {
   let namespace = utils::key::resolve_nsp(&qs.namespace);
   let cargo_key = utils::key::gen_key(&namespace, &path.1);
-  let spec = SpecDb::read_by_pk(&path.2, &state.inner.pool)
-    .await?
-    .try_to_cargo_spec()?;
+  let spec =
+    SpecDb::read_by_kind_pk("Cargo", &cargo_key, &path.2, &state.inner.pool)
+      .await?
+      .try_to_cargo_spec()?;
   let obj = &CargoObjPutIn {
     spec: spec.into(),
     version: path.0.clone(),
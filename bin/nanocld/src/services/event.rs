@@ -3,7 +3,7 @@ use ntex::web;
 use nanocl_error::http::{HttpResult, HttpError};
 use nanocl_stubs::{
   generic::{GenericFilter, GenericListQuery},
-  system::EventCondition,
+  system::{EventCondition, EventWatchQuery},
 };
 
 use crate::{
@@ -35,11 +35,15 @@ pub async fn list_event(
   Ok(web::HttpResponse::Ok().json(&events))
 }
 
-/// Watch on new events using Server-Sent Events / EventSource
+/// Watch on new events using Server-Sent Events / EventSource.
+/// With a `since` cursor the persisted events after it are replayed first.
 #[cfg_attr(feature = "dev", utoipa::path(
   post,
   tag = "Events",
   path = "/events/watch",
+  params(
+    ("since" = Option<String>, Query, description = "Key of the last event received or a RFC 3339 date", example = "2024-01-01T00:00:00Z"),
+  ),
   request_body = Option<Vec<EventCondition>>,
   responses(
     (status = 200, description = "Event stream", body = String),
@@ -48,11 +52,19 @@ pub async fn list_event(
 #[web::post("/events/watch")]
 pub async fn watch_event(
   state: web::types::State<SystemState>,
+  qs: web::types::Query<EventWatchQuery>,
   condition: Option<web::types::Json<Vec<EventCondition>>>,
 ) -> HttpResult<web::HttpResponse> {
-  let stream = state
-    .subscribe_raw(condition.map(|c| c.into_inner()))
-    .await?;
+  let condition = condition.map(|c| c.into_inner());
+  if let Some(since) = &qs.since {
+    let stream = state.subscribe_raw_since(condition, since).await?;
+    return Ok(
+      web::HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .streaming(stream),
+    );
+  }
+  let stream = state.subscribe_raw(condition).await?;
   Ok(
     web::HttpResponse::Ok()
       .content_type("text/event-stream")
@@ -72,10 +84,13 @@ mod tests {
   use ntex::{rt, http};
   use nanocl_stubs::{
     cargo_spec::CargoSpecPartial,
-    system::{EventActorKind, EventCondition, EventKind, NativeEventAction},
+    system::{
+      Event, EventActorKind, EventCondition, EventKind, EventPartial,
+      EventWatchQuery, NativeEventAction,
+    },
   };
 
-  use crate::utils::tests::*;
+  use crate::{repositories::generic::*, models::EventDb, utils::tests::*};
 
   #[ntex::test]
   async fn basic() {
@@ -140,4 +155,63 @@ mod tests {
       .await;
     assert!(wait_task.await.is_ok())
   }
+
+  #[ntex::test]
+  async fn watch_events_since() {
+    let system = gen_default_test_system().await;
+    let client = system.client;
+    let gen_event = || EventPartial {
+      reporting_controller: "nanocl.io/test".to_owned(),
+      reporting_node: "test".to_owned(),
+      kind: EventKind::Normal,
+      action: "test".to_owned(),
+      reason: "test".to_owned(),
+      note: None,
+      actor: None,
+      related: None,
+      metadata: None,
+    };
+    let first = EventDb::create_try_from(gen_event(), &system.state.inner.pool)
+      .await
+      .unwrap();
+    let second =
+      EventDb::create_try_from(gen_event(), &system.state.inner.pool)
+        .await
+        .unwrap();
+    let res = client
+      .send_post(
+        "/events/watch",
+        None::<String>,
+        Some(EventWatchQuery::since_key(&first.key)),
+      )
+      .await;
+    test_status_code!(res.status(), http::StatusCode::OK, "watch events since");
+    let mut stream = res.into_stream();
+    let mut found = false;
+    while let Some(Ok(chunk)) = stream.next().await {
+      for line in chunk.split(|b| *b == b'\n').filter(|l| !l.is_empty()) {
+        let event = serde_json::from_slice::<Event>(line).unwrap();
+        assert_ne!(event.key, first.key, "cursor event replayed");
+        found |= event.key == second.key;
+      }
+      if found {
+        break;
+      }
+    }
+    assert!(found, "event after the cursor not replayed");
+    let res = client
+      .send_post(
+        "/events/watch",
+        None::<String>,
+        Some(EventWatchQuery {
+          since: Some("yesterday".to_owned()),
+        }),
+      )
+      .await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::BAD_REQUEST,
+      "watch events invalid since"
+    );
+  }
 }
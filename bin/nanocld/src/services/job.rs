@@ -1,11 +1,16 @@
 use ntex::web;
 
-use nanocl_error::http::HttpResult;
-use nanocl_stubs::job::JobPartial;
+use nanocl_error::http::{HttpError, HttpResult};
+use nanocl_stubs::{
+  generic::{GenericClause, GenericFilter, GenericListQuery},
+  job::JobPartial,
+};
 
 use crate::{
+  utils,
   objects::generic::*,
-  models::{SystemState, JobDb},
+  repositories::generic::*,
+  models::{SystemState, JobDb, JobRunDb},
 };
 
 /// List jobs
@@ -13,6 +18,9 @@ use crate::{
   get,
   tag = "Jobs",
   path = "/jobs",
+  params(
+    ("filter" = Option<String>, Query, description = "Generic filter", example = "{ \"where\": { \"key\": { \"eq\": \"test\" } } }"),
+  ),
   responses(
     (status = 200, description = "List of jobs", body = [JobSummary]),
   ),
@@ -21,8 +29,11 @@ use crate::{
 pub async fn list_job(
   state: web::types::State<SystemState>,
   _version: web::types::Path<String>,
+  qs: web::types::Query<GenericListQuery>,
 ) -> HttpResult<web::HttpResponse> {
-  let jobs = JobDb::list(&state).await?;
+  let filter = GenericFilter::try_from(qs.into_inner())
+    .map_err(|err| HttpError::bad_request(err.to_string()))?;
+  let jobs = JobDb::list(&filter, &state).await?;
   Ok(web::HttpResponse::Ok().json(&jobs))
 }
 
@@ -89,17 +100,50 @@ pub async fn inspect_job(
   Ok(web::HttpResponse::Ok().json(&job))
 }
 
+/// List the runs of a job, the newest first
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  tag = "Jobs",
+  path = "/jobs/{name}/runs",
+  params(
+    ("name" = String, Path, description = "Name of the job"),
+    ("filter" = Option<String>, Query, description = "Generic filter", example = "{ \"where\": { \"status\": { \"eq\": \"missed\" } } }"),
+  ),
+  responses(
+    (status = 200, description = "List of runs", body = [JobRun]),
+    (status = 404, description = "Job does not exist"),
+  ),
+))]
+#[web::get("/jobs/{name}/runs")]
+pub async fn list_job_run(
+  state: web::types::State<SystemState>,
+  path: web::types::Path<(String, String)>,
+  query: web::types::Query<GenericListQuery>,
+) -> HttpResult<web::HttpResponse> {
+  JobDb::read_by_pk(&path.1, &state.inner.pool).await?;
+  let filter = GenericFilter::try_from(query.into_inner())
+    .map_err(|err| HttpError::bad_request(err.to_string()))?
+    .r#where("job_name", GenericClause::Eq(path.1.clone()));
+  utils::store::validate_dates(&filter, &["scheduled_at", "ended_at"])?;
+  let runs = JobRunDb::transform_read_by(&filter, &state.inner.pool).await?;
+  Ok(web::HttpResponse::Ok().json(&runs))
+}
+
 pub fn ntex_config(config: &mut web::ServiceConfig) {
   config.service(list_job);
   config.service(create_job);
   config.service(delete_job);
   config.service(inspect_job);
+  config.service(list_job_run);
 }
 
 #[cfg(test)]
 mod tests {
   use ntex::http;
-  use nanocl_stubs::job::{Job, JobSummary};
+  use nanocl_stubs::{
+    job::{Job, JobInspect, JobRun, JobRunStatus, JobSummary},
+    system::ObjPsStatusKind,
+  };
 
   use crate::utils::tests::*;
 
@@ -133,6 +177,141 @@ mod tests {
     );
   }
 
+  #[ntex::test]
+  async fn invalid_steps() {
+    let system = gen_default_test_system().await;
+    let client = system.client;
+    let res = client
+      .send_post(
+        ENDPOINT,
+        Some(serde_json::json!({
+          "Name": "test-job-cycle",
+          "Steps": [
+            { "Name": "a", "DependsOn": ["b"], "Container": { "Image": "alpine:latest" } },
+            { "Name": "b", "DependsOn": ["a"], "Container": { "Image": "alpine:latest" } },
+          ],
+        })),
+        None::<String>,
+      )
+      .await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::BAD_REQUEST,
+      "create job with a cycle"
+    );
+    let res = client
+      .send_post(
+        ENDPOINT,
+        Some(serde_json::json!({
+          "Name": "test-job-unknown",
+          "Steps": [
+            { "Name": "a", "DependsOn": ["c"], "Container": { "Image": "alpine:latest" } },
+          ],
+        })),
+        None::<String>,
+      )
+      .await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::BAD_REQUEST,
+      "create job with an unknown step"
+    );
+  }
+
+  #[ntex::test]
+  async fn invalid_schedule() {
+    let system = gen_default_test_system().await;
+    let client = system.client;
+    for (schedule, time_zone) in
+      [("every monday", "UTC"), ("0 9 * * *", "Mars/Olympus")]
+    {
+      let res = client
+        .send_post(
+          ENDPOINT,
+          Some(serde_json::json!({
+            "Name": "test-job-schedule",
+            "Schedule": schedule,
+            "TimeZone": time_zone,
+            "Containers": [{ "Image": "alpine:latest" }],
+          })),
+          None::<String>,
+        )
+        .await;
+      test_status_code!(
+        res.status(),
+        http::StatusCode::BAD_REQUEST,
+        format!("create job with schedule {schedule} in {time_zone}")
+      );
+    }
+    let res = client
+      .send_get(
+        &format!("{ENDPOINT}/test-job-schedule/runs"),
+        None::<String>,
+      )
+      .await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::NOT_FOUND,
+      "list runs of a job not found"
+    );
+  }
+
+  #[ntex::test]
+  async fn retry() {
+    const NAME: &str = "test-job-retry";
+    let system = gen_default_test_system().await;
+    let client = system.client;
+    let job_endpoint = format!("{ENDPOINT}/{NAME}");
+    let _ = client.send_delete(&job_endpoint, None::<String>).await;
+    let res = client
+      .send_post(
+        ENDPOINT,
+        Some(serde_json::json!({
+          "Name": NAME,
+          "BackoffLimit": 1,
+          "BackoffSeconds": 0,
+          "Containers": [{
+            "Image": "alpine:latest",
+            "Cmd": ["sh", "-c", "exit 1"],
+          }],
+        })),
+        None::<String>,
+      )
+      .await;
+    test_status_code!(res.status(), http::StatusCode::CREATED, "create job");
+    client
+      .send_post(
+        &format!("/processes/job/{NAME}/start"),
+        None::<String>,
+        None::<String>,
+      )
+      .await;
+    let mut job = None;
+    for _ in 0..60 {
+      let mut res = client
+        .send_get(&format!("{job_endpoint}/inspect"), None::<String>)
+        .await;
+      let inspect = res.json::<JobInspect>().await.unwrap();
+      if inspect.status.actual == ObjPsStatusKind::Fail {
+        job = Some(inspect);
+        break;
+      }
+      ntex::time::sleep(std::time::Duration::from_secs(1)).await;
+    }
+    let job = job.expect("job not failed");
+    let attempts = job
+      .runs
+      .iter()
+      .map(|run| (run.attempt, run.status.clone()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      attempts,
+      vec![(2, JobRunStatus::Failed), (1, JobRunStatus::Failed)]
+    );
+    let _ = client.send_delete(&job_endpoint, None::<String>).await;
+    system.state.wait_event_loop().await;
+  }
+
   #[ntex::test]
   async fn basic() {
     let system = gen_default_test_system().await;
@@ -176,6 +355,15 @@ mod tests {
       http::StatusCode::OK,
       format!("inspect job {}", &job.name)
     );
+    let mut res = client
+      .send_get(&format!("{job_endpoint}/runs"), None::<String>)
+      .await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::OK,
+      format!("list runs of job {}", &job.name)
+    );
+    let _ = res.json::<Vec<JobRun>>().await.unwrap();
     let _ = client.send_delete(&job_endpoint, None::<String>).await;
     ntex::time::sleep(std::time::Duration::from_secs(1)).await;
     system.state.wait_event_loop().await;
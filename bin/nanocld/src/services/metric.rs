@@ -3,36 +3,48 @@ use ntex::web;
 use nanocl_error::http::{HttpError, HttpResult};
 
 use nanocl_stubs::{
-  metric::MetricPartial,
+  metric::{MetricPartial, MetricListQuery},
   generic::{GenericFilter, GenericListQuery},
 };
 
 use crate::{
+  utils,
   repositories::generic::*,
   models::{SystemState, MetricDb, MetricNodePartial},
 };
 
-/// Get metrics of all peer nodes
+/// Get metrics of all peer nodes, aggregated when one of `group_by`, `interval` or `aggregate` is set
 #[cfg_attr(feature = "dev", utoipa::path(
   get,
   tag = "Metrics",
   path = "/metrics",
   params(
     ("filter" = Option<String>, Query, description = "Generic filter", example = "{ \"where\": { \"kind\": { \"eq\": \"CPU\" } } }"),
+    ("since" = Option<String>, Query, description = "Only the metrics created at or after this date", example = "2024-01-01T00:00:00Z"),
+    ("until" = Option<String>, Query, description = "Only the metrics created before this date", example = "2024-01-02T00:00:00Z"),
+    ("group_by" = Option<String>, Query, description = "Comma separated fields to group by: kind, node_name or proxy_host", example = "kind,proxy_host"),
+    ("interval" = Option<u64>, Query, description = "Size of the time buckets in seconds", example = 3600),
+    ("aggregate" = Option<String>, Query, description = "Comma separated aggregate functions: count, avg, p95 or sum", example = "count,p95"),
+    ("field" = Option<String>, Query, description = "Field of the data used by avg, p95 and sum", example = "request_time"),
   ),
   responses(
     (status = 200, description = "List of metrics", body = Vec<Metric>),
+    (status = 200, description = "List of aggregated metrics", body = Vec<MetricAggregate>),
   ),
 ))]
 #[web::get("/metrics")]
 pub async fn list_metric(
   state: web::types::State<SystemState>,
-  qs: web::types::Query<GenericListQuery>,
+  qs: web::types::Query<MetricListQuery>,
 ) -> HttpResult<web::HttpResponse> {
-  let filter = GenericFilter::try_from(qs.into_inner()).map_err(|err| {
+  let qs = qs.into_inner();
+  let filter = GenericFilter::try_from(GenericListQuery {
+    filter: qs.filter.clone(),
+  })
+  .map_err(|err| {
     HttpError::bad_request(format!("Invalid query string: {err}"))
   })?;
-  let metrics = MetricDb::read_by(&filter, &state.inner.pool).await?;
+  let metrics = utils::metric::list(&filter, &qs, &state).await?;
   Ok(web::HttpResponse::Ok().json(&metrics))
 }
 
@@ -57,10 +69,39 @@ pub async fn create_metric(
   let new_metric =
     MetricNodePartial::try_new_node(&state.inner.config.hostname, &payload)?;
   let metric = MetricDb::create_from(&new_metric, &state.inner.pool).await?;
+  if let Err(err) = state
+    .inner
+    .proxy_metrics
+    .observe(&metric.kind, &metric.data)
+  {
+    log::warn!("metric::create_metric: {err}");
+  }
   Ok(web::HttpResponse::Created().json(&metric))
 }
 
+/// Get metrics of the current node in the prometheus text format
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  tag = "Metrics",
+  path = "/metrics/prometheus",
+  responses(
+    (status = 200, description = "Metrics in the prometheus text format", content_type = "text/plain", body = String),
+  ),
+))]
+#[web::get("/metrics/prometheus")]
+pub async fn prometheus_metric(
+  state: web::types::State<SystemState>,
+) -> HttpResult<web::HttpResponse> {
+  let exposition = utils::prometheus::gen_exposition(&state).await?;
+  Ok(
+    web::HttpResponse::Ok()
+      .content_type(utils::prometheus::CONTENT_TYPE)
+      .body(exposition),
+  )
+}
+
 pub fn ntex_config(config: &mut web::ServiceConfig) {
+  config.service(prometheus_metric);
   config.service(list_metric);
   config.service(create_metric);
 }
@@ -69,7 +110,7 @@ pub fn ntex_config(config: &mut web::ServiceConfig) {
 mod tests {
   use ntex::http;
   use nanocl_stubs::{
-    metric::MetricPartial,
+    metric::{MetricPartial, MetricListQuery, MetricAggregate},
     generic::{GenericFilter, GenericClause, GenericListQuery},
   };
 
@@ -114,5 +155,39 @@ mod tests {
     let qs = GenericListQuery::try_from(filter).unwrap();
     let res = client.send_get(ENDPOINT, Some(&qs)).await;
     test_status_code!(res.status(), http::StatusCode::OK, "list metric");
+    let qs = MetricListQuery {
+      since: Some(
+        chrono::DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap(),
+      ),
+      group_by: Some("kind,node_name".to_owned()),
+      interval: Some(3600),
+      aggregate: Some("count,p95".to_owned()),
+      ..Default::default()
+    };
+    let res = client.send_get(ENDPOINT, Some(&qs)).await;
+    test_status_code!(res.status(), http::StatusCode::OK, "aggregate metric");
+    let aggregates = TestClient::res_json::<Vec<MetricAggregate>>(res).await;
+    assert!(aggregates
+      .iter()
+      .any(|item| item.kind.as_deref() == Some("test.io/test")));
+    let qs = MetricListQuery {
+      aggregate: Some("median".to_owned()),
+      ..Default::default()
+    };
+    let res = client.send_get(ENDPOINT, Some(&qs)).await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::BAD_REQUEST,
+      "invalid aggregate metric"
+    );
+    let mut res = client
+      .send_get(&format!("{ENDPOINT}/prometheus"), None::<String>)
+      .await;
+    test_status_code!(res.status(), http::StatusCode::OK, "prometheus metric");
+    let body = res.body().await.unwrap();
+    let body = String::from_utf8_lossy(&body);
+    assert!(body.contains("# TYPE nanocl_daemon_tasks gauge"));
+    assert!(body
+      .contains("# TYPE nanocl_proxy_http_request_duration_seconds histogram"));
   }
 }
@@ -15,10 +15,13 @@ mod metric;
 mod vm;
 mod vm_image;
 mod secret;
+mod secret_kind;
 mod job;
 mod process;
 mod resource_kind;
 mod event;
+mod state;
+mod webhook;
 
 pub async fn unhandled() -> HttpResult<web::HttpResponse> {
   Err(HttpError::not_found("Route or method unhandled"))
@@ -60,10 +63,13 @@ pub fn ntex_config(config: &mut web::ServiceConfig) {
       .configure(vm::ntex_config)
       .configure(metric::ntex_config)
       .configure(secret::ntex_config)
+      .configure(secret_kind::ntex_config)
       .configure(process::ntex_config)
       .configure(job::ntex_config)
       .configure(event::ntex_config)
-      .configure(resource_kind::ntex_config),
+      .configure(state::ntex_config)
+      .configure(resource_kind::ntex_config)
+      .configure(webhook::ntex_config),
   );
 }
 
@@ -37,7 +37,11 @@ use bollard_next::service::{
 use nanocl_stubs::node::Node;
 use nanocl_stubs::process::{Process, ProcessKind, ProcessStats};
 use nanocl_stubs::config::DaemonConfig;
-use nanocl_stubs::secret::{Secret, SecretPartial, SecretUpdate};
+use nanocl_stubs::secret::{Secret, SecretPartial, SecretUpdate, SecretHistory};
+use nanocl_stubs::secret_kind::{SecretKind, SecretKindPartial};
+use nanocl_stubs::webhook::{
+  Webhook, WebhookPartial, WebhookRetry, WebhookDelivery, WebhookDeliveryStatus,
+};
 use nanocl_stubs::generic::{
   GenericCount, GenericClause, GenericFilter, ImagePullPolicy,
 };
@@ -45,18 +49,23 @@ use nanocl_stubs::system::{
   BinaryInfo, Event, EventActor, EventActorKind, EventCondition, EventKind,
   HostInfo, NativeEventAction, ObjPsStatus, ObjPsStatusKind, SslConfig,
 };
-use nanocl_stubs::metric::{Metric, MetricPartial};
+use nanocl_stubs::metric::{
+  Metric, MetricPartial, MetricAggregate, MetricAggregateFn, HttpMetricSummary,
+};
 use nanocl_stubs::vm_image::{VmImage, VmImageResizePayload};
 use nanocl_stubs::namespace::{
   Namespace, NamespaceSummary, NamespacePartial, NamespaceInspect,
 };
-use nanocl_stubs::job::{Job, JobPartial, JobInspect, JobSummary};
+use nanocl_stubs::job::{
+  Job, JobPartial, JobInspect, JobSummary, JobStep, JobDependency,
+  JobDependencyKind, JobConcurrencyPolicy, JobRun, JobRunStatus,
+};
 use nanocl_stubs::cargo::{
   Cargo, CargoInspect, CargoSummary, CargoKillOptions, CreateExecOptions,
 };
 use nanocl_stubs::cargo_spec::{
   CargoSpec, CargoSpecPartial, CargoSpecUpdate, ReplicationMode,
-  ReplicationStatic,
+  ReplicationStatic, CargoAutoscale, UpdateStrategy, UpdateStrategyKind,
 };
 use nanocl_stubs::vm::{Vm, VmInspect, VmSummary};
 use nanocl_stubs::vm_spec::{
@@ -67,25 +76,27 @@ use nanocl_stubs::resource_kind::{
   ResourceKindVersion,
 };
 use nanocl_stubs::resource::{
-  Resource, ResourceUpdate, ResourceSpec, ResourcePartial,
+  Resource, ResourceUpdate, ResourceSpec, ResourcePartial, ResourceValidation,
 };
 use nanocl_stubs::dns::{ResourceDnsRule, DnsEntry};
 use nanocl_stubs::proxy::{
-  ResourceProxyRule, ProxyRuleHttp, ProxyHttpLocation, ProxySsl,
-  ProxyRuleStream, StreamTarget, ProxyStreamProtocol, UriTarget,
-  LocationTarget, HttpTarget, UrlRedirect, UpstreamTarget, ProxyRule,
-  UnixTarget, ProxySslConfig,
+  ResourceProxyRule, ProxyRuleHttp, ProxyHttpLocation, ProxyRateLimit,
+  ProxyHttpProtocol, ProxyAuth, ProxyBasicAuth, ProxyForwardAuth, ProxySsl,
+  ProxySslAcme, AcmeChallenge, ProxyRuleStream, StreamTarget,
+  ProxyStreamProtocol, UriTarget, LocationTarget, HttpTarget, UrlRedirect,
+  UpstreamTarget, UpstreamBalancing, UpstreamHealth, ProxyRule, UnixTarget,
+  ProxySslConfig, SplitTarget, SplitUpstream, SplitSticky,
 };
 use nanocl_stubs::statefile::{
   Statefile, StatefileArg, StatefileArgKind, SubState, SubStateDef,
-  SubStateArg, SubStateValue,
+  SubStateArg, SubStateValue, StateStream, StateStreamStatus,
 };
 
 use crate::vars;
 
 use super::{
   node, system, namespace, exec, cargo, vm, vm_image, resource, metric, secret,
-  job, process, resource_kind, event,
+  job, process, resource_kind, event, state, secret_kind, webhook,
 };
 
 /// When returning a [HttpError](nanocl_error::http::HttpError)
@@ -245,11 +256,25 @@ impl Modify for VersionModifier {
     secret::create_secret,
     secret::delete_secret,
     secret::patch_secret,
+    secret::list_secret_history,
+    secret::revert_secret,
+    // Secret kind
+    secret_kind::list_secret_kind,
+    secret_kind::create_secret_kind,
+    secret_kind::delete_secret_kind,
+    secret_kind::inspect_secret_kind,
+    // Webhook
+    webhook::list_webhook,
+    webhook::create_webhook,
+    webhook::delete_webhook,
+    webhook::inspect_webhook,
+    webhook::list_webhook_delivery,
     // Job
     job::list_job,
     job::delete_job,
     job::inspect_job,
     job::create_job,
+    job::list_job_run,
     // Cargo
     cargo::list_cargo,
     cargo::inspect_cargo,
@@ -288,6 +313,7 @@ impl Modify for VersionModifier {
     resource::list_resource,
     resource::inspect_resource,
     resource::create_resource,
+    resource::validate_resource,
     resource::delete_resource,
     resource::put_resource,
     resource::list_resource_history,
@@ -295,6 +321,7 @@ impl Modify for VersionModifier {
     // Metric
     metric::list_metric,
     metric::create_metric,
+    metric::prometheus_metric,
     // Process
     process::logs_processes,
     process::logs_process,
@@ -308,6 +335,9 @@ impl Modify for VersionModifier {
     // Event
     event::list_event,
     event::watch_event,
+    // State
+    state::apply_state,
+    state::remove_state,
   ),
   components(schemas(
     // Node
@@ -316,6 +346,7 @@ impl Modify for VersionModifier {
     Secret,
     SecretPartial,
     SecretUpdate,
+    SecretHistory,
     // System
     BinaryInfo,
     HostInfo,
@@ -366,6 +397,12 @@ impl Modify for VersionModifier {
     JobPartial,
     JobInspect,
     JobSummary,
+    JobStep,
+    JobDependency,
+    JobDependencyKind,
+    JobConcurrencyPolicy,
+    JobRun,
+    JobRunStatus,
     // Cargo
     Cargo,
     CreateExecOptions,
@@ -377,6 +414,9 @@ impl Modify for VersionModifier {
     CargoSpecPartial,
     CargoSpecUpdate,
     ReplicationStatic,
+    CargoAutoscale,
+    UpdateStrategy,
+    UpdateStrategyKind,
     PidsStats,
     NetworkStats,
     BlkioStats,
@@ -459,6 +499,7 @@ impl Modify for VersionModifier {
     ResourceUpdate,
     ResourceSpec,
     ResourcePartial,
+    ResourceValidation,
     // State
     Statefile,
     StatefileArg,
@@ -467,13 +508,22 @@ impl Modify for VersionModifier {
     SubStateDef,
     SubStateArg,
     SubStateValue,
+    StateStream,
+    StateStreamStatus,
     // ProxyRules
     ResourceProxyRule,
     ProxyRule,
     ProxyRuleHttp,
     ProxyHttpLocation,
+    ProxyRateLimit,
+    ProxyHttpProtocol,
+    ProxyAuth,
+    ProxyBasicAuth,
+    ProxyForwardAuth,
     ProxySsl,
     ProxySslConfig,
+    ProxySslAcme,
+    AcmeChallenge,
     ProxyRuleStream,
     StreamTarget,
     ProxyStreamProtocol,
@@ -481,8 +531,13 @@ impl Modify for VersionModifier {
     HttpTarget,
     UrlRedirect,
     UpstreamTarget,
+    UpstreamBalancing,
+    UpstreamHealth,
     UnixTarget,
     UriTarget,
+    SplitTarget,
+    SplitUpstream,
+    SplitSticky,
     // DnsRules
     ResourceDnsRule,
     DnsEntry,
@@ -492,9 +547,21 @@ impl Modify for VersionModifier {
     ResourceKindSpec,
     ResourceKind,
     ResourceKindVersion,
+    // Secret Kind
+    SecretKindPartial,
+    SecretKind,
+    // Webhook
+    WebhookPartial,
+    Webhook,
+    WebhookRetry,
+    WebhookDelivery,
+    WebhookDeliveryStatus,
     // Metric
     Metric,
     MetricPartial,
+    MetricAggregate,
+    MetricAggregateFn,
+    HttpMetricSummary,
     // Daemon
     DaemonConfig,
     // Error
@@ -525,8 +592,11 @@ impl Modify for VersionModifier {
     (name = "Metrics", description = "Metrics management endpoints."),
     (name = "Processes", description = "Processes management endpoints."),
     (name = "Secrets", description = "Secrets management endpoints."),
+    (name = "SecretKinds", description = "Secret kinds management endpoints."),
     (name = "Jobs", description = "Jobs management endpoints."),
     (name = "Events", description = "Events management endpoints."),
+    (name = "States", description = "Statefiles management endpoints."),
+    (name = "Webhooks", description = "Webhooks management endpoints."),
   ),
   modifiers(&VersionModifier),
 )]
@@ -13,8 +13,8 @@ use nanocl_stubs::{
   cargo::CargoKillOptions,
   generic::{GenericFilter, GenericListQuery, GenericNspQuery},
   process::{
-    ProcessLogQuery, ProcessOutputLog, ProcessStats, ProcessStatsQuery,
-    ProcessWaitQuery, ProcessWaitResponse,
+    ProcessKind, ProcessLogQuery, ProcessOutputLog, ProcessStats,
+    ProcessStatsQuery, ProcessWaitQuery, ProcessWaitResponse,
   },
 };
 
@@ -225,7 +225,10 @@ pub async fn start_processes(
   let (_, kind, name) = path.into_inner();
   let kind = kind.parse().map_err(HttpError::bad_request)?;
   let kind_key = utils::key::gen_kind_key(&kind, &name, &qs.namespace);
-  utils::container::emit_starting(&kind_key, &kind, &state).await?;
+  match kind {
+    ProcessKind::Job => utils::job::emit_starting(&kind_key, &state).await?,
+    _ => utils::container::emit_starting(&kind_key, &kind, &state).await?,
+  }
   Ok(web::HttpResponse::Accepted().finish())
 }
 
@@ -6,7 +6,7 @@ use ntex::web;
 use nanocl_error::http::{HttpError, HttpResult};
 
 use nanocl_stubs::{
-  generic::{GenericFilter, GenericClause, GenericListQuery},
+  generic::{GenericFilter, GenericListQuery},
   resource::{ResourceSpec, ResourcePartial, ResourceUpdate},
 };
 
@@ -81,6 +81,26 @@ pub async fn create_resource(
   Ok(web::HttpResponse::Created().json(&resource))
 }
 
+/// Validate a resource without creating it
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  request_body = ResourcePartial,
+  tag = "Resources",
+  path = "/resources/validate",
+  responses(
+    (status = 200, description = "The validation of the resource with its errors", body = ResourceValidation),
+  ),
+))]
+#[web::post("/resources/validate")]
+pub async fn validate_resource(
+  state: web::types::State<SystemState>,
+  payload: web::types::Json<ResourcePartial>,
+) -> HttpResult<web::HttpResponse> {
+  let validation =
+    ResourceDb::hook_validate(&payload, &state.inner.pool).await?;
+  Ok(web::HttpResponse::Ok().json(&validation))
+}
+
 /// Delete a resource
 #[cfg_attr(feature = "dev", utoipa::path(
   delete,
@@ -154,9 +174,7 @@ pub async fn list_resource_history(
   state: web::types::State<SystemState>,
   path: web::types::Path<(String, String)>,
 ) -> HttpResult<web::HttpResponse> {
-  let filter =
-    GenericFilter::new().r#where("kind_key", GenericClause::Eq(path.1.clone()));
-  let items = SpecDb::read_by(&filter, &state.inner.pool)
+  let items = SpecDb::read_by_kind_key("Resource", &path.1, &state.inner.pool)
     .await?
     .into_iter()
     .map(ResourceSpec::from)
@@ -183,9 +201,11 @@ pub async fn revert_resource(
   state: web::types::State<SystemState>,
   path: web::types::Path<(String, String, uuid::Uuid)>,
 ) -> HttpResult<web::HttpResponse> {
-  let history = SpecDb::read_by_pk(&path.2, &state.inner.pool).await?;
   let resource =
     ResourceDb::transform_read_by_pk(&path.1, &state.inner.pool).await?;
+  let history =
+    SpecDb::read_by_kind_pk("Resource", &path.1, &path.2, &state.inner.pool)
+      .await?;
   let new_resource = ResourcePartial {
     name: resource.spec.resource_key,
     kind: resource.kind,
@@ -199,6 +219,7 @@ pub async fn revert_resource(
 
 pub fn ntex_config(config: &mut web::ServiceConfig) {
   config.service(create_resource);
+  config.service(validate_resource);
   config.service(delete_resource);
   config.service(list_resource);
   config.service(inspect_resource);
@@ -211,7 +232,7 @@ pub fn ntex_config(config: &mut web::ServiceConfig) {
 mod tests {
   use ntex::http;
   use nanocl_stubs::{
-    resource::{Resource, ResourcePartial, ResourceUpdate},
+    resource::{Resource, ResourcePartial, ResourceUpdate, ResourceValidation},
     generic::{GenericFilter, GenericClause, GenericListQuery},
     resource_kind::{ResourceKindPartial, ResourceKindSpec},
   };
@@ -286,6 +307,37 @@ mod tests {
     let resource = res.json::<Resource>().await.unwrap();
     assert_eq!(resource.spec.resource_key, TEST_RESOURCE);
     assert_eq!(resource.kind, TEST_RESOURCE_KIND);
+    let mut partial = ResourcePartial {
+      name: TEST_RESOURCE.to_owned(),
+      kind: TEST_RESOURCE_KIND.to_owned(),
+      data: data.clone(),
+      metadata: None,
+    };
+    let mut res = client
+      .send_post(
+        &format!("{ENDPOINT}/validate"),
+        Some(&partial),
+        None::<String>,
+      )
+      .await;
+    test_status_code!(res.status(), http::StatusCode::OK, "validate resource");
+    let validation = res.json::<ResourceValidation>().await.unwrap();
+    assert!(validation.errors.is_empty());
+    partial.data = serde_json::json!({ "Password": "test" });
+    let mut res = client
+      .send_post(
+        &format!("{ENDPOINT}/validate"),
+        Some(&partial),
+        None::<String>,
+      )
+      .await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::OK,
+      "validate invalid resource"
+    );
+    let validation = res.json::<ResourceValidation>().await.unwrap();
+    assert_eq!(validation.errors.len(), 1);
     // Basic list
     let mut res = client.send_get(ENDPOINT, None::<String>).await;
     test_status_code!(res.status(), http::StatusCode::OK, "list resource");
@@ -75,7 +75,7 @@ pub async fn delete_resource_kind(
   let key = format!("{}/{}", path.1, path.2);
   ResourceKindDb::read_by_pk(&key, &state.inner.pool).await?;
   ResourceKindDb::del_by_pk(&key, &state.inner.pool).await?;
-  SpecDb::del_by_kind_key(&key, &state.inner.pool).await?;
+  SpecDb::del_by_kind_key("ResourceKind", &key, &state.inner.pool).await?;
   Ok(web::HttpResponse::Accepted().into())
 }
 
@@ -122,7 +122,8 @@ pub async fn inspect_resource_kind_version(
 ) -> HttpResult<web::HttpResponse> {
   let key = format!("{}/{}", path.1, path.2);
   let kind_version =
-    SpecDb::get_version(&key, &path.3, &state.inner.pool).await?;
+    SpecDb::get_version("ResourceKind", &key, &path.3, &state.inner.pool)
+      .await?;
   let kind_version: ResourceKindVersion = kind_version.try_into()?;
   Ok(web::HttpResponse::Ok().json(&kind_version))
 }
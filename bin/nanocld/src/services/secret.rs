@@ -1,22 +1,23 @@
-use bollard_next::auth::DockerCredentials;
 /*
 * Endpoints to manipulate secrets
 */
 use ntex::web;
 
-use nanocl_error::http::{HttpError, HttpResult};
+use nanocl_error::{
+  io::IoResult,
+  http::{HttpError, HttpResult},
+};
 
 use nanocl_stubs::{
   generic::{GenericFilter, GenericListQuery},
-  proxy::ProxySslConfig,
-  secret::{SecretPartial, SecretUpdate},
+  secret::{SecretPartial, SecretUpdate, SecretInspectQuery},
 };
 
 use crate::{
   utils,
   objects::generic::*,
   repositories::generic::*,
-  models::{SystemState, SecretDb},
+  models::{SystemState, SecretDb, SpecDb},
 };
 
 /// List secret
@@ -38,7 +39,11 @@ pub async fn list_secret(
 ) -> HttpResult<web::HttpResponse> {
   let filter = GenericFilter::try_from(query.into_inner())
     .map_err(|err| HttpError::bad_request(err.to_string()))?;
-  let items = SecretDb::transform_read_by(&filter, &state.inner.pool).await?;
+  let items = SecretDb::transform_read_by(&filter, &state.inner.pool)
+    .await?
+    .into_iter()
+    .map(utils::secret::redact)
+    .collect::<Vec<_>>();
   Ok(web::HttpResponse::Ok().json(&items))
 }
 
@@ -48,7 +53,8 @@ pub async fn list_secret(
   tag = "Secrets",
   path = "/secrets/{key}/inspect",
   params(
-    ("key" = String, Path, description = "Key of the secret")
+    ("key" = String, Path, description = "Key of the secret"),
+    ("reveal" = Option<bool>, Query, description = "Return the data instead of redacting it"),
   ),
   responses(
     (status = 200, description = "Detailed information about a secret", body = Secret),
@@ -59,9 +65,13 @@ pub async fn list_secret(
 pub async fn inspect_secret(
   state: web::types::State<SystemState>,
   path: web::types::Path<(String, String)>,
+  qs: web::types::Query<SecretInspectQuery>,
 ) -> HttpResult<web::HttpResponse> {
-  let secret =
+  let mut secret =
     SecretDb::transform_read_by_pk(&path.1, &state.inner.pool).await?;
+  if !qs.reveal.unwrap_or_default() {
+    secret = utils::secret::redact(secret);
+  }
   Ok(web::HttpResponse::Ok().json(&secret))
 }
 
@@ -82,22 +92,8 @@ pub async fn create_secret(
   payload: web::types::Json<SecretPartial>,
 ) -> HttpResult<web::HttpResponse> {
   utils::key::ensure_kind(&payload.kind)?;
-  match payload.kind.as_str() {
-    "nanocl.io/tls" => {
-      serde_json::from_value::<ProxySslConfig>(payload.data.clone())
-        .map_err(|e| HttpError::bad_request(e.to_string()))?;
-    }
-    "nanocl.io/env" => {
-      serde_json::from_value::<Vec<String>>(payload.data.clone())
-        .map_err(|e| HttpError::bad_request(e.to_string()))?;
-    }
-    "nanocl.io/container-registry" => {
-      serde_json::from_value::<DockerCredentials>(payload.data.clone())
-        .map_err(|e| HttpError::bad_request(e.to_string()))?;
-    }
-    _ => {}
-  }
   let secret = SecretDb::create_obj(&payload, &state).await?;
+  let secret = utils::secret::redact(secret);
   Ok(web::HttpResponse::Created().json(&secret))
 }
 
@@ -144,6 +140,75 @@ pub async fn patch_secret(
   payload: web::types::Json<SecretUpdate>,
 ) -> HttpResult<web::HttpResponse> {
   let item = SecretDb::patch_obj_by_pk(&path.1, &payload, &state).await?;
+  let item = utils::secret::redact(item);
+  Ok(web::HttpResponse::Ok().json(&item))
+}
+
+/// List secret histories
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  tag = "Secrets",
+  path = "/secrets/{key}/histories",
+  params(
+    ("key" = String, Path, description = "Key of the secret"),
+    ("reveal" = Option<bool>, Query, description = "Return the data instead of redacting it"),
+  ),
+  responses(
+    (status = 200, description = "List of secret histories", body = Vec<SecretHistory>),
+    (status = 404, description = "Secret does not exist", body = ApiError),
+  ),
+))]
+#[web::get("/secrets/{key}/histories")]
+pub async fn list_secret_history(
+  state: web::types::State<SystemState>,
+  path: web::types::Path<(String, String)>,
+  qs: web::types::Query<SecretInspectQuery>,
+) -> HttpResult<web::HttpResponse> {
+  SecretDb::read_by_pk(&path.1, &state.inner.pool).await?;
+  let reveal = qs.reveal.unwrap_or_default();
+  let histories = SpecDb::read_secret_histories(&path.1, &state.inner.pool)
+    .await?
+    .into_iter()
+    .map(|spec| {
+      let mut history = spec.try_to_secret_history()?;
+      if !reveal {
+        history.data = utils::secret::redact_data(history.data);
+      }
+      Ok(history)
+    })
+    .collect::<IoResult<Vec<_>>>()?;
+  Ok(web::HttpResponse::Ok().json(&histories))
+}
+
+/// Revert a secret to a specific history
+#[cfg_attr(feature = "dev", utoipa::path(
+  patch,
+  tag = "Secrets",
+  path = "/secrets/{key}/histories/{id}/revert",
+  params(
+    ("key" = String, Path, description = "Key of the secret"),
+    ("id" = String, Path, description = "Id of the secret history"),
+  ),
+  responses(
+    (status = 200, description = "Secret reverted", body = Secret),
+    (status = 404, description = "Secret or history does not exist", body = ApiError),
+  ),
+))]
+#[web::patch("/secrets/{key}/histories/{id}/revert")]
+pub async fn revert_secret(
+  state: web::types::State<SystemState>,
+  path: web::types::Path<(String, String, uuid::Uuid)>,
+) -> HttpResult<web::HttpResponse> {
+  let history =
+    SpecDb::read_by_kind_pk("Secret", &path.1, &path.2, &state.inner.pool)
+      .await?
+      .try_to_secret_history()?;
+  let update = SecretUpdate {
+    data: history.data,
+    metadata: history.metadata,
+  };
+  let item = SecretDb::patch_obj_by_pk(&path.1, &update, &state).await?;
+  let item = utils::secret::redact(item);
   Ok(web::HttpResponse::Ok().json(&item))
 }
 
@@ -153,6 +218,8 @@ pub fn ntex_config(config: &mut web::ServiceConfig) {
   config.service(inspect_secret);
   config.service(delete_secret);
   config.service(patch_secret);
+  config.service(list_secret_history);
+  config.service(revert_secret);
 }
 
 #[cfg(test)]
@@ -161,7 +228,9 @@ mod test_secret {
 
   use serde_json::json;
 
-  use nanocl_stubs::secret::{Secret, SecretPartial};
+  use nanocl_stubs::secret::{
+    Secret, SecretPartial, SecretUpdate, SecretHistory, SecretInspectQuery,
+  };
 
   use crate::utils::tests::*;
 
@@ -212,13 +281,107 @@ mod test_secret {
       http::StatusCode::BAD_REQUEST,
       "create secret with no body"
     );
+    let mut secret = SecretPartial {
+      name: String::from("test-secret-invalid"),
+      kind: String::from("nanocl.io/tsl"),
+      immutable: None,
+      data: json!({}),
+      metadata: None,
+    };
+    let res = client
+      .send_post(ENDPOINT, Some(&secret), None::<String>)
+      .await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::BAD_REQUEST,
+      "create secret with unknown built-in kind"
+    );
+    secret.kind = String::from("nanocl.io/env");
+    secret.data = json!(["MY_ENV"]);
+    let res = client
+      .send_post(ENDPOINT, Some(&secret), None::<String>)
+      .await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::BAD_REQUEST,
+      "create secret with invalid env"
+    );
+    secret.kind = String::from("nanocl.io/htpasswd");
+    secret.data = json!(["admin"]);
+    let res = client
+      .send_post(ENDPOINT, Some(&secret), None::<String>)
+      .await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::BAD_REQUEST,
+      "create secret with invalid htpasswd"
+    );
   }
 
   async fn test_inspect_by_id(client: &TestClient) {
-    let res = client
+    let mut res = client
       .send_get(&format!("{ENDPOINT}/test-secret/inspect"), None::<String>)
       .await;
     test_status_code!(res.status(), http::StatusCode::OK, "inspect secret");
+    let secret = res.json::<Secret>().await.unwrap();
+    assert_eq!(secret.data, json!({ "Tls": "<redacted>" }));
+    let mut res = client
+      .send_get(
+        &format!("{ENDPOINT}/test-secret/inspect"),
+        Some(&SecretInspectQuery { reveal: Some(true) }),
+      )
+      .await;
+    test_status_code!(res.status(), http::StatusCode::OK, "reveal secret");
+    let secret = res.json::<Secret>().await.unwrap();
+    assert_eq!(
+      secret.data,
+      json!({ "Tls": { "cert": "MY CERT", "key": "MY KEY" } })
+    );
+  }
+
+  async fn test_history(client: &TestClient) {
+    let update = SecretUpdate {
+      data: json!({ "Tls": { "cert": "NEW CERT", "key": "NEW KEY" } }),
+      metadata: None,
+    };
+    let res = client
+      .send_patch(
+        &format!("{ENDPOINT}/test-secret"),
+        Some(&update),
+        None::<String>,
+      )
+      .await;
+    test_status_code!(res.status(), http::StatusCode::OK, "patch secret");
+    let mut res = client
+      .send_get(
+        &format!("{ENDPOINT}/test-secret/histories"),
+        Some(&SecretInspectQuery { reveal: Some(true) }),
+      )
+      .await;
+    test_status_code!(res.status(), http::StatusCode::OK, "secret histories");
+    let histories = res.json::<Vec<SecretHistory>>().await.unwrap();
+    assert_eq!(histories.len(), 2);
+    let first = histories.last().unwrap();
+    assert_eq!(
+      first.data,
+      json!({ "Tls": { "cert": "MY CERT", "key": "MY KEY" } })
+    );
+    let res = client
+      .send_patch(
+        &format!("{ENDPOINT}/test-secret/histories/{}/revert", first.key),
+        None::<String>,
+        None::<String>,
+      )
+      .await;
+    test_status_code!(res.status(), http::StatusCode::OK, "revert secret");
+    let mut res = client
+      .send_get(
+        &format!("{ENDPOINT}/test-secret/inspect"),
+        Some(&SecretInspectQuery { reveal: Some(true) }),
+      )
+      .await;
+    let secret = res.json::<Secret>().await.unwrap();
+    assert_eq!(secret.data, first.data);
   }
 
   async fn test_delete(client: &TestClient) {
@@ -239,6 +402,7 @@ mod test_secret {
     test_fail_create(&client).await;
     test_create(&client).await;
     test_inspect_by_id(&client).await;
+    test_history(&client).await;
     test_list(&client).await;
     test_delete(&client).await;
   }
@@ -0,0 +1,121 @@
+use ntex::web;
+
+use nanocl_error::http::HttpResult;
+
+use nanocl_stubs::statefile::Statefile;
+
+use crate::{utils, models::SystemState};
+
+/// Apply a rendered Statefile and stream the progress of each object
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  tag = "States",
+  path = "/states/apply",
+  request_body = Statefile,
+  responses(
+    (status = 200, description = "Stream of the objects progress", content_type = "application/vdn.nanocl.raw-stream", body = StateStream),
+  ),
+))]
+#[web::post("/states/apply")]
+pub async fn apply_state(
+  state: web::types::State<SystemState>,
+  path: web::types::Path<String>,
+  payload: web::types::Json<Statefile>,
+) -> HttpResult<web::HttpResponse> {
+  let rx = utils::state::apply(&path.into_inner(), &payload, &state);
+  Ok(
+    web::HttpResponse::Ok()
+      .content_type("application/vdn.nanocl.raw-stream")
+      .streaming(rx),
+  )
+}
+
+/// Remove the objects of a rendered Statefile and stream their progress
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  tag = "States",
+  path = "/states/remove",
+  request_body = Statefile,
+  responses(
+    (status = 200, description = "Stream of the objects progress", content_type = "application/vdn.nanocl.raw-stream", body = StateStream),
+  ),
+))]
+#[web::post("/states/remove")]
+pub async fn remove_state(
+  state: web::types::State<SystemState>,
+  payload: web::types::Json<Statefile>,
+) -> HttpResult<web::HttpResponse> {
+  let rx = utils::state::remove(&payload, &state);
+  Ok(
+    web::HttpResponse::Ok()
+      .content_type("application/vdn.nanocl.raw-stream")
+      .streaming(rx),
+  )
+}
+
+pub fn ntex_config(config: &mut web::ServiceConfig) {
+  config.service(apply_state);
+  config.service(remove_state);
+}
+
+#[cfg(test)]
+mod tests {
+  use ntex::http;
+  use serde_json::json;
+
+  use nanocl_stubs::{
+    secret::SecretPartial,
+    statefile::{Statefile, StateStream, StateStreamStatus},
+  };
+
+  use crate::utils::tests::*;
+
+  fn parse_stream(body: &[u8]) -> Vec<StateStream> {
+    String::from_utf8_lossy(body)
+      .split("\r\n")
+      .filter(|line| !line.is_empty())
+      .map(|line| serde_json::from_str(line).unwrap())
+      .collect()
+  }
+
+  #[ntex::test]
+  async fn apply_and_remove() {
+    let system = gen_default_test_system().await;
+    let client = system.client;
+    let statefile = Statefile {
+      api_version: "v0.14".to_owned(),
+      args: None,
+      sub_states: None,
+      group: None,
+      namespace: None,
+      secrets: Some(vec![SecretPartial {
+        name: "test-state-secret".to_owned(),
+        kind: "test-state.io/test".to_owned(),
+        immutable: None,
+        data: json!({ "Value": "test" }),
+        metadata: None,
+      }]),
+      resources: None,
+      cargoes: None,
+      virtual_machines: None,
+      jobs: None,
+    };
+    let mut res = client
+      .send_post("/states/apply", Some(&statefile), None::<String>)
+      .await;
+    test_status_code!(res.status(), http::StatusCode::OK, "apply state");
+    let items = parse_stream(&res.body().await.unwrap());
+    assert_eq!(items.last().unwrap().status, StateStreamStatus::Created);
+    let mut res = client
+      .send_post("/states/apply", Some(&statefile), None::<String>)
+      .await;
+    let items = parse_stream(&res.body().await.unwrap());
+    assert_eq!(items.last().unwrap().status, StateStreamStatus::Unchanged);
+    let mut res = client
+      .send_post("/states/remove", Some(&statefile), None::<String>)
+      .await;
+    test_status_code!(res.status(), http::StatusCode::OK, "remove state");
+    let items = parse_stream(&res.body().await.unwrap());
+    assert_eq!(items.last().unwrap().status, StateStreamStatus::Removed);
+  }
+}
@@ -17,7 +17,7 @@ use nanocl_error::{
 use bollard_next::container::AttachContainerOptions;
 use nanocl_stubs::{
   process::OutputLog,
-  generic::GenericNspQuery,
+  generic::{GenericFilter, GenericListNspQuery, GenericNspQuery},
   vm_spec::{VmSpecPartial, VmSpecUpdate},
 };
 
@@ -35,6 +35,7 @@ use crate::{
   tag = "Vms",
   path = "/vms",
   params(
+    ("filter" = Option<String>, Query, description = "Generic filter", example = "{ \"where\": { \"name\": { \"eq\": \"test\" } } }"),
     ("namespace" = Option<String>, Query, description = "The namespace of the virtual machine"),
   ),
   responses(
@@ -44,10 +45,13 @@ use crate::{
 #[web::get("/vms")]
 pub async fn list_vm(
   state: web::types::State<SystemState>,
-  qs: web::types::Query<GenericNspQuery>,
+  qs: web::types::Query<GenericListNspQuery>,
 ) -> HttpResult<web::HttpResponse> {
   let namespace = utils::key::resolve_nsp(&qs.namespace);
-  let vms = VmDb::list_by_namespace(&namespace, &state.inner.pool).await?;
+  let filter = GenericFilter::try_from(qs.into_inner())
+    .map_err(|err| HttpError::bad_request(err.to_string()))?;
+  let vms =
+    VmDb::list_by_namespace(&namespace, &filter, &state.inner.pool).await?;
   Ok(web::HttpResponse::Ok().json(&vms))
 }
 
@@ -154,7 +158,7 @@ pub async fn list_vm_history(
 ) -> HttpResult<web::HttpResponse> {
   let namespace = utils::key::resolve_nsp(&qs.namespace);
   let key = utils::key::gen_key(&namespace, &path.1);
-  let histories = SpecDb::read_by_kind_key(&key, &state.inner.pool)
+  let histories = SpecDb::read_by_kind_key("Vm", &key, &state.inner.pool)
     .await?
     .into_iter()
     .map(|i| i.try_to_vm_spec())
@@ -0,0 +1,291 @@
+/*
+* Endpoints to manipulate webhooks and inspect their deliveries
+*/
+use ntex::web;
+
+use nanocl_error::http::{HttpError, HttpResult};
+
+use nanocl_stubs::{
+  generic::{GenericClause, GenericFilter, GenericListQuery},
+  webhook::{Webhook, WebhookPartial},
+};
+
+use crate::{
+  utils,
+  repositories::generic::*,
+  models::{SystemState, WebhookDb, WebhookDeliveryDb},
+};
+
+/// List webhooks
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  tag = "Webhooks",
+  path = "/webhooks",
+  params(
+    ("filter" = Option<String>, Query, description = "Generic filter", example = "{ \"where\": { \"name\": { \"eq\": \"deploy-bot\" } } }"),
+  ),
+  responses(
+    (status = 200, description = "List of webhooks", body = [Webhook]),
+  ),
+))]
+#[web::get("/webhooks")]
+pub async fn list_webhook(
+  state: web::types::State<SystemState>,
+  query: web::types::Query<GenericListQuery>,
+) -> HttpResult<web::HttpResponse> {
+  let filter = GenericFilter::try_from(query.into_inner())
+    .map_err(|err| HttpError::bad_request(err.to_string()))?;
+  let webhooks =
+    WebhookDb::transform_read_by(&filter, &state.inner.pool).await?;
+  Ok(web::HttpResponse::Ok().json(&webhooks))
+}
+
+/// Create a webhook, the matching events will be posted to its url
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  tag = "Webhooks",
+  path = "/webhooks",
+  request_body = WebhookPartial,
+  responses(
+    (status = 201, description = "Webhook created", body = Webhook),
+    (status = 400, description = "Invalid name, url or retry policy", body = ApiError),
+    (status = 409, description = "Webhook already exist", body = ApiError),
+  ),
+))]
+#[web::post("/webhooks")]
+pub async fn create_webhook(
+  state: web::types::State<SystemState>,
+  payload: web::types::Json<WebhookPartial>,
+) -> HttpResult<web::HttpResponse> {
+  utils::webhook::validate(&payload)?;
+  let item = WebhookDb::create_try_from(&*payload, &state.inner.pool).await?;
+  let item = Webhook::try_from(item)?;
+  Ok(web::HttpResponse::Created().json(&item))
+}
+
+/// Delete a webhook and its deliveries
+#[cfg_attr(feature = "dev", utoipa::path(
+  delete,
+  tag = "Webhooks",
+  path = "/webhooks/{name}",
+  params(
+    ("name" = String, Path, description = "Name of the webhook"),
+  ),
+  responses(
+    (status = 202, description = "Webhook deleted"),
+    (status = 404, description = "Webhook does not exist", body = ApiError),
+  ),
+))]
+#[web::delete("/webhooks/{name}")]
+pub async fn delete_webhook(
+  state: web::types::State<SystemState>,
+  path: web::types::Path<(String, String)>,
+) -> HttpResult<web::HttpResponse> {
+  WebhookDb::read_by_pk(&path.1, &state.inner.pool).await?;
+  WebhookDb::del_by_pk(&path.1, &state.inner.pool).await?;
+  Ok(web::HttpResponse::Accepted().into())
+}
+
+/// Inspect a webhook
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  tag = "Webhooks",
+  path = "/webhooks/{name}/inspect",
+  params(
+    ("name" = String, Path, description = "Name of the webhook"),
+  ),
+  responses(
+    (status = 200, description = "Details about a webhook", body = Webhook),
+    (status = 404, description = "Webhook does not exist", body = ApiError),
+  ),
+))]
+#[web::get("/webhooks/{name}/inspect")]
+pub async fn inspect_webhook(
+  state: web::types::State<SystemState>,
+  path: web::types::Path<(String, String)>,
+) -> HttpResult<web::HttpResponse> {
+  let webhook =
+    WebhookDb::transform_read_by_pk(&path.1, &state.inner.pool).await?;
+  Ok(web::HttpResponse::Ok().json(&webhook))
+}
+
+/// List the deliveries of a webhook, the failed ones are kept as dead-letter
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  tag = "Webhooks",
+  path = "/webhooks/{name}/deliveries",
+  params(
+    ("name" = String, Path, description = "Name of the webhook"),
+    ("filter" = Option<String>, Query, description = "Generic filter", example = "{ \"where\": { \"status\": { \"eq\": \"deadletter\" } } }"),
+  ),
+  responses(
+    (status = 200, description = "List of deliveries", body = [WebhookDelivery]),
+    (status = 404, description = "Webhook does not exist", body = ApiError),
+  ),
+))]
+#[web::get("/webhooks/{name}/deliveries")]
+pub async fn list_webhook_delivery(
+  state: web::types::State<SystemState>,
+  path: web::types::Path<(String, String)>,
+  query: web::types::Query<GenericListQuery>,
+) -> HttpResult<web::HttpResponse> {
+  WebhookDb::read_by_pk(&path.1, &state.inner.pool).await?;
+  let filter = GenericFilter::try_from(query.into_inner())
+    .map_err(|err| HttpError::bad_request(err.to_string()))?
+    .r#where("webhook_name", GenericClause::Eq(path.1.clone()));
+  let deliveries =
+    WebhookDeliveryDb::transform_read_by(&filter, &state.inner.pool).await?;
+  Ok(web::HttpResponse::Ok().json(&deliveries))
+}
+
+pub fn ntex_config(config: &mut web::ServiceConfig) {
+  config
+    .service(list_webhook)
+    .service(create_webhook)
+    .service(delete_webhook)
+    .service(inspect_webhook)
+    .service(list_webhook_delivery);
+}
+
+#[cfg(test)]
+mod tests {
+  use ntex::http;
+
+  use nanocl_stubs::{
+    system::{
+      EventActor, EventActorKind, EventCondition, EventKind, EventPartial,
+    },
+    webhook::{
+      Webhook, WebhookDelivery, WebhookDeliveryStatus, WebhookPartial,
+      WebhookRetry,
+    },
+  };
+
+  use crate::utils::tests::*;
+
+  const ENDPOINT: &str = "/webhooks";
+
+  #[ntex::test]
+  async fn invalid_url() {
+    let system = gen_default_test_system().await;
+    let client = system.client;
+    let payload = WebhookPartial {
+      name: "test-webhook-invalid".to_owned(),
+      url: "ftp://127.0.0.1/hook".to_owned(),
+      conditions: None,
+      secret: None,
+      retry: None,
+      metadata: None,
+    };
+    let res = client
+      .send_post(ENDPOINT, Some(&payload), None::<String>)
+      .await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::BAD_REQUEST,
+      "webhook invalid url"
+    );
+  }
+
+  #[ntex::test]
+  async fn basic() {
+    const NAME: &str = "test-webhook";
+    let system = gen_default_test_system().await;
+    let client = system.client;
+    client
+      .send_delete(&format!("{ENDPOINT}/{NAME}"), None::<String>)
+      .await;
+    let payload = WebhookPartial {
+      name: NAME.to_owned(),
+      // Nothing listen on the discard port so every attempt fail
+      url: "http://127.0.0.1:9/hook".to_owned(),
+      conditions: Some(vec![EventCondition {
+        actor_key: Some(NAME.to_owned()),
+        ..Default::default()
+      }]),
+      secret: Some("my-secret".to_owned()),
+      retry: Some(WebhookRetry {
+        max_attempts: 2,
+        backoff: 0,
+        max_backoff: 0,
+      }),
+      metadata: None,
+    };
+    let mut res = client
+      .send_post(ENDPOINT, Some(&payload), None::<String>)
+      .await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::CREATED,
+      "webhook create"
+    );
+    let webhook = res.json::<serde_json::Value>().await.unwrap();
+    assert!(webhook.get("Secret").is_none(), "webhook secret returned");
+    let webhook = serde_json::from_value::<Webhook>(webhook).unwrap();
+    assert!(webhook.signed);
+    let res = client
+      .send_get(&format!("{ENDPOINT}/{NAME}/inspect"), None::<String>)
+      .await;
+    test_status_code!(res.status(), http::StatusCode::OK, "webhook inspect");
+    let res = client.send_get(ENDPOINT, None::<String>).await;
+    test_status_code!(res.status(), http::StatusCode::OK, "webhook list");
+    system
+      .state
+      .emit_event(EventPartial {
+        reporting_controller: "nanocl.io/test".to_owned(),
+        reporting_node: "test".to_owned(),
+        kind: EventKind::Normal,
+        action: "create".to_owned(),
+        reason: "test".to_owned(),
+        note: None,
+        actor: Some(EventActor {
+          key: Some(NAME.to_owned()),
+          kind: EventActorKind::Resource,
+          attributes: None,
+        }),
+        related: None,
+        metadata: None,
+      })
+      .await
+      .unwrap();
+    let mut delivery = None;
+    for _ in 0..20 {
+      let mut res = client
+        .send_get(&format!("{ENDPOINT}/{NAME}/deliveries"), None::<String>)
+        .await;
+      test_status_code!(
+        res.status(),
+        http::StatusCode::OK,
+        "webhook deliveries"
+      );
+      let deliveries = res.json::<Vec<WebhookDelivery>>().await.unwrap();
+      delivery = deliveries
+        .into_iter()
+        .find(|delivery| delivery.status == WebhookDeliveryStatus::DeadLetter);
+      if delivery.is_some() {
+        break;
+      }
+      ntex::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+    let delivery = delivery.expect("delivery not dead-lettered");
+    assert_eq!(delivery.attempts, 2);
+    assert!(delivery.error.is_some());
+    assert!(delivery.next_attempt_at.is_none());
+    let res = client
+      .send_delete(&format!("{ENDPOINT}/{NAME}"), None::<String>)
+      .await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::ACCEPTED,
+      "webhook delete"
+    );
+    let res = client
+      .send_get(&format!("{ENDPOINT}/{NAME}/deliveries"), None::<String>)
+      .await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::NOT_FOUND,
+      "webhook deliveries after delete"
+    );
+  }
+}
@@ -0,0 +1,173 @@
+use std::{collections::HashMap, time::Duration};
+
+use ntex::{rt, time::interval};
+use futures_util::StreamExt;
+use bollard_next::container::StatsOptions;
+
+use nanocl_error::io::IoResult;
+
+use nanocl_stubs::{
+  cargo::Cargo,
+  cargo_spec::CargoAutoscale,
+  generic::GenericFilter,
+  process::Process,
+  system::{
+    EventActorKind, EventKind, EventPartial, NativeEventAction, ObjPsStatusKind,
+  },
+};
+
+use crate::{
+  vars, utils,
+  repositories::generic::*,
+  models::{CargoDb, SystemState},
+};
+
+/// Compute the average cpu and memory usage of the given instances.
+/// Return `None` when the stats of no instance could be read.
+async fn average_usage(
+  instances: &[Process],
+  state: &SystemState,
+) -> Option<(f64, f64)> {
+  let mut cpu = 0.0;
+  let mut memory = 0.0;
+  let mut count = 0.0;
+  for instance in instances {
+    let opts = StatsOptions {
+      stream: false,
+      one_shot: false,
+    };
+    let mut stream = state.inner.docker_api.stats(&instance.key, Some(opts));
+    let Some(Ok(stats)) = stream.next().await else {
+      continue;
+    };
+    cpu += utils::autoscale::cpu_percent(&stats);
+    memory += utils::autoscale::memory_percent(&stats);
+    count += 1.0;
+  }
+  if count == 0.0 {
+    return None;
+  }
+  Some((cpu / count, memory / count))
+}
+
+/// Evaluate the autoscaling policy of a cargo on the current node
+/// and scale his instances when needed.
+/// Return true when a scaling decision has been applied.
+async fn autoscale_cargo(
+  cargo: &Cargo,
+  autoscale: &CargoAutoscale,
+  state: &SystemState,
+) -> IoResult<bool> {
+  if utils::replication::local_replicas(cargo, state).await? == 0 {
+    return Ok(false);
+  }
+  let instances =
+    utils::replication::list_local_instances(cargo, state).await?;
+  let current = instances.len();
+  let Some((cpu, memory)) = average_usage(&instances, state).await else {
+    log::debug!(
+      "autoscale::autoscale_cargo: {} no usage sample, skipping",
+      cargo.spec.cargo_key
+    );
+    return Ok(false);
+  };
+  let wanted = utils::autoscale::gen_replicas(autoscale, current, cpu, memory);
+  if wanted == current {
+    return Ok(false);
+  }
+  // Wait for any pending task on the cargo to avoid data races,
+  // it may have changed the instances so they are listed again
+  let task_key = format!("{}@{}", EventActorKind::Cargo, cargo.spec.cargo_key);
+  state.inner.task_manager.wait_task(&task_key).await;
+  let instances =
+    utils::replication::list_local_instances(cargo, state).await?;
+  let current = instances.len();
+  let wanted = utils::autoscale::gen_replicas(autoscale, current, cpu, memory);
+  if wanted == current {
+    return Ok(false);
+  }
+  log::info!(
+    "autoscale::autoscale_cargo: {} from {current} to {wanted} instances",
+    cargo.spec.cargo_key
+  );
+  utils::replication::scale(cargo, &instances, wanted, state).await?;
+  let event = EventPartial {
+    reporting_controller: vars::CONTROLLER_NAME.to_owned(),
+    reporting_node: state.inner.config.hostname.clone(),
+    kind: EventKind::Normal,
+    action: NativeEventAction::Scale.to_string(),
+    related: None,
+    reason: "autoscale".to_owned(),
+    note: Some(format!("Scaled from {current} to {wanted} instances")),
+    metadata: Some(serde_json::json!({
+      "From": current,
+      "To": wanted,
+      "CpuPercent": cpu,
+      "MemoryPercent": memory,
+    })),
+    actor: Some(cargo.clone().into()),
+  };
+  state.spawn_emit_event(event);
+  Ok(true)
+}
+
+/// Evaluate the autoscaling policy of every running cargo
+/// Cargoes still in their cooldown period are skipped
+async fn autoscale_cargoes(
+  last_scales: &mut HashMap<String, std::time::Instant>,
+  state: &SystemState,
+) -> IoResult<()> {
+  let cargoes =
+    CargoDb::transform_read_by(&GenericFilter::new(), &state.inner.pool)
+      .await?;
+  for cargo in cargoes {
+    let Some(autoscale) = &cargo.spec.autoscale else {
+      continue;
+    };
+    if cargo.status.actual != ObjPsStatusKind::Start {
+      continue;
+    }
+    let cooldown = Duration::from_secs(
+      autoscale
+        .cooldown
+        .unwrap_or(utils::autoscale::DEFAULT_COOLDOWN),
+    );
+    if let Some(last_scale) = last_scales.get(&cargo.spec.cargo_key) {
+      if last_scale.elapsed() < cooldown {
+        continue;
+      }
+    }
+    match autoscale_cargo(&cargo, autoscale, state).await {
+      Err(err) => {
+        log::warn!(
+          "autoscale::autoscale_cargoes: {} {err}",
+          cargo.spec.cargo_key
+        );
+      }
+      Ok(true) => {
+        last_scales
+          .insert(cargo.spec.cargo_key.clone(), std::time::Instant::now());
+      }
+      Ok(false) => {}
+    }
+  }
+  Ok(())
+}
+
+/// Spawn a background thread that evaluate the autoscaling policies
+/// of the cargoes running on the current node.
+pub fn spawn(state: &SystemState) {
+  let state = state.clone();
+  rt::Arbiter::new().exec_fn(move || {
+    rt::spawn(async move {
+      let mut last_scales = HashMap::new();
+      let ticker = interval(Duration::from_secs(15));
+      loop {
+        ticker.tick().await;
+        if let Err(err) = autoscale_cargoes(&mut last_scales, &state).await {
+          log::warn!("autoscale::spawn: {err}");
+        }
+      }
+    });
+  });
+}
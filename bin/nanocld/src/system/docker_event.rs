@@ -13,7 +13,7 @@ use nanocl_stubs::system::{
 };
 
 use crate::{
-  vars,
+  vars, utils,
   repositories::generic::*,
   models::{ProcessDb, ProcessUpdateDb, SystemState},
 };
@@ -59,8 +59,8 @@ async fn exec_docker(
     kind: EventKind::Normal,
     action: NativeEventAction::Destroy.to_string(),
     related: Some(EventActor {
-      key: Some(kind_key),
-      kind,
+      key: Some(kind_key.clone()),
+      kind: kind.clone(),
       attributes: None,
     }),
     reason: "state_sync".to_owned(),
@@ -90,6 +90,16 @@ async fn exec_docker(
       action.clone_into(&mut event.action);
     }
   }
+  // The secret mounts are tmpfs emptied once no instance use them anymore
+  if action == "start" && kind == EventActorKind::Cargo {
+    let state = state.clone();
+    rt::spawn(async move {
+      if let Err(err) = utils::secret_mount::write_all(&kind_key, &state).await
+      {
+        log::warn!("event::exec_docker: {kind_key} {err}");
+      }
+    });
+  }
   state.spawn_emit_event(event);
   let instance = state
     .inner
@@ -5,74 +5,13 @@ use ntex::rt;
 use nanocl_error::io::IoResult;
 use nanocl_stubs::system::{
   Event, EventActor, EventActorKind, EventKind, NativeEventAction,
-  ObjPsStatusKind,
 };
 
 use crate::{
-  utils,
   tasks::generic::*,
-  objects::generic::*,
-  repositories::generic::*,
-  models::{CargoDb, JobDb, ObjPsStatusDb, ProcessDb, SystemState, VmDb},
+  models::{CargoDb, JobDb, SystemState, VmDb},
 };
 
-/// Remove a job after when finished and ttl is set
-async fn job_ttl(actor: &EventActor, state: &SystemState) -> IoResult<()> {
-  let attributes = actor.attributes.clone().unwrap_or_default();
-  let job_id = match attributes.get("io.nanocl.j") {
-    None => return Ok(()),
-    Some(job_id) => job_id.as_str().unwrap_or_default(),
-  };
-  log::debug!("event::job_ttl: {job_id}");
-  let job = JobDb::transform_read_by_pk(job_id, &state.inner.pool).await?;
-  match job.status.actual {
-    ObjPsStatusKind::Finish | ObjPsStatusKind::Fail => {
-      log::debug!("event::job_ttl: {job_id} is already done");
-      return Ok(());
-    }
-    _ => {}
-  }
-  let instances =
-    ProcessDb::read_by_kind_key(&job.name, &state.inner.pool).await?;
-  let (_, instance_failed, _, running) =
-    utils::container::count_status(&instances);
-  log::debug!(
-    "event::job_ttl: {} has {running} running instances",
-    job.name
-  );
-  if running != 0 {
-    return Ok(());
-  }
-  if instance_failed > 0 {
-    ObjPsStatusDb::update_actual_status(
-      &job.name,
-      &ObjPsStatusKind::Fail,
-      &state.inner.pool,
-    )
-    .await?;
-    state.emit_normal_native_action(&job, NativeEventAction::Fail);
-  } else {
-    ObjPsStatusDb::update_actual_status(
-      &job.name,
-      &ObjPsStatusKind::Finish,
-      &state.inner.pool,
-    )
-    .await?;
-    state.emit_normal_native_action(&job, NativeEventAction::Finish);
-  }
-  let ttl = match job.ttl {
-    None => return Ok(()),
-    Some(ttl) => ttl,
-  };
-  let state = state.clone();
-  rt::spawn(async move {
-    log::debug!("event::job_ttl: {} will be deleted in {ttl}s", job.name);
-    ntex::time::sleep(std::time::Duration::from_secs(ttl as u64)).await;
-    let _ = JobDb::del_obj_by_pk(&job.name, &(), &state).await;
-  });
-  Ok(())
-}
-
 fn start(
   key: &str,
   actor: &EventActor,
@@ -185,17 +124,22 @@ async fn _exec_event(e: &Event, state: &SystemState) -> IoResult<()> {
   // If a task is already running for this object, we wait for it to finish
   // This is to avoid data races conditions when manipulating an object
   let task_key = format!("{}@{key}", &actor.kind);
-  state.inner.task_manager.wait_task(&task_key).await;
   let action = NativeEventAction::from_str(e.action.as_str())?;
+  // A job may wait for its dependencies forever so it's cancelled instead
+  if actor.kind == EventActorKind::Job
+    && matches!(
+      action,
+      NativeEventAction::Stopping | NativeEventAction::Destroying
+    )
+  {
+    state.inner.task_manager.remove_task(&task_key).await;
+  }
+  state.inner.task_manager.wait_task(&task_key).await;
   let task: Option<ObjTaskFuture> = match action {
     NativeEventAction::Starting => start(&key, actor, state),
     NativeEventAction::Stopping => stop(&key, actor, state),
     NativeEventAction::Updating => update(&key, actor, state),
     NativeEventAction::Destroying => delete(&key, actor, state),
-    NativeEventAction::Die => {
-      job_ttl(actor, state).await?;
-      None
-    }
     _ => None,
   };
   let Some(task) = task else { return Ok(()) };
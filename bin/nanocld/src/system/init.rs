@@ -1,4 +1,4 @@
-use std::{path::Path, process::Command, os::unix::prelude::PermissionsExt};
+use std::{path::Path, os::unix::prelude::PermissionsExt};
 
 use ntex::rt;
 use tokio::fs;
@@ -81,30 +81,6 @@ fn set_uds_perm() {
   });
 }
 
-/// Create a new thread and spawn and manage a crond instance to run cron jobs
-fn spawn_crond() {
-  log::trace!("boot::spawn_crond: start thread");
-  rt::Arbiter::new().exec_fn(|| {
-    rt::spawn(async {
-      let task = ntex::web::block(move || {
-        match Command::new("crond").args(["-f"]).spawn() {
-          Ok(mut child) => {
-            child.wait()?;
-            Ok(())
-          }
-          Err(err) => Err(err),
-        }
-      })
-      .await;
-      if let Err(err) = task {
-        log::error!("boot::spawn_crond: {err}");
-      }
-      log::trace!("boot::spawn_crond: stop thread");
-      rt::Arbiter::current().stop();
-    });
-  });
-}
-
 /// Ensure that the state dir exists and is ready to use
 async fn ensure_state_dir(state_dir: &str) -> IoResult<()> {
   let vm_dir = format!("{state_dir}/vms/images");
@@ -117,11 +93,14 @@ async fn ensure_state_dir(state_dir: &str) -> IoResult<()> {
 /// Init function called before http server start.
 /// To boot and initialize our state and database.
 pub async fn init(conf: &DaemonConfig) -> IoResult<SystemState> {
-  spawn_crond();
   set_uds_perm();
   ensure_state_dir(&conf.state_dir).await?;
   let system_state = SystemState::new(conf).await?;
   let system_ptr = system_state.clone();
+  let sealed = utils::secret::seal_rows(&system_ptr.inner.pool).await?;
+  if sealed > 0 {
+    log::info!("boot::init: {sealed} secrets encrypted");
+  }
   NodeDb::register(&system_ptr).await?;
   utils::system::register_namespace("global", true, &system_ptr).await?;
   utils::system::register_namespace("system", false, &system_ptr).await?;
@@ -129,6 +108,10 @@ pub async fn init(conf: &DaemonConfig) -> IoResult<SystemState> {
     let fut = async move {
       utils::system::sync_processes(&system_ptr).await?;
       utils::system::sync_vm_images(&system_ptr).await?;
+      let resumed = utils::webhook::resume(&system_ptr).await?;
+      if resumed > 0 {
+        log::info!("boot::init: {resumed} webhook deliveries resumed");
+      }
       Ok::<_, IoError>(())
     };
     if let Err(err) = fut.await {
@@ -138,6 +121,10 @@ pub async fn init(conf: &DaemonConfig) -> IoResult<SystemState> {
   });
   super::docker_event::analyze(&system_state);
   super::metric::spawn(&system_state);
+  super::metric::spawn_downsample(&system_state);
+  super::replication::spawn(&system_state);
+  super::autoscale::spawn(&system_state);
+  super::scheduler::spawn(&system_state);
   Ok(system_state)
 }
 
@@ -8,6 +8,7 @@ use nanocl_error::io::IoResult;
 use metrsd_client::{MetrsdClient, stubs::MetrsdEvent};
 
 use crate::{
+  utils,
   repositories::generic::*,
   models::{Pool, SystemState, MetricDb, MetricNodePartial},
 };
@@ -94,3 +95,20 @@ pub fn spawn(state: &SystemState) {
     });
   });
 }
+
+/// Spawn a background thread that roll the old http metrics of the node
+/// into hourly summaries and delete the expired metrics every hour
+pub fn spawn_downsample(state: &SystemState) {
+  let state = state.clone();
+  rt::Arbiter::new().exec_fn(move || {
+    rt::spawn(async move {
+      let ticker = interval(Duration::from_secs(3600));
+      loop {
+        ticker.tick().await;
+        if let Err(err) = utils::metric::downsample(&state).await {
+          log::warn!("metric::spawn_downsample: {err}");
+        }
+      }
+    });
+  });
+}
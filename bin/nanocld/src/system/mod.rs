@@ -2,6 +2,9 @@ mod init;
 mod event;
 mod metric;
 mod docker_event;
+mod replication;
+mod autoscale;
+mod scheduler;
 mod system_state;
 
 pub use event::exec_event;
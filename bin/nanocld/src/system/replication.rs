@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use ntex::{rt, time::interval};
+
+use nanocl_error::io::IoResult;
+
+use nanocl_stubs::{
+  generic::GenericFilter,
+  system::{EventActorKind, ObjPsStatusKind},
+};
+
+use crate::{
+  utils,
+  repositories::generic::*,
+  models::{CargoDb, NodeDb, SystemState},
+};
+
+/// Get the sorted list of node names of the cluster
+async fn list_node_names(state: &SystemState) -> IoResult<Vec<String>> {
+  NodeDb::read_names(&state.inner.pool).await
+}
+
+/// Converge the instances of every running cargo on the current node
+async fn converge_cargoes(state: &SystemState) -> IoResult<()> {
+  let cargoes =
+    CargoDb::transform_read_by(&GenericFilter::new(), &state.inner.pool)
+      .await?;
+  for cargo in cargoes {
+    if cargo.status.wanted != ObjPsStatusKind::Start {
+      continue;
+    }
+    // Wait for any pending task on the cargo to avoid data races
+    let task_key =
+      format!("{}@{}", EventActorKind::Cargo, cargo.spec.cargo_key);
+    state.inner.task_manager.wait_task(&task_key).await;
+    if let Err(err) = utils::replication::converge(&cargo, state).await {
+      log::warn!(
+        "replication::converge_cargoes: {} {err}",
+        cargo.spec.cargo_key
+      );
+    }
+  }
+  Ok(())
+}
+
+/// Spawn a background thread that watch the nodes of the cluster
+/// and converge the cargo instances when a node join or leave.
+pub fn spawn(state: &SystemState) {
+  let state = state.clone();
+  rt::Arbiter::new().exec_fn(move || {
+    rt::spawn(async move {
+      let mut nodes = list_node_names(&state).await.unwrap_or_default();
+      let ticker = interval(Duration::from_secs(10));
+      loop {
+        ticker.tick().await;
+        let new_nodes = match list_node_names(&state).await {
+          Err(err) => {
+            log::warn!("replication::spawn: {err}");
+            continue;
+          }
+          Ok(new_nodes) => new_nodes,
+        };
+        if new_nodes == nodes {
+          continue;
+        }
+        log::info!("replication::spawn: nodes changed converging cargoes");
+        nodes = new_nodes;
+        if let Err(err) = converge_cargoes(&state).await {
+          log::warn!("replication::spawn: {err}");
+        }
+      }
+    });
+  });
+}
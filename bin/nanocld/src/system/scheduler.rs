@@ -0,0 +1,183 @@
+use std::{collections::HashMap, time::Duration};
+
+use ntex::{rt, time::interval};
+use chrono::{DateTime, Utc};
+
+use nanocl_error::io::IoResult;
+
+use nanocl_stubs::{
+  generic::{GenericClause, GenericFilter},
+  job::{Job, JobConcurrencyPolicy, JobRunStatus},
+  process::ProcessKind,
+  system::{EventActorKind, ObjPsStatusKind},
+};
+
+use crate::{
+  utils,
+  repositories::generic::*,
+  models::{JobDb, JobRunDb, SystemState},
+};
+
+/// Record a scheduled run of a job that is not started.
+/// Nothing is recorded when another node already handled the due time.
+async fn record(
+  job: &Job,
+  scheduled_at: &DateTime<Utc>,
+  status: JobRunStatus,
+  reason: &str,
+  state: &SystemState,
+) -> IoResult<()> {
+  let run = JobRunDb::new(
+    &job.name,
+    Some(scheduled_at.naive_utc()),
+    status.clone(),
+    Some(reason.to_owned()),
+  );
+  if JobRunDb::claim(run, &state.inner.pool).await? {
+    log::info!(
+      "scheduler::record: {} run due at {scheduled_at} {status}: {reason}",
+      job.name
+    );
+  }
+  Ok(())
+}
+
+/// Start a scheduled run of a job according to its concurrency policy.
+/// The run is claimed first so a due run is triggered by a single node.
+async fn trigger(
+  job: &Job,
+  scheduled_at: &DateTime<Utc>,
+  state: &SystemState,
+) -> IoResult<()> {
+  let running = matches!(
+    job.status.actual,
+    ObjPsStatusKind::Starting | ObjPsStatusKind::Start
+  );
+  let policy = job.concurrency_policy.clone().unwrap_or_default();
+  if running && policy == JobConcurrencyPolicy::Forbid {
+    return record(
+      job,
+      scheduled_at,
+      JobRunStatus::Skipped,
+      "the previous run is still running",
+      state,
+    )
+    .await;
+  }
+  let run = JobRunDb::new(
+    &job.name,
+    Some(scheduled_at.naive_utc()),
+    JobRunStatus::Pending,
+    None,
+  );
+  if !JobRunDb::claim(run.clone(), &state.inner.pool).await? {
+    return Ok(());
+  }
+  if running && policy == JobConcurrencyPolicy::Replace {
+    let task_key = format!("{}@{}", EventActorKind::Job, job.name);
+    state.inner.task_manager.remove_task(&task_key).await;
+    JobRunDb::end_running(
+      &job.name,
+      &JobRunStatus::Replaced,
+      format!("replaced by the run due at {scheduled_at}").as_str(),
+      &state.inner.pool,
+    )
+    .await?;
+    utils::container::stop_instances(&job.name, &ProcessKind::Job, state)
+      .await?;
+  }
+  // With the Allow policy the run is queued,
+  // its start task wait for the running one to end
+  utils::job::emit_run_starting(&run, state).await?;
+  Ok(())
+}
+
+/// Start or record the runs of a job that are due since the last tick.
+/// Only the latest due run is started, the older ones are recorded as missed.
+async fn schedule_job(
+  job: &Job,
+  schedule: &str,
+  since: &DateTime<Utc>,
+  now: &DateTime<Utc>,
+  state: &SystemState,
+) -> IoResult<()> {
+  let schedule = utils::cron::parse_schedule(schedule)?;
+  let time_zone = utils::cron::parse_time_zone(&job.time_zone)?;
+  let due = utils::cron::due_times(&schedule, &time_zone, since, now);
+  let Some((latest, missed)) = due.split_last() else {
+    return Ok(());
+  };
+  for scheduled_at in missed {
+    record(
+      job,
+      scheduled_at,
+      JobRunStatus::Missed,
+      "a later run is due",
+      state,
+    )
+    .await?;
+  }
+  let late = (*now - *latest).num_seconds();
+  match job.starting_deadline_seconds {
+    Some(deadline) if late > deadline as i64 => {
+      record(
+        job,
+        latest,
+        JobRunStatus::Missed,
+        format!("due {late}s ago, the starting deadline is {deadline}s")
+          .as_str(),
+        state,
+      )
+      .await
+    }
+    _ => trigger(job, latest, state).await,
+  }
+}
+
+/// Evaluate the schedule of every job.
+/// The last evaluated time of a job is read from its run history
+/// the first time so the runs missed while the daemon was down are recorded.
+async fn schedule_jobs(
+  last_ticks: &mut HashMap<String, DateTime<Utc>>,
+  state: &SystemState,
+) -> IoResult<()> {
+  let now = Utc::now();
+  let filter = GenericFilter::new()
+    .r#where("data", GenericClause::HasKey("Schedule".to_owned()));
+  let jobs = JobDb::transform_read_by(&filter, &state.inner.pool).await?;
+  last_ticks.retain(|name, _| jobs.iter().any(|job| job.name == *name));
+  for job in jobs {
+    let Some(schedule) = &job.schedule else {
+      continue;
+    };
+    let since = match last_ticks.get(&job.name) {
+      Some(since) => *since,
+      None => JobRunDb::read_last_scheduled(&job.name, &state.inner.pool)
+        .await?
+        .unwrap_or(job.created_at)
+        .and_utc(),
+    };
+    if let Err(err) = schedule_job(&job, schedule, &since, &now, state).await {
+      log::warn!("scheduler::schedule_jobs: {} {err}", job.name);
+    }
+    last_ticks.insert(job.name.clone(), now);
+  }
+  Ok(())
+}
+
+/// Spawn a background thread that start the jobs on their schedule
+pub fn spawn(state: &SystemState) {
+  let state = state.clone();
+  rt::Arbiter::new().exec_fn(move || {
+    rt::spawn(async move {
+      let mut last_ticks = HashMap::new();
+      let ticker = interval(Duration::from_secs(1));
+      loop {
+        ticker.tick().await;
+        if let Err(err) = schedule_jobs(&mut last_ticks, &state).await {
+          log::warn!("scheduler::spawn: {err}");
+        }
+      }
+    });
+  });
+}
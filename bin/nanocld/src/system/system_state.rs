@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
-use ntex::rt;
-use futures::channel::mpsc;
+use ntex::{rt, web, util::Bytes};
+use futures::{Stream, channel::mpsc};
 use futures_util::{SinkExt, StreamExt};
 
 use nanocl_error::io::{FromIo, IoError, IoResult};
@@ -19,7 +19,7 @@ use crate::{
   repositories::generic::*,
   models::{
     EventDb, RawEventEmitter, RawEventReceiver, SystemState, SystemStateInner,
-    TaskManager,
+    TaskManager, ProxyMetrics,
   },
 };
 
@@ -34,6 +34,7 @@ impl SystemState {
       bollard_next::API_DEFAULT_VERSION,
     )
     .map_err(|err| err.map_err_context(|| "Docker"))?;
+    utils::secret::init(conf)?;
     let pool = utils::store::init(conf).await?;
     let (sx, rx) = mpsc::unbounded();
     let system_state = SystemState {
@@ -44,6 +45,7 @@ impl SystemState {
         event_emitter: sx,
         event_emitter_raw: RawEventEmitter::new(),
         task_manager: TaskManager::new(),
+        proxy_metrics: ProxyMetrics::default(),
         version: vars::VERSION.to_owned(),
         arbiter: rt::Arbiter::new(),
       }),
@@ -61,6 +63,7 @@ impl SystemState {
             log::error!("system::run: raw emit {err}");
           }
           super::exec_event(&e, &self);
+          utils::webhook::dispatch(&e, &self);
         }
         Ok::<(), IoError>(())
       });
@@ -102,6 +105,20 @@ impl SystemState {
     self.inner.event_emitter_raw.subscribe(condition).await
   }
 
+  /// Subscribe an http client to the event loop after replaying
+  /// the persisted events since a cursor, the key of an event or a date
+  pub async fn subscribe_raw_since(
+    &self,
+    condition: Option<Vec<EventCondition>>,
+    since: &str,
+  ) -> IoResult<impl Stream<Item = Result<Bytes, web::Error>> + Unpin> {
+    // Subscribe before reading the store so no event is missed in between
+    let receiver = self.subscribe_raw(condition.clone()).await?;
+    let cursor = EventDb::parse_cursor(since, &self.inner.pool).await?;
+    let events = EventDb::stream_since(cursor, &self.inner.pool);
+    Ok(receiver.replay(events, condition))
+  }
+
   /// Emit a Error event action
   pub fn emit_error_native_action<A>(
     &self,
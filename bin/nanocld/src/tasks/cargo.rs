@@ -1,8 +1,7 @@
-use futures_util::StreamExt;
-use futures::stream::FuturesUnordered;
-use ntex::rt;
+use std::time::Duration;
+
 use bollard_next::container::{
-  RemoveContainerOptions, RenameContainerOptions, StopContainerOptions,
+  RemoveContainerOptions, StartContainerOptions, StopContainerOptions,
 };
 
 use nanocl_error::{
@@ -10,15 +9,18 @@ use nanocl_error::{
   http::{HttpError, HttpResult},
 };
 use nanocl_stubs::{
-  cargo_spec::ReplicationMode,
-  process::ProcessKind,
+  cargo::Cargo,
+  cargo_spec::{UpdateStrategy, UpdateStrategyKind},
+  process::{Process, ProcessKind},
   system::{NativeEventAction, ObjPsStatusKind},
 };
 
 use crate::{
   utils,
   repositories::generic::*,
-  models::{CargoDb, ObjPsStatusDb, ProcessDb, SystemState},
+  models::{
+    CargoDb, CargoUpdateDb, ObjPsStatusDb, ProcessDb, SpecDb, SystemState,
+  },
 };
 
 use super::generic::*;
@@ -32,16 +34,7 @@ impl ObjTaskStart for CargoDb {
     Box::pin(async move {
       let cargo =
         CargoDb::transform_read_by_pk(&key, &state.inner.pool).await?;
-      let processes =
-        ProcessDb::read_by_kind_key(&cargo.spec.cargo_key, &state.inner.pool)
-          .await?;
-      if processes.is_empty() {
-        let number = match &cargo.spec.replication {
-          Some(ReplicationMode::Static(replication)) => replication.number,
-          _ => 1,
-        };
-        utils::container::create_cargo(&cargo, number, &state).await?;
-      }
+      utils::replication::converge(&cargo, &state).await?;
       utils::container::start_instances(
         &cargo.spec.cargo_key,
         &ProcessKind::Cargo,
@@ -76,12 +69,168 @@ impl ObjTaskDelete for CargoDb {
       let cargo =
         CargoDb::transform_read_by_pk(&key, &state.inner.pool).await?;
       CargoDb::clear_by_pk(&key, &state.inner.pool).await?;
+      utils::secret_mount::remove_all(&key, &state).await;
       state.emit_normal_native_action(&cargo, NativeEventAction::Destroy);
       Ok::<_, IoError>(())
     })
   }
 }
 
+fn instance_keys(instances: &[Process]) -> Vec<String> {
+  instances.iter().map(|p| p.key.clone()).collect()
+}
+
+/// Create and start `number` new instances of a cargo
+/// and wait for them to be healthy.
+/// Created instances are pushed in `created` to be able to clean them on failure.
+/// The cargo must have been prepared by `prepare_cargo` first.
+async fn create_healthy_instances(
+  cargo: &Cargo,
+  number: usize,
+  deadline: Duration,
+  created: &mut Vec<String>,
+  state: &SystemState,
+) -> HttpResult<()> {
+  if number == 0 {
+    return Ok(());
+  }
+  let instances =
+    utils::container::create_cargo_instances(cargo, number, state).await?;
+  created.extend(instance_keys(&instances));
+  for instance in &instances {
+    state
+      .inner
+      .docker_api
+      .start_container(&instance.key, None::<StartContainerOptions<String>>)
+      .await?;
+  }
+  utils::container::wait_instances_healthy(&instances, deadline, state).await
+}
+
+/// Remove all the old instances then create the new ones
+async fn update_recreate(
+  cargo: &Cargo,
+  old: &[Process],
+  number: usize,
+  deadline: Duration,
+  created: &mut Vec<String>,
+  state: &SystemState,
+) -> HttpResult<()> {
+  utils::container::delete_instances(&instance_keys(old), state).await?;
+  create_healthy_instances(cargo, number, deadline, created, state).await
+}
+
+/// Create all the new instances and remove the old ones once they are healthy
+async fn update_blue_green(
+  cargo: &Cargo,
+  old: &[Process],
+  number: usize,
+  deadline: Duration,
+  created: &mut Vec<String>,
+  state: &SystemState,
+) -> HttpResult<()> {
+  create_healthy_instances(cargo, number, deadline, created, state).await?;
+  utils::container::delete_instances(&instance_keys(old), state).await
+}
+
+/// Replace the old instances by the batches of `utils::update::rolling_batches`
+async fn update_rolling(
+  cargo: &Cargo,
+  old: &[Process],
+  number: usize,
+  strategy: &UpdateStrategy,
+  deadline: Duration,
+  created: &mut Vec<String>,
+  state: &SystemState,
+) -> HttpResult<()> {
+  let batches = utils::update::rolling_batches(
+    number,
+    old.len(),
+    strategy
+      .max_surge
+      .unwrap_or(utils::update::DEFAULT_MAX_SURGE),
+    strategy
+      .max_unavailable
+      .unwrap_or(utils::update::DEFAULT_MAX_UNAVAILABLE),
+  );
+  let mut old = instance_keys(old);
+  for batch in batches {
+    let unavailable = old.drain(..batch.down).collect::<Vec<_>>();
+    utils::container::delete_instances(&unavailable, state).await?;
+    create_healthy_instances(cargo, batch.create, deadline, created, state)
+      .await?;
+    let replaced = old.drain(..batch.replace).collect::<Vec<_>>();
+    utils::container::delete_instances(&replaced, state).await?;
+  }
+  utils::container::delete_instances(&old, state).await
+}
+
+/// Replace the old instances of a cargo according to its update strategy
+async fn update_instances(
+  cargo: &Cargo,
+  old: &[Process],
+  number: usize,
+  strategy: &UpdateStrategy,
+  created: &mut Vec<String>,
+  state: &SystemState,
+) -> HttpResult<()> {
+  let deadline = Duration::from_secs(
+    strategy
+      .health_deadline
+      .unwrap_or(utils::update::DEFAULT_HEALTH_DEADLINE),
+  );
+  utils::container::prepare_cargo(cargo, state).await?;
+  match strategy.kind {
+    UpdateStrategyKind::Recreate => {
+      update_recreate(cargo, old, number, deadline, created, state).await
+    }
+    UpdateStrategyKind::BlueGreen => {
+      update_blue_green(cargo, old, number, deadline, created, state).await
+    }
+    UpdateStrategyKind::Rolling => {
+      update_rolling(cargo, old, number, strategy, deadline, created, state)
+        .await
+    }
+  }
+}
+
+/// Revert a cargo to his previous spec history entry
+/// The instances created during the failed update are removed
+/// and the instances are converged with the previous spec.
+async fn rollback(
+  cargo: &Cargo,
+  created: &[String],
+  state: &SystemState,
+) -> HttpResult<Cargo> {
+  utils::container::delete_instances(created, state).await?;
+  // Histories are sorted by creation date, the newest first
+  let histories =
+    SpecDb::read_by_kind_key("Cargo", &cargo.spec.cargo_key, &state.inner.pool)
+      .await?;
+  let previous = histories
+    .iter()
+    .skip_while(|spec| spec.key != cargo.spec.key)
+    .nth(1)
+    .ok_or_else(|| HttpError::not_found("No previous spec to rollback to"))?;
+  let new_item = CargoUpdateDb {
+    spec_key: Some(previous.key),
+    ..Default::default()
+  };
+  CargoDb::update_pk(&cargo.spec.cargo_key, new_item, &state.inner.pool)
+    .await?;
+  let cargo =
+    CargoDb::transform_read_by_pk(&cargo.spec.cargo_key, &state.inner.pool)
+      .await?;
+  utils::replication::converge(&cargo, state).await?;
+  utils::container::start_instances(
+    &cargo.spec.cargo_key,
+    &ProcessKind::Cargo,
+    state,
+  )
+  .await?;
+  Ok(cargo)
+}
+
 impl ObjTaskUpdate for CargoDb {
   fn create_update_task(key: &str, state: &SystemState) -> ObjTaskFuture {
     let key = key.to_owned();
@@ -89,109 +238,55 @@ impl ObjTaskUpdate for CargoDb {
     Box::pin(async move {
       let cargo =
         CargoDb::transform_read_by_pk(&key, &state.inner.pool).await?;
-      let processes =
-        ProcessDb::read_by_kind_key(&key, &state.inner.pool).await?;
-      // rename old instances to flag them for deletion
-      processes
-        .iter()
-        .map(|process| {
-          let docker_api = state.inner.docker_api.clone();
-          async move {
-            let new_name = format!("tmp-{}", process.name);
-            docker_api
-              .rename_container(
-                &process.key,
-                RenameContainerOptions { name: &new_name },
-              )
-              .await?;
-            Ok::<_, HttpError>(())
-          }
-        })
-        .collect::<FuturesUnordered<_>>()
-        .collect::<Vec<_>>()
-        .await
-        .into_iter()
-        .collect::<HttpResult<Vec<_>>>()?;
-      let number = match &cargo.spec.replication {
-        Some(ReplicationMode::Static(replication)) => replication.number,
-        _ => 1,
+      let old =
+        utils::replication::list_local_instances(&cargo, &state).await?;
+      let number =
+        utils::replication::wanted_replicas(&cargo, old.len(), &state).await?;
+      let strategy = cargo.spec.update_strategy.clone().unwrap_or_default();
+      let mut created = Vec::new();
+      let res =
+        update_instances(&cargo, &old, number, &strategy, &mut created, &state)
+          .await;
+      let Err(err) = res else {
+        ObjPsStatusDb::update_actual_status(
+          &key,
+          &ObjPsStatusKind::Start,
+          &state.inner.pool,
+        )
+        .await?;
+        state.emit_normal_native_action(&cargo, NativeEventAction::Start);
+        return Ok::<_, IoError>(());
       };
-      // Create instance with the new spec
-      let new_instances =
-        match utils::container::create_cargo(&cargo, number, &state).await {
-          Err(err) => {
-            log::warn!(
-              "Unable to create cargo instance {} : {err}",
-              cargo.spec.cargo_key
-            );
-            Vec::default()
-          }
-          Ok(instances) => instances,
-        };
-      // start created containers
-      match utils::container::start_instances(&key, &ProcessKind::Cargo, &state)
-        .await
-      {
-        Err(err) => {
-          log::error!(
-            "Unable to start cargo instance {} : {err}",
-            cargo.spec.cargo_key
-          );
-          let state_ptr_ptr = state.clone();
-          rt::spawn(async move {
-            ntex::time::sleep(std::time::Duration::from_secs(2)).await;
-            let _ = utils::container::delete_instances(
-              &new_instances
-                .iter()
-                .map(|p| p.key.clone())
-                .collect::<Vec<_>>(),
-              &state_ptr_ptr,
-            )
-            .await;
-            let res = processes
-              .iter()
-              .map(|process| {
-                let docker_api = state_ptr_ptr.inner.docker_api.clone();
-                async move {
-                  docker_api
-                    .rename_container(
-                      &process.key,
-                      RenameContainerOptions {
-                        name: &process.name,
-                      },
-                    )
-                    .await?;
-                  Ok::<_, HttpError>(())
-                }
-              })
-              .collect::<FuturesUnordered<_>>()
-              .collect::<Vec<_>>()
-              .await
-              .into_iter()
-              .collect::<HttpResult<Vec<_>>>();
-            if let Err(err) = res {
-              log::error!("Unable to rename containers back: {err}");
-            }
-          });
-        }
-        Ok(_) => {
-          // Delete old containers
-          let state_ptr_ptr = state.clone();
-          let _ = utils::container::delete_instances(
-            &processes.iter().map(|p| p.key.clone()).collect::<Vec<_>>(),
-            &state_ptr_ptr,
+      log::error!(
+        "Unable to update cargo instances {} : {err}",
+        cargo.spec.cargo_key
+      );
+      if !strategy.auto_rollback.unwrap_or_default() {
+        let _ = utils::container::delete_instances(&created, &state).await;
+        return Err(err.into());
+      }
+      // The task fails in both cases so the update is reported as an error
+      match rollback(&cargo, &created, &state).await {
+        Ok(prev_cargo) => {
+          ObjPsStatusDb::update_actual_status(
+            &key,
+            &ObjPsStatusKind::Start,
+            &state.inner.pool,
           )
-          .await;
+          .await?;
+          Err(IoError::interrupted(
+            "Cargo update",
+            &format!("{err}, rolled back to {}", prev_cargo.spec.key),
+          ))
+        }
+        Err(rollback_err) => {
+          let _ = utils::container::delete_instances(&created, &state).await;
+          Err(IoError::interrupted(
+            "Cargo update",
+            &format!("{err}, rollback failed: {rollback_err}"),
+          ))
         }
       }
-      ObjPsStatusDb::update_actual_status(
-        &key,
-        &ObjPsStatusKind::Start,
-        &state.inner.pool,
-      )
-      .await?;
-      state.emit_normal_native_action(&cargo, NativeEventAction::Start);
-      Ok::<_, IoError>(())
     })
   }
 }
@@ -1,18 +1,13 @@
-use futures_util::StreamExt;
-
-use bollard_next::container::{StartContainerOptions, WaitContainerOptions};
-
-use nanocl_error::{io::IoError, http::HttpError};
+use nanocl_error::io::IoError;
 
 use nanocl_stubs::{
-  process::ProcessKind,
-  system::{NativeEventAction, ObjPsStatusKind},
+  job::JobRunStatus, process::ProcessKind, system::NativeEventAction,
 };
 
 use crate::{
   utils,
   repositories::generic::*,
-  models::{JobDb, ObjPsStatusDb, ProcessDb, SystemState},
+  models::{JobDb, JobRunDb, ProcessDb, SystemState},
 };
 
 use super::generic::*;
@@ -25,39 +20,7 @@ impl ObjTaskStart for JobDb {
     let state = state.clone();
     Box::pin(async move {
       let job = JobDb::transform_read_by_pk(&key, &state.inner.pool).await?;
-      let mut processes =
-        ProcessDb::read_by_kind_key(&job.name, &state.inner.pool).await?;
-      if processes.is_empty() {
-        processes =
-          utils::container::create_job_instances(&job, &state).await?;
-      }
-      ObjPsStatusDb::update_actual_status(
-        &key,
-        &ObjPsStatusKind::Start,
-        &state.inner.pool,
-      )
-      .await?;
-      state.emit_normal_native_action(&job, NativeEventAction::Start);
-      for process in processes {
-        // We currently run a sequential order so we wait for the container to finish to start the next one.
-        let mut stream = state.inner.docker_api.wait_container(
-          &process.key,
-          Some(WaitContainerOptions {
-            condition: "not-running",
-          }),
-        );
-        let _ = state
-          .inner
-          .docker_api
-          .start_container(&process.key, None::<StartContainerOptions<String>>)
-          .await;
-        while let Some(stream) = stream.next().await {
-          let result = stream.map_err(HttpError::internal_server_error)?;
-          if result.status_code == 0 {
-            break;
-          }
-        }
-      }
+      utils::job::run(&job, &state).await?;
       Ok::<_, IoError>(())
     })
   }
@@ -80,9 +43,6 @@ impl ObjTaskDelete for JobDb {
       )
       .await?;
       JobDb::clear_by_pk(&job.name, &state.inner.pool).await?;
-      if job.schedule.is_some() {
-        utils::cron::remove_cron_rule(&job, &state).await?;
-      }
       state.emit_normal_native_action(&job, NativeEventAction::Destroy);
       Ok::<_, IoError>(())
     })
@@ -94,6 +54,13 @@ impl ObjTaskStop for JobDb {
     let key = key.to_owned();
     let state = state.clone();
     Box::pin(async move {
+      JobRunDb::end_running(
+        &key,
+        &JobRunStatus::Failed,
+        "the job have been stopped",
+        &state.inner.pool,
+      )
+      .await?;
       utils::container::stop_instances(&key, &ProcessKind::Job, &state).await?;
       Ok::<_, IoError>(())
     })
@@ -0,0 +1,138 @@
+use bollard_next::container::{MemoryStatsStats, Stats};
+
+use nanocl_error::http::{HttpError, HttpResult};
+
+use nanocl_stubs::cargo_spec::CargoAutoscale;
+
+/// Default delay in seconds between two scaling decisions
+pub const DEFAULT_COOLDOWN: u64 = 60;
+
+/// Ensure the autoscaling policy of a cargo is valid
+pub fn validate(autoscale: &CargoAutoscale) -> HttpResult<()> {
+  if autoscale.min_replicas > autoscale.max_replicas {
+    return Err(HttpError::bad_request(
+      "Autoscale MinReplicas cannot be greater than MaxReplicas",
+    ));
+  }
+  if autoscale.target_cpu_percent.is_none()
+    && autoscale.target_memory_percent.is_none()
+  {
+    return Err(HttpError::bad_request(
+      "Autoscale requires a TargetCpuPercent or a TargetMemoryPercent",
+    ));
+  }
+  for target in [
+    autoscale.target_cpu_percent,
+    autoscale.target_memory_percent,
+  ]
+  .into_iter()
+  .flatten()
+  {
+    if target == 0 || target > 100 {
+      return Err(HttpError::bad_request(
+        "Autoscale targets must be between 1 and 100 percent",
+      ));
+    }
+  }
+  Ok(())
+}
+
+/// Compute the cpu usage in percent of a container from his stats
+pub fn cpu_percent(stats: &Stats) -> f64 {
+  let cpu_delta = stats.cpu_stats.cpu_usage.total_usage as f64
+    - stats.precpu_stats.cpu_usage.total_usage as f64;
+  let system_cpu_delta = stats.cpu_stats.system_cpu_usage.unwrap_or_default()
+    as f64
+    - stats.precpu_stats.system_cpu_usage.unwrap_or_default() as f64;
+  let number_cpus = stats.cpu_stats.online_cpus.unwrap_or(1) as f64;
+  if cpu_delta <= 0.0 || system_cpu_delta <= 0.0 {
+    return 0.0;
+  }
+  (cpu_delta / system_cpu_delta) * number_cpus * 100.0
+}
+
+/// Compute the memory usage in percent of the memory limit of a container
+pub fn memory_percent(stats: &Stats) -> f64 {
+  let limit = stats.memory_stats.limit.unwrap_or_default() as f64;
+  if limit <= 0.0 {
+    return 0.0;
+  }
+  let mut used = stats.memory_stats.usage.unwrap_or_default() as f64;
+  if let Some(memory_stats) = &stats.memory_stats.stats {
+    used -= match memory_stats {
+      MemoryStatsStats::V1(mem_stat) => mem_stat.cache as f64,
+      MemoryStatsStats::V2(mem_stat) => mem_stat.inactive_file as f64,
+    };
+  }
+  (used.max(0.0) / limit) * 100.0
+}
+
+/// Compute the number of instances wanted from the average usages.
+/// For each target we compute `ceil(current * usage / target)`
+/// and keep the highest result bounded by the policy.
+pub fn gen_replicas(
+  autoscale: &CargoAutoscale,
+  current: usize,
+  cpu: f64,
+  memory: f64,
+) -> usize {
+  let current = current.max(1) as f64;
+  let wanted = [
+    autoscale.target_cpu_percent.map(|target| (target, cpu)),
+    autoscale
+      .target_memory_percent
+      .map(|target| (target, memory)),
+  ]
+  .into_iter()
+  .flatten()
+  .map(|(target, usage)| (current * usage / target as f64).ceil() as usize)
+  .max()
+  .unwrap_or(current as usize);
+  wanted.clamp(autoscale.min_replicas, autoscale.max_replicas)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn policy() -> CargoAutoscale {
+    CargoAutoscale {
+      min_replicas: 1,
+      max_replicas: 5,
+      target_cpu_percent: Some(50),
+      target_memory_percent: Some(80),
+      cooldown: None,
+    }
+  }
+
+  #[test]
+  fn replicas_bounds() {
+    let autoscale = policy();
+    assert_eq!(gen_replicas(&autoscale, 2, 50.0, 10.0), 2);
+    assert_eq!(gen_replicas(&autoscale, 2, 100.0, 10.0), 4);
+    assert_eq!(gen_replicas(&autoscale, 2, 10.0, 120.0), 3);
+    assert_eq!(gen_replicas(&autoscale, 4, 400.0, 10.0), 5);
+    assert_eq!(gen_replicas(&autoscale, 3, 0.0, 0.0), 1);
+  }
+
+  #[test]
+  fn validate_policy() {
+    assert!(validate(&policy()).is_ok());
+    let autoscale = CargoAutoscale {
+      min_replicas: 6,
+      ..policy()
+    };
+    assert!(validate(&autoscale).is_err());
+    let autoscale = CargoAutoscale {
+      target_cpu_percent: None,
+      target_memory_percent: None,
+      ..policy()
+    };
+    assert!(validate(&autoscale).is_err());
+    let autoscale = CargoAutoscale {
+      target_cpu_percent: Some(0),
+      ..policy()
+    };
+    assert!(validate(&autoscale).is_err());
+  }
+}
@@ -0,0 +1,24 @@
+/// Delay in seconds before the next attempt once `attempts` have failed.
+/// It start at `backoff` and is doubled after each failure up to `max_backoff`.
+pub fn delay(backoff: u64, max_backoff: u64, attempts: u32) -> u64 {
+  let factor = 1u64
+    .checked_shl(attempts.saturating_sub(1))
+    .unwrap_or(u64::MAX);
+  backoff.saturating_mul(factor).min(max_backoff)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn exponential() {
+    assert_eq!(delay(2, 30, 0), 2);
+    assert_eq!(delay(2, 30, 1), 2);
+    assert_eq!(delay(2, 30, 2), 4);
+    assert_eq!(delay(2, 30, 4), 16);
+    assert_eq!(delay(2, 30, 5), 30);
+    assert_eq!(delay(2, 30, 80), 30);
+    assert_eq!(delay(u64::MAX, u64::MAX, 3), u64::MAX);
+  }
+}
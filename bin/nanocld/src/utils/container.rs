@@ -10,7 +10,10 @@ use bollard_next::{
     RemoveContainerOptions, StartContainerOptions, StopContainerOptions,
     WaitContainerOptions,
   },
-  service::{DeviceMapping, HostConfig, RestartPolicy, RestartPolicyNameEnum},
+  service::{
+    DeviceMapping, HealthStatusEnum, HostConfig, RestartPolicy,
+    RestartPolicyNameEnum,
+  },
 };
 use nanocl_error::{
   http::{HttpError, HttpResult},
@@ -20,7 +23,7 @@ use nanocl_error::{
 use nanocl_stubs::{
   cargo::{Cargo, CargoKillOptions},
   generic::{GenericClause, GenericFilter, ImagePullPolicy},
-  job::Job,
+  job::{Job, JobStep},
   process::{Process, ProcessKind, ProcessPartial},
   system::{
     EventActor, EventActorKind, EventKind, EventPartial, NativeEventAction,
@@ -73,7 +76,8 @@ where
   }
   let credentials = match secret {
     Some(secret) => {
-      let secret = SecretDb::read_by_pk(&secret, &state.inner.pool).await?;
+      let secret =
+        SecretDb::transform_read_by_pk(&secret, &state.inner.pool).await?;
       serde_json::from_value::<DockerCredentials>(secret.data)
         .map(Some)
         .map_err(|err| HttpError::bad_request(err.to_string()))?
@@ -322,14 +326,12 @@ async fn execute_cargo_before(
   }
 }
 
-/// Create instances (containers) based on the cargo spec
-/// The number of containers created is based on the number of instances defined in the cargo spec
-/// Example: cargo-key-(random-id), cargo-key-(random-id1), cargo-key-(random-id2)
-pub async fn create_cargo(
+/// Run the init container of a cargo and pull its image
+/// before instances are created
+pub async fn prepare_cargo(
   cargo: &Cargo,
-  number: usize,
   state: &SystemState,
-) -> HttpResult<Vec<Process>> {
+) -> HttpResult<()> {
   execute_cargo_before(cargo, state).await?;
   download_image(
     &cargo.spec.container.image.clone().unwrap_or_default(),
@@ -338,12 +340,35 @@ pub async fn create_cargo(
     cargo,
     state,
   )
-  .await?;
+  .await
+}
+
+/// Create instances (containers) based on the cargo spec
+/// The number of containers created is based on the number of instances defined in the cargo spec
+/// Example: cargo-key-(random-id), cargo-key-(random-id1), cargo-key-(random-id2)
+pub async fn create_cargo(
+  cargo: &Cargo,
+  number: usize,
+  state: &SystemState,
+) -> HttpResult<Vec<Process>> {
+  prepare_cargo(cargo, state).await?;
+  create_cargo_instances(cargo, number, state).await
+}
+
+/// Create instances of a cargo already prepared by `prepare_cargo`
+pub async fn create_cargo_instances(
+  cargo: &Cargo,
+  number: usize,
+  state: &SystemState,
+) -> HttpResult<Vec<Process>> {
   let mut secret_envs: Vec<String> = Vec::new();
   if let Some(secrets) = &cargo.spec.secrets {
     let filter = GenericFilter::new()
       .r#where("key", GenericClause::In(secrets.clone()))
-      .r#where("kind", GenericClause::Eq("nanocl.io/env".to_owned()));
+      .r#where(
+        "kind",
+        GenericClause::Eq(super::secret::KIND_ENV.to_owned()),
+      );
     let secrets = SecretDb::transform_read_by(&filter, &state.inner.pool)
       .await?
       .into_iter()
@@ -355,11 +380,13 @@ pub async fn create_cargo(
     // Flatten the secrets to have envs in a single vector
     secret_envs = secrets.into_iter().flatten().collect();
   }
+  let secret_binds = super::secret_mount::create_volumes(cargo, state).await?;
   (0..number)
     .collect::<Vec<usize>>()
     .into_iter()
     .map(move |current| {
       let secret_envs = secret_envs.clone();
+      let secret_binds = secret_binds.clone();
       async move {
         let ordinal_index = if current > 0 {
           current.to_string()
@@ -410,6 +437,9 @@ pub async fn create_cargo(
           None => format!("{}{}", ordinal_index, cargo.spec.name),
           Some(hostname) => format!("{}{}", ordinal_index, hostname),
         };
+        // Bind the secret mounts in read only
+        let mut binds = host_config.binds.clone().unwrap_or_default();
+        binds.extend(secret_binds);
         let new_process = bollard_next::container::Config {
           attach_stderr: Some(true),
           attach_stdout: Some(true),
@@ -419,6 +449,7 @@ pub async fn create_cargo(
           env: Some(env),
           host_config: Some(HostConfig {
             restart_policy,
+            binds: Some(binds),
             network_mode: Some(
                 host_config
                 .network_mode
@@ -552,7 +583,7 @@ pub async fn stop_instances(
   for process in processes {
     let process_state = process.data.state.unwrap_or_default();
     if !process_state.running.unwrap_or_default() {
-      return Ok(());
+      continue;
     }
     state
       .inner
@@ -612,6 +643,62 @@ pub async fn start_instances(
   Ok(())
 }
 
+/// Wait for the given instances (containers) to be healthy.
+/// When a container define a healthcheck we wait for docker to report it healthy
+/// otherwise we wait for it to be running without restarting.
+/// Return an error if an instance fail or if the deadline is reached.
+pub async fn wait_instances_healthy(
+  instances: &[Process],
+  deadline: std::time::Duration,
+  state: &SystemState,
+) -> HttpResult<()> {
+  let started_at = std::time::Instant::now();
+  loop {
+    let mut healthy = 0;
+    for instance in instances {
+      let inspect = state
+        .inner
+        .docker_api
+        .inspect_container(&instance.key, None::<InspectContainerOptions>)
+        .await?;
+      let container_state = inspect.state.unwrap_or_default();
+      match container_state.health.and_then(|health| health.status) {
+        Some(HealthStatusEnum::HEALTHY) => healthy += 1,
+        Some(HealthStatusEnum::UNHEALTHY) => {
+          return Err(HttpError::internal_server_error(format!(
+            "Instance {} is unhealthy",
+            instance.name
+          )));
+        }
+        Some(HealthStatusEnum::STARTING) => {}
+        _ => {
+          let running = container_state.running.unwrap_or_default();
+          let restarting = container_state.restarting.unwrap_or_default();
+          if restarting || container_state.dead.unwrap_or_default() {
+            return Err(HttpError::internal_server_error(format!(
+              "Instance {} failed to start",
+              instance.name
+            )));
+          }
+          if running {
+            healthy += 1;
+          }
+        }
+      }
+    }
+    if healthy == instances.len() {
+      return Ok(());
+    }
+    if started_at.elapsed() > deadline {
+      return Err(HttpError::internal_server_error(format!(
+        "Instances are not healthy after {}s",
+        deadline.as_secs()
+      )));
+    }
+    ntex::time::sleep(std::time::Duration::from_secs(1)).await;
+  }
+}
+
 /// Count the status for the given instances
 /// Return a tuple with the total, failed, success and running instances
 pub fn count_status(instances: &[Process]) -> (usize, usize, usize, usize) {
@@ -757,43 +844,36 @@ pub async fn create_vm_instance(
   Ok(process)
 }
 
-/// Create process (container) for a job
-async fn create_job_instance(
-  name: &str,
+/// Create the process (container) of a job step, its image is downloaded first
+pub async fn create_job_instance(
+  job: &Job,
   index: usize,
-  container: &Config,
+  step: &JobStep,
   state: &SystemState,
 ) -> HttpResult<Process> {
-  let mut container = container.clone();
+  let mut container = step.container.clone();
+  download_image(
+    &container.image.clone().unwrap_or_default(),
+    job.image_pull_secret.clone(),
+    job.image_pull_policy.clone().unwrap_or_default(),
+    job,
+    state,
+  )
+  .await?;
   let mut labels = container.labels.clone().unwrap_or_default();
-  labels.insert("io.nanocl.j".to_owned(), name.to_owned());
+  labels.insert("io.nanocl.j".to_owned(), job.name.clone());
+  labels.insert(super::job::STEP_LABEL.to_owned(), step.name.clone());
   container.labels = Some(labels);
   let short_id = super::key::generate_short_id(6);
-  let container_name = format!("{name}-{index}-{short_id}.j");
-  create_instance(&ProcessKind::Job, &container_name, name, &container, state)
-    .await
-}
-
-/// Create processes (container) for a job
-pub async fn create_job_instances(
-  job: &Job,
-  state: &SystemState,
-) -> HttpResult<Vec<Process>> {
-  let mut processes = Vec::new();
-  for (index, container) in job.containers.iter().enumerate() {
-    download_image(
-      &container.image.clone().unwrap_or_default(),
-      job.image_pull_secret.clone(),
-      job.image_pull_policy.clone().unwrap_or_default(),
-      job,
-      state,
-    )
-    .await?;
-    let process =
-      create_job_instance(&job.name, index, container, state).await?;
-    processes.push(process);
-  }
-  Ok(processes)
+  let container_name = format!("{}-{index}-{short_id}.j", job.name);
+  create_instance(
+    &ProcessKind::Job,
+    &container_name,
+    &job.name,
+    &container,
+    state,
+  )
+  .await
 }
 
 /// Emit a starting event to the system for the related process object (job, cargo, vm)
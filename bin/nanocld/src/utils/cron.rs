@@ -1,82 +1,109 @@
-use ntex::web;
-use tokio::{fs, io::AsyncWriteExt};
+use std::str::FromStr;
 
-use nanocl_error::io::{FromIo, IoError, IoResult};
-use nanocl_stubs::job::Job;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 
-use crate::{vars, models::SystemState};
+use nanocl_error::io::{IoError, IoResult};
 
-/// Format the cron job command to start a job at a given time
-fn format_cron_job_command(job: &Job, state: &SystemState) -> String {
-  let host = state
-    .inner
-    .config
-    .hosts
-    .first()
-    .cloned()
-    .unwrap_or("unix:///run/nanocl/nanocl.sock".to_owned())
-    .replace("unix://", "");
-  format!(
-    "curl -X POST --unix {host} http://localhost/v{}/processes/job/{}/start",
-    vars::VERSION,
-    &job.name
-  )
+/// Maximum number of due times computed at once after a long downtime,
+/// the latest due time is always included
+pub const MAX_DUE_TIMES: usize = 100;
+
+/// Parse a cron expression, the seconds field is optional
+/// so the usual crontab syntax is accepted
+pub fn parse_schedule(expr: &str) -> IoResult<cron::Schedule> {
+  let expr = expr.trim();
+  let full_expr = match expr.split_whitespace().count() {
+    5 => format!("0 {expr}"),
+    _ => expr.to_owned(),
+  };
+  cron::Schedule::from_str(&full_expr).map_err(|err| {
+    IoError::invalid_input("Schedule", format!("{expr}: {err}").as_str())
+  })
 }
 
-/// Execute the crontab command to update the cron jobs
-async fn exec_crontab() -> IoResult<()> {
-  web::block(|| {
-    std::process::Command::new("crontab")
-      .arg("/tmp/crontab")
-      .output()
-      .map_err(|err| err.map_err_context(|| "Cron job"))?;
-    Ok::<_, IoError>(())
+/// Parse the time zone of a schedule, default to `UTC`
+pub fn parse_time_zone(time_zone: &Option<String>) -> IoResult<Tz> {
+  let Some(time_zone) = time_zone else {
+    return Ok(Tz::UTC);
+  };
+  Tz::from_str(time_zone).map_err(|err| {
+    IoError::invalid_input("TimeZone", format!("{time_zone}: {err}").as_str())
   })
-  .await?;
-  Ok(())
 }
 
-/// Add a cron rule to the crontab to start a job at a given time
-pub async fn add_cron_rule(
-  item: &Job,
-  schedule: &str,
-  state: &SystemState,
-) -> IoResult<()> {
-  let cmd = format_cron_job_command(item, state);
-  let cron_rule = format!("{} {cmd}", schedule);
-  log::debug!("Creating cron rule: {cron_rule}");
-  fs::copy("/var/spool/cron/crontabs/root", "/tmp/crontab")
-    .await
-    .map_err(|err| err.map_err_context(|| "Cron job"))?;
-  let mut file = fs::OpenOptions::new()
-    .write(true)
-    .append(true)
-    .open("/tmp/crontab")
-    .await
-    .map_err(|err| err.map_err_context(|| "Cron job"))?;
-  file
-    .write_all(format!("{cron_rule}\n").as_bytes())
-    .await
-    .map_err(|err| err.map_err_context(|| "Cron job"))?;
-  exec_crontab().await?;
-  Ok(())
+/// List the times a schedule was due after `since` until `now`
+pub fn due_times(
+  schedule: &cron::Schedule,
+  time_zone: &Tz,
+  since: &DateTime<Utc>,
+  now: &DateTime<Utc>,
+) -> Vec<DateTime<Utc>> {
+  let mut due = schedule
+    .after(&since.with_timezone(time_zone))
+    .map(|date| date.with_timezone(&Utc))
+    .take_while(|date| date <= now)
+    .take(MAX_DUE_TIMES)
+    .collect::<Vec<_>>();
+  if due.len() < MAX_DUE_TIMES {
+    return due;
+  }
+  let latest = schedule
+    .after(&now.with_timezone(time_zone))
+    .next_back()
+    .map(|date| date.with_timezone(&Utc));
+  if let Some(latest) = latest {
+    if due.last().is_some_and(|last| *last < latest) {
+      due.push(latest);
+    }
+  }
+  due
 }
 
-/// Remove a cron rule from the crontab for the given job
-pub async fn remove_cron_rule(item: &Job, state: &SystemState) -> IoResult<()> {
-  let mut content = fs::read_to_string("/var/spool/cron/crontabs/root")
-    .await
-    .map_err(|err| err.map_err_context(|| "Cron job"))?;
-  let cmd = format_cron_job_command(item, state);
-  log::debug!("Removing cron rule: {cmd}");
-  content = content
-    .lines()
-    .filter(|line| !line.contains(&cmd))
-    .collect::<Vec<_>>()
-    .join("\n");
-  fs::write("/tmp/crontab", format!("{content}\n"))
-    .await
-    .map_err(|err| err.map_err_context(|| "Cron job"))?;
-  exec_crontab().await?;
-  Ok(())
+#[cfg(test)]
+mod tests {
+  use chrono::TimeZone;
+
+  use super::*;
+
+  #[test]
+  fn schedule() {
+    assert!(parse_schedule("*/5 * * * *").is_ok());
+    assert!(parse_schedule("0 */5 * * * *").is_ok());
+    assert!(parse_schedule("@daily").is_ok());
+    assert!(parse_schedule("not a schedule").is_err());
+    assert_eq!(parse_time_zone(&None).unwrap(), Tz::UTC);
+    assert!(parse_time_zone(&Some("Europe/Paris".to_owned())).is_ok());
+    assert!(parse_time_zone(&Some("Mars/Olympus".to_owned())).is_err());
+  }
+
+  #[test]
+  fn due() {
+    let schedule = parse_schedule("0 9 * * *").unwrap();
+    let since = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    let now = Utc.with_ymd_and_hms(2024, 1, 3, 12, 0, 0).unwrap();
+    let due = due_times(&schedule, &Tz::UTC, &since, &now);
+    assert_eq!(
+      due,
+      vec![
+        Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap(),
+        Utc.with_ymd_and_hms(2024, 1, 2, 9, 0, 0).unwrap(),
+        Utc.with_ymd_and_hms(2024, 1, 3, 9, 0, 0).unwrap(),
+      ]
+    );
+    // 9:00 in Paris is 8:00 UTC in winter
+    let paris = parse_time_zone(&Some("Europe/Paris".to_owned())).unwrap();
+    let due = due_times(&schedule, &paris, &since, &now);
+    assert_eq!(
+      due.first(),
+      Some(&Utc.with_ymd_and_hms(2024, 1, 1, 8, 0, 0).unwrap())
+    );
+    let since = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+    let due = due_times(&schedule, &Tz::UTC, &since, &now);
+    assert_eq!(due.len(), MAX_DUE_TIMES + 1);
+    assert_eq!(
+      due.last(),
+      Some(&Utc.with_ymd_and_hms(2024, 1, 3, 9, 0, 0).unwrap())
+    );
+  }
 }
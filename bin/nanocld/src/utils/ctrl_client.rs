@@ -6,6 +6,8 @@ use nanocl_error::io::FromIo;
 use nanocl_error::http::HttpError;
 use nanocl_error::http_client::HttpClientError;
 
+use nanocl_stubs::resource::ResourceValidation;
+
 /// Controller client
 pub struct CtrlClient {
   /// Name of the controller eg: (ProxyRule)
@@ -113,6 +115,26 @@ impl CtrlClient {
     self.res_json(&mut res).await
   }
 
+  /// Call validate rule method on controller
+  pub async fn validate_rule(
+    &self,
+    version: &str,
+    name: &str,
+    data: &serde_json::Value,
+  ) -> Result<ResourceValidation, HttpClientError> {
+    let url = self.format_url(&format!("/{version}/rules/{name}/validate"));
+    log::debug!("CtrlClient::validate_rule url: {}", url);
+    let mut res = self
+      .client
+      .post(url)
+      .send_json(data)
+      .await
+      .map_err(|err| err.map_err_context(|| self.name.to_owned()))?;
+    let status = res.status();
+    self.is_api_error(&mut res, &status).await?;
+    self.res_json(&mut res).await
+  }
+
   /// Call delete rule method on controller
   pub async fn delete_rule(
     &self,
@@ -0,0 +1,696 @@
+use std::collections::{HashMap, HashSet};
+
+use ntex::rt;
+use futures_util::{StreamExt, stream::FuturesUnordered};
+use bollard_next::container::{
+  KillContainerOptions, StartContainerOptions, WaitContainerOptions,
+};
+
+use nanocl_error::{
+  io::{IoError, IoResult},
+  http::{HttpError, HttpResult},
+};
+
+use nanocl_stubs::{
+  generic::{GenericClause, GenericFilter},
+  job::{
+    Job, JobDependency, JobDependencyKind, JobPartial, JobRunStatus, JobStep,
+  },
+  process::{Process, ProcessKind},
+  system::{NativeEventAction, ObjPsStatusKind},
+};
+
+use crate::{
+  utils,
+  objects::generic::*,
+  repositories::generic::*,
+  models::{JobDb, JobRunDb, ObjPsStatusDb, ProcessDb, SystemState},
+};
+
+/// Label of the job containers with the name of their step
+pub const STEP_LABEL: &str = "io.nanocl.j.step";
+
+/// Default delay in seconds before the first retry of a job
+pub const DEFAULT_BACKOFF: u64 = 10;
+
+/// Default maximum delay in seconds between two attempts of a job
+pub const DEFAULT_MAX_BACKOFF: u64 = 360;
+
+/// Default delay in seconds to wait for a dependency of a job to be ready
+pub const DEFAULT_DEPENDENCY_TIMEOUT: u64 = 600;
+
+/// Convert the containers and the steps of a job into a list of steps.
+/// A container is a step named by its index depending on the previous one.
+pub fn gen_steps(
+  containers: &[bollard_next::container::Config],
+  steps: &Option<Vec<JobStep>>,
+) -> Vec<JobStep> {
+  let mut res = containers
+    .iter()
+    .enumerate()
+    .map(|(index, container)| JobStep {
+      name: index.to_string(),
+      depends_on: index.checked_sub(1).map(|prev| vec![prev.to_string()]),
+      retry: None,
+      container: container.clone(),
+    })
+    .collect::<Vec<_>>();
+  res.extend(steps.clone().unwrap_or_default());
+  res
+}
+
+/// Sort the steps so every step come after the steps it depends on.
+/// Fail if a step depends on an unknown step or if there is a cycle.
+pub fn sort_steps(steps: &[JobStep]) -> IoResult<Vec<usize>> {
+  let mut indexes = HashMap::new();
+  for (index, step) in steps.iter().enumerate() {
+    if indexes.insert(step.name.as_str(), index).is_some() {
+      return Err(IoError::invalid_input(
+        "Job step",
+        format!("{} is duplicated", step.name).as_str(),
+      ));
+    }
+  }
+  let mut degrees = vec![0; steps.len()];
+  let mut dependents = vec![Vec::new(); steps.len()];
+  for (index, step) in steps.iter().enumerate() {
+    for dependency in step.depends_on.clone().unwrap_or_default() {
+      let Some(dependency) = indexes.get(dependency.as_str()) else {
+        return Err(IoError::invalid_input(
+          "Job step",
+          format!("{} depends on unknown step {dependency}", step.name)
+            .as_str(),
+        ));
+      };
+      degrees[index] += 1;
+      dependents[*dependency].push(index);
+    }
+  }
+  let mut ready = (0..steps.len())
+    .filter(|index| degrees[*index] == 0)
+    .collect::<Vec<_>>();
+  let mut order = Vec::with_capacity(steps.len());
+  while let Some(index) = ready.pop() {
+    order.push(index);
+    for dependent in &dependents[index] {
+      degrees[*dependent] -= 1;
+      if degrees[*dependent] == 0 {
+        ready.push(*dependent);
+      }
+    }
+  }
+  if order.len() != steps.len() {
+    return Err(IoError::invalid_input(
+      "Job step",
+      "the dependencies between the steps contain a cycle",
+    ));
+  }
+  Ok(order)
+}
+
+/// Validate the steps, the schedule and the dependencies of a new job
+pub fn validate(job: &JobPartial) -> HttpResult<()> {
+  sort_steps(&gen_steps(&job.containers, &job.steps))?;
+  if let Some(schedule) = &job.schedule {
+    utils::cron::parse_schedule(schedule)?;
+  }
+  utils::cron::parse_time_zone(&job.time_zone)?;
+  Ok(())
+}
+
+/// Names of the jobs a job depends on
+fn job_dependencies(depends_on: &Option<Vec<JobDependency>>) -> Vec<String> {
+  depends_on
+    .iter()
+    .flatten()
+    .filter(|dependency| dependency.kind == JobDependencyKind::Job)
+    .map(|dependency| dependency.name.clone())
+    .collect()
+}
+
+/// Find a path of dependencies going from the job `name` back to itself
+fn find_cycle(
+  name: &str,
+  graph: &HashMap<String, Vec<String>>,
+) -> Option<Vec<String>> {
+  let mut visited = HashSet::new();
+  let mut stack = vec![vec![name.to_owned()]];
+  while let Some(path) = stack.pop() {
+    let last = path.last()?;
+    for dependency in graph.get(last).into_iter().flatten() {
+      let mut next = path.clone();
+      next.push(dependency.clone());
+      if dependency == name {
+        return Some(next);
+      }
+      if visited.insert(dependency.clone()) {
+        stack.push(next);
+      }
+    }
+  }
+  None
+}
+
+/// Ensure the jobs a new job depends on don't depend on it in return.
+/// The missing jobs are ignored, they may be created later.
+pub async fn validate_dependencies(
+  job: &JobPartial,
+  state: &SystemState,
+) -> HttpResult<()> {
+  let mut graph = HashMap::new();
+  let mut pending = job_dependencies(&job.depends_on);
+  graph.insert(job.name.clone(), pending.clone());
+  while let Some(name) = pending.pop() {
+    if graph.contains_key(&name) {
+      continue;
+    }
+    let filter =
+      GenericFilter::new().r#where("key", GenericClause::Eq(name.clone()));
+    let dependencies = JobDb::transform_read_by(&filter, &state.inner.pool)
+      .await?
+      .first()
+      .map(|dependency| job_dependencies(&dependency.depends_on))
+      .unwrap_or_default();
+    pending.extend(dependencies.iter().cloned());
+    graph.insert(name, dependencies);
+  }
+  if let Some(cycle) = find_cycle(&job.name, &graph) {
+    return Err(HttpError::bad_request(format!(
+      "Job dependencies contain a cycle: {}",
+      cycle.join(" -> ")
+    )));
+  }
+  Ok(())
+}
+
+/// Return if an object is missing from the store,
+/// the dependencies may be created after the job.
+fn is_missing(err: &IoError) -> bool {
+  err.inner.kind() == std::io::ErrorKind::NotFound
+}
+
+/// Wait until a job succeeded a run ended after the given date.
+/// The job is started when it isn't pending or running.
+/// Fail when the run started for the dependency failed.
+async fn wait_job(
+  name: &str,
+  since: &chrono::NaiveDateTime,
+  state: &SystemState,
+) -> IoResult<()> {
+  let pool = &state.inner.pool;
+  let mut started = false;
+  loop {
+    let status = match ObjPsStatusDb::read_by_pk(name, pool).await {
+      Err(err) if is_missing(&err) => {
+        ntex::time::sleep(std::time::Duration::from_secs(1)).await;
+        continue;
+      }
+      Err(err) => return Err(err),
+      Ok(status) => status,
+    };
+    let succeeded =
+      JobRunDb::read_ended_since(name, &JobRunStatus::Succeeded, since, pool)
+        .await?;
+    if !succeeded.is_empty() {
+      return Ok(());
+    }
+    // A job waiting for its next attempt has no pending or running run
+    let active = status.actual == ObjPsStatusKind::Start.to_string()
+      || JobRunDb::is_active(name, pool).await?;
+    if started && !active && status.actual == ObjPsStatusKind::Fail.to_string()
+    {
+      return Err(IoError::interrupted(
+        "Job dependency",
+        format!("{name} failed").as_str(),
+      ));
+    }
+    if !started && !active {
+      log::debug!("job::wait_job: starting {name}");
+      emit_starting(name, state).await.map_err(|err| {
+        IoError::interrupted("Job dependency", err.to_string().as_str())
+      })?;
+      started = true;
+    }
+    ntex::time::sleep(std::time::Duration::from_secs(1)).await;
+  }
+}
+
+/// Wait until a cargo is started
+async fn wait_cargo(key: &str, state: &SystemState) -> IoResult<()> {
+  loop {
+    match ObjPsStatusDb::read_by_pk(key, &state.inner.pool).await {
+      Err(err) if is_missing(&err) => {}
+      Err(err) => return Err(err),
+      Ok(status) => {
+        if status.actual == ObjPsStatusKind::Start.to_string() {
+          return Ok(());
+        }
+        if status.actual == ObjPsStatusKind::Fail.to_string() {
+          return Err(IoError::interrupted(
+            "Job dependency",
+            format!("{key} failed").as_str(),
+          ));
+        }
+      }
+    }
+    ntex::time::sleep(std::time::Duration::from_secs(1)).await;
+  }
+}
+
+/// Wait until the jobs the job depends on succeeded a run ended after
+/// the given date and the cargoes are started.
+/// The jobs that are not pending or running are started,
+/// the missing dependencies are waited until their timeout.
+async fn wait_dependencies(
+  job: &Job,
+  since: &chrono::NaiveDateTime,
+  state: &SystemState,
+) -> IoResult<()> {
+  for dependency in job.depends_on.clone().unwrap_or_default() {
+    let key = match dependency.kind {
+      JobDependencyKind::Job => dependency.name.clone(),
+      JobDependencyKind::Cargo => {
+        let namespace = utils::key::resolve_nsp(&dependency.namespace);
+        utils::key::gen_key(&namespace, &dependency.name)
+      }
+    };
+    log::debug!("job::wait_dependencies: {} wait for {key}", job.name);
+    let timeout = dependency
+      .timeout_seconds
+      .unwrap_or(DEFAULT_DEPENDENCY_TIMEOUT);
+    let duration = std::time::Duration::from_secs(timeout);
+    let res = match dependency.kind {
+      JobDependencyKind::Job => {
+        ntex::time::timeout(duration, wait_job(&key, since, state)).await
+      }
+      JobDependencyKind::Cargo => {
+        ntex::time::timeout(duration, wait_cargo(&key, state)).await
+      }
+    };
+    res.map_err(|_| {
+      IoError::interrupted(
+        "Job dependency",
+        format!("{key} not ready after {timeout}s").as_str(),
+      )
+    })??;
+  }
+  Ok(())
+}
+
+/// Start a job container and wait for it to exit, return its exit code
+async fn run_container(key: &str, state: &SystemState) -> IoResult<i64> {
+  // The stream is lazy, the wait request is sent once the container started
+  let mut stream = state.inner.docker_api.wait_container(
+    key,
+    Some(WaitContainerOptions {
+      condition: "not-running",
+    }),
+  );
+  state
+    .inner
+    .docker_api
+    .start_container(key, None::<StartContainerOptions<String>>)
+    .await
+    .map_err(HttpError::internal_server_error)?;
+  let mut code = 0;
+  while let Some(res) = stream.next().await {
+    code = match res {
+      Ok(res) => res.status_code,
+      Err(bollard_next::errors::Error::DockerContainerWaitError {
+        code,
+        ..
+      }) => code,
+      Err(err) => return Err(HttpError::internal_server_error(err).into()),
+    };
+  }
+  Ok(code)
+}
+
+/// Run the container of a step until it succeed or its retries are exhausted
+async fn run_step(
+  job: &str,
+  step: &JobStep,
+  process: &str,
+  state: &SystemState,
+) -> IoResult<bool> {
+  let retry = step.retry.unwrap_or_default();
+  for attempt in 0..=retry {
+    if attempt > 0 {
+      log::info!("job::run_step: {job} retry {} {attempt}/{retry}", step.name);
+    }
+    if run_container(process, state).await? == 0 {
+      return Ok(true);
+    }
+  }
+  Ok(false)
+}
+
+/// Find the process of each step, the missing ones are created
+async fn gen_processes(
+  job: &Job,
+  steps: &[JobStep],
+  state: &SystemState,
+) -> IoResult<Vec<String>> {
+  let processes =
+    ProcessDb::read_by_kind_key(&job.name, &state.inner.pool).await?;
+  let step_of = |process: &Process| {
+    process
+      .data
+      .config
+      .as_ref()
+      .and_then(|config| config.labels.as_ref())
+      .and_then(|labels| labels.get(STEP_LABEL).cloned())
+  };
+  let mut keys = Vec::with_capacity(steps.len());
+  for (index, step) in steps.iter().enumerate() {
+    let process = processes
+      .iter()
+      .find(|process| step_of(process).as_deref() == Some(step.name.as_str()));
+    let key = match process {
+      Some(process) => process.key.clone(),
+      None => {
+        utils::container::create_job_instance(job, index, step, state)
+          .await?
+          .key
+      }
+    };
+    keys.push(key);
+  }
+  Ok(keys)
+}
+
+/// Run the steps of a job as a DAG.
+/// A step start once the steps it depends on succeeded,
+/// no new step is started after a failure.
+/// The running steps are killed when a step can't be run.
+/// The steps in `done` already succeeded and are not run again,
+/// the steps succeeding are added to it.
+async fn run_steps(
+  job: &Job,
+  done: &mut HashSet<String>,
+  state: &SystemState,
+) -> IoResult<()> {
+  let steps = gen_steps(&job.containers, &job.steps);
+  sort_steps(&steps)?;
+  let processes = gen_processes(job, &steps, state).await?;
+  ObjPsStatusDb::update_actual_status(
+    &job.name,
+    &ObjPsStatusKind::Start,
+    &state.inner.pool,
+  )
+  .await?;
+  state.emit_normal_native_action(job, NativeEventAction::Start);
+  let mut started = steps
+    .iter()
+    .enumerate()
+    .filter(|(_, step)| done.contains(&step.name))
+    .map(|(index, _)| index)
+    .collect::<HashSet<_>>();
+  let mut failed = None;
+  let mut running = FuturesUnordered::new();
+  loop {
+    if failed.is_none() {
+      for (index, step) in steps.iter().enumerate() {
+        let ready = step
+          .depends_on
+          .iter()
+          .flatten()
+          .all(|dependency| done.contains(dependency));
+        if !ready || !started.insert(index) {
+          continue;
+        }
+        let process = &processes[index];
+        running.push(async move {
+          (index, run_step(&job.name, step, process, state).await)
+        });
+      }
+    }
+    let Some((index, res)) = running.next().await else {
+      break;
+    };
+    let res = match res {
+      Ok(res) => res,
+      Err(err) => {
+        drop(running);
+        kill_processes(job, state).await?;
+        return Err(err);
+      }
+    };
+    if res {
+      done.insert(steps[index].name.clone());
+    } else {
+      failed = Some(steps[index].name.clone());
+    }
+  }
+  if let Some(step) = failed {
+    return Err(IoError::interrupted(
+      "Job step",
+      format!("{step} failed").as_str(),
+    ));
+  }
+  Ok(())
+}
+
+/// Delete the job once its time to live is elapsed
+pub fn spawn_ttl(job: &Job, state: &SystemState) {
+  let Some(ttl) = job.ttl else {
+    return;
+  };
+  let name = job.name.clone();
+  let state = state.clone();
+  rt::spawn(async move {
+    log::debug!("job::spawn_ttl: {name} will be deleted in {ttl}s");
+    ntex::time::sleep(std::time::Duration::from_secs(ttl as u64)).await;
+    let _ = JobDb::del_obj_by_pk(&name, &(), &state).await;
+  });
+}
+
+/// Delay in seconds before the next attempt once `attempt` failed.
+/// It start at `BackoffSeconds` and is doubled after each failure
+/// up to `MaxBackoffSeconds`.
+pub fn backoff_delay(job: &Job, attempt: u32) -> u64 {
+  let backoff = job.backoff_seconds.unwrap_or(DEFAULT_BACKOFF);
+  let max_backoff = job.max_backoff_seconds.unwrap_or(DEFAULT_MAX_BACKOFF);
+  utils::backoff::delay(backoff, max_backoff, attempt)
+}
+
+/// Record a pending run of a job started manually and emit its starting event.
+/// Every start has its own run, the start tasks take them from the oldest.
+pub async fn emit_starting(name: &str, state: &SystemState) -> HttpResult<()> {
+  JobDb::read_by_pk(name, &state.inner.pool).await?;
+  let run = JobRunDb::new(name, None, JobRunStatus::Pending, None);
+  let run = JobRunDb::create_from(run, &state.inner.pool).await?;
+  emit_run_starting(&run, state).await
+}
+
+/// Emit the starting event of the job of a recorded pending run.
+/// The run is failed when the job can't be started.
+pub async fn emit_run_starting(
+  run: &JobRunDb,
+  state: &SystemState,
+) -> HttpResult<()> {
+  let res =
+    utils::container::emit_starting(&run.job_name, &ProcessKind::Job, state)
+      .await;
+  if let Err(err) = &res {
+    JobRunDb::end(
+      &run.key,
+      &JobRunStatus::Failed,
+      Some(err.to_string()),
+      &state.inner.pool,
+    )
+    .await?;
+  }
+  res
+}
+
+/// Kill the running processes of a job
+async fn kill_processes(job: &Job, state: &SystemState) -> IoResult<()> {
+  let processes =
+    ProcessDb::read_by_kind_key(&job.name, &state.inner.pool).await?;
+  for process in processes {
+    let res = state
+      .inner
+      .docker_api
+      .kill_container(&process.key, None::<KillContainerOptions<String>>)
+      .await;
+    // The processes that are not running can't be killed
+    if let Err(err) = res {
+      log::debug!("job::kill_processes: {} {err}", process.key);
+    }
+  }
+  Ok(())
+}
+
+/// Run the attempts of a job until one succeed or its backoff limit is reached.
+/// Each attempt is recorded as a run and resume from the steps that didn't
+/// succeed in the previous ones.
+async fn run_attempts(job: &Job, state: &SystemState) -> IoResult<()> {
+  let backoff_limit = job.backoff_limit.unwrap_or_default();
+  let mut run = JobRunDb::start(&job.name, &state.inner.pool).await?;
+  let since = run.created_at;
+  let mut done = HashSet::new();
+  loop {
+    let res = match wait_dependencies(job, &since, state).await {
+      Ok(_) => run_steps(job, &mut done, state).await,
+      Err(err) => Err(err),
+    };
+    let Err(err) = res else {
+      JobRunDb::end(
+        &run.key,
+        &JobRunStatus::Succeeded,
+        None,
+        &state.inner.pool,
+      )
+      .await?;
+      return Ok(());
+    };
+    JobRunDb::end(
+      &run.key,
+      &JobRunStatus::Failed,
+      Some(err.to_string()),
+      &state.inner.pool,
+    )
+    .await?;
+    let attempt = run.attempt as u32;
+    if attempt > backoff_limit {
+      return Err(err);
+    }
+    let delay = backoff_delay(job, attempt);
+    log::info!(
+      "job::run_attempts: {} attempt {attempt} failed, retry in {delay}s",
+      job.name
+    );
+    ntex::time::sleep(std::time::Duration::from_secs(delay)).await;
+    run = JobRunDb::create_from(run.next_attempt(), &state.inner.pool).await?;
+  }
+}
+
+/// Run a job once its dependencies are ready
+/// and roll the result of its attempts up into its status.
+/// The processes are killed when its active deadline is exceeded.
+pub async fn run(job: &Job, state: &SystemState) -> IoResult<()> {
+  let res = match job.active_deadline_seconds {
+    None => run_attempts(job, state).await,
+    Some(deadline) => {
+      let duration = std::time::Duration::from_secs(deadline);
+      match ntex::time::timeout(duration, run_attempts(job, state)).await {
+        Ok(res) => res,
+        Err(_) => {
+          let reason = format!("active deadline of {deadline}s exceeded");
+          kill_processes(job, state).await?;
+          JobRunDb::end_running(
+            &job.name,
+            &JobRunStatus::Failed,
+            &reason,
+            &state.inner.pool,
+          )
+          .await?;
+          Err(IoError::interrupted("Job", reason.as_str()))
+        }
+      }
+    }
+  };
+  let (status, action) = match &res {
+    Ok(_) => (ObjPsStatusKind::Finish, NativeEventAction::Finish),
+    Err(err) => {
+      log::warn!("job::run: {} {err}", job.name);
+      (ObjPsStatusKind::Fail, NativeEventAction::Fail)
+    }
+  };
+  ObjPsStatusDb::update_actual_status(&job.name, &status, &state.inner.pool)
+    .await?;
+  state.emit_normal_native_action(job, action);
+  spawn_ttl(job, state);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use bollard_next::container::Config;
+
+  use super::*;
+
+  fn gen_step(name: &str, depends_on: &[&str]) -> JobStep {
+    JobStep {
+      name: name.to_owned(),
+      depends_on: Some(depends_on.iter().map(|d| d.to_string()).collect()),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn containers_steps() {
+    let steps = gen_steps(
+      &[Config::default(), Config::default()],
+      &Some(vec![gen_step("smoke", &["1"])]),
+    );
+    assert_eq!(steps.len(), 3);
+    assert_eq!(steps[0].depends_on, None);
+    assert_eq!(steps[1].depends_on, Some(vec!["0".to_owned()]));
+    assert_eq!(sort_steps(&steps).unwrap(), vec![0, 1, 2]);
+  }
+
+  #[test]
+  fn sort() {
+    let steps = vec![
+      gen_step("smoke", &["seed", "assets"]),
+      gen_step("seed", &["migrate"]),
+      gen_step("assets", &[]),
+      gen_step("migrate", &[]),
+    ];
+    let order = sort_steps(&steps).unwrap();
+    let position = |name: &str| {
+      order
+        .iter()
+        .position(|index| steps[*index].name == name)
+        .unwrap()
+    };
+    assert!(position("migrate") < position("seed"));
+    assert!(position("seed") < position("smoke"));
+    assert!(position("assets") < position("smoke"));
+    let cycle = vec![gen_step("a", &["b"]), gen_step("b", &["a"])];
+    assert!(sort_steps(&cycle).is_err());
+    let unknown = vec![gen_step("a", &["c"])];
+    assert!(sort_steps(&unknown).is_err());
+    let duplicated = vec![gen_step("a", &[]), gen_step("a", &[])];
+    assert!(sort_steps(&duplicated).is_err());
+  }
+
+  #[test]
+  fn cycle() {
+    let graph = HashMap::from([
+      ("a".to_owned(), vec!["b".to_owned()]),
+      ("b".to_owned(), vec!["c".to_owned(), "d".to_owned()]),
+      ("c".to_owned(), vec![]),
+      ("d".to_owned(), vec!["a".to_owned()]),
+    ]);
+    assert_eq!(
+      find_cycle("a", &graph),
+      Some(
+        vec!["a", "b", "d", "a"]
+          .into_iter()
+          .map(String::from)
+          .collect()
+      )
+    );
+    assert_eq!(find_cycle("c", &graph), None);
+    let graph = HashMap::from([("a".to_owned(), vec!["a".to_owned()])]);
+    assert!(find_cycle("a", &graph).is_some());
+  }
+
+  #[test]
+  fn backoff() {
+    let job = Job {
+      backoff_seconds: Some(10),
+      max_backoff_seconds: Some(60),
+      ..Default::default()
+    };
+    assert_eq!(backoff_delay(&job, 1), 10);
+    assert_eq!(backoff_delay(&job, 2), 20);
+    assert_eq!(backoff_delay(&job, 3), 40);
+    assert_eq!(backoff_delay(&job, 4), 60);
+    assert_eq!(backoff_delay(&job, 100), 60);
+    assert_eq!(backoff_delay(&Job::default(), 1), DEFAULT_BACKOFF);
+  }
+}
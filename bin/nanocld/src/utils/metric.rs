@@ -0,0 +1,439 @@
+use std::collections::BTreeMap;
+
+use nanocl_error::io::{IoError, IoResult};
+
+use nanocl_stubs::{
+  generic::{GenericClause, GenericFilter},
+  metric::{
+    HttpMetricSummary, MetricAggregate, MetricAggregateFn, MetricListQuery,
+  },
+};
+
+use crate::{
+  utils,
+  repositories::generic::*,
+  models::{MetricDb, MetricNodePartial, SystemState},
+};
+
+/// Kind of the http metrics saved by ncproxy
+pub const HTTP_KIND: &str = "ncproxy.io/http";
+/// Kind of the hourly summaries of the http metrics
+pub const HTTP_HOURLY_KIND: &str = "ncproxy.io/http-hourly";
+/// Hours the http metrics are kept before being rolled into hourly summaries
+pub const HTTP_RETENTION_HOURS: i64 = 24;
+/// Days the hourly summaries are kept
+pub const HTTP_HOURLY_RETENTION_DAYS: i64 = 365;
+/// Maximum number of metrics loaded to compute an aggregation or a summary
+pub const AGGREGATE_LIMIT: usize = 100_000;
+
+/// Field the metrics can be grouped by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricGroupBy {
+  Kind,
+  NodeName,
+  ProxyHost,
+}
+
+impl std::str::FromStr for MetricGroupBy {
+  type Err = IoError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "kind" => Ok(Self::Kind),
+      "node_name" => Ok(Self::NodeName),
+      "proxy_host" => Ok(Self::ProxyHost),
+      _ => Err(IoError::invalid_input(
+        "GroupBy",
+        &format!("{s} must be one of kind, node_name or proxy_host"),
+      )),
+    }
+  }
+}
+
+/// Aggregation options parsed from a `MetricListQuery`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricAggregateOpts {
+  pub group_by: Vec<MetricGroupBy>,
+  pub interval: Option<i64>,
+  pub aggregate: Vec<MetricAggregateFn>,
+  pub field: String,
+}
+
+/// Parse a comma separated list of values
+fn parse_list<T>(list: &Option<String>) -> IoResult<Vec<T>>
+where
+  T: std::str::FromStr,
+  T::Err: std::fmt::Display,
+{
+  list
+    .as_deref()
+    .unwrap_or_default()
+    .split(',')
+    .map(str::trim)
+    .filter(|item| !item.is_empty())
+    .map(|item| {
+      item
+        .parse::<T>()
+        .map_err(|err| IoError::invalid_input("Query", &err.to_string()))
+    })
+    .collect()
+}
+
+impl TryFrom<&MetricListQuery> for MetricAggregateOpts {
+  type Error = IoError;
+
+  fn try_from(query: &MetricListQuery) -> Result<Self, Self::Error> {
+    let interval = match query.interval {
+      Some(0) => {
+        return Err(IoError::invalid_input(
+          "Interval",
+          "must be greater than 0",
+        ))
+      }
+      Some(interval) => Some(interval as i64),
+      None => None,
+    };
+    let mut aggregate = parse_list::<MetricAggregateFn>(&query.aggregate)?;
+    if aggregate.is_empty() {
+      aggregate.push(MetricAggregateFn::Count);
+    }
+    Ok(Self {
+      group_by: parse_list(&query.group_by)?,
+      interval,
+      aggregate,
+      field: query
+        .field
+        .clone()
+        .unwrap_or_else(|| "request_time".to_owned()),
+    })
+  }
+}
+
+/// Start of the time bucket of a date
+fn gen_bucket(
+  date: &chrono::NaiveDateTime,
+  interval: i64,
+) -> chrono::NaiveDateTime {
+  let secs = date.and_utc().timestamp();
+  chrono::DateTime::from_timestamp(secs - secs.rem_euclid(interval), 0)
+    .unwrap_or_default()
+    .naive_utc()
+}
+
+/// Value of a field of the data of a metric.
+/// ncproxy saves the numbers of nginx as strings.
+fn field_value(data: &serde_json::Value, field: &str) -> Option<f64> {
+  match data.get(field)? {
+    serde_json::Value::Number(value) => value.as_f64(),
+    serde_json::Value::String(value) => value.parse().ok(),
+    _ => None,
+  }
+}
+
+/// Value at the given percentile using the nearest rank method
+fn percentile(values: &mut [f64], percent: f64) -> f64 {
+  if values.is_empty() {
+    return 0.0;
+  }
+  values.sort_by(|a, b| a.total_cmp(b));
+  let rank = (percent / 100.0 * values.len() as f64).ceil() as usize;
+  values[rank.clamp(1, values.len()) - 1]
+}
+
+type GroupKey = (
+  Option<chrono::NaiveDateTime>,
+  Option<String>,
+  Option<String>,
+  Option<String>,
+);
+
+/// Group the metrics and apply the aggregate functions on each group
+pub fn aggregate(
+  metrics: &[MetricDb],
+  opts: &MetricAggregateOpts,
+) -> Vec<MetricAggregate> {
+  let mut groups: BTreeMap<GroupKey, (u64, Vec<f64>)> = BTreeMap::new();
+  for metric in metrics {
+    let group = |group_by: MetricGroupBy, value: &str| {
+      opts.group_by.contains(&group_by).then(|| value.to_owned())
+    };
+    let proxy_host = metric
+      .data
+      .get("proxy_host")
+      .and_then(|value| value.as_str())
+      .unwrap_or_default();
+    let key = (
+      opts
+        .interval
+        .map(|interval| gen_bucket(&metric.created_at, interval)),
+      group(MetricGroupBy::Kind, &metric.kind),
+      group(MetricGroupBy::NodeName, &metric.node_name),
+      group(MetricGroupBy::ProxyHost, proxy_host),
+    );
+    let (count, values) = groups.entry(key).or_default();
+    *count += 1;
+    if let Some(value) = field_value(&metric.data, &opts.field) {
+      values.push(value);
+    }
+  }
+  groups
+    .into_iter()
+    .map(
+      |((bucket, kind, node_name, proxy_host), (count, mut values))| {
+        let sum = values.iter().sum::<f64>();
+        let mut item = MetricAggregate {
+          bucket,
+          kind,
+          node_name,
+          proxy_host,
+          ..Default::default()
+        };
+        for aggregate in &opts.aggregate {
+          match aggregate {
+            MetricAggregateFn::Count => item.count = Some(count),
+            MetricAggregateFn::Sum => item.sum = Some(sum),
+            MetricAggregateFn::Avg => {
+              item.avg = Some(if values.is_empty() {
+                0.0
+              } else {
+                sum / values.len() as f64
+              })
+            }
+            MetricAggregateFn::P95 => {
+              item.p95 = Some(percentile(&mut values, 95.0))
+            }
+          }
+        }
+        item
+      },
+    )
+    .collect()
+}
+
+/// List the metrics or aggregate them depending on the query
+pub async fn list(
+  filter: &GenericFilter,
+  query: &MetricListQuery,
+  state: &SystemState,
+) -> IoResult<serde_json::Value> {
+  utils::store::validate_dates(filter, &["created_at", "expires_at"])?;
+  let since = query.since.map(|date| date.naive_utc());
+  let until = query.until.map(|date| date.naive_utc());
+  if !query.is_aggregate() {
+    let metrics =
+      MetricDb::read_range(filter, since, until, &state.inner.pool).await?;
+    return Ok(serde_json::to_value(metrics)?);
+  }
+  let opts = MetricAggregateOpts::try_from(query)?;
+  let mut filter = filter.clone();
+  let limit = filter.limit;
+  // One more metric is read to know when the range is over the limit
+  filter.limit = Some(limit.unwrap_or(AGGREGATE_LIMIT + 1));
+  let metrics =
+    MetricDb::read_range(&filter, since, until, &state.inner.pool).await?;
+  if limit.is_none() && metrics.len() > AGGREGATE_LIMIT {
+    return Err(IoError::invalid_input(
+      "Aggregate",
+      &format!(
+        "more than {AGGREGATE_LIMIT} metrics to aggregate, narrow the range with since and until"
+      ),
+    ));
+  }
+  Ok(serde_json::to_value(aggregate(&metrics, &opts))?)
+}
+
+/// Summarize http metrics by upstream and hour
+pub fn gen_http_summaries(metrics: &[MetricDb]) -> Vec<HttpMetricSummary> {
+  let mut groups: BTreeMap<_, (HttpMetricSummary, Vec<f64>)> = BTreeMap::new();
+  for metric in metrics {
+    let bucket = gen_bucket(&metric.created_at, 3600);
+    let proxy_host = metric
+      .data
+      .get("proxy_host")
+      .and_then(|value| value.as_str())
+      .unwrap_or_default()
+      .to_owned();
+    let (summary, values) = groups
+      .entry((bucket, proxy_host.clone()))
+      .or_insert_with(|| {
+        let summary = HttpMetricSummary {
+          bucket,
+          proxy_host,
+          ..Default::default()
+        };
+        (summary, Vec::new())
+      });
+    summary.count += 1;
+    let status = metric
+      .data
+      .get("status")
+      .and_then(|value| value.as_str())
+      .unwrap_or_default()
+      .to_owned();
+    *summary.statuses.entry(status).or_default() += 1;
+    summary.bytes_sent +=
+      field_value(&metric.data, "bytes_sent").unwrap_or_default() as i64;
+    if let Some(value) = field_value(&metric.data, "request_time") {
+      values.push(value);
+    }
+  }
+  groups
+    .into_values()
+    .map(|(mut summary, mut values)| {
+      summary.sum = values.iter().sum();
+      if !values.is_empty() {
+        summary.avg = summary.sum / values.len() as f64;
+      }
+      summary.p95 = percentile(&mut values, 95.0);
+      summary
+    })
+    .collect()
+}
+
+/// Roll the http metrics of the current node older than the retention
+/// into hourly summaries and delete the expired metrics
+pub async fn downsample(state: &SystemState) -> IoResult<()> {
+  let node = &state.inner.config.hostname;
+  let now = chrono::Utc::now().naive_utc();
+  let cutoff = gen_bucket(
+    &(now - chrono::Duration::try_hours(HTTP_RETENTION_HOURS).unwrap()),
+    3600,
+  );
+  let filter = GenericFilter::new()
+    .r#where("node_name", GenericClause::Eq(node.clone()))
+    .r#where("kind", GenericClause::Eq(HTTP_KIND.to_owned()))
+    .limit(AGGREGATE_LIMIT);
+  let mut metrics =
+    MetricDb::read_range(&filter, None, Some(cutoff), &state.inner.pool)
+      .await?;
+  // The oldest hour may be partial when the limit is reached,
+  // it's summarized on the next run with the rest of its metrics
+  if metrics.len() >= AGGREGATE_LIMIT {
+    let oldest = metrics
+      .iter()
+      .map(|metric| gen_bucket(&metric.created_at, 3600))
+      .min();
+    let newer = metrics
+      .iter()
+      .filter(|metric| Some(gen_bucket(&metric.created_at, 3600)) != oldest)
+      .count();
+    if newer > 0 {
+      metrics
+        .retain(|metric| Some(gen_bucket(&metric.created_at, 3600)) != oldest);
+    }
+  }
+  let summaries = gen_http_summaries(&metrics);
+  let items = summaries
+    .iter()
+    .map(|summary| {
+      let partial = MetricNodePartial {
+        kind: HTTP_HOURLY_KIND.to_owned(),
+        node_name: node.clone(),
+        data: serde_json::to_value(summary)?,
+        note: Some(format!(
+          "{} {} requests",
+          summary.proxy_host, summary.count
+        )),
+      };
+      let mut item = MetricDb::from(&partial);
+      item.created_at = summary.bucket;
+      item.expires_at =
+        now + chrono::Duration::try_days(HTTP_HOURLY_RETENTION_DAYS).unwrap();
+      Ok(item)
+    })
+    .collect::<IoResult<Vec<_>>>()?;
+  if !metrics.is_empty() {
+    let keys = metrics.iter().map(|metric| metric.key).collect();
+    MetricDb::replace(items, keys, &state.inner.pool).await?;
+    log::info!(
+      "metric::downsample: {} http metrics rolled into {} summaries",
+      metrics.len(),
+      summaries.len()
+    );
+  }
+  let filter = GenericFilter::new()
+    .r#where("expires_at", GenericClause::Lt(now.and_utc().to_rfc3339()));
+  MetricDb::del_by(&filter, &state.inner.pool).await?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn gen_metric(date: &str, proxy_host: &str, request_time: &str) -> MetricDb {
+    MetricDb {
+      key: uuid::Uuid::new_v4(),
+      created_at: chrono::DateTime::parse_from_rfc3339(date)
+        .unwrap()
+        .naive_utc(),
+      expires_at: chrono::Utc::now().naive_utc(),
+      node_name: "node".to_owned(),
+      kind: HTTP_KIND.to_owned(),
+      data: serde_json::json!({
+        "proxy_host": proxy_host,
+        "request_time": request_time,
+        "status": "200",
+        "bytes_sent": "10",
+      }),
+      note: None,
+    }
+  }
+
+  #[test]
+  fn aggregate_metrics() {
+    let metrics = [
+      gen_metric("2024-01-01T10:05:00Z", "a", "0.1"),
+      gen_metric("2024-01-01T10:10:00Z", "a", "0.3"),
+      gen_metric("2024-01-01T10:20:00Z", "b", "1"),
+      gen_metric("2024-01-01T11:05:00Z", "a", "0.5"),
+    ];
+    let query = MetricListQuery {
+      group_by: Some("proxy_host".to_owned()),
+      interval: Some(3600),
+      aggregate: Some("count,avg,p95,sum".to_owned()),
+      ..Default::default()
+    };
+    let opts = MetricAggregateOpts::try_from(&query).unwrap();
+    let res = aggregate(&metrics, &opts);
+    assert_eq!(res.len(), 3);
+    assert_eq!(res[0].proxy_host.as_deref(), Some("a"));
+    assert_eq!(res[0].count, Some(2));
+    assert_eq!(res[0].p95, Some(0.3));
+    assert!((res[0].avg.unwrap() - 0.2).abs() < f64::EPSILON);
+    assert_eq!(res[1].proxy_host.as_deref(), Some("b"));
+    assert_eq!(res[2].count, Some(1));
+    assert_eq!(res[2].kind, None);
+    let query = MetricListQuery {
+      aggregate: Some("median".to_owned()),
+      ..Default::default()
+    };
+    assert!(MetricAggregateOpts::try_from(&query).is_err());
+    let query = MetricListQuery {
+      interval: Some(0),
+      ..Default::default()
+    };
+    assert!(MetricAggregateOpts::try_from(&query).is_err());
+  }
+
+  #[test]
+  fn http_summaries() {
+    let metrics = [
+      gen_metric("2024-01-01T10:05:00Z", "a", "0.1"),
+      gen_metric("2024-01-01T10:10:00Z", "a", "0.3"),
+      gen_metric("2024-01-01T11:05:00Z", "a", "0.5"),
+    ];
+    let summaries = gen_http_summaries(&metrics);
+    assert_eq!(summaries.len(), 2);
+    assert_eq!(summaries[0].count, 2);
+    assert_eq!(summaries[0].statuses.get("200"), Some(&2));
+    assert_eq!(summaries[0].bytes_sent, 20);
+    assert_eq!(summaries[0].p95, 0.3);
+    assert_eq!(
+      summaries[1].bucket,
+      chrono::DateTime::parse_from_rfc3339("2024-01-01T11:00:00Z")
+        .unwrap()
+        .naive_utc()
+    );
+  }
+}
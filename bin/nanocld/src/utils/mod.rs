@@ -10,6 +10,17 @@ pub mod exec;
 pub mod ctrl_client;
 pub mod server;
 pub mod container;
+pub mod replication;
+pub mod autoscale;
+pub mod update;
+pub mod state;
+pub mod secret;
+pub mod secret_mount;
+pub mod prometheus;
+pub mod metric;
+pub mod backoff;
+pub mod webhook;
+pub mod job;
 
 #[cfg(test)]
 pub mod tests {
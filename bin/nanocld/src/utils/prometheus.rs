@@ -0,0 +1,550 @@
+use std::fmt::Write;
+
+use futures_util::{StreamExt, future::join_all};
+use bollard_next::container::{Stats, StatsOptions};
+use metrsd_client::stubs::{DiskInfo, MetrsdEvent, NetworkInfo};
+
+use nanocl_error::io::IoResult;
+
+use nanocl_stubs::{
+  process::Process,
+  generic::{GenericFilter, GenericClause},
+};
+
+use crate::{
+  utils,
+  repositories::generic::*,
+  models::{
+    SystemState, MetricDb, ProcessDb, ProxyMetricsInner, HTTP_DURATION_BUCKETS,
+  },
+};
+
+/// Read the value of a sample from a metric
+type SampleValue<T> = fn(&T) -> f64;
+
+/// Content type of the prometheus text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Metrics written in the prometheus text exposition format.
+/// The samples of a family must follow its header.
+#[derive(Default)]
+pub struct Exposition(String);
+
+impl Exposition {
+  /// Write the header of a family of samples
+  pub fn family(&mut self, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(self.0, "# HELP {name} {help}");
+    let _ = writeln!(self.0, "# TYPE {name} {kind}");
+  }
+
+  /// Write a sample of the current family
+  pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+    self.0.push_str(name);
+    if !labels.is_empty() {
+      let labels = labels
+        .iter()
+        .map(|(key, value)| format!("{key}=\"{}\"", escape_label(value)))
+        .collect::<Vec<_>>()
+        .join(",");
+      let _ = write!(self.0, "{{{labels}}}");
+    }
+    let _ = writeln!(self.0, " {}", fmt_value(value));
+  }
+
+  pub fn finish(self) -> String {
+    self.0
+  }
+}
+
+/// Escape a label value, backslash, double quote and line feed are not allowed
+fn escape_label(value: &str) -> String {
+  value
+    .replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('\n', "\\n")
+}
+
+/// Format a sample value, infinity and NaN have their own spelling
+fn fmt_value(value: f64) -> String {
+  if value.is_nan() {
+    return "NaN".to_owned();
+  }
+  if value.is_infinite() {
+    return if value > 0.0 { "+Inf" } else { "-Inf" }.to_owned();
+  }
+  value.to_string()
+}
+
+/// Write the cpu, memory, disk and network usage of the node
+/// from the latest metric saved by metrsd
+pub fn gen_node(node: &str, metrs: &MetrsdEvent, exp: &mut Exposition) {
+  exp.family(
+    "nanocl_node_cpu_usage_percent",
+    "gauge",
+    "Usage in percent of a cpu of the node",
+  );
+  for (index, cpu) in metrs.cpus.iter().enumerate() {
+    let index = index.to_string();
+    let name = match cpu.name.as_str() {
+      "" => index.as_str(),
+      name => name,
+    };
+    exp.sample(
+      "nanocl_node_cpu_usage_percent",
+      &[("node", node), ("cpu", name)],
+      cpu.usage as f64,
+    );
+  }
+  exp.family(
+    "nanocl_node_memory_used_bytes",
+    "gauge",
+    "Memory used by the node",
+  );
+  exp.sample(
+    "nanocl_node_memory_used_bytes",
+    &[("node", node)],
+    metrs.memory.used as f64,
+  );
+  exp.family(
+    "nanocl_node_memory_total_bytes",
+    "gauge",
+    "Memory available on the node",
+  );
+  exp.sample(
+    "nanocl_node_memory_total_bytes",
+    &[("node", node)],
+    metrs.memory.total as f64,
+  );
+  let disk_families: [(&str, &str, SampleValue<DiskInfo>); 2] = [
+    (
+      "nanocl_node_disk_total_bytes",
+      "Size of a disk of the node",
+      |disk| disk.total_space as f64,
+    ),
+    (
+      "nanocl_node_disk_available_bytes",
+      "Space left on a disk of the node",
+      |disk| disk.available_space as f64,
+    ),
+  ];
+  for (name, help, value) in disk_families {
+    exp.family(name, "gauge", help);
+    for disk in &metrs.disks {
+      let labels = [
+        ("node", node),
+        ("device", disk.device_name.as_str()),
+        ("mountpoint", disk.mount_point.as_str()),
+      ];
+      exp.sample(name, &labels, value(disk));
+    }
+  }
+  let network_families: [(&str, &str, SampleValue<NetworkInfo>); 2] = [
+    (
+      "nanocl_node_network_received_bytes",
+      "Bytes received by a network interface of the node",
+      |network| network.received as f64,
+    ),
+    (
+      "nanocl_node_network_transmitted_bytes",
+      "Bytes transmitted by a network interface of the node",
+      |network| network.transmitted as f64,
+    ),
+  ];
+  for (name, help, value) in network_families {
+    exp.family(name, "gauge", help);
+    for network in &metrs.networks {
+      let labels = [("node", node), ("interface", network.name.as_str())];
+      exp.sample(name, &labels, value(network));
+    }
+  }
+}
+
+/// Write the resources used by the running processes of the node
+async fn gen_processes(
+  state: &SystemState,
+  exp: &mut Exposition,
+) -> IoResult<()> {
+  let filter = GenericFilter::new().r#where(
+    "node_key",
+    GenericClause::Eq(state.inner.config.hostname.clone()),
+  );
+  let processes = ProcessDb::transform_read_by(&filter, &state.inner.pool)
+    .await?
+    .into_iter()
+    .filter(|process| {
+      process
+        .data
+        .state
+        .as_ref()
+        .and_then(|state| state.running)
+        .unwrap_or_default()
+    })
+    .collect::<Vec<Process>>();
+  let stats = join_all(processes.iter().map(|process| async move {
+    let opts = StatsOptions {
+      stream: false,
+      one_shot: false,
+    };
+    let mut stream = state.inner.docker_api.stats(&process.key, Some(opts));
+    match stream.next().await {
+      Some(Ok(stats)) => Some((process, stats)),
+      _ => None,
+    }
+  }))
+  .await
+  .into_iter()
+  .flatten()
+  .collect::<Vec<_>>();
+  let families: [(&str, &str, SampleValue<Stats>); 5] = [
+    (
+      "nanocl_process_cpu_usage_percent",
+      "Cpu usage in percent of a process",
+      utils::autoscale::cpu_percent,
+    ),
+    (
+      "nanocl_process_memory_usage_percent",
+      "Memory usage in percent of the memory limit of a process",
+      utils::autoscale::memory_percent,
+    ),
+    (
+      "nanocl_process_memory_usage_bytes",
+      "Memory used by a process",
+      |stats| stats.memory_stats.usage.unwrap_or_default() as f64,
+    ),
+    (
+      "nanocl_process_network_received_bytes_total",
+      "Bytes received by a process",
+      |stats| {
+        let networks = stats.networks.as_ref();
+        networks.map_or(0.0, |n| n.values().map(|n| n.rx_bytes as f64).sum())
+      },
+    ),
+    (
+      "nanocl_process_network_transmitted_bytes_total",
+      "Bytes transmitted by a process",
+      |stats| {
+        let networks = stats.networks.as_ref();
+        networks.map_or(0.0, |n| n.values().map(|n| n.tx_bytes as f64).sum())
+      },
+    ),
+  ];
+  for (name, help, value) in families {
+    let kind = if name.ends_with("_total") {
+      "counter"
+    } else {
+      "gauge"
+    };
+    exp.family(name, kind, help);
+    for (process, stats) in &stats {
+      let process_kind = process.kind.to_string();
+      let labels = [
+        ("node", process.node_key.as_str()),
+        ("kind", process_kind.as_str()),
+        ("key", process.kind_key.as_str()),
+        ("name", process.name.as_str()),
+      ];
+      exp.sample(name, &labels, value(stats));
+    }
+  }
+  Ok(())
+}
+
+/// Write the requests and sessions proxied by ncproxy per upstream
+pub fn gen_proxy(
+  node: &str,
+  metrics: &ProxyMetricsInner,
+  exp: &mut Exposition,
+) {
+  exp.family(
+    "nanocl_proxy_http_requests_total",
+    "counter",
+    "Http requests proxied to an upstream by status code",
+  );
+  for (upstream, stats) in &metrics.http {
+    for (status, count) in &stats.statuses {
+      let status = status.to_string();
+      let labels = [
+        ("node", node),
+        ("upstream", upstream.as_str()),
+        ("status", status.as_str()),
+      ];
+      exp.sample("nanocl_proxy_http_requests_total", &labels, *count as f64);
+    }
+  }
+  exp.family(
+    "nanocl_proxy_http_request_duration_seconds",
+    "histogram",
+    "Time to serve the http requests proxied to an upstream",
+  );
+  for (upstream, stats) in &metrics.http {
+    let mut cumulated = 0;
+    for (bound, count) in HTTP_DURATION_BUCKETS.iter().zip(stats.buckets) {
+      cumulated += count;
+      let bound = bound.to_string();
+      let labels = [
+        ("node", node),
+        ("upstream", upstream.as_str()),
+        ("le", bound.as_str()),
+      ];
+      exp.sample(
+        "nanocl_proxy_http_request_duration_seconds_bucket",
+        &labels,
+        cumulated as f64,
+      );
+    }
+    let labels = [("node", node), ("upstream", upstream.as_str())];
+    exp.sample(
+      "nanocl_proxy_http_request_duration_seconds_bucket",
+      &[labels[0], labels[1], ("le", "+Inf")],
+      stats.count as f64,
+    );
+    exp.sample(
+      "nanocl_proxy_http_request_duration_seconds_sum",
+      &labels,
+      stats.duration_sum,
+    );
+    exp.sample(
+      "nanocl_proxy_http_request_duration_seconds_count",
+      &labels,
+      stats.count as f64,
+    );
+  }
+  exp.family(
+    "nanocl_proxy_http_sent_bytes_total",
+    "counter",
+    "Bytes sent to the clients of an http upstream",
+  );
+  for (upstream, stats) in &metrics.http {
+    let labels = [("node", node), ("upstream", upstream.as_str())];
+    exp.sample(
+      "nanocl_proxy_http_sent_bytes_total",
+      &labels,
+      stats.bytes_sent as f64,
+    );
+  }
+  exp.family(
+    "nanocl_proxy_stream_sessions_total",
+    "counter",
+    "Tcp and udp sessions proxied to an upstream by status code",
+  );
+  for (upstream, stats) in &metrics.streams {
+    for (status, count) in &stats.statuses {
+      let status = status.to_string();
+      let labels = [
+        ("node", node),
+        ("upstream", upstream.as_str()),
+        ("status", status.as_str()),
+      ];
+      exp.sample("nanocl_proxy_stream_sessions_total", &labels, *count as f64);
+    }
+  }
+  for (name, help, received) in [
+    (
+      "nanocl_proxy_stream_sent_bytes_total",
+      "Bytes sent to the clients of a stream upstream",
+      false,
+    ),
+    (
+      "nanocl_proxy_stream_received_bytes_total",
+      "Bytes received from the clients of a stream upstream",
+      true,
+    ),
+  ] {
+    exp.family(name, "counter", help);
+    for (upstream, stats) in &metrics.streams {
+      let labels = [("node", node), ("upstream", upstream.as_str())];
+      let value = if received {
+        stats.bytes_received
+      } else {
+        stats.bytes_sent
+      };
+      exp.sample(name, &labels, value as f64);
+    }
+  }
+}
+
+/// Write the state of the daemon internals
+async fn gen_daemon(state: &SystemState, exp: &mut Exposition) -> IoResult<()> {
+  let node = state.inner.config.hostname.as_str();
+  let tasks = state.inner.task_manager.tasks.lock().await.len();
+  exp.family(
+    "nanocl_daemon_tasks",
+    "gauge",
+    "Tasks running or queued in the task manager",
+  );
+  exp.sample("nanocl_daemon_tasks", &[("node", node)], tasks as f64);
+  let subscribers = state.inner.event_emitter_raw.subscriber_count()?;
+  exp.family(
+    "nanocl_daemon_event_subscribers",
+    "gauge",
+    "Clients subscribed to the events of the daemon",
+  );
+  exp.sample(
+    "nanocl_daemon_event_subscribers",
+    &[("node", node)],
+    subscribers as f64,
+  );
+  Ok(())
+}
+
+/// Gather the metrics of the current node in the prometheus text format
+pub async fn gen_exposition(state: &SystemState) -> IoResult<String> {
+  let node = state.inner.config.hostname.as_str();
+  let mut exp = Exposition::default();
+  let filter = GenericFilter::new()
+    .r#where("node_name", GenericClause::Eq(node.to_owned()))
+    .r#where("kind", GenericClause::Eq("nanocl.io/metrs".to_owned()))
+    .limit(1);
+  let metrs = MetricDb::read_by(&filter, &state.inner.pool).await?;
+  if let Some(metric) = metrs.first() {
+    match serde_json::from_value::<MetrsdEvent>(metric.data.clone()) {
+      Ok(metrs) => gen_node(node, &metrs, &mut exp),
+      Err(err) => log::warn!("prometheus::gen_exposition: {err}"),
+    }
+  }
+  gen_processes(state, &mut exp).await?;
+  gen_proxy(node, &state.inner.proxy_metrics.snapshot(), &mut exp);
+  gen_daemon(state, &mut exp).await?;
+  Ok(exp.finish())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::models::ProxyMetrics;
+
+  #[test]
+  fn exposition() {
+    let mut exp = Exposition::default();
+    exp.family("test_total", "counter", "Test");
+    exp.sample("test_total", &[("name", "a\"b\\c\nd")], 2.0);
+    exp.sample("test_total", &[], f64::INFINITY);
+    assert_eq!(
+      exp.finish(),
+      "# HELP test_total Test\n# TYPE test_total counter\n\
+       test_total{name=\"a\\\"b\\\\c\\nd\"} 2\ntest_total +Inf\n"
+    );
+  }
+
+  #[test]
+  fn node() {
+    let data = serde_json::json!({
+      "Memory": {
+        "Total": 16000000000u64,
+        "Free": 4000000000u64,
+        "Used": 12000000000u64,
+        "SwapTotal": 0,
+        "SwapFree": 0,
+        "SwapUsed": 0,
+      },
+      "Cpus": [
+        {
+          "Name": "cpu0",
+          "VendorId": "GenuineIntel",
+          "Brand": "Intel(R) Core(TM) i7",
+          "Frequency": 2400,
+          "Usage": 12.5,
+        },
+      ],
+      "Disks": [
+        {
+          "Kind": "SSD",
+          "DeviceName": "/dev/nvme0n1p2",
+          "FileSystem": "ext4",
+          "MountPoint": "/",
+          "TotalSpace": 500000000000u64,
+          "AvailableSpace": 200000000000u64,
+          "IsRemovable": false,
+        },
+      ],
+      "Networks": [
+        {
+          "Name": "eth0",
+          "MacAddr": "00:00:00:00:00:00",
+          "Received": 1024,
+          "Transmitted": 2048,
+          "PacketsReceived": 8,
+          "PacketsTransmitted": 16,
+          "ErrorReceived": 0,
+          "ErrorTransmitted": 0,
+        },
+      ],
+    });
+    let metrs = serde_json::from_value::<MetrsdEvent>(data).unwrap();
+    let mut exp = Exposition::default();
+    gen_node("node", &metrs, &mut exp);
+    let text = exp.finish();
+    let disk = "node=\"node\",device=\"/dev/nvme0n1p2\",mountpoint=\"/\"";
+    for line in [
+      "nanocl_node_cpu_usage_percent{node=\"node\",cpu=\"cpu0\"} 12.5"
+        .to_owned(),
+      "nanocl_node_memory_used_bytes{node=\"node\"} 12000000000".to_owned(),
+      "nanocl_node_memory_total_bytes{node=\"node\"} 16000000000".to_owned(),
+      format!("nanocl_node_disk_total_bytes{{{disk}}} 500000000000"),
+      format!("nanocl_node_disk_available_bytes{{{disk}}} 200000000000"),
+      "nanocl_node_network_received_bytes{node=\"node\",interface=\"eth0\"} 1024"
+        .to_owned(),
+      "nanocl_node_network_transmitted_bytes{node=\"node\",interface=\"eth0\"} 2048"
+        .to_owned(),
+    ] {
+      assert!(text.contains(&line), "missing {line} in {text}");
+    }
+  }
+
+  #[test]
+  fn proxy_histogram() {
+    let metrics = ProxyMetrics::default();
+    for (status, request_time) in
+      [("200", "0.003"), ("200", "0.2"), ("502", "12")]
+    {
+      let data = serde_json::json!({
+        "date_gmt": "2024-01-01T00:00:00+00:00",
+        "uri": "/",
+        "host": "example.com",
+        "remote_addr": "127.0.0.1",
+        "realip_remote_addr": "127.0.0.1",
+        "server_protocol": "HTTP/1.1",
+        "request_method": "GET",
+        "bytes_sent": "10",
+        "content_length": "",
+        "status": status,
+        "request_time": request_time,
+        "body_bytes_sent": "0",
+        "proxy_host": "deploy-example.global.c-9000",
+        "upstream_addr": "",
+        "query_string": "",
+        "request_body": "",
+        "content_type": "",
+        "http_user_agent": "",
+        "http_referrer": "",
+        "http_accept_language": "",
+      });
+      metrics.observe("ncproxy.io/http", &data).unwrap();
+    }
+    let mut exp = Exposition::default();
+    gen_proxy("node", &metrics.snapshot(), &mut exp);
+    let text = exp.finish();
+    let upstream = "node=\"node\",upstream=\"deploy-example.global.c-9000\"";
+    for line in [
+      format!("nanocl_proxy_http_requests_total{{{upstream},status=\"200\"}} 2"),
+      format!("nanocl_proxy_http_requests_total{{{upstream},status=\"502\"}} 1"),
+      format!(
+        "nanocl_proxy_http_request_duration_seconds_bucket{{{upstream},le=\"0.005\"}} 1"
+      ),
+      format!(
+        "nanocl_proxy_http_request_duration_seconds_bucket{{{upstream},le=\"0.25\"}} 2"
+      ),
+      format!(
+        "nanocl_proxy_http_request_duration_seconds_bucket{{{upstream},le=\"10\"}} 2"
+      ),
+      format!(
+        "nanocl_proxy_http_request_duration_seconds_bucket{{{upstream},le=\"+Inf\"}} 3"
+      ),
+      format!("nanocl_proxy_http_request_duration_seconds_count{{{upstream}}} 3"),
+      format!("nanocl_proxy_http_sent_bytes_total{{{upstream}}} 30"),
+    ] {
+      assert!(text.contains(&line), "missing {line} in {text}");
+    }
+  }
+}
@@ -0,0 +1,279 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use bollard_next::container::StartContainerOptions;
+
+use nanocl_error::http::HttpResult;
+
+use nanocl_stubs::{
+  cargo::Cargo,
+  cargo_spec::ReplicationMode,
+  generic::{GenericClause, GenericFilter},
+  process::Process,
+  system::ObjPsStatusKind,
+};
+
+use crate::{
+  repositories::generic::*,
+  models::{NodeDb, NodeGroupLinkDb, ProcessDb, SystemState},
+};
+
+/// Number of nodes running an instance of a cargo in `Auto` mode,
+/// one for the service and a second one for redundancy
+pub const AUTO_REPLICAS: usize = 2;
+
+/// Compute the number of instances wanted on each node for a replication mode.
+/// `nodes` are the nodes of the cluster and `group_nodes` the nodes
+/// belonging to the node groups referenced by the mode.
+/// When no replication is set we fallback to a single instance on the local node.
+pub fn gen_replicas(
+  mode: Option<&ReplicationMode>,
+  local_node: &str,
+  nodes: &[String],
+  group_nodes: &[String],
+) -> HashMap<String, usize> {
+  let mut nodes = nodes.to_vec();
+  nodes.sort();
+  nodes.dedup();
+  if nodes.is_empty() {
+    nodes.push(local_node.to_owned());
+  }
+  let each = |names: &[String], number: usize| {
+    nodes
+      .iter()
+      .filter(|node| names.contains(node))
+      .map(|node| (node.clone(), number))
+      .collect::<HashMap<_, _>>()
+  };
+  match mode {
+    None => HashMap::from([(local_node.to_owned(), 1)]),
+    Some(ReplicationMode::Static(replication)) => {
+      HashMap::from([(local_node.to_owned(), replication.number)])
+    }
+    Some(ReplicationMode::Unique) => HashMap::from([(nodes[0].clone(), 1)]),
+    // One instance on the first nodes sorted by name,
+    // a single node cluster keeps a single instance
+    Some(ReplicationMode::Auto) => nodes
+      .iter()
+      .take(AUTO_REPLICAS)
+      .map(|node| (node.clone(), 1))
+      .collect(),
+    Some(ReplicationMode::UniqueByNode) => {
+      nodes.iter().map(|node| (node.clone(), 1)).collect()
+    }
+    Some(ReplicationMode::StaticByNodes(replication)) => nodes
+      .iter()
+      .map(|node| (node.clone(), replication.number))
+      .collect(),
+    Some(ReplicationMode::UniqueByNodeGroups { .. }) => each(group_nodes, 1),
+    Some(ReplicationMode::UniqueByNodeNames { names }) => each(names, 1),
+    Some(ReplicationMode::StaticByNodeGroups { number, .. }) => {
+      each(group_nodes, (*number).max(0) as usize)
+    }
+    Some(ReplicationMode::StaticByNodeNames { names, number }) => {
+      each(names, (*number).max(0) as usize)
+    }
+  }
+}
+
+/// Get the number of instances the current node should run for a cargo
+pub async fn local_replicas(
+  cargo: &Cargo,
+  state: &SystemState,
+) -> HttpResult<usize> {
+  let nodes = NodeDb::read_names(&state.inner.pool).await?;
+  let group_nodes = match &cargo.spec.replication {
+    Some(ReplicationMode::UniqueByNodeGroups { groups })
+    | Some(ReplicationMode::StaticByNodeGroups { groups, .. }) => {
+      NodeGroupLinkDb::read_by_groups(groups, &state.inner.pool)
+        .await?
+        .into_iter()
+        .map(|link| link.node_name)
+        .collect::<Vec<_>>()
+    }
+    _ => Vec::new(),
+  };
+  let hostname = &state.inner.config.hostname;
+  let replicas = gen_replicas(
+    cargo.spec.replication.as_ref(),
+    hostname,
+    &nodes,
+    &group_nodes,
+  );
+  Ok(replicas.get(hostname).cloned().unwrap_or_default())
+}
+
+/// List the instances of a cargo running on the current node
+/// Init containers are excluded since they are not replicas
+pub async fn list_local_instances(
+  cargo: &Cargo,
+  state: &SystemState,
+) -> HttpResult<Vec<Process>> {
+  let filter = GenericFilter::new()
+    .r#where("kind_key", GenericClause::Eq(cargo.spec.cargo_key.clone()))
+    .r#where(
+      "node_key",
+      GenericClause::Eq(state.inner.config.hostname.clone()),
+    );
+  let processes = ProcessDb::transform_read_by(&filter, &state.inner.pool)
+    .await?
+    .into_iter()
+    .filter(|process| {
+      let labels = process
+        .data
+        .config
+        .clone()
+        .unwrap_or_default()
+        .labels
+        .unwrap_or_default();
+      !labels.contains_key("io.nanocl.init-c")
+    })
+    .collect();
+  Ok(processes)
+}
+
+/// Scale the given instances of a cargo on the current node to `wanted`.
+/// Missing instances are created (and started if the cargo is running)
+/// and extra instances are removed starting by the most recent ones.
+pub async fn scale(
+  cargo: &Cargo,
+  instances: &[Process],
+  wanted: usize,
+  state: &SystemState,
+) -> HttpResult<()> {
+  let current = instances.len();
+  match current.cmp(&wanted) {
+    Ordering::Less => {
+      let new_instances =
+        super::container::create_cargo(cargo, wanted - current, state).await?;
+      if cargo.status.actual != ObjPsStatusKind::Start {
+        return Ok(());
+      }
+      for instance in new_instances {
+        state
+          .inner
+          .docker_api
+          .start_container(&instance.key, None::<StartContainerOptions<String>>)
+          .await?;
+      }
+    }
+    Ordering::Greater => {
+      // Instances are sorted by creation date, the newest first
+      let extra = instances
+        .iter()
+        .take(current - wanted)
+        .map(|instance| instance.key.clone())
+        .collect::<Vec<_>>();
+      super::container::delete_instances(&extra, state).await?;
+    }
+    Ordering::Equal => {}
+  }
+  Ok(())
+}
+
+/// Get the number of instances the current node should run for a cargo
+/// knowing the `current` number of instances.
+/// When an autoscaling policy is set the replication only select the nodes
+/// and the current number of instances is kept within the policy bounds.
+pub async fn wanted_replicas(
+  cargo: &Cargo,
+  current: usize,
+  state: &SystemState,
+) -> HttpResult<usize> {
+  let replicas = local_replicas(cargo, state).await?;
+  let wanted = match &cargo.spec.autoscale {
+    Some(autoscale) if replicas > 0 => {
+      current.clamp(autoscale.min_replicas, autoscale.max_replicas)
+    }
+    _ => replicas,
+  };
+  Ok(wanted)
+}
+
+/// Converge the instances of a cargo on the current node
+/// to the number computed from his replication mode.
+pub async fn converge(cargo: &Cargo, state: &SystemState) -> HttpResult<()> {
+  let instances = list_local_instances(cargo, state).await?;
+  let current = instances.len();
+  let wanted = wanted_replicas(cargo, current, state).await?;
+  log::debug!(
+    "replication::converge: {} has {current}/{wanted} instances",
+    cargo.spec.cargo_key
+  );
+  scale(cargo, &instances, wanted, state).await
+}
+
+#[cfg(test)]
+mod tests {
+  use nanocl_stubs::cargo_spec::ReplicationStatic;
+
+  use super::*;
+
+  fn nodes() -> Vec<String> {
+    vec!["node2".to_owned(), "node1".to_owned(), "node3".to_owned()]
+  }
+
+  #[test]
+  fn replicas_default() {
+    let replicas = gen_replicas(None, "node2", &nodes(), &[]);
+    assert_eq!(replicas, HashMap::from([("node2".to_owned(), 1)]));
+    let mode = ReplicationMode::Static(ReplicationStatic { number: 3 });
+    let replicas = gen_replicas(Some(&mode), "node2", &nodes(), &[]);
+    assert_eq!(replicas, HashMap::from([("node2".to_owned(), 3)]));
+  }
+
+  #[test]
+  fn replicas_cluster() {
+    let replicas =
+      gen_replicas(Some(&ReplicationMode::Unique), "node2", &nodes(), &[]);
+    assert_eq!(replicas, HashMap::from([("node1".to_owned(), 1)]));
+    let replicas =
+      gen_replicas(Some(&ReplicationMode::Auto), "node3", &nodes(), &[]);
+    assert_eq!(replicas.len(), 2);
+    assert!(!replicas.contains_key("node3"));
+    let replicas = gen_replicas(
+      Some(&ReplicationMode::UniqueByNode),
+      "node3",
+      &nodes(),
+      &[],
+    );
+    assert_eq!(replicas.len(), 3);
+    let mode = ReplicationMode::StaticByNodes(ReplicationStatic { number: 2 });
+    let replicas = gen_replicas(Some(&mode), "node3", &nodes(), &[]);
+    assert!(replicas.values().all(|number| *number == 2));
+  }
+
+  #[test]
+  fn replicas_auto() {
+    let mode = ReplicationMode::Auto;
+    let replicas = gen_replicas(Some(&mode), "node1", &[], &[]);
+    assert_eq!(replicas, HashMap::from([("node1".to_owned(), 1)]));
+    let nodes = (1..=150).map(|i| format!("node{i:03}")).collect::<Vec<_>>();
+    let replicas = gen_replicas(Some(&mode), "node150", &nodes, &[]);
+    assert_eq!(replicas.len(), AUTO_REPLICAS);
+    assert_eq!(replicas.get("node001"), Some(&1));
+    assert_eq!(replicas.get("node002"), Some(&1));
+  }
+
+  #[test]
+  fn replicas_selected_nodes() {
+    let mode = ReplicationMode::StaticByNodeGroups {
+      groups: vec!["edge".to_owned()],
+      number: 2,
+    };
+    let group_nodes = vec!["node3".to_owned(), "unknown".to_owned()];
+    let replicas = gen_replicas(Some(&mode), "node1", &nodes(), &group_nodes);
+    assert_eq!(replicas, HashMap::from([("node3".to_owned(), 2)]));
+    let mode = ReplicationMode::UniqueByNodeNames {
+      names: vec!["node1".to_owned(), "node2".to_owned()],
+    };
+    let replicas = gen_replicas(Some(&mode), "node1", &nodes(), &[]);
+    assert_eq!(replicas.len(), 2);
+    assert_eq!(replicas.get("node1"), Some(&1));
+    let mode = ReplicationMode::StaticByNodeNames {
+      names: vec!["node2".to_owned()],
+      number: -1,
+    };
+    let replicas = gen_replicas(Some(&mode), "node1", &nodes(), &[]);
+    assert_eq!(replicas, HashMap::from([("node2".to_owned(), 0)]));
+  }
+}
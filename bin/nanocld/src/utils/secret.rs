@@ -0,0 +1,660 @@
+use std::{
+  fmt::Write,
+  path::Path,
+  collections::HashSet,
+  sync::{OnceLock, RwLock},
+  os::unix::fs::PermissionsExt,
+};
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use jsonschema::{Draft, JSONSchema};
+use bollard_next::auth::DockerCredentials;
+use openssl::{
+  base64,
+  rand::rand_bytes,
+  sha::sha256,
+  symm::{self, Cipher},
+};
+
+use nanocl_error::{
+  io::{FromIo, IoError, IoResult},
+  http::{HttpError, HttpResult},
+};
+use nanocl_stubs::{
+  config::DaemonConfig,
+  proxy::ProxySslConfig,
+  secret::Secret,
+  generic::{GenericClause, GenericFilter},
+};
+
+use crate::{
+  repositories::generic::*,
+  models::{
+    Pool, SecretDb, SecretKindDb, SecretUpdateDb, SpecDb, SpecUpdateDb,
+    WebhookDb, WebhookUpdateDb,
+  },
+};
+
+/// Secret kind used by ncproxy to load a certificate
+pub const KIND_TLS: &str = "nanocl.io/tls";
+/// Secret kind injected as environment variables in cargoes
+pub const KIND_ENV: &str = "nanocl.io/env";
+/// Secret kind used to pull images from a private registry
+pub const KIND_REGISTRY: &str = "nanocl.io/container-registry";
+/// Secret kind used by ncproxy for basic authentication, lines of `user:hash`
+pub const KIND_HTPASSWD: &str = "nanocl.io/htpasswd";
+/// Domain reserved for the built-in secret kinds
+const BUILTIN_DOMAIN: &str = "nanocl.io/";
+
+/// Size in bytes of the AES-256 keys
+const KEY_LEN: usize = 32;
+/// Size in bytes of the AES-GCM nonce
+const NONCE_LEN: usize = 12;
+/// Size in bytes of the AES-GCM authentication tag
+const TAG_LEN: usize = 16;
+/// Value displayed instead of the data of a redacted secret
+const REDACTED: &str = "<redacted>";
+/// Number of rows read at once when every secret is sealed
+const PAGE_SIZE: usize = 100;
+
+/// Master keys currently loaded, the first one is used to encrypt
+static KEYRING: RwLock<Vec<MasterKey>> = RwLock::new(Vec::new());
+/// Provider used to load the master keys
+static PROVIDER: OnceLock<Box<dyn KeyProvider>> = OnceLock::new();
+
+/// A master key used to wrap the data key of every secret
+#[derive(Clone)]
+pub struct MasterKey {
+  /// Identifier of the key stored next to the encrypted data
+  pub id: String,
+  key: Vec<u8>,
+}
+
+impl MasterKey {
+  pub fn new(key: Vec<u8>) -> IoResult<Self> {
+    if key.len() != KEY_LEN {
+      return Err(IoError::invalid_data(
+        "Master key".to_owned(),
+        format!("expected {KEY_LEN} bytes got {}", key.len()),
+      ));
+    }
+    let id = sha256(&key)[..8]
+      .iter()
+      .fold(String::new(), |mut id, byte| {
+        let _ = write!(id, "{byte:02x}");
+        id
+      });
+    Ok(Self { id, key })
+  }
+
+  /// Generate a new random master key
+  pub fn generate() -> IoResult<Self> {
+    Self::new(random_bytes(KEY_LEN)?)
+  }
+}
+
+/// Source of the master keys.
+/// The first loaded key is used to encrypt, the others are only kept
+/// to decrypt the rows sealed before a rotation.
+pub trait KeyProvider: Send + Sync {
+  /// Load the available master keys
+  fn load(&self) -> IoResult<Vec<MasterKey>>;
+  /// Store a new master key, the previous ones are kept for decryption
+  fn store(&self, key: &MasterKey) -> IoResult<()>;
+}
+
+/// Load the master key from `{conf_dir}/secret.key` if it exists
+/// or from `{state_dir}/secret.key` otherwise.
+/// Every previous key is kept in `secret.key.old`, one per line,
+/// until no row is sealed by it anymore.
+pub struct FileKeyProvider {
+  path: String,
+}
+
+impl FileKeyProvider {
+  pub fn new(conf: &DaemonConfig) -> Self {
+    let conf_path = format!("{}/secret.key", conf.conf_dir);
+    let path = if Path::new(&conf_path).exists() {
+      conf_path
+    } else {
+      format!("{}/secret.key", conf.state_dir)
+    };
+    Self { path }
+  }
+
+  fn old_path(&self) -> String {
+    format!("{}.old", self.path)
+  }
+
+  /// Read the keys of a file, one base64 encoded key per line
+  fn read_keys(path: &str) -> IoResult<Vec<MasterKey>> {
+    if !Path::new(path).exists() {
+      return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(path)
+      .map_err(|err| err.map_err_context(|| path.to_owned()))?;
+    content
+      .lines()
+      .map(str::trim)
+      .filter(|line| !line.is_empty())
+      .map(|line| {
+        let key = base64::decode_block(line).map_err(|err| {
+          IoError::invalid_data(path.to_owned(), err.to_string())
+        })?;
+        MasterKey::new(key)
+      })
+      .collect()
+  }
+
+  /// Atomically replace the keys of a file
+  fn write_keys(path: &str, keys: &[MasterKey]) -> IoResult<()> {
+    let context = || path.to_owned();
+    let tmp_path = format!("{path}.new");
+    let content = keys
+      .iter()
+      .map(|key| base64::encode_block(&key.key) + "\n")
+      .collect::<String>();
+    std::fs::write(&tmp_path, content)
+      .map_err(|err| err.map_err_context(context))?;
+    std::fs::set_permissions(&tmp_path, std::fs::Permissions::from_mode(0o600))
+      .map_err(|err| err.map_err_context(context))?;
+    std::fs::rename(&tmp_path, path)
+      .map_err(|err| err.map_err_context(context))?;
+    Ok(())
+  }
+
+  /// Remove the previous keys that aren't in `used` anymore
+  pub fn retain(&self, used: &HashSet<String>) -> IoResult<()> {
+    let old_path = self.old_path();
+    let keys = Self::read_keys(&old_path)?;
+    let len = keys.len();
+    let keys = keys
+      .into_iter()
+      .filter(|key| used.contains(&key.id))
+      .collect::<Vec<_>>();
+    if keys.len() == len {
+      return Ok(());
+    }
+    log::info!(
+      "secret::retain: {} previous master keys removed",
+      len - keys.len()
+    );
+    Self::write_keys(&old_path, &keys)
+  }
+}
+
+impl KeyProvider for FileKeyProvider {
+  fn load(&self) -> IoResult<Vec<MasterKey>> {
+    let mut keys = Self::read_keys(&self.path)?;
+    keys.truncate(1);
+    keys.extend(Self::read_keys(&self.old_path())?);
+    Ok(keys)
+  }
+
+  fn store(&self, key: &MasterKey) -> IoResult<()> {
+    if let Some(parent) = Path::new(&self.path).parent() {
+      std::fs::create_dir_all(parent)
+        .map_err(|err| err.map_err_context(|| self.path.clone()))?;
+    }
+    let mut previous = Self::read_keys(&self.path)?;
+    if !previous.is_empty() {
+      let old_path = self.old_path();
+      previous.truncate(1);
+      previous.extend(Self::read_keys(&old_path)?);
+      Self::write_keys(&old_path, &previous)?;
+    }
+    Self::write_keys(&self.path, std::slice::from_ref(key))
+  }
+}
+
+/// Encrypted data stored in place of the secret data
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Envelope {
+  /// Id of the master key that wrapped the data key
+  key_id: String,
+  /// The data key encrypted by the master key
+  data_key: String,
+  /// The secret data encrypted by the data key
+  data: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+  #[serde(rename = "NanoclEnvelope")]
+  envelope: Envelope,
+}
+
+fn random_bytes(len: usize) -> IoResult<Vec<u8>> {
+  let mut bytes = vec![0; len];
+  rand_bytes(&mut bytes).map_err(|err| {
+    IoError::interrupted("Secret random", err.to_string().as_str())
+  })?;
+  Ok(bytes)
+}
+
+/// Encrypt with AES-256-GCM and return `nonce || cipher || tag`
+fn aead_encrypt(key: &[u8], plain: &[u8]) -> IoResult<Vec<u8>> {
+  let nonce = random_bytes(NONCE_LEN)?;
+  let mut tag = [0; TAG_LEN];
+  let cipher = symm::encrypt_aead(
+    Cipher::aes_256_gcm(),
+    key,
+    Some(&nonce),
+    &[],
+    plain,
+    &mut tag,
+  )
+  .map_err(|err| {
+    IoError::interrupted("Secret encrypt", err.to_string().as_str())
+  })?;
+  Ok([nonce, cipher, tag.to_vec()].concat())
+}
+
+/// Decrypt a `nonce || cipher || tag` AES-256-GCM payload
+fn aead_decrypt(key: &[u8], payload: &[u8]) -> IoResult<Vec<u8>> {
+  if payload.len() < NONCE_LEN + TAG_LEN {
+    return Err(IoError::invalid_data("Secret decrypt", "payload too short"));
+  }
+  let (nonce, rest) = payload.split_at(NONCE_LEN);
+  let (cipher, tag) = rest.split_at(rest.len() - TAG_LEN);
+  symm::decrypt_aead(Cipher::aes_256_gcm(), key, Some(nonce), &[], cipher, tag)
+    .map_err(|err| {
+      IoError::invalid_data("Secret decrypt", err.to_string().as_str())
+    })
+}
+
+fn decode(value: &str) -> IoResult<Vec<u8>> {
+  base64::decode_block(value).map_err(|err| {
+    IoError::invalid_data("Secret decode", err.to_string().as_str())
+  })
+}
+
+fn parse_envelope(data: &Value) -> Option<Envelope> {
+  match data.as_object() {
+    Some(object) if object.len() == 1 => {
+      serde_json::from_value::<Sealed>(data.clone())
+        .ok()
+        .map(|sealed| sealed.envelope)
+    }
+    _ => None,
+  }
+}
+
+/// Encrypt the data with a new data key wrapped by the given master key
+pub fn seal(master: &MasterKey, data: &Value) -> IoResult<Value> {
+  let data_key = random_bytes(KEY_LEN)?;
+  let plain = serde_json::to_vec(data)
+    .map_err(|err| err.map_err_context(|| "Secret data"))?;
+  let sealed = Sealed {
+    envelope: Envelope {
+      key_id: master.id.clone(),
+      data_key: base64::encode_block(&aead_encrypt(&master.key, &data_key)?),
+      data: base64::encode_block(&aead_encrypt(&data_key, &plain)?),
+    },
+  };
+  let sealed = serde_json::to_value(sealed)
+    .map_err(|err| err.map_err_context(|| "Secret envelope"))?;
+  Ok(sealed)
+}
+
+/// Decrypt the data using the matching master key.
+/// Data that was never encrypted is returned as is.
+pub fn open(keys: &[MasterKey], data: &Value) -> IoResult<Value> {
+  let Some(envelope) = parse_envelope(data) else {
+    return Ok(data.clone());
+  };
+  let master = keys
+    .iter()
+    .find(|key| key.id == envelope.key_id)
+    .ok_or_else(|| {
+      IoError::not_found("Master key", envelope.key_id.as_str())
+    })?;
+  let data_key = aead_decrypt(&master.key, &decode(&envelope.data_key)?)?;
+  let plain = aead_decrypt(&data_key, &decode(&envelope.data)?)?;
+  let data = serde_json::from_slice(&plain)
+    .map_err(|err| err.map_err_context(|| "Secret data"))?;
+  Ok(data)
+}
+
+/// Load the master keys using the given provider,
+/// a new key is generated on the first boot
+pub fn init_with(provider: Box<dyn KeyProvider>) -> IoResult<()> {
+  if PROVIDER.get().is_some() {
+    return Ok(());
+  }
+  let mut keys = provider.load()?;
+  if keys.is_empty() {
+    log::info!("secret::init: generating a new master key");
+    let key = MasterKey::generate()?;
+    provider.store(&key)?;
+    keys.push(key);
+  }
+  *KEYRING.write()? = keys;
+  let _ = PROVIDER.set(provider);
+  Ok(())
+}
+
+/// Load the master keys from the config or state directory
+pub fn init(conf: &DaemonConfig) -> IoResult<()> {
+  init_with(Box::new(FileKeyProvider::new(conf)))
+}
+
+/// Reload the master keys, used when the key was rotated
+/// while the daemon was running
+fn reload() -> IoResult<()> {
+  let Some(provider) = PROVIDER.get() else {
+    return Ok(());
+  };
+  let keys = provider.load()?;
+  if !keys.is_empty() {
+    *KEYRING.write()? = keys;
+  }
+  Ok(())
+}
+
+/// Encrypt the data with the current master key.
+/// The keys are reloaded first so a rotation done while the daemon
+/// is running never leaves rows sealed by a key that was removed.
+pub fn encrypt(data: &Value) -> IoResult<Value> {
+  reload()?;
+  let keys = KEYRING.read()?;
+  let master = keys.first().ok_or_else(|| {
+    IoError::not_found("Master key", "secret encryption is not initialized")
+  })?;
+  seal(master, data)
+}
+
+/// Decrypt the data of a secret row
+pub fn decrypt(data: &Value) -> IoResult<Value> {
+  let res = open(&KEYRING.read()?, data);
+  match res {
+    Err(err) if err.inner.kind() == std::io::ErrorKind::NotFound => {
+      reload()?;
+      open(&KEYRING.read()?, data)
+    }
+    _ => res,
+  }
+}
+
+/// Hide secret data, only the keys of an object are kept
+pub fn redact_data(data: Value) -> Value {
+  match data {
+    Value::Object(object) => Value::Object(
+      object
+        .into_iter()
+        .map(|(key, _)| (key, Value::String(REDACTED.to_owned())))
+        .collect(),
+    ),
+    _ => Value::String(REDACTED.to_owned()),
+  }
+}
+
+/// Hide the data of a secret
+pub fn redact(mut secret: Secret) -> Secret {
+  secret.data = redact_data(secret.data);
+  secret
+}
+
+fn parse_data<T>(kind: &str, data: &Value) -> HttpResult<T>
+where
+  T: serde::de::DeserializeOwned,
+{
+  serde_json::from_value::<T>(data.clone()).map_err(|err| {
+    HttpError::bad_request(format!(
+      "Invalid data for secret kind {kind}: {err}"
+    ))
+  })
+}
+
+/// Compile the JSONSchema of a secret kind
+pub fn compile_schema(schema: &Value) -> HttpResult<JSONSchema> {
+  JSONSchema::options()
+    .with_draft(Draft::Draft7)
+    .compile(schema)
+    .map_err(|err| HttpError::bad_request(format!("Invalid schema {err}")))
+}
+
+/// Validate the data of a secret against its kind.
+/// Built-in kinds are checked against the structure nanocl expects,
+/// custom kinds against the JSONSchema registered for them if any.
+pub async fn validate(kind: &str, data: &Value, pool: &Pool) -> HttpResult<()> {
+  match kind {
+    KIND_TLS => {
+      parse_data::<ProxySslConfig>(kind, data)?;
+    }
+    KIND_REGISTRY => {
+      parse_data::<DockerCredentials>(kind, data)?;
+    }
+    KIND_ENV => {
+      let envs = parse_data::<Vec<String>>(kind, data)?;
+      if let Some(env) = envs
+        .iter()
+        .find(|env| env.split_once('=').map_or(true, |(key, _)| key.is_empty()))
+      {
+        return Err(HttpError::bad_request(format!(
+          "Invalid data for secret kind {kind}: {env} must be of the form KEY=VALUE"
+        )));
+      }
+    }
+    KIND_HTPASSWD => {
+      let users = parse_data::<Vec<String>>(kind, data)?;
+      if let Some(user) = users.iter().find(|user| {
+        user.split_once(':').map_or(true, |(name, hash)| {
+          name.is_empty() || hash.is_empty() || user.contains(['\n', '\r'])
+        })
+      }) {
+        return Err(HttpError::bad_request(format!(
+          "Invalid data for secret kind {kind}: {user} must be of the form user:hash"
+        )));
+      }
+    }
+    _ if kind.starts_with(BUILTIN_DOMAIN) => {
+      return Err(HttpError::bad_request(format!(
+        "Unknown secret kind {kind} expected one of {KIND_TLS}, {KIND_ENV}, {KIND_REGISTRY}, {KIND_HTPASSWD}"
+      )));
+    }
+    _ => {
+      let filter = GenericFilter::new()
+        .r#where("name", GenericClause::Eq(kind.to_owned()));
+      let Some(secret_kind) = SecretKindDb::read_by(&filter, pool)
+        .await?
+        .into_iter()
+        .next()
+      else {
+        return Ok(());
+      };
+      let schema = compile_schema(&secret_kind.schema)?;
+      schema.validate(data).map_err(|errors| {
+        let errors = errors.map(|err| err.to_string()).collect::<Vec<_>>();
+        HttpError::bad_request(format!(
+          "Invalid data for secret kind {kind}: {}",
+          errors.join(", ")
+        ))
+      })?;
+    }
+  }
+  Ok(())
+}
+
+/// Ensure a custom secret kind can be registered
+pub fn ensure_custom_kind(name: &str) -> HttpResult<()> {
+  super::key::ensure_kind(name)?;
+  if name.starts_with(BUILTIN_DOMAIN) {
+    return Err(HttpError::bad_request(format!(
+      "Secret kind domain {BUILTIN_DOMAIN} is reserved"
+    )));
+  }
+  Ok(())
+}
+
+/// Read every row matching the filter, page by page
+async fn read_all<T>(
+  filter: &GenericFilter,
+  pool: &Pool,
+) -> IoResult<Vec<T::Output>>
+where
+  T: RepositoryReadBy,
+  T::Output: Sized + Send + 'static,
+{
+  let mut rows = Vec::new();
+  loop {
+    let filter = filter.clone().limit(PAGE_SIZE).offset(rows.len());
+    let page = T::read_by(&filter, pool).await?;
+    let len = page.len();
+    rows.extend(page);
+    if len < PAGE_SIZE {
+      return Ok(rows);
+    }
+  }
+}
+
+fn secret_history_filter() -> GenericFilter {
+  GenericFilter::new()
+    .r#where("kind_name", GenericClause::Eq("Secret".to_owned()))
+}
+
+/// Seal every secret, secret history and webhook secret that isn't encrypted
+/// with the current master key.
+/// Rows in plain text or sealed by a previous key are re-encrypted.
+pub async fn seal_rows(pool: &Pool) -> IoResult<usize> {
+  let master = KEYRING.read()?.first().cloned().ok_or_else(|| {
+    IoError::not_found("Master key", "secret encryption is not initialized")
+  })?;
+  let is_sealed = |data: &Value| {
+    parse_envelope(data).map_or(false, |envelope| envelope.key_id == master.id)
+  };
+  let mut count = 0;
+  for row in read_all::<SecretDb>(&GenericFilter::new(), pool).await? {
+    if is_sealed(&row.data) {
+      continue;
+    }
+    let data = seal(&master, &decrypt(&row.data)?)?;
+    let update = SecretUpdateDb {
+      data: Some(data),
+      ..Default::default()
+    };
+    SecretDb::update_pk(&row.key, update, pool).await?;
+    count += 1;
+  }
+  for history in read_all::<SpecDb>(&secret_history_filter(), pool).await? {
+    if is_sealed(&history.data) {
+      continue;
+    }
+    let update = SpecUpdateDb {
+      data: Some(seal(&master, &decrypt(&history.data)?)?),
+    };
+    SpecDb::update_pk(&history.key, update, pool).await?;
+  }
+  for webhook in read_all::<WebhookDb>(&GenericFilter::new(), pool).await? {
+    let Some(secret) = webhook.secret.as_ref() else {
+      continue;
+    };
+    if is_sealed(secret) {
+      continue;
+    }
+    let update = WebhookUpdateDb {
+      secret: Some(Some(seal(&master, &decrypt(secret)?)?)),
+    };
+    WebhookDb::update_pk(&webhook.name, update, pool).await?;
+  }
+  Ok(count)
+}
+
+/// List the ids of the master keys sealing at least one row
+async fn used_key_ids(pool: &Pool) -> IoResult<HashSet<String>> {
+  let secrets = read_all::<SecretDb>(&GenericFilter::new(), pool).await?;
+  let histories = read_all::<SpecDb>(&secret_history_filter(), pool).await?;
+  let webhooks = read_all::<WebhookDb>(&GenericFilter::new(), pool).await?;
+  let used = secrets
+    .iter()
+    .map(|row| &row.data)
+    .chain(histories.iter().map(|history| &history.data))
+    .chain(
+      webhooks
+        .iter()
+        .filter_map(|webhook| webhook.secret.as_ref()),
+    )
+    .filter_map(parse_envelope)
+    .map(|envelope| envelope.key_id)
+    .collect();
+  Ok(used)
+}
+
+/// Generate a new master key and re-encrypt every secret with it.
+/// The new key is stored before the rows are updated and the previous
+/// keys are only removed once no row is sealed by them,
+/// so an interrupted rotation can be resumed by running it again.
+/// Called by `nanocld secret rotate-key`.
+pub async fn rotate_key(conf: &DaemonConfig) -> IoResult<()> {
+  let provider = FileKeyProvider::new(conf);
+  let pool = super::store::init(conf).await?;
+  let mut keys = provider.load()?;
+  let master = MasterKey::generate()?;
+  provider.store(&master)?;
+  keys.insert(0, master);
+  *KEYRING.write()? = keys;
+  let count = seal_rows(&pool).await?;
+  log::info!("secret::rotate_key: {count} secrets re-encrypted");
+  provider.retain(&used_key_ids(&pool).await?)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  #[test]
+  fn seal_and_open() {
+    let master = MasterKey::generate().unwrap();
+    let data = json!({ "Username": "admin", "Password": "secret" });
+    let sealed = seal(&master, &data).unwrap();
+    assert!(parse_envelope(&sealed).is_some());
+    assert!(!sealed.to_string().contains("secret"));
+    let keys = vec![master];
+    assert_eq!(open(&keys, &sealed).unwrap(), data);
+    assert_eq!(open(&keys, &data).unwrap(), data);
+    let other = vec![MasterKey::generate().unwrap()];
+    assert!(open(&other, &sealed).is_err());
+  }
+
+  #[test]
+  fn tampered_data() {
+    let master = MasterKey::generate().unwrap();
+    let mut sealed = seal(&master, &json!(["A=1"])).unwrap();
+    sealed["NanoclEnvelope"]["Data"] =
+      Value::String(base64::encode_block(&[0; 40]));
+    assert!(open(&[master], &sealed).is_err());
+  }
+
+  #[test]
+  fn file_keyring() {
+    let dir = std::env::temp_dir()
+      .join(format!("nanocl-keyring-{}", std::process::id()));
+    let provider = FileKeyProvider {
+      path: dir.join("secret.key").to_string_lossy().to_string(),
+    };
+    let first = MasterKey::generate().unwrap();
+    let second = MasterKey::generate().unwrap();
+    let third = MasterKey::generate().unwrap();
+    provider.store(&first).unwrap();
+    provider.store(&second).unwrap();
+    provider.store(&third).unwrap();
+    let ids = |keys: Vec<MasterKey>| {
+      keys.into_iter().map(|key| key.id).collect::<Vec<_>>()
+    };
+    assert_eq!(
+      ids(provider.load().unwrap()),
+      vec![third.id.clone(), second.id.clone(), first.id.clone()]
+    );
+    provider.retain(&HashSet::from([first.id.clone()])).unwrap();
+    assert_eq!(
+      ids(provider.load().unwrap()),
+      vec![third.id.clone(), first.id.clone()]
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+}
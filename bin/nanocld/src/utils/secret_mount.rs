@@ -0,0 +1,501 @@
+use std::{
+  collections::HashMap,
+  path::{Component, Path},
+};
+
+use ntex::{
+  rt,
+  http::client::{Client, Connector},
+};
+use bollard_next::{
+  container::{Config, CreateContainerOptions, RemoveContainerOptions},
+  service::HostConfig,
+  volume::{CreateVolumeOptions, ListVolumesOptions, RemoveVolumeOptions},
+};
+
+use nanocl_error::http::{HttpError, HttpResult};
+
+use nanocl_stubs::{
+  cargo::Cargo, cargo_spec::SecretMount, generic::GenericFilter,
+  process::ProcessKind,
+};
+
+use crate::{
+  repositories::generic::*,
+  models::{CargoDb, SecretDb, SystemState},
+};
+
+/// Default mode of the files of a secret mount
+const SECRET_MOUNT_MODE: u32 = 0o400;
+/// Maximum size of the tmpfs volume of a secret mount
+const SECRET_MOUNT_SIZE: &str = "16m";
+/// Label set on the volumes of the secret mounts with the key of the cargo
+const SECRET_MOUNT_LABEL: &str = "io.nanocl.secret-mount";
+/// Path of the volume in the container used to write the files
+const WRITER_PATH: &str = "/nanocl-secret";
+/// Size of a block of a tar archive
+const BLOCK_SIZE: usize = 512;
+
+/// A file of a secret mount
+#[derive(Debug, PartialEq)]
+pub struct SecretFile {
+  /// Path relative to the mount target
+  pub path: String,
+  pub content: Vec<u8>,
+  pub mode: u32,
+}
+
+/// Ensure a path is relative and stays inside its parent directory
+fn is_relative_path(path: &str) -> bool {
+  !path.is_empty()
+    && Path::new(path)
+      .components()
+      .all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Ensure the secret mounts of a cargo are valid.
+/// Targets must be absolute without `..` and item paths relative to them.
+pub fn validate(mounts: &[SecretMount]) -> HttpResult<()> {
+  for mount in mounts {
+    let target = Path::new(&mount.target);
+    let is_valid = target.is_absolute()
+      && target
+        .components()
+        .any(|c| matches!(c, Component::Normal(_)))
+      && target
+        .components()
+        .all(|c| matches!(c, Component::RootDir | Component::Normal(_)));
+    if !is_valid {
+      return Err(HttpError::bad_request(format!(
+        "Secret mount target {} must be an absolute path without ..",
+        mount.target
+      )));
+    }
+    for item in mount.items.iter().flatten() {
+      let path = item.path.as_deref().unwrap_or(&item.key);
+      if !is_relative_path(path) {
+        return Err(HttpError::bad_request(format!(
+          "Secret mount path {path} must be relative to the target"
+        )));
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Generate the files of a mount from the data of its secret.
+/// Strings are written as is, other values as json.
+pub fn gen_files(
+  mount: &SecretMount,
+  data: &serde_json::Value,
+) -> HttpResult<Vec<SecretFile>> {
+  let items = match (&mount.items, data.as_object()) {
+    (Some(items), _) => items
+      .iter()
+      .map(|item| (item.key.clone(), item.path.clone(), item.mode))
+      .collect::<Vec<_>>(),
+    (None, Some(data)) => {
+      data.keys().map(|key| (key.clone(), None, None)).collect()
+    }
+    (None, None) => {
+      return Err(HttpError::bad_request(format!(
+        "Secret {} data must be an object to be mounted without items",
+        mount.secret
+      )))
+    }
+  };
+  items
+    .into_iter()
+    .map(|(key, path, mode)| {
+      let path = path.unwrap_or(key.clone());
+      if !is_relative_path(&path) {
+        return Err(HttpError::bad_request(format!(
+          "Secret mount path {path} must be relative to the target"
+        )));
+      }
+      let content = match data.get(&key) {
+        Some(serde_json::Value::String(value)) => value.clone(),
+        Some(value) => value.to_string(),
+        None => {
+          return Err(HttpError::bad_request(format!(
+            "Secret {} has no key {key}",
+            mount.secret
+          )))
+        }
+      };
+      Ok(SecretFile {
+        path,
+        content: content.into_bytes(),
+        mode: mode.or(mount.mode).unwrap_or(SECRET_MOUNT_MODE),
+      })
+    })
+    .collect()
+}
+
+/// Write a zero padded octal number in a field of a tar header
+fn write_octal(field: &mut [u8], value: u64) {
+  let len = field.len() - 1;
+  let octal = format!("{value:0len$o}");
+  field[..len].copy_from_slice(&octal.as_bytes()[octal.len() - len..]);
+  field[len] = 0;
+}
+
+/// Generate a ustar archive of the files to upload in the volume of a mount
+pub fn gen_archive(files: &[SecretFile]) -> HttpResult<Vec<u8>> {
+  let mut archive = Vec::new();
+  for file in files {
+    if file.path.len() > 100 {
+      return Err(HttpError::bad_request(format!(
+        "Secret mount path {} is longer than 100 bytes",
+        file.path
+      )));
+    }
+    let mut header = [0; BLOCK_SIZE];
+    header[..file.path.len()].copy_from_slice(file.path.as_bytes());
+    write_octal(&mut header[100..108], (file.mode & 0o7777) as u64);
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    write_octal(&mut header[124..136], file.content.len() as u64);
+    write_octal(&mut header[136..148], 0);
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    // The checksum is computed with its own field filled with spaces
+    header[148..156].fill(b' ');
+    let checksum = header.iter().map(|b| *b as u64).sum::<u64>();
+    write_octal(&mut header[148..155], checksum);
+    archive.extend_from_slice(&header);
+    archive.extend_from_slice(&file.content);
+    let padding = (BLOCK_SIZE - file.content.len() % BLOCK_SIZE) % BLOCK_SIZE;
+    archive.resize(archive.len() + padding, 0);
+  }
+  // An archive ends with two empty blocks
+  archive.resize(archive.len() + 2 * BLOCK_SIZE, 0);
+  Ok(archive)
+}
+
+/// Name of the volume holding the files of a secret mount
+fn volume_name(cargo_key: &str, index: usize) -> String {
+  format!("{cargo_key}-secret-{index}")
+}
+
+/// Options of the tmpfs volume of a secret mount,
+/// its root directory is owned by the owner of the files
+fn volume_options(
+  cargo_key: &str,
+  volume: &str,
+  mount: &SecretMount,
+) -> CreateVolumeOptions<String> {
+  let opts = format!(
+    "size={SECRET_MOUNT_SIZE},mode=0755,uid={},gid={}",
+    mount.uid.unwrap_or_default(),
+    mount.gid.unwrap_or_default()
+  );
+  CreateVolumeOptions {
+    name: volume.to_owned(),
+    driver: "local".to_owned(),
+    driver_opts: HashMap::from([
+      ("type".to_owned(), "tmpfs".to_owned()),
+      ("device".to_owned(), "tmpfs".to_owned()),
+      ("o".to_owned(), opts),
+    ]),
+    labels: HashMap::from([(
+      SECRET_MOUNT_LABEL.to_owned(),
+      cargo_key.to_owned(),
+    )]),
+  }
+}
+
+/// Upload an archive in a container owned by the user of the container.
+/// The docker client doesn't expose the `copyUIDGID` option
+/// so the request is sent to the docker socket directly.
+async fn upload_archive(
+  container: &str,
+  archive: Vec<u8>,
+  state: &SystemState,
+) -> HttpResult<()> {
+  let socket = state
+    .inner
+    .config
+    .docker_host
+    .trim_start_matches("unix://")
+    .to_owned();
+  let client = Client::build()
+    .connector(
+      Connector::default()
+        .connector(ntex::service::fn_service(move |_| {
+          let socket = socket.clone();
+          async move { Ok(rt::unix_connect(socket).await?) }
+        }))
+        .finish(),
+    )
+    .finish();
+  let url = format!(
+    "http://localhost/containers/{container}/archive?path={WRITER_PATH}&copyUIDGID=1"
+  );
+  let mut res = client
+    .put(url)
+    .header("Content-Type", "application/x-tar")
+    .send_body(archive)
+    .await
+    .map_err(HttpError::internal_server_error)?;
+  if !res.status().is_success() {
+    let body = res.body().await.unwrap_or_default();
+    return Err(HttpError::internal_server_error(format!(
+      "Unable to write the secret files: {}",
+      String::from_utf8_lossy(&body)
+    )));
+  }
+  Ok(())
+}
+
+/// Write the files of a mount in its volume.
+/// The volume is a tmpfs so the files only live while an instance mount it,
+/// they are uploaded through a container created but never started.
+async fn write_volume(
+  cargo: &Cargo,
+  volume: &str,
+  mount: &SecretMount,
+  state: &SystemState,
+) -> HttpResult<()> {
+  let secret =
+    SecretDb::transform_read_by_pk(&mount.secret, &state.inner.pool).await?;
+  let archive = gen_archive(&gen_files(mount, &secret.data)?)?;
+  let docker_api = &state.inner.docker_api;
+  let writer = format!("{volume}-{}", super::key::generate_short_id(6));
+  let config = Config {
+    image: cargo.spec.container.image.clone(),
+    user: Some(format!(
+      "{}:{}",
+      mount.uid.unwrap_or_default(),
+      mount.gid.unwrap_or_default()
+    )),
+    network_disabled: Some(true),
+    host_config: Some(HostConfig {
+      binds: Some(vec![format!("{volume}:{WRITER_PATH}")]),
+      ..Default::default()
+    }),
+    ..Default::default()
+  };
+  let options = Some(CreateContainerOptions {
+    name: writer.as_str(),
+    ..Default::default()
+  });
+  docker_api.create_container(options, config).await?;
+  let res = upload_archive(&writer, archive, state).await;
+  let options = Some(RemoveContainerOptions {
+    force: true,
+    ..Default::default()
+  });
+  if let Err(err) = docker_api.remove_container(&writer, options).await {
+    log::warn!("secret_mount::write_volume: {writer} {err}");
+  }
+  res
+}
+
+/// Create the volumes of the secret mounts of a cargo
+/// and return the binds to add to its instances
+pub async fn create_volumes(
+  cargo: &Cargo,
+  state: &SystemState,
+) -> HttpResult<Vec<String>> {
+  let mut binds = Vec::new();
+  for (index, mount) in cargo.spec.secret_mounts.iter().flatten().enumerate() {
+    let volume = volume_name(&cargo.spec.cargo_key, index);
+    let options = volume_options(&cargo.spec.cargo_key, &volume, mount);
+    state.inner.docker_api.create_volume(options).await?;
+    binds.push(format!("{volume}:{}:ro", mount.target));
+  }
+  Ok(binds)
+}
+
+/// Write the files of the secret mounts of a cargo.
+/// Called each time an instance start since tmpfs doesn't survive a restart.
+pub async fn write_all(cargo_key: &str, state: &SystemState) -> HttpResult<()> {
+  let cargo =
+    CargoDb::transform_read_by_pk(cargo_key, &state.inner.pool).await?;
+  for (index, mount) in cargo.spec.secret_mounts.iter().flatten().enumerate() {
+    let volume = volume_name(&cargo.spec.cargo_key, index);
+    write_volume(&cargo, &volume, mount, state).await?;
+  }
+  Ok(())
+}
+
+/// Remove the volumes of the secret mounts of a cargo
+pub async fn remove_all(cargo_key: &str, state: &SystemState) {
+  let options = ListVolumesOptions {
+    filters: HashMap::from([(
+      "label".to_owned(),
+      vec![format!("{SECRET_MOUNT_LABEL}={cargo_key}")],
+    )]),
+  };
+  let volumes = match state.inner.docker_api.list_volumes(Some(options)).await {
+    Ok(res) => res.volumes.unwrap_or_default(),
+    Err(err) => {
+      log::warn!("secret_mount::remove_all: {cargo_key} {err}");
+      return;
+    }
+  };
+  for volume in volumes {
+    let options = Some(RemoveVolumeOptions { force: true });
+    let res = state
+      .inner
+      .docker_api
+      .remove_volume(&volume.name, options)
+      .await;
+    if let Err(err) = res {
+      log::warn!("secret_mount::remove_all: {} {err}", volume.name);
+    }
+  }
+}
+
+/// Rewrite the files of the cargoes mounting an updated secret
+/// and restart their instances when asked by the mount.
+/// Files of keys removed from the secret are kept until the cargo is deleted.
+pub async fn sync(secret_key: &str, state: &SystemState) -> HttpResult<()> {
+  let cargoes =
+    CargoDb::transform_read_by(&GenericFilter::new(), &state.inner.pool)
+      .await?;
+  for cargo in cargoes {
+    let mut restart = false;
+    for (index, mount) in cargo.spec.secret_mounts.iter().flatten().enumerate()
+    {
+      if mount.secret != secret_key {
+        continue;
+      }
+      let volume = volume_name(&cargo.spec.cargo_key, index);
+      write_volume(&cargo, &volume, mount, state).await?;
+      restart |= mount.restart_on_update.unwrap_or_default();
+    }
+    if restart {
+      super::container::restart_instances(
+        &cargo.spec.cargo_key,
+        &ProcessKind::Cargo,
+        state,
+      )
+      .await?;
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use nanocl_stubs::cargo_spec::SecretMountItem;
+
+  use super::*;
+
+  fn mount(target: &str) -> SecretMount {
+    SecretMount {
+      secret: "tls".to_owned(),
+      target: target.to_owned(),
+      items: None,
+      mode: None,
+      uid: None,
+      gid: None,
+      restart_on_update: None,
+    }
+  }
+
+  #[test]
+  fn validate_target() {
+    assert!(validate(&[mount("/etc/tls")]).is_ok());
+    assert!(validate(&[mount("/etc/./tls")]).is_ok());
+    assert!(validate(&[mount("etc/tls")]).is_err());
+    assert!(validate(&[mount("/etc/../tls")]).is_err());
+    assert!(validate(&[mount("/")]).is_err());
+    assert!(validate(&[mount("")]).is_err());
+    let with_item = |path: &str| SecretMount {
+      items: Some(vec![SecretMountItem {
+        key: "Certificate".to_owned(),
+        path: Some(path.to_owned()),
+        mode: None,
+      }]),
+      ..mount("/etc/tls")
+    };
+    assert!(validate(&[with_item("certs/cert.pem")]).is_ok());
+    assert!(validate(&[with_item("../cert.pem")]).is_err());
+    assert!(validate(&[with_item("/cert.pem")]).is_err());
+  }
+
+  #[test]
+  fn files() {
+    let data = serde_json::json!({
+      "Certificate": "cert",
+      "Port": 443,
+    });
+    let files = gen_files(&mount("/etc/tls"), &data).unwrap();
+    assert_eq!(
+      files,
+      vec![
+        SecretFile {
+          path: "Certificate".to_owned(),
+          content: b"cert".to_vec(),
+          mode: SECRET_MOUNT_MODE,
+        },
+        SecretFile {
+          path: "Port".to_owned(),
+          content: b"443".to_vec(),
+          mode: SECRET_MOUNT_MODE,
+        },
+      ]
+    );
+    let with_items = SecretMount {
+      items: Some(vec![SecretMountItem {
+        key: "Missing".to_owned(),
+        path: None,
+        mode: None,
+      }]),
+      ..mount("/etc/tls")
+    };
+    assert!(gen_files(&with_items, &data).is_err());
+    assert!(gen_files(&mount("/etc/tls"), &serde_json::json!(["A=1"])).is_err());
+  }
+
+  #[test]
+  fn tmpfs_volume() {
+    let mount = SecretMount {
+      uid: Some(1000),
+      ..mount("/etc/tls")
+    };
+    let options = volume_options("app.global", "app.global-secret-0", &mount);
+    assert_eq!(options.driver_opts["type"], "tmpfs");
+    assert_eq!(options.driver_opts["device"], "tmpfs");
+    assert_eq!(
+      options.driver_opts["o"],
+      format!("size={SECRET_MOUNT_SIZE},mode=0755,uid=1000,gid=0")
+    );
+    assert_eq!(options.labels[SECRET_MOUNT_LABEL], "app.global");
+  }
+
+  #[test]
+  fn archive() {
+    let files = vec![SecretFile {
+      path: "certs/cert.pem".to_owned(),
+      content: b"certificate".to_vec(),
+      mode: 0o440,
+    }];
+    let archive = gen_archive(&files).unwrap();
+    assert_eq!(archive.len(), 4 * BLOCK_SIZE);
+    let header = &archive[..BLOCK_SIZE];
+    assert_eq!(&header[..14], b"certs/cert.pem");
+    assert_eq!(&header[100..108], b"0000440\0");
+    assert_eq!(&header[124..136], b"00000000013\0");
+    assert_eq!(&header[257..263], b"ustar\0");
+    let checksum = header
+      .iter()
+      .enumerate()
+      .map(|(i, b)| if (148..156).contains(&i) { b' ' } else { *b } as u64)
+      .sum::<u64>();
+    let field = std::str::from_utf8(&header[148..154]).unwrap();
+    assert_eq!(u64::from_str_radix(field, 8).unwrap(), checksum);
+    assert_eq!(&archive[BLOCK_SIZE..BLOCK_SIZE + 11], b"certificate");
+    assert!(archive[BLOCK_SIZE + 11..].iter().all(|b| *b == 0));
+    let long = vec![SecretFile {
+      path: "a".repeat(101),
+      content: Vec::new(),
+      mode: 0o400,
+    }];
+    assert!(gen_archive(&long).is_err());
+  }
+}
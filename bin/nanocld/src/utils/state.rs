@@ -0,0 +1,473 @@
+use std::time::Duration;
+
+use ntex::{
+  rt,
+  util::Bytes,
+  channel::mpsc::{self, Receiver, Sender},
+};
+
+use nanocl_error::http::{HttpError, HttpResult};
+
+use nanocl_stubs::{
+  cargo::CargoDeleteQuery,
+  cargo_spec::CargoSpecPartial,
+  job::JobPartial,
+  namespace::NamespacePartial,
+  process::ProcessKind,
+  resource::ResourcePartial,
+  secret::{SecretPartial, SecretUpdate},
+  statefile::{Statefile, StateStream, StateStreamStatus},
+  vm_spec::VmSpecPartial,
+};
+
+use crate::{
+  utils,
+  objects::generic::*,
+  repositories::generic::*,
+  models::{
+    CargoDb, CargoObjCreateIn, CargoObjPutIn, JobDb, NamespaceDb, ResourceDb,
+    SecretDb, SystemState, VmDb, VmObjCreateIn, VmObjPutIn,
+  },
+};
+
+/// Change made on an object during an apply with the previous specification.
+/// When the previous specification is `None` the object has been created.
+enum StateChange {
+  Secret(String, Option<SecretPartial>),
+  Job(String, Option<JobPartial>),
+  Cargo(String, Option<CargoSpecPartial>),
+  Vm(String, Option<VmSpecPartial>),
+  Resource(String, Option<ResourcePartial>),
+}
+
+/// Send the progress of an object to the client
+fn send(
+  tx: &Sender<HttpResult<Bytes>>,
+  kind: &str,
+  key: &str,
+  status: StateStreamStatus,
+  context: Option<String>,
+) {
+  let stream = StateStream {
+    kind: kind.to_owned(),
+    key: key.to_owned(),
+    status,
+    context,
+  };
+  let item = serde_json::to_string(&stream)
+    .map(|item| Bytes::from(item + "\r\n"))
+    .map_err(|err| {
+      HttpError::internal_server_error(format!(
+        "Failed stringify stream item: {err}"
+      ))
+    });
+  let _ = tx.send(item);
+}
+
+/// Wait for a job to be removed by his destroy task
+async fn wait_job_deleted(name: &str, state: &SystemState) -> HttpResult<()> {
+  for _ in 0..60 {
+    if JobDb::read_by_pk(name, &state.inner.pool).await.is_err() {
+      return Ok(());
+    }
+    ntex::time::sleep(Duration::from_millis(500)).await;
+  }
+  Err(HttpError::internal_server_error(format!(
+    "Job {name} is still being deleted"
+  )))
+}
+
+/// Replace a job by a new one, jobs cannot be updated
+async fn replace_job(
+  name: &str,
+  job: Option<&JobPartial>,
+  state: &SystemState,
+) -> HttpResult<()> {
+  if JobDb::read_by_pk(name, &state.inner.pool).await.is_ok() {
+    JobDb::del_obj_by_pk(name, &(), state).await?;
+    wait_job_deleted(name, state).await?;
+  }
+  if let Some(job) = job {
+    JobDb::create_obj(job, state).await?;
+    utils::job::emit_starting(name, state).await?;
+  }
+  Ok(())
+}
+
+async fn apply_secret(
+  secret: &SecretPartial,
+  state: &SystemState,
+) -> HttpResult<(StateStreamStatus, Option<StateChange>)> {
+  let key = &secret.name;
+  match SecretDb::transform_read_by_pk(key, &state.inner.pool).await {
+    Err(_) => {
+      SecretDb::create_obj(secret, state).await?;
+      let change = StateChange::Secret(key.clone(), None);
+      Ok((StateStreamStatus::Created, Some(change)))
+    }
+    Ok(current) => {
+      let current: SecretPartial = current.into();
+      if current == *secret {
+        return Ok((StateStreamStatus::Unchanged, None));
+      }
+      let update: SecretUpdate = secret.clone().into();
+      SecretDb::patch_obj_by_pk(key, &update, state).await?;
+      let change = StateChange::Secret(key.clone(), Some(current));
+      Ok((StateStreamStatus::Updated, Some(change)))
+    }
+  }
+}
+
+async fn apply_job(
+  job: &JobPartial,
+  state: &SystemState,
+) -> HttpResult<(StateStreamStatus, Option<StateChange>)> {
+  let key = &job.name;
+  match JobDb::transform_read_by_pk(key, &state.inner.pool).await {
+    Err(_) => {
+      replace_job(key, Some(job), state).await?;
+      let change = StateChange::Job(key.clone(), None);
+      Ok((StateStreamStatus::Created, Some(change)))
+    }
+    Ok(current) => {
+      let current: JobPartial = current.into();
+      if current == *job {
+        return Ok((StateStreamStatus::Unchanged, None));
+      }
+      replace_job(key, Some(job), state).await?;
+      let change = StateChange::Job(key.clone(), Some(current));
+      Ok((StateStreamStatus::Updated, Some(change)))
+    }
+  }
+}
+
+async fn apply_cargo(
+  version: &str,
+  namespace: &str,
+  cargo: &CargoSpecPartial,
+  state: &SystemState,
+) -> HttpResult<(StateStreamStatus, Option<StateChange>)> {
+  let key = utils::key::gen_key(namespace, &cargo.name);
+  let (status, change) =
+    match CargoDb::transform_read_by_pk(&key, &state.inner.pool).await {
+      Err(_) => {
+        let obj = CargoObjCreateIn {
+          namespace: namespace.to_owned(),
+          spec: cargo.clone(),
+          version: version.to_owned(),
+        };
+        CargoDb::create_obj(&obj, state).await?;
+        let change = StateChange::Cargo(key.clone(), None);
+        (StateStreamStatus::Created, Some(change))
+      }
+      Ok(current) => {
+        let current: CargoSpecPartial = current.into();
+        if current == *cargo {
+          (StateStreamStatus::Unchanged, None)
+        } else {
+          let obj = CargoObjPutIn {
+            spec: cargo.clone(),
+            version: version.to_owned(),
+          };
+          CargoDb::put_obj_by_pk(&key, &obj, state).await?;
+          let change = StateChange::Cargo(key.clone(), Some(current));
+          (StateStreamStatus::Updated, Some(change))
+        }
+      }
+    };
+  utils::container::emit_starting(&key, &ProcessKind::Cargo, state).await?;
+  Ok((status, change))
+}
+
+async fn apply_vm(
+  version: &str,
+  namespace: &str,
+  vm: &VmSpecPartial,
+  state: &SystemState,
+) -> HttpResult<(StateStreamStatus, Option<StateChange>)> {
+  let key = utils::key::gen_key(namespace, &vm.name);
+  let (status, change) =
+    match VmDb::transform_read_by_pk(&key, &state.inner.pool).await {
+      Err(_) => {
+        let obj = VmObjCreateIn {
+          namespace: namespace.to_owned(),
+          spec: vm.clone(),
+          version: version.to_owned(),
+        };
+        VmDb::create_obj(&obj, state).await?;
+        let change = StateChange::Vm(key.clone(), None);
+        (StateStreamStatus::Created, Some(change))
+      }
+      Ok(current) => {
+        let current: VmSpecPartial = current.into();
+        if current == *vm {
+          (StateStreamStatus::Unchanged, None)
+        } else {
+          let obj = VmObjPutIn {
+            spec: vm.clone(),
+            version: version.to_owned(),
+          };
+          VmDb::put_obj_by_pk(&key, &obj, state).await?;
+          let change = StateChange::Vm(key.clone(), Some(current));
+          (StateStreamStatus::Updated, Some(change))
+        }
+      }
+    };
+  utils::container::emit_starting(&key, &ProcessKind::Vm, state).await?;
+  Ok((status, change))
+}
+
+async fn apply_resource(
+  resource: &ResourcePartial,
+  state: &SystemState,
+) -> HttpResult<(StateStreamStatus, Option<StateChange>)> {
+  let key = &resource.name;
+  match ResourceDb::transform_read_by_pk(key, &state.inner.pool).await {
+    Err(_) => {
+      ResourceDb::create_obj(resource, state).await?;
+      let change = StateChange::Resource(key.clone(), None);
+      Ok((StateStreamStatus::Created, Some(change)))
+    }
+    Ok(current) => {
+      let current: ResourcePartial = current.into();
+      if current == *resource {
+        return Ok((StateStreamStatus::Unchanged, None));
+      }
+      ResourceDb::put_obj_by_pk(key, resource, state).await?;
+      let change = StateChange::Resource(key.clone(), Some(current));
+      Ok((StateStreamStatus::Updated, Some(change)))
+    }
+  }
+}
+
+/// Send the result of an object apply and keep track of his change
+fn track(
+  tx: &Sender<HttpResult<Bytes>>,
+  kind: &str,
+  key: &str,
+  res: HttpResult<(StateStreamStatus, Option<StateChange>)>,
+  changes: &mut Vec<StateChange>,
+) -> HttpResult<()> {
+  match res {
+    Err(err) => {
+      send(
+        tx,
+        kind,
+        key,
+        StateStreamStatus::Failed,
+        Some(err.to_string()),
+      );
+      Err(err)
+    }
+    Ok((status, change)) => {
+      send(tx, kind, key, status, None);
+      changes.extend(change);
+      Ok(())
+    }
+  }
+}
+
+/// Apply every object of a Statefile in the same order as the cli
+/// Secrets, jobs, cargoes, virtual machines and resources
+async fn apply_objects(
+  version: &str,
+  statefile: &Statefile,
+  tx: &Sender<HttpResult<Bytes>>,
+  changes: &mut Vec<StateChange>,
+  state: &SystemState,
+) -> HttpResult<()> {
+  let namespace = utils::key::resolve_nsp(&statefile.namespace);
+  if NamespaceDb::read_by_pk(&namespace, &state.inner.pool)
+    .await
+    .is_err()
+  {
+    let partial = NamespacePartial {
+      name: namespace.clone(),
+    };
+    NamespaceDb::create_obj(&partial, state).await?;
+  }
+  for secret in statefile.secrets.iter().flatten() {
+    let key = &secret.name;
+    send(tx, "Secret", key, StateStreamStatus::Pending, None);
+    let res = apply_secret(secret, state).await;
+    track(tx, "Secret", key, res, changes)?;
+  }
+  for job in statefile.jobs.iter().flatten() {
+    let key = &job.name;
+    send(tx, "Job", key, StateStreamStatus::Pending, None);
+    let res = apply_job(job, state).await;
+    track(tx, "Job", key, res, changes)?;
+  }
+  for cargo in statefile.cargoes.iter().flatten() {
+    let key = utils::key::gen_key(&namespace, &cargo.name);
+    send(tx, "Cargo", &key, StateStreamStatus::Pending, None);
+    let res = apply_cargo(version, &namespace, cargo, state).await;
+    track(tx, "Cargo", &key, res, changes)?;
+  }
+  for vm in statefile.virtual_machines.iter().flatten() {
+    let key = utils::key::gen_key(&namespace, &vm.name);
+    send(tx, "Vm", &key, StateStreamStatus::Pending, None);
+    let res = apply_vm(version, &namespace, vm, state).await;
+    track(tx, "Vm", &key, res, changes)?;
+  }
+  for resource in statefile.resources.iter().flatten() {
+    let key = &resource.name;
+    send(tx, "Resource", key, StateStreamStatus::Pending, None);
+    let res = apply_resource(resource, state).await;
+    track(tx, "Resource", key, res, changes)?;
+  }
+  Ok(())
+}
+
+/// Revert a change made during an apply
+async fn rollback(
+  version: &str,
+  change: &StateChange,
+  state: &SystemState,
+) -> HttpResult<()> {
+  match change {
+    StateChange::Secret(key, None) => {
+      SecretDb::del_obj_by_pk(key, &(), state).await?;
+    }
+    StateChange::Secret(key, Some(prev)) => {
+      let update: SecretUpdate = prev.clone().into();
+      SecretDb::patch_obj_by_pk(key, &update, state).await?;
+    }
+    StateChange::Job(key, prev) => {
+      replace_job(key, prev.as_ref(), state).await?;
+    }
+    StateChange::Cargo(key, None) => {
+      let opts = CargoDeleteQuery {
+        namespace: None,
+        force: Some(true),
+      };
+      CargoDb::del_obj_by_pk(key, &opts, state).await?;
+    }
+    StateChange::Cargo(key, Some(prev)) => {
+      let obj = CargoObjPutIn {
+        spec: prev.clone(),
+        version: version.to_owned(),
+      };
+      CargoDb::put_obj_by_pk(key, &obj, state).await?;
+    }
+    StateChange::Vm(key, None) => {
+      VmDb::del_obj_by_pk(key, &(), state).await?;
+    }
+    StateChange::Vm(key, Some(prev)) => {
+      let obj = VmObjPutIn {
+        spec: prev.clone(),
+        version: version.to_owned(),
+      };
+      VmDb::put_obj_by_pk(key, &obj, state).await?;
+    }
+    StateChange::Resource(key, None) => {
+      ResourceDb::del_obj_by_pk(key, &(), state).await?;
+    }
+    StateChange::Resource(key, Some(prev)) => {
+      ResourceDb::put_obj_by_pk(key, prev, state).await?;
+    }
+  }
+  Ok(())
+}
+
+/// Apply a rendered Statefile and stream the progress of each object.
+/// When an object fails to be applied every change already made is reverted
+/// in the reverse order so the Statefile is applied entirely or not at all.
+pub fn apply(
+  version: &str,
+  statefile: &Statefile,
+  state: &SystemState,
+) -> Receiver<HttpResult<Bytes>> {
+  let (tx, rx) = mpsc::channel::<HttpResult<Bytes>>();
+  let version = version.to_owned();
+  let statefile = statefile.clone();
+  let state = state.clone();
+  rt::spawn(async move {
+    let mut changes = Vec::new();
+    let Err(err) =
+      apply_objects(&version, &statefile, &tx, &mut changes, &state).await
+    else {
+      return;
+    };
+    log::warn!("state::apply: {err}, rolling back");
+    for change in changes.iter().rev() {
+      let (kind, key) = match change {
+        StateChange::Secret(key, _) => ("Secret", key),
+        StateChange::Job(key, _) => ("Job", key),
+        StateChange::Cargo(key, _) => ("Cargo", key),
+        StateChange::Vm(key, _) => ("Vm", key),
+        StateChange::Resource(key, _) => ("Resource", key),
+      };
+      match rollback(&version, change, &state).await {
+        Err(err) => {
+          log::error!("state::apply: unable to rollback {kind} {key}: {err}");
+          let context = Some(err.to_string());
+          send(&tx, kind, key, StateStreamStatus::Failed, context);
+        }
+        Ok(_) => send(&tx, kind, key, StateStreamStatus::RolledBack, None),
+      }
+    }
+  });
+  rx
+}
+
+/// Remove every object of a rendered Statefile and stream the progress
+/// Objects are removed in the reverse order of the apply
+pub fn remove(
+  statefile: &Statefile,
+  state: &SystemState,
+) -> Receiver<HttpResult<Bytes>> {
+  let (tx, rx) = mpsc::channel::<HttpResult<Bytes>>();
+  let statefile = statefile.clone();
+  let state = state.clone();
+  rt::spawn(async move {
+    let namespace = utils::key::resolve_nsp(&statefile.namespace);
+    let mut objects = Vec::new();
+    for resource in statefile.resources.iter().flatten() {
+      objects.push(("Resource", resource.name.clone()));
+    }
+    for vm in statefile.virtual_machines.iter().flatten() {
+      objects.push(("Vm", utils::key::gen_key(&namespace, &vm.name)));
+    }
+    for cargo in statefile.cargoes.iter().flatten() {
+      objects.push(("Cargo", utils::key::gen_key(&namespace, &cargo.name)));
+    }
+    for job in statefile.jobs.iter().flatten() {
+      objects.push(("Job", job.name.clone()));
+    }
+    for secret in statefile.secrets.iter().flatten() {
+      objects.push(("Secret", secret.name.clone()));
+    }
+    for (kind, key) in objects {
+      send(&tx, kind, &key, StateStreamStatus::Pending, None);
+      let res = match kind {
+        "Resource" => ResourceDb::del_obj_by_pk(&key, &(), &state)
+          .await
+          .map(|_| ()),
+        "Vm" => VmDb::del_obj_by_pk(&key, &(), &state).await.map(|_| ()),
+        "Cargo" => {
+          let opts = CargoDeleteQuery {
+            namespace: statefile.namespace.clone(),
+            force: Some(true),
+          };
+          CargoDb::del_obj_by_pk(&key, &opts, &state)
+            .await
+            .map(|_| ())
+        }
+        "Job" => JobDb::del_obj_by_pk(&key, &(), &state).await.map(|_| ()),
+        _ => SecretDb::del_obj_by_pk(&key, &(), &state).await.map(|_| ()),
+      };
+      match res {
+        Ok(_) => send(&tx, kind, &key, StateStreamStatus::Removed, None),
+        Err(err) if err.status == ntex::http::StatusCode::NOT_FOUND => {
+          send(&tx, kind, &key, StateStreamStatus::NotFound, None)
+        }
+        Err(err) => {
+          let context = Some(err.to_string());
+          send(&tx, kind, &key, StateStreamStatus::Failed, context);
+        }
+      }
+    }
+  });
+  rx
+}
@@ -8,7 +8,10 @@ use diesel::{
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 
 use nanocl_error::io::{IoError, IoResult};
-use nanocl_stubs::config::DaemonConfig;
+use nanocl_stubs::{
+  config::DaemonConfig,
+  generic::{GenericClause, GenericFilter},
+};
 
 use crate::models::{Pool, DBConn};
 
@@ -88,3 +91,49 @@ pub async fn init(daemon_conf: &DaemonConfig) -> IoResult<Pool> {
   log::info!("store::init: migrations success");
   Ok(pool)
 }
+
+/// Ensure the dates of the where clauses of a filter on the given columns
+/// are RFC 3339 dates, the queries would compare the invalid ones with the epoch
+pub fn validate_dates(
+  filter: &GenericFilter,
+  columns: &[&str],
+) -> IoResult<()> {
+  let r#where = filter.r#where.clone().unwrap_or_default();
+  for column in columns {
+    let date = match r#where.get(*column) {
+      Some(GenericClause::Eq(date))
+      | Some(GenericClause::Gt(date))
+      | Some(GenericClause::Lt(date))
+      | Some(GenericClause::Ge(date))
+      | Some(GenericClause::Le(date)) => date,
+      _ => continue,
+    };
+    if let Err(err) = chrono::DateTime::parse_from_rfc3339(date) {
+      return Err(IoError::invalid_input(
+        "Filter",
+        &format!("{column} {date} is not a RFC 3339 date: {err}"),
+      ));
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn filter_dates() {
+    let filter = GenericFilter::new().r#where(
+      "created_at",
+      GenericClause::Ge("2024-01-01T10:00:00Z".to_owned()),
+    );
+    assert!(validate_dates(&filter, &["created_at", "expires_at"]).is_ok());
+    let filter = GenericFilter::new()
+      .r#where("created_at", GenericClause::Ge("yesterday".to_owned()));
+    assert!(validate_dates(&filter, &["created_at", "expires_at"]).is_err());
+    let filter = GenericFilter::new()
+      .r#where("expires_at", GenericClause::Lt("2024-01-01".to_owned()));
+    assert!(validate_dates(&filter, &["created_at", "expires_at"]).is_err());
+  }
+}
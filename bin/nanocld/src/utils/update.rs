@@ -0,0 +1,172 @@
+use nanocl_error::http::{HttpError, HttpResult};
+
+use nanocl_stubs::cargo_spec::{UpdateStrategy, UpdateStrategyKind};
+
+/// Default delay in seconds for new instances to become healthy
+pub const DEFAULT_HEALTH_DEADLINE: u64 = 60;
+/// Default number of instances created above the wanted number
+pub const DEFAULT_MAX_SURGE: usize = 1;
+/// Default number of instances unavailable during a rolling update
+pub const DEFAULT_MAX_UNAVAILABLE: usize = 0;
+
+/// Ensure the update strategy of a cargo is valid
+pub fn validate(strategy: &UpdateStrategy) -> HttpResult<()> {
+  if strategy.health_deadline == Some(0) {
+    return Err(HttpError::bad_request(
+      "UpdateStrategy HealthDeadline must be greater than 0",
+    ));
+  }
+  if strategy.kind != UpdateStrategyKind::Rolling {
+    if strategy.max_surge.is_some() || strategy.max_unavailable.is_some() {
+      return Err(HttpError::bad_request(
+        "UpdateStrategy MaxSurge and MaxUnavailable are only used by the Rolling kind",
+      ));
+    }
+    return Ok(());
+  }
+  if strategy.max_surge == Some(0) && strategy.max_unavailable.unwrap_or(0) == 0
+  {
+    return Err(HttpError::bad_request(
+      "UpdateStrategy MaxSurge and MaxUnavailable cannot both be 0",
+    ));
+  }
+  Ok(())
+}
+
+/// A step of a rolling update
+#[derive(Debug, PartialEq, Eq)]
+pub struct RollingBatch {
+  /// Old instances removed before the new ones are created
+  pub down: usize,
+  /// New instances created and waited to be healthy
+  pub create: usize,
+  /// Old instances removed once the new ones are healthy
+  pub replace: usize,
+}
+
+/// Split the replacement of `old` instances by `number` new ones in batches.
+/// At most `max_unavailable` old instances are removed before a batch is created
+/// and at most `max_surge` instances are running above the wanted number.
+/// The old instances left after the last batch are to be removed.
+pub fn rolling_batches(
+  number: usize,
+  old: usize,
+  max_surge: usize,
+  max_unavailable: usize,
+) -> Vec<RollingBatch> {
+  let mut batches = Vec::new();
+  let mut old = old;
+  let mut created = 0;
+  while created < number {
+    let down = max_unavailable.min(old);
+    old -= down;
+    let create = (max_surge + down).max(1).min(number - created);
+    created += create;
+    // The new instances take over the old ones
+    let replace = create.saturating_sub(down).min(old);
+    old -= replace;
+    batches.push(RollingBatch {
+      down,
+      create,
+      replace,
+    });
+  }
+  batches
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn batch(down: usize, create: usize, replace: usize) -> RollingBatch {
+    RollingBatch {
+      down,
+      create,
+      replace,
+    }
+  }
+
+  #[test]
+  fn batches() {
+    assert_eq!(
+      rolling_batches(3, 3, 1, 0),
+      vec![batch(0, 1, 1), batch(0, 1, 1), batch(0, 1, 1)]
+    );
+    assert_eq!(
+      rolling_batches(3, 3, 1, 1),
+      vec![batch(1, 2, 1), batch(1, 1, 0)]
+    );
+    assert_eq!(
+      rolling_batches(4, 4, 2, 0),
+      vec![batch(0, 2, 2), batch(0, 2, 2)]
+    );
+    assert_eq!(
+      rolling_batches(4, 4, 0, 2),
+      vec![batch(2, 2, 0), batch(2, 2, 0)]
+    );
+    // Scaling up while updating
+    assert_eq!(
+      rolling_batches(3, 1, 1, 0),
+      vec![batch(0, 1, 1), batch(0, 1, 0), batch(0, 1, 0)]
+    );
+    // Scaling down while updating, the last old instance is removed after
+    assert_eq!(rolling_batches(1, 2, 1, 0), vec![batch(0, 1, 1)]);
+    assert_eq!(rolling_batches(0, 2, 1, 0), vec![]);
+    // Never stuck without any surge nor unavailability
+    assert_eq!(
+      rolling_batches(2, 2, 0, 0),
+      vec![batch(0, 1, 1), batch(0, 1, 1)]
+    );
+  }
+
+  #[test]
+  fn batches_bounds() {
+    for (number, old, max_surge, max_unavailable) in
+      [(5, 5, 1, 0), (5, 3, 2, 1), (3, 6, 1, 2), (10, 10, 3, 3)]
+    {
+      let mut running = old;
+      let mut old_left = old;
+      let mut created = 0;
+      for batch in rolling_batches(number, old, max_surge, max_unavailable) {
+        running -= batch.down;
+        old_left -= batch.down;
+        assert!(old.min(number).saturating_sub(running) <= max_unavailable);
+        running += batch.create;
+        created += batch.create;
+        assert!(running <= number.max(old) + max_surge);
+        running -= batch.replace;
+        old_left -= batch.replace;
+      }
+      assert_eq!(created, number);
+      assert_eq!(running - old_left, number);
+    }
+  }
+
+  #[test]
+  fn validate_strategy() {
+    assert!(validate(&UpdateStrategy::default()).is_ok());
+    let strategy = UpdateStrategy {
+      max_surge: Some(0),
+      ..Default::default()
+    };
+    assert!(validate(&strategy).is_err());
+    let strategy = UpdateStrategy {
+      max_surge: Some(0),
+      max_unavailable: Some(1),
+      ..Default::default()
+    };
+    assert!(validate(&strategy).is_ok());
+    let strategy = UpdateStrategy {
+      kind: UpdateStrategyKind::Recreate,
+      max_surge: Some(2),
+      ..Default::default()
+    };
+    assert!(validate(&strategy).is_err());
+    let strategy = UpdateStrategy {
+      kind: UpdateStrategyKind::BlueGreen,
+      health_deadline: Some(0),
+      ..Default::default()
+    };
+    assert!(validate(&strategy).is_err());
+  }
+}
@@ -0,0 +1,405 @@
+use std::{fmt::Write, str::FromStr};
+
+use ntex::{rt, http::Client};
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+
+use nanocl_error::{
+  io::{FromIo, IoError, IoResult},
+  http::{HttpError, HttpResult},
+};
+
+use nanocl_stubs::{
+  generic::{GenericClause, GenericFilter},
+  system::{
+    Event, EventActor, EventActorKind, EventCondition, NativeEventAction,
+  },
+  webhook::{WebhookDeliveryStatus, WebhookPartial, WebhookRetry},
+};
+
+use crate::{
+  utils,
+  repositories::generic::*,
+  models::{
+    EventDb, SystemState, WebhookDb, WebhookDeliveryDb, WebhookDeliveryUpdateDb,
+  },
+};
+
+/// Header containing the HMAC-SHA256 signature of the body
+pub const SIGNATURE_HEADER: &str = "X-Nanocl-Signature";
+/// Maximum number of webhooks or deliveries loaded at once
+const READ_LIMIT: usize = 10_000;
+/// Timeout in seconds of a delivery request
+const TIMEOUT: u32 = 10;
+
+/// Validate the name, the url and the retry policy of a new webhook
+pub fn validate(webhook: &WebhookPartial) -> HttpResult<()> {
+  if webhook.name.is_empty()
+    || !webhook
+      .name
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+  {
+    return Err(HttpError::bad_request(format!(
+      "Webhook name {} is invalid",
+      webhook.name
+    )));
+  }
+  let url = url::Url::parse(&webhook.url).map_err(|err| {
+    HttpError::bad_request(format!("Webhook url {}: {err}", webhook.url))
+  })?;
+  if url.scheme() != "http" && url.scheme() != "https" {
+    return Err(HttpError::bad_request(format!(
+      "Webhook url {} must use http or https",
+      webhook.url
+    )));
+  }
+  if let Some(retry) = &webhook.retry {
+    if retry.max_attempts == 0 {
+      return Err(HttpError::bad_request(
+        "Webhook retry MaxAttempts must be at least 1",
+      ));
+    }
+  }
+  Ok(())
+}
+
+/// Check if an actor match the kind and the key of a condition when set
+fn match_actor(
+  kind: &Option<EventActorKind>,
+  key: &Option<String>,
+  actor: &Option<EventActor>,
+) -> bool {
+  if kind.is_none() && key.is_none() {
+    return true;
+  }
+  let Some(actor) = actor else {
+    return false;
+  };
+  kind
+    .as_ref()
+    .map(|kind| kind == &actor.kind)
+    .unwrap_or(true)
+    && key
+      .as_ref()
+      .map(|key| Some(key) == actor.key.as_ref())
+      .unwrap_or(true)
+}
+
+/// Check if an event match a condition.
+/// Unlike the watch conditions an unset or empty field match any value.
+pub fn match_condition(condition: &EventCondition, e: &Event) -> bool {
+  if !condition.kind.is_empty() && !condition.kind.contains(&e.kind) {
+    return false;
+  }
+  if !condition.action.is_empty() {
+    match NativeEventAction::from_str(&e.action) {
+      Ok(action) if condition.action.contains(&action) => {}
+      _ => return false,
+    }
+  }
+  match_actor(&condition.actor_kind, &condition.actor_key, &e.actor)
+    && match_actor(&condition.related_kind, &condition.related_key, &e.related)
+}
+
+/// Check if an event must be delivered to a webhook with the given conditions
+pub fn match_event(
+  conditions: &Option<Vec<EventCondition>>,
+  e: &Event,
+) -> bool {
+  match conditions {
+    Some(conditions) if !conditions.is_empty() => conditions
+      .iter()
+      .any(|condition| match_condition(condition, e)),
+    _ => true,
+  }
+}
+
+/// Sign a body with HMAC-SHA256, the result is formatted as `sha256=<hex>`
+pub fn sign(secret: &str, body: &[u8]) -> IoResult<String> {
+  let ssl_err = |err: openssl::error::ErrorStack| {
+    IoError::invalid_data("Webhook signature", err.to_string().as_str())
+  };
+  let key = PKey::hmac(secret.as_bytes()).map_err(ssl_err)?;
+  let mut signer =
+    Signer::new(MessageDigest::sha256(), &key).map_err(ssl_err)?;
+  signer.update(body).map_err(ssl_err)?;
+  let hmac = signer.sign_to_vec().map_err(ssl_err)?;
+  let hex = hmac.iter().fold(String::new(), |mut hex, byte| {
+    let _ = write!(hex, "{byte:02x}");
+    hex
+  });
+  Ok(format!("sha256={hex}"))
+}
+
+/// Delay in seconds before the next attempt once `attempts` have failed.
+/// It start at `Backoff` and is doubled after each failure up to `MaxBackoff`.
+pub fn backoff_delay(retry: &WebhookRetry, attempts: u32) -> u64 {
+  utils::backoff::delay(retry.backoff, retry.max_backoff, attempts)
+}
+
+/// Post the event to the url of the webhook and return the status code
+async fn post(
+  webhook: &WebhookDb,
+  delivery: &WebhookDeliveryDb,
+  e: &Event,
+) -> IoResult<ntex::http::StatusCode> {
+  let body = serde_json::to_vec(e)?;
+  let client = Client::build()
+    .timeout(ntex::time::Millis::from_secs(TIMEOUT))
+    .finish();
+  let mut req = client
+    .post(webhook.url.as_str())
+    .header("User-Agent", "nanocld")
+    .header("Content-Type", "application/json")
+    .header("X-Nanocl-Webhook", webhook.name.as_str())
+    .header("X-Nanocl-Delivery", delivery.key.to_string())
+    .header("X-Nanocl-Event", e.key.to_string());
+  if let Some(secret) = &webhook.secret {
+    let secret = utils::secret::decrypt(secret)?;
+    let secret = secret.as_str().unwrap_or_default();
+    req = req.header(SIGNATURE_HEADER, sign(secret, &body)?);
+  }
+  let res = req.send_body(body).await.map_err(|err| {
+    err.map_err_context(|| format!("Webhook {}", webhook.url))
+  })?;
+  Ok(res.status())
+}
+
+/// Deliver an event to a webhook until it succeed or the attempts are exhausted.
+/// The result of every attempt is recorded on the delivery.
+async fn deliver(
+  mut delivery: WebhookDeliveryDb,
+  e: Event,
+  state: SystemState,
+) -> IoResult<()> {
+  loop {
+    if let Some(next_attempt_at) = delivery.next_attempt_at {
+      let wait = next_attempt_at - chrono::Utc::now().naive_utc();
+      if let Ok(wait) = wait.to_std() {
+        ntex::time::sleep(wait).await;
+      }
+    }
+    // The webhook may have been updated or deleted while waiting
+    let webhook =
+      match WebhookDb::read_by_pk(&delivery.webhook_name, &state.inner.pool)
+        .await
+      {
+        Ok(webhook) => webhook,
+        Err(_) => return Ok(()),
+      };
+    let retry: WebhookRetry = serde_json::from_value(webhook.retry.clone())?;
+    let attempts = delivery.attempts + 1;
+    let now = chrono::Utc::now().naive_utc();
+    let mut update = WebhookDeliveryUpdateDb {
+      updated_at: Some(now),
+      attempts: Some(attempts),
+      ..Default::default()
+    };
+    let error = match post(&webhook, &delivery, &e).await {
+      Ok(status) => {
+        update.status_code = Some(Some(status.as_u16() as i64));
+        if status.is_success() {
+          None
+        } else {
+          Some(format!("Unexpected status code {status}"))
+        }
+      }
+      Err(err) => {
+        update.status_code = Some(None);
+        Some(err.to_string())
+      }
+    };
+    let status = match &error {
+      None => WebhookDeliveryStatus::Delivered,
+      Some(_) if attempts >= retry.max_attempts as i64 => {
+        WebhookDeliveryStatus::DeadLetter
+      }
+      Some(_) => WebhookDeliveryStatus::Pending,
+    };
+    update.next_attempt_at = match status {
+      WebhookDeliveryStatus::Pending => {
+        let delay = backoff_delay(&retry, attempts as u32);
+        let delay =
+          chrono::Duration::try_seconds(delay as i64).unwrap_or_default();
+        Some(Some(now + delay))
+      }
+      _ => Some(None),
+    };
+    if let Some(error) = &error {
+      log::warn!(
+        "webhook::deliver: {} attempt {attempts} {error}",
+        webhook.name
+      );
+    }
+    update.error = Some(error);
+    update.status = Some(status.to_string());
+    delivery =
+      WebhookDeliveryDb::update_pk(&delivery.key, update, &state.inner.pool)
+        .await?;
+    if status != WebhookDeliveryStatus::Pending {
+      return Ok(());
+    }
+  }
+}
+
+/// Deliver an event in the background
+fn spawn_deliver(delivery: WebhookDeliveryDb, e: Event, state: &SystemState) {
+  let state = state.clone();
+  rt::spawn(async move {
+    let key = delivery.key;
+    if let Err(err) = deliver(delivery, e, state).await {
+      log::error!("webhook::spawn_deliver: {key} {err}");
+    }
+  });
+}
+
+async fn _dispatch(e: &Event, state: &SystemState) -> IoResult<()> {
+  let filter = GenericFilter::new().limit(READ_LIMIT);
+  let webhooks = WebhookDb::read_by(&filter, &state.inner.pool).await?;
+  for webhook in webhooks {
+    let conditions = webhook
+      .conditions
+      .clone()
+      .map(serde_json::from_value)
+      .transpose()?;
+    if !match_event(&conditions, e) {
+      continue;
+    }
+    let delivery = WebhookDeliveryDb::new(
+      &webhook.name,
+      &e.key,
+      &state.inner.config.hostname,
+    );
+    let delivery =
+      WebhookDeliveryDb::create_from(delivery, &state.inner.pool).await?;
+    spawn_deliver(delivery, e.clone(), state);
+  }
+  Ok(())
+}
+
+/// Deliver an event to the webhooks it match in the background
+pub fn dispatch(e: &Event, state: &SystemState) {
+  let e = e.clone();
+  let state = state.clone();
+  rt::spawn(async move {
+    if let Err(err) = _dispatch(&e, &state).await {
+      log::error!("webhook::dispatch: {err}");
+    }
+  });
+}
+
+/// Resume the pending deliveries of the current node after a restart
+pub async fn resume(state: &SystemState) -> IoResult<usize> {
+  let filter = GenericFilter::new()
+    .r#where(
+      "node_name",
+      GenericClause::Eq(state.inner.config.hostname.clone()),
+    )
+    .r#where(
+      "status",
+      GenericClause::Eq(WebhookDeliveryStatus::Pending.to_string()),
+    )
+    .limit(READ_LIMIT);
+  let deliveries =
+    WebhookDeliveryDb::read_by(&filter, &state.inner.pool).await?;
+  let count = deliveries.len();
+  for delivery in deliveries {
+    match EventDb::transform_read_by_pk(&delivery.event_key, &state.inner.pool)
+      .await
+    {
+      Ok(e) => spawn_deliver(delivery, e, state),
+      Err(err) => {
+        let update = WebhookDeliveryUpdateDb {
+          updated_at: Some(chrono::Utc::now().naive_utc()),
+          status: Some(WebhookDeliveryStatus::DeadLetter.to_string()),
+          error: Some(Some(format!("Event {}: {err}", delivery.event_key))),
+          next_attempt_at: Some(None),
+          ..Default::default()
+        };
+        WebhookDeliveryDb::update_pk(&delivery.key, update, &state.inner.pool)
+          .await?;
+      }
+    }
+  }
+  Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+  use nanocl_stubs::system::EventKind;
+
+  use super::*;
+
+  fn gen_event(action: NativeEventAction, key: &str) -> Event {
+    Event {
+      key: uuid::Uuid::new_v4(),
+      created_at: chrono::Utc::now().naive_utc(),
+      expires_at: chrono::Utc::now().naive_utc(),
+      reporting_node: "test".to_owned(),
+      reporting_controller: "nanocl.io/core".to_owned(),
+      kind: EventKind::Normal,
+      action: action.to_string(),
+      reason: "state".to_owned(),
+      note: None,
+      actor: Some(EventActor {
+        key: Some(key.to_owned()),
+        kind: EventActorKind::Cargo,
+        attributes: None,
+      }),
+      related: None,
+      metadata: None,
+    }
+  }
+
+  #[test]
+  fn signature() {
+    // RFC 4231 test case 2
+    let signature = sign("Jefe", b"what do ya want for nothing?").unwrap();
+    assert_eq!(
+      signature,
+      "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+  }
+
+  #[test]
+  fn conditions() {
+    let e = gen_event(NativeEventAction::Start, "web.global");
+    assert!(match_event(&None, &e));
+    assert!(match_event(&Some(vec![]), &e));
+    let by_kind = EventCondition {
+      actor_kind: Some(EventActorKind::Cargo),
+      ..Default::default()
+    };
+    assert!(match_event(&Some(vec![by_kind.clone()]), &e));
+    let by_action = EventCondition {
+      action: vec![NativeEventAction::Destroy],
+      ..by_kind.clone()
+    };
+    assert!(!match_event(&Some(vec![by_action.clone()]), &e));
+    assert!(match_event(&Some(vec![by_action, by_kind]), &e));
+    let by_key = EventCondition {
+      actor_key: Some("db.global".to_owned()),
+      ..Default::default()
+    };
+    assert!(!match_event(&Some(vec![by_key]), &e));
+    let by_related = EventCondition {
+      related_kind: Some(EventActorKind::Job),
+      ..Default::default()
+    };
+    assert!(!match_event(&Some(vec![by_related]), &e));
+  }
+
+  #[test]
+  fn backoff() {
+    let retry = WebhookRetry {
+      max_attempts: 10,
+      backoff: 2,
+      max_backoff: 30,
+    };
+    assert_eq!(backoff_delay(&retry, 1), 2);
+    assert_eq!(backoff_delay(&retry, 2), 4);
+    assert_eq!(backoff_delay(&retry, 4), 16);
+    assert_eq!(backoff_delay(&retry, 5), 30);
+    assert_eq!(backoff_delay(&retry, 80), 30);
+  }
+}
@@ -1,5 +1,7 @@
 use clap::Parser;
 
+use crate::models::ProxyBackend;
+
 #[derive(Parser)]
 pub struct Cli {
   /// Path to nginx config directory
@@ -8,6 +10,15 @@ pub struct Cli {
   /// Path to state directory
   #[clap(long)]
   pub state_dir: String,
+  /// Path to a CA certificate to trust when contacting the ACME server
+  #[clap(long)]
+  pub acme_ca_file: Option<String>,
+  /// Address where TLS-ALPN-01 challenges are answered, it must be on port 443 to allow them
+  #[clap(long, default_value = "0.0.0.0:5001")]
+  pub acme_tls_alpn_addr: String,
+  /// Backend proxying the traffic, native serves the rules in process without nginx
+  #[clap(long, value_enum, default_value_t = ProxyBackend::Nginx)]
+  pub backend: ProxyBackend,
 }
 
 #[cfg(test)]
@@ -28,6 +39,31 @@ mod tests {
     let args = Cli::parse_from(["ncproxy", "--state-dir", "/test/state"]);
     assert_eq!(args.nginx_dir, "/etc/nginx");
     assert_eq!(args.state_dir, "/test/state");
+    assert_eq!(args.acme_ca_file, None);
+    assert_eq!(args.acme_tls_alpn_addr, "0.0.0.0:5001");
+    assert_eq!(args.backend, ProxyBackend::Nginx);
+    let args = Cli::parse_from([
+      "ncproxy",
+      "--state-dir",
+      "/test/state",
+      "--acme-ca-file",
+      "/test/pebble.minica.pem",
+      "--acme-tls-alpn-addr",
+      "0.0.0.0:443",
+    ]);
+    assert_eq!(
+      args.acme_ca_file.as_deref(),
+      Some("/test/pebble.minica.pem")
+    );
+    assert_eq!(args.acme_tls_alpn_addr, "0.0.0.0:443");
+    let args = Cli::parse_from([
+      "ncproxy",
+      "--state-dir",
+      "/test/state",
+      "--backend",
+      "native",
+    ]);
+    assert_eq!(args.backend, ProxyBackend::Native);
     let _ = Cli::try_parse();
   }
 }
@@ -0,0 +1,25 @@
+use std::{
+  sync::{Arc, Mutex},
+  collections::HashSet,
+};
+
+/// Configuration of the ACME client and the domains being processed
+#[derive(Clone)]
+pub struct AcmeState {
+  /// Path to a CA certificate to trust when contacting the ACME server
+  pub ca_file: Option<String>,
+  /// Address where TLS-ALPN-01 challenges are answered
+  pub tls_alpn_addr: String,
+  /// Domains with an order in progress
+  pub pending: Arc<Mutex<HashSet<String>>>,
+}
+
+impl AcmeState {
+  pub fn new(ca_file: Option<String>, tls_alpn_addr: &str) -> Self {
+    Self {
+      ca_file,
+      tls_alpn_addr: tls_alpn_addr.to_owned(),
+      pending: Arc::new(Mutex::new(HashSet::new())),
+    }
+  }
+}
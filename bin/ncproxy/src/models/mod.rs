@@ -1,7 +1,11 @@
+mod acme;
+mod native;
 mod store;
 mod system;
 mod template;
 
+pub use acme::*;
+pub use native::*;
 pub use store::*;
 pub use system::*;
 pub use template::*;
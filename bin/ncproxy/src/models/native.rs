@@ -0,0 +1,294 @@
+use std::{
+  fs,
+  io::Write,
+  net::IpAddr,
+  collections::{BTreeMap, HashMap},
+  sync::{
+    mpsc, Arc, Mutex, OnceLock, RwLock,
+    atomic::{AtomicUsize, Ordering},
+  },
+};
+
+use ntex::rt;
+use openssl::ssl::SslContext;
+
+use nanocld_client::stubs::proxy::ProxyStreamProtocol;
+
+/// Backend used to proxy the traffic, selected at startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProxyBackend {
+  /// Render nginx configuration files and reload nginx
+  #[default]
+  Nginx,
+  /// Proxy the traffic in process and apply the rules in memory
+  Native,
+}
+
+/// How a server of an upstream is picked for a request or a connection
+#[derive(Debug, Clone, PartialEq)]
+pub enum NativeBalancing {
+  RoundRobin,
+  IpHash,
+  /// Hash of the value of a request header
+  Header(String),
+  /// Hash of the value of a cookie, set on the response when it's missing
+  Cookie(String),
+}
+
+/// Servers of a cargo or a vm resolved when the rule is applied
+#[derive(Debug)]
+pub struct NativeUpstream {
+  /// Addresses in the form of `ip:port`, repeated by their weight
+  pub addresses: Vec<String>,
+  pub balancing: NativeBalancing,
+  pub next: AtomicUsize,
+}
+
+impl NativeUpstream {
+  pub fn new(addresses: Vec<String>, balancing: NativeBalancing) -> Self {
+    Self {
+      addresses,
+      balancing,
+      next: AtomicUsize::new(0),
+    }
+  }
+
+  /// Pick a server for a client, the key is used by the hash balancing
+  pub fn pick(&self, key: Option<&str>) -> Option<&str> {
+    if self.addresses.is_empty() {
+      return None;
+    }
+    let index = match (&self.balancing, key) {
+      (NativeBalancing::RoundRobin, _) | (_, None) => {
+        self.next.fetch_add(1, Ordering::Relaxed)
+      }
+      (_, Some(key)) => {
+        // FNV-1a keeps the same server for the same key across restarts
+        key.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+          (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        }) as usize
+      }
+    };
+    Some(&self.addresses[index % self.addresses.len()])
+  }
+}
+
+/// Where the requests of a location are sent
+#[derive(Debug)]
+pub enum NativeTarget {
+  /// Servers of a cargo or a vm, the path replaces the location path
+  Upstream {
+    upstream: NativeUpstream,
+    path: String,
+  },
+  /// An url proxied as is, the request path is appended
+  Url(String),
+  /// An url the client is redirected to
+  Redirect { url: String, status: u16 },
+}
+
+/// A network and its prefix length allowed to access a location
+#[derive(Debug, Clone, PartialEq)]
+pub struct NativeIpRange {
+  pub addr: IpAddr,
+  pub prefix: u8,
+}
+
+impl NativeIpRange {
+  /// Whether an ip address is part of the range
+  pub fn contains(&self, ip: &IpAddr) -> bool {
+    match (self.addr, ip) {
+      (IpAddr::V4(range), IpAddr::V4(ip)) => {
+        let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+        u32::from(range) & mask == u32::from(*ip) & mask
+      }
+      (IpAddr::V6(range), IpAddr::V6(ip)) => {
+        let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+        u128::from(range) & mask == u128::from(*ip) & mask
+      }
+      _ => false,
+    }
+  }
+}
+
+/// A location of an http rule ready to serve requests
+#[derive(Debug)]
+pub struct NativeLocation {
+  pub path: String,
+  /// Only match the exact path, for locations like `= /path`
+  pub exact: bool,
+  pub target: NativeTarget,
+  pub allowed_ips: Option<Vec<NativeIpRange>>,
+  /// Headers added to the proxied request
+  pub headers: Vec<(String, String)>,
+  pub max_body_size: Option<u64>,
+  /// Log the requests, disabled by the `DisableLogging` of the upstream
+  pub logging: bool,
+}
+
+impl NativeLocation {
+  /// Length of the matched path or None when the location doesn't match
+  pub fn matches(&self, path: &str) -> Option<usize> {
+    if self.exact {
+      return (path == self.path).then_some(self.path.len());
+    }
+    path.starts_with(&self.path).then_some(self.path.len())
+  }
+}
+
+/// An http rule ready to serve requests
+#[derive(Debug)]
+pub struct NativeHttpRule {
+  /// Address of the plain http listener
+  pub listen: String,
+  /// Address of the https listener when the rule has a certificate
+  pub listen_https: Option<String>,
+  pub domain: Option<String>,
+  pub ssl: Option<SslContext>,
+  /// Redirect the plain http requests to https
+  pub redirect_https: bool,
+  pub locations: Vec<NativeLocation>,
+}
+
+/// A tcp or udp rule ready to accept connections
+#[derive(Debug)]
+pub struct NativeStreamRule {
+  pub listen: String,
+  pub protocol: ProxyStreamProtocol,
+  pub upstream: NativeUpstream,
+}
+
+/// A `ResourceProxyRule` resolved for the native backend
+#[derive(Debug, Default)]
+pub struct NativeRule {
+  pub http: Vec<NativeHttpRule>,
+  pub streams: Vec<NativeStreamRule>,
+}
+
+/// A socket opened by the native backend
+pub enum NativeListener {
+  Http(ntex::server::Server),
+  Stream(rt::JoinHandle<()>),
+}
+
+/// Lines appended to the log files by a dedicated thread
+/// so the workers never wait for the disk
+#[derive(Clone, Default)]
+pub struct NativeMetrics {
+  sender: Arc<OnceLock<mpsc::Sender<(String, String)>>>,
+}
+
+impl NativeMetrics {
+  /// Queue a line to append to a file, the thread is started on first use
+  pub fn write(&self, path: String, line: String) {
+    let sender = self.sender.get_or_init(Self::spawn_writer);
+    if let Err(err) = sender.send((path, line)) {
+      log::warn!("native::metrics: {err}");
+    }
+  }
+
+  fn spawn_writer() -> mpsc::Sender<(String, String)> {
+    let (sender, receiver) = mpsc::channel::<(String, String)>();
+    std::thread::spawn(move || {
+      for (path, line) in receiver {
+        let res = fs::OpenOptions::new()
+          .create(true)
+          .append(true)
+          .open(&path)
+          .and_then(|mut file| file.write_all(line.as_bytes()));
+        if let Err(err) = res {
+          log::warn!("native::metrics: {path} {err}");
+        }
+      }
+    });
+    sender
+  }
+}
+
+/// State of the native backend
+#[derive(Clone, Default)]
+pub struct NativeState {
+  /// Rules by resource name, a rule is replaced as a whole when it changes.
+  /// Sorted so the first rule without domain of a listener is its default
+  pub rules: Arc<RwLock<BTreeMap<String, Arc<NativeRule>>>>,
+  /// Listeners by `{kind}:{addr}`, kind being http, https, tcp or udp
+  pub listeners: Arc<Mutex<HashMap<String, NativeListener>>>,
+  pub metrics: NativeMetrics,
+}
+
+impl NativeState {
+  /// Snapshot of the rules, requests in flight keep the rules they started with
+  pub fn rules(&self) -> Vec<Arc<NativeRule>> {
+    match self.rules.read() {
+      Ok(rules) => rules.values().cloned().collect(),
+      Err(err) => err.into_inner().values().cloned().collect(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn upstream_pick() {
+    let upstream = NativeUpstream::new(
+      vec!["10.0.0.1:80".to_owned(), "10.0.0.2:80".to_owned()],
+      NativeBalancing::RoundRobin,
+    );
+    assert_eq!(upstream.pick(None), Some("10.0.0.1:80"));
+    assert_eq!(upstream.pick(Some("a")), Some("10.0.0.2:80"));
+    assert_eq!(upstream.pick(None), Some("10.0.0.1:80"));
+    let upstream = NativeUpstream::new(
+      vec!["10.0.0.1:80".to_owned(), "10.0.0.2:80".to_owned()],
+      NativeBalancing::IpHash,
+    );
+    let first = upstream.pick(Some("172.16.0.4")).unwrap().to_owned();
+    for _ in 0..4 {
+      assert_eq!(upstream.pick(Some("172.16.0.4")), Some(first.as_str()));
+    }
+    let upstream = NativeUpstream::new(vec![], NativeBalancing::RoundRobin);
+    assert_eq!(upstream.pick(None), None);
+  }
+
+  #[test]
+  fn ip_range() {
+    let range = NativeIpRange {
+      addr: "10.1.0.0".parse().unwrap(),
+      prefix: 16,
+    };
+    assert!(range.contains(&"10.1.42.3".parse().unwrap()));
+    assert!(!range.contains(&"10.2.0.1".parse().unwrap()));
+    assert!(!range.contains(&"::1".parse().unwrap()));
+    let all = NativeIpRange {
+      addr: "0.0.0.0".parse().unwrap(),
+      prefix: 0,
+    };
+    assert!(all.contains(&"192.168.1.1".parse().unwrap()));
+    let host = NativeIpRange {
+      addr: "::1".parse().unwrap(),
+      prefix: 128,
+    };
+    assert!(host.contains(&"::1".parse().unwrap()));
+  }
+
+  #[test]
+  fn metrics_writer() {
+    let path = std::env::temp_dir()
+      .join(format!("ncproxy-metrics-{}.log", std::process::id()));
+    let path = path.display().to_string();
+    let metrics = NativeMetrics::default();
+    metrics.write(path.clone(), "first\n".to_owned());
+    metrics.clone().write(path.clone(), "second\n".to_owned());
+    let mut content = String::new();
+    for _ in 0..50 {
+      content = fs::read_to_string(&path).unwrap_or_default();
+      if content.lines().count() == 2 {
+        break;
+      }
+      std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    let _ = fs::remove_file(&path);
+    assert_eq!(content, "first\nsecond\n");
+  }
+}
@@ -51,12 +51,23 @@ impl FromStr for NginxRuleKind {
 #[derive(Clone)]
 pub struct Store {
   pub dir: String,
+  /// Files are only written to be validated, nothing is served from them
+  pub sandbox: bool,
 }
 
 impl Store {
   pub fn new(dir: &str) -> Self {
     Self {
       dir: dir.to_owned(),
+      sandbox: false,
+    }
+  }
+
+  /// Create a store in a temporary directory used to validate a rule
+  pub fn new_sandbox(dir: &str) -> Self {
+    Self {
+      dir: dir.to_owned(),
+      sandbox: true,
     }
   }
 
@@ -9,7 +9,7 @@ use nanocld_client::NanocldClient;
 
 use crate::utils;
 
-use super::Store;
+use super::{Store, AcmeState, ProxyBackend, NativeState};
 
 /// Shared state of the program
 #[derive(Clone)]
@@ -18,6 +18,9 @@ pub struct SystemState {
   pub client: NanocldClient,
   pub event_emitter: EventEmitter,
   pub nginx_dir: String,
+  pub acme: AcmeState,
+  pub backend: ProxyBackend,
+  pub native: NativeState,
 }
 
 pub type SystemStateRef = Arc<SystemState>;
@@ -29,15 +32,26 @@ pub enum SystemEventKind {
 
 struct SystemEventInner {
   client: NanocldClient,
+  backend: ProxyBackend,
+  native: NativeState,
+  state_dir: String,
   task: ntex::rt::JoinHandle<IoResult<()>>,
 }
 
 pub struct SystemEvent(SystemEventInner);
 
 impl SystemEvent {
-  pub fn new(client: &NanocldClient) -> Self {
+  pub fn new(
+    client: &NanocldClient,
+    backend: ProxyBackend,
+    native: &NativeState,
+    state_dir: &str,
+  ) -> Self {
     Self(SystemEventInner {
       client: client.clone(),
+      backend,
+      native: native.clone(),
+      state_dir: state_dir.to_owned(),
       task: rt::spawn(async move { Ok::<_, IoError>(()) }),
     })
   }
@@ -49,9 +63,18 @@ impl SystemEvent {
       abort_handle.abort();
     }
     let client = self.0.client.clone();
+    let backend = self.0.backend;
+    let native = self.0.native.clone();
+    let state_dir = self.0.state_dir.clone();
     self.0.task = rt::spawn(async move {
       ntex::time::sleep(std::time::Duration::from_millis(750)).await;
-      if let Err(err) = utils::nginx::reload(&client).await {
+      let res = match backend {
+        ProxyBackend::Nginx => utils::nginx::reload(&client).await,
+        ProxyBackend::Native => {
+          utils::native::sync_listeners(&native, &state_dir).await
+        }
+      };
+      if let Err(err) = res {
         log::warn!("system: {err}");
       }
       Ok::<_, IoError>(())
@@ -64,12 +87,20 @@ pub struct EventEmitter(pub Arc<mpsc::UnboundedSender<SystemEventKind>>);
 
 impl EventEmitter {
   /// Create a new thread with it's own event loop and return an emitter to send events to it
-  pub fn new(client: &NanocldClient) -> Self {
+  pub fn new(
+    client: &NanocldClient,
+    backend: ProxyBackend,
+    native: &NativeState,
+    state_dir: &str,
+  ) -> Self {
     let (tx, mut rx) = mpsc::unbounded();
     let client = client.clone();
+    let native = native.clone();
+    let state_dir = state_dir.to_owned();
     rt::Arbiter::new().exec_fn(move || {
       ntex::rt::spawn(async move {
-        let mut local_event = SystemEvent::new(&client);
+        let mut local_event =
+          SystemEvent::new(&client, backend, &native, &state_dir);
         while let Some(e) = rx.next().await {
           local_event.handle(e);
         }
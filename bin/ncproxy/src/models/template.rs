@@ -11,6 +11,46 @@ pub struct LocationTemplate {
   pub allowed_ips: Option<Vec<String>>,
   pub version: Option<f64>,
   pub headers: Option<Vec<String>>,
+  pub rate_limit: Option<String>,
+  pub connection_limit: Option<String>,
+  pub max_body_size: Option<String>,
+  pub basic_auth: Option<BasicAuthTemplate>,
+  pub forward_auth: Option<ForwardAuthTemplate>,
+  /// Either `http`, `websocket` or `grpc`
+  pub protocol: String,
+  /// Variable holding the Set-Cookie header of a sticky split target
+  pub split_cookie: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SplitCookieTemplate {
+  pub name: String,
+  /// Variable holding the cookie or a new request id when it's missing
+  pub variable: String,
+  /// Variable holding the Set-Cookie header when the cookie is missing
+  pub set_cookie: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BasicAuthTemplate {
+  pub realm: String,
+  pub user_file: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForwardAuthTemplate {
+  /// Path of the internal location calling the authorization service
+  pub path: String,
+  pub upstream: String,
+  pub headers: Vec<ForwardAuthHeaderTemplate>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForwardAuthHeaderTemplate {
+  pub name: String,
+  /// Variable holding the header of the authorization response
+  pub variable: String,
+  pub upstream_variable: String,
 }
 
 pub struct Template<'a> {
@@ -52,6 +92,10 @@ pub const UPSTREAM_TEMPLATE: &Template = &Template {
   data: include_str!("templates/upstream.conf"),
 };
 
+pub const SPLIT_UPSTREAM_TEMPLATE: &Template = &Template {
+  data: include_str!("templates/split_upstream.conf"),
+};
+
 pub const UNIX_UPSTREAM_TEMPLATE: &Template = &Template {
   data: include_str!("templates/unix_upstream.conf"),
 };
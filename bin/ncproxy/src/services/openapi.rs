@@ -2,9 +2,12 @@ use utoipa::OpenApi;
 
 use nanocld_client::stubs::proxy::{
   ProxyRule, ProxyRuleHttp, ProxyRuleStream, ResourceProxyRule,
-  ProxyHttpLocation, ProxySsl, ProxyStreamProtocol, StreamTarget,
-  LocationTarget, UpstreamTarget, HttpTarget, UriTarget, UrlRedirect,
-  UnixTarget, ProxySslConfig,
+  ProxyHttpLocation, ProxyRateLimit, ProxyHttpProtocol, ProxyAuth,
+  ProxyBasicAuth, ProxyForwardAuth, ProxySsl, ProxyStreamProtocol,
+  StreamTarget, LocationTarget, UpstreamTarget, UpstreamBalancing,
+  UpstreamHealth, HttpTarget, UriTarget, UrlRedirect, UnixTarget,
+  ProxySslConfig, ProxySslAcme, AcmeChallenge, SplitTarget, SplitUpstream,
+  SplitSticky, ProxyRuleValidation,
 };
 
 use super::rule;
@@ -37,6 +40,7 @@ impl utoipa::Modify for VersionModifier {
   paths(
     rule::apply_rule,
     rule::remove_rule,
+    rule::validate_rule,
   ),
   components(schemas(
     ResourceProxyRule,
@@ -44,16 +48,29 @@ impl utoipa::Modify for VersionModifier {
     ProxyRuleHttp,
     ProxyRuleStream,
     ProxyHttpLocation,
+    ProxyRateLimit,
+    ProxyHttpProtocol,
+    ProxyAuth,
+    ProxyBasicAuth,
+    ProxyForwardAuth,
     ProxySsl,
     ProxySslConfig,
+    ProxySslAcme,
+    AcmeChallenge,
     ProxyStreamProtocol,
     StreamTarget,
     LocationTarget,
     UpstreamTarget,
+    UpstreamBalancing,
+    UpstreamHealth,
     HttpTarget,
     UriTarget,
     UrlRedirect,
     UnixTarget,
+    SplitTarget,
+    SplitUpstream,
+    SplitSticky,
+    ProxyRuleValidation,
   )),
   tags(
     (name = "Rules", description = "Rules management endpoints."),
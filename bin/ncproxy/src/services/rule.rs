@@ -2,7 +2,7 @@ use ntex::web;
 
 use nanocl_error::http::HttpError;
 
-use nanocld_client::stubs::proxy::ResourceProxyRule;
+use nanocld_client::stubs::proxy::{ResourceProxyRule, ProxyRuleValidation};
 
 use crate::{utils, models::SystemStateRef};
 
@@ -26,7 +26,8 @@ pub async fn apply_rule(
   payload: web::types::Json<ResourceProxyRule>,
 ) -> Result<web::HttpResponse, HttpError> {
   log::info!("apply_rule: {}", path.1);
-  utils::nginx::add_rule(&path.1, &payload, &state).await?;
+  utils::backend::validate(&payload, &state)?;
+  utils::backend::add_rule(&path.1, &payload, &state).await?;
   state.event_emitter.emit_reload().await;
   Ok(web::HttpResponse::Ok().json(&payload.into_inner()))
 }
@@ -49,20 +50,50 @@ pub async fn remove_rule(
   path: web::types::Path<(String, String)>,
 ) -> Result<web::HttpResponse, HttpError> {
   log::info!("remove_rule: {}", path.1);
-  utils::nginx::del_rule(&path.1, &state).await;
+  utils::backend::del_rule(&path.1, &state).await;
   state.event_emitter.emit_reload().await;
   Ok(web::HttpResponse::Ok().finish())
 }
 
+/// Render a ProxyRule and test it without applying it
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  tag = "Rules",
+  path = "/rules/{name}/validate",
+  request_body = ResourceProxyRule,
+  params(
+    ("name" = String, Path, description = "Name of the rule"),
+  ),
+  responses(
+    (status = 200, description = "The generated config and the errors", body = ProxyRuleValidation),
+  ),
+))]
+#[web::post("/rules/{name}/validate")]
+pub async fn validate_rule(
+  state: web::types::State<SystemStateRef>,
+  path: web::types::Path<(String, String)>,
+  payload: web::types::Json<ResourceProxyRule>,
+) -> Result<web::HttpResponse, HttpError> {
+  log::info!("validate_rule: {}", path.1);
+  let validation: ProxyRuleValidation =
+    utils::backend::validate_rule(&path.1, &payload, &state).await?;
+  Ok(web::HttpResponse::Ok().json(&validation))
+}
+
 pub fn ntex_config(config: &mut web::ServiceConfig) {
   config.service(apply_rule);
   config.service(remove_rule);
+  config.service(validate_rule);
 }
 
 #[cfg(test)]
 mod tests {
   use ntex::http;
 
+  use nanocld_client::stubs::proxy::{
+    ProxyRule, ProxyHttpProtocol, ProxyRuleValidation,
+  };
+
   use crate::utils::tests::*;
 
   #[ntex::test]
@@ -77,6 +108,54 @@ mod tests {
     let json = res.json::<serde_yaml::Value>().await.unwrap();
     println!("{:?}", json);
     test_status_code!(res.status(), http::StatusCode::OK, "put a rule");
+    let mut invalid = payload.clone();
+    if let ProxyRule::Http(http_rule) = &mut invalid.rules[0] {
+      http_rule.locations[0].max_body_size = Some("10mb".to_owned());
+    }
+    let res = client
+      .send_put(&format!("/rules/{name}"), Some(&invalid), None::<String>)
+      .await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::BAD_REQUEST,
+      "put a rule with an invalid body size"
+    );
+    let mut invalid = payload.clone();
+    if let ProxyRule::Http(http_rule) = &mut invalid.rules[0] {
+      http_rule.locations[0].protocol = Some(ProxyHttpProtocol::Grpc);
+    }
+    let res = client
+      .send_put(&format!("/rules/{name}"), Some(&invalid), None::<String>)
+      .await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::BAD_REQUEST,
+      "put a grpc rule without ssl"
+    );
+    let mut res = client
+      .send_post(
+        &format!("/rules/{name}/validate"),
+        Some(&payload),
+        None::<String>,
+      )
+      .await;
+    test_status_code!(res.status(), http::StatusCode::OK, "validate a rule");
+    let validation = res.json::<ProxyRuleValidation>().await.unwrap();
+    assert!(validation.config.contains("location /cargo"));
+    let mut res = client
+      .send_post(
+        &format!("/rules/{name}/validate"),
+        Some(&invalid),
+        None::<String>,
+      )
+      .await;
+    test_status_code!(
+      res.status(),
+      http::StatusCode::OK,
+      "validate an invalid rule"
+    );
+    let validation = res.json::<ProxyRuleValidation>().await.unwrap();
+    assert!(!validation.errors.is_empty());
     let res = client
       .send_delete(&format!("/rules/{name}"), None::<String>)
       .await;
@@ -0,0 +1,59 @@
+/// Periodically renew the certificates of the rules using `ProxySsl::Acme`
+use std::{sync::Arc, time::Duration};
+
+use ntex::rt;
+
+use nanocl_error::io::IoResult;
+
+use nanocld_client::stubs::{
+  resource::ResourcePartial,
+  proxy::{ProxyRule, ProxySsl},
+};
+
+use crate::{utils, models::SystemStateRef};
+
+/// Interval between two checks of the certificates expiration
+const RENEW_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// Ensure the certificate of every http rule using acme
+async fn renew_all(state: &SystemStateRef) -> IoResult<()> {
+  let resources = utils::resource::list_by_acme(None, &state.client).await?;
+  for resource in resources {
+    let resource: ResourcePartial = resource.into();
+    let rule = utils::resource::serialize(&resource.data)?;
+    for rule in rule.rules {
+      let ProxyRule::Http(http_rule) = rule else {
+        continue;
+      };
+      let (Some(domain), Some(ProxySsl::Acme { acme })) =
+        (http_rule.domain, http_rule.ssl)
+      else {
+        continue;
+      };
+      if let Err(err) = utils::acme::ensure(&domain, &acme, state).await {
+        log::warn!("acme::renew_all: {domain} {err}");
+      }
+    }
+  }
+  Ok(())
+}
+
+async fn r#loop(state: &SystemStateRef) {
+  loop {
+    if let Err(err) = renew_all(state).await {
+      log::warn!("acme::loop: {err}");
+    }
+    ntex::time::sleep(RENEW_INTERVAL).await;
+  }
+}
+
+/// Spawn new thread with a loop renewing the acme certificates
+pub(crate) fn spawn(state: &SystemStateRef) {
+  let state = Arc::clone(state);
+  rt::Arbiter::new().exec_fn(move || {
+    ntex::rt::spawn(async move {
+      r#loop(&state).await;
+      rt::Arbiter::current().stop();
+    });
+  });
+}
@@ -11,7 +11,7 @@ use nanocld_client::{
   stubs::{
     system::Event,
     resource::ResourcePartial,
-    system::{EventActorKind, NativeEventAction},
+    system::{EventActorKind, EventWatchQuery, NativeEventAction},
     resource_kind::{ResourceKindPartial, ResourceKindSpec},
   },
 };
@@ -54,7 +54,7 @@ async fn update_cargo_rule(
       let resource: ResourcePartial = resource.into();
       let rule = utils::resource::serialize(&resource.data)?;
       if let Err(err) =
-        utils::nginx::add_rule(&resource.name, &rule, state).await
+        utils::backend::add_rule(&resource.name, &rule, state).await
       {
         log::warn!("event::update_cargo_rule: {err}");
       }
@@ -146,18 +146,24 @@ async fn ensure_self_config(client: &NanocldClient) -> IoResult<()> {
 }
 
 async fn r#loop(state: &SystemStateRef) {
+  // Key of the last event received, used to replay the events missed
+  // while reconnecting
+  let mut last_key = None;
   loop {
     log::info!("event::loop: subscribing to nanocld events");
-    match state.client.watch_events(None).await {
+    let query = last_key.as_ref().map(EventWatchQuery::since_key);
+    match state.client.watch_events(None, query.as_ref()).await {
       Err(err) => {
         log::warn!("event::loop: {err}");
+        // The last event may have expired, resume without replay
+        last_key = None;
       }
       Ok(mut stream) => {
         if let Err(err) = ensure_self_config(&state.client).await {
           log::warn!("event::loop: {err}");
           continue;
         }
-        let _ = utils::nginx::ensure_conf(state).await;
+        let _ = utils::backend::ensure_conf(state).await;
         log::info!("event::loop: subscribed to nanocld events");
         while let Some(event) = stream.next().await {
           let event = match event {
@@ -167,6 +173,7 @@ async fn r#loop(state: &SystemStateRef) {
             }
             Ok(event) => event,
           };
+          last_key = Some(event.key);
           if let Err(err) = on_event(&event, state).await {
             log::warn!("event::loop: {err}");
           }
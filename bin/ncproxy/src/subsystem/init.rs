@@ -6,10 +6,12 @@ use nanocld_client::NanocldClient;
 
 use crate::{
   cli::Cli,
-  models::{Store, SystemState, SystemStateRef, EventEmitter},
+  models::{
+    Store, SystemState, SystemStateRef, EventEmitter, AcmeState, NativeState,
+  },
 };
 
-use super::{event, metric};
+use super::{event, metric, acme};
 
 pub async fn init(cli: &Cli) -> IoResult<SystemStateRef> {
   #[allow(unused)]
@@ -22,14 +24,20 @@ pub async fn init(cli: &Cli) -> IoResult<SystemStateRef> {
       ..Default::default()
     });
   }
-  let event_emitter = EventEmitter::new(&client);
+  let native = NativeState::default();
+  let event_emitter =
+    EventEmitter::new(&client, cli.backend, &native, &cli.state_dir);
   let state = Arc::new(SystemState {
     client,
     event_emitter,
     store: Store::new(&cli.state_dir),
     nginx_dir: cli.nginx_dir.clone(),
+    acme: AcmeState::new(cli.acme_ca_file.clone(), &cli.acme_tls_alpn_addr),
+    backend: cli.backend,
+    native,
   });
   event::spawn(&state);
   metric::spawn(&state);
+  acme::spawn(&state);
   Ok(state)
 }
@@ -1,5 +1,6 @@
 mod init;
 mod event;
 mod metric;
+mod acme;
 
 pub use init::init;
@@ -0,0 +1,790 @@
+//! ACME client (RFC 8555) used to obtain and renew the certificates
+//! of the rules using `ProxySsl::Acme`.
+//! Certificates are stored as `nanocl.io/tls` secrets named `acme-{domain}`.
+use std::{
+  fmt::Write,
+  io::ErrorKind,
+  net::TcpListener,
+  time::Duration,
+  os::unix::fs::PermissionsExt,
+  sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+  },
+};
+
+use ntex::{rt, http, util::Bytes};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::{json, Value};
+use openssl::{
+  base64,
+  sha::sha256,
+  nid::Nid,
+  stack::Stack,
+  hash::MessageDigest,
+  ecdsa::EcdsaSig,
+  error::ErrorStack,
+  pkey::{PKey, Private},
+  ec::{EcGroup, EcKey},
+  bn::{BigNum, BigNumContext, MsbOption},
+  asn1::{Asn1Object, Asn1OctetString, Asn1Time},
+  ssl::{AlpnError, SslAcceptor, SslConnector, SslMethod, select_next_proto},
+  x509::{
+    X509, X509Builder, X509Extension, X509NameBuilder, X509ReqBuilder,
+    extension::SubjectAlternativeName,
+  },
+};
+
+use nanocl_error::io::{IoError, IoResult, FromIo};
+
+use nanocld_client::stubs::{
+  secret::{SecretInspectQuery, SecretPartial, SecretUpdate},
+  proxy::{AcmeChallenge, ProxySslAcme, ProxySslConfig},
+};
+
+use crate::models::{AcmeState, SystemStateRef};
+
+/// Directory used when none is provided
+pub const LETS_ENCRYPT_DIRECTORY: &str =
+  "https://acme-v02.api.letsencrypt.org/directory";
+/// Number of days before the expiration to renew a certificate by default
+const RENEW_BEFORE_DAYS: u32 = 30;
+/// Protocol list in wire format answered during a TLS-ALPN-01 challenge
+const ACME_TLS_ALPN: &[u8] = b"\x0aacme-tls/1";
+/// OID of the acmeIdentifier extension (RFC 8737)
+const ACME_IDENTIFIER_OID: &str = "1.3.6.1.5.5.7.1.31";
+/// Maximum size of a response from the ACME server
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+/// Number of times an order or an authorization is polled
+const POLL_ATTEMPTS: usize = 30;
+
+fn ssl_err(err: ErrorStack) -> IoError {
+  IoError::new("Acme", err.into())
+}
+
+/// Encode data in base64url without padding as required by JWS
+fn b64url(data: &[u8]) -> String {
+  base64::encode_block(data)
+    .trim_end_matches('=')
+    .replace('+', "-")
+    .replace('/', "_")
+}
+
+/// Generate a new P-256 key
+fn gen_key() -> Result<PKey<Private>, ErrorStack> {
+  let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+  PKey::from_ec_key(EcKey::generate(&group)?)
+}
+
+/// Name of the secret storing the certificate of a domain
+pub fn secret_name(domain: &str) -> String {
+  format!("acme-{domain}")
+}
+
+/// Directory where the HTTP-01 key authorizations are served by nginx
+pub fn http_challenge_dir(state_dir: &str) -> String {
+  format!("{state_dir}/acme/http-01")
+}
+
+/// Path to the account key of an email for a given ACME directory
+fn account_key_path(state_dir: &str, directory: &str, email: &str) -> String {
+  let id = sha256(format!("{directory}{email}").as_bytes())
+    .iter()
+    .take(8)
+    .fold(String::new(), |mut id, b| {
+      let _ = write!(id, "{b:02x}");
+      id
+    });
+  format!("{state_dir}/acme/account-{id}.pem")
+}
+
+/// Load the account key or generate it on first use
+async fn load_account_key(path: &str) -> IoResult<PKey<Private>> {
+  if let Ok(pem) = tokio::fs::read(path).await {
+    return PKey::private_key_from_pem(&pem).map_err(ssl_err);
+  }
+  let key = gen_key().map_err(ssl_err)?;
+  let pem = key.private_key_to_pem_pkcs8().map_err(ssl_err)?;
+  tokio::fs::write(path, pem).await.map_err(|err| {
+    err.map_err_context(|| format!("Unable to write account key {path}"))
+  })?;
+  tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+    .await?;
+  Ok(key)
+}
+
+/// Whether the certificate expires in less than the given number of days
+pub fn needs_renewal(certificate: &str, days: u32) -> IoResult<bool> {
+  let cert = X509::from_pem(certificate.as_bytes()).map_err(ssl_err)?;
+  let limit = Asn1Time::days_from_now(days).map_err(ssl_err)?;
+  let ordering = cert.not_after().compare(&limit).map_err(ssl_err)?;
+  Ok(ordering == std::cmp::Ordering::Less)
+}
+
+/// Generate a certificate signing request for a domain
+fn gen_csr(domain: &str) -> Result<(Vec<u8>, PKey<Private>), ErrorStack> {
+  let key = gen_key()?;
+  let mut name = X509NameBuilder::new()?;
+  name.append_entry_by_nid(Nid::COMMONNAME, domain)?;
+  let mut builder = X509ReqBuilder::new()?;
+  builder.set_subject_name(&name.build())?;
+  builder.set_pubkey(&key)?;
+  let mut extensions = Stack::new()?;
+  let san = SubjectAlternativeName::new()
+    .dns(domain)
+    .build(&builder.x509v3_context(None))?;
+  extensions.push(san)?;
+  builder.add_extensions(&extensions)?;
+  builder.sign(&key, MessageDigest::sha256())?;
+  Ok((builder.build().to_der()?, key))
+}
+
+/// Generate the self signed certificate answered during a TLS-ALPN-01 challenge
+/// It contains the digest of the key authorization in the acmeIdentifier extension
+fn gen_tls_alpn_cert(
+  domain: &str,
+  key_authorization: &str,
+) -> Result<(X509, PKey<Private>), ErrorStack> {
+  let key = gen_key()?;
+  let mut name = X509NameBuilder::new()?;
+  name.append_entry_by_nid(Nid::COMMONNAME, domain)?;
+  let name = name.build();
+  let mut serial = BigNum::new()?;
+  serial.rand(64, MsbOption::MAYBE_ZERO, false)?;
+  let mut builder = X509Builder::new()?;
+  builder.set_version(2)?;
+  let serial = serial.to_asn1_integer()?;
+  builder.set_serial_number(&serial)?;
+  builder.set_subject_name(&name)?;
+  builder.set_issuer_name(&name)?;
+  builder.set_pubkey(&key)?;
+  let not_before = Asn1Time::days_from_now(0)?;
+  let not_after = Asn1Time::days_from_now(1)?;
+  builder.set_not_before(&not_before)?;
+  builder.set_not_after(&not_after)?;
+  let san = SubjectAlternativeName::new()
+    .dns(domain)
+    .build(&builder.x509v3_context(None, None))?;
+  builder.append_extension(san)?;
+  // DER encoded OCTET STRING of the sha256 digest
+  let mut digest = vec![0x04, 0x20];
+  digest.extend_from_slice(&sha256(key_authorization.as_bytes()));
+  let oid = Asn1Object::from_str(ACME_IDENTIFIER_OID)?;
+  let value = Asn1OctetString::new_from_bytes(&digest)?;
+  builder.append_extension(X509Extension::new_from_der(&oid, true, &value)?)?;
+  builder.sign(&key, MessageDigest::sha256())?;
+  Ok((builder.build(), key))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+  new_nonce: String,
+  new_account: String,
+  new_order: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Problem {
+  #[serde(default, rename = "type")]
+  kind: String,
+  #[serde(default)]
+  detail: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Order {
+  status: String,
+  authorizations: Vec<String>,
+  finalize: String,
+  certificate: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Identifier {
+  value: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Challenge {
+  #[serde(rename = "type")]
+  kind: String,
+  url: String,
+  #[serde(default)]
+  token: String,
+  error: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Authorization {
+  status: String,
+  identifier: Identifier,
+  challenges: Vec<Challenge>,
+}
+
+struct AcmeResponse {
+  location: Option<String>,
+  body: Bytes,
+}
+
+fn get_header(
+  res: &http::client::ClientResponse,
+  name: &str,
+) -> Option<String> {
+  res
+    .headers()
+    .get(name)
+    .and_then(|value| value.to_str().ok())
+    .map(|value| value.to_owned())
+}
+
+/// Minimal ACME client signing its requests with an ES256 account key
+struct AcmeClient {
+  client: http::client::Client,
+  directory: Directory,
+  key: PKey<Private>,
+  jwk: Value,
+  thumbprint: String,
+  kid: Option<String>,
+  nonce: Option<String>,
+}
+
+impl AcmeClient {
+  async fn new(
+    directory_url: &str,
+    key: PKey<Private>,
+    ca_file: Option<&str>,
+  ) -> IoResult<Self> {
+    let mut builder =
+      SslConnector::builder(SslMethod::tls()).map_err(ssl_err)?;
+    if let Some(ca_file) = ca_file {
+      builder.set_ca_file(ca_file).map_err(ssl_err)?;
+    }
+    let client = http::client::Client::build()
+      .connector(
+        http::client::Connector::default()
+          .openssl(builder.build())
+          .finish(),
+      )
+      .timeout(ntex::time::Millis::from_secs(30))
+      .finish();
+    let mut res = client.get(directory_url).send().await.map_err(|err| {
+      err.map_err_context(|| format!("Unable to get directory {directory_url}"))
+    })?;
+    let directory = res.json::<Directory>().await.map_err(|err| {
+      err.map_err_context(|| format!("Invalid directory {directory_url}"))
+    })?;
+    let (jwk, thumbprint) = Self::gen_jwk(&key).map_err(ssl_err)?;
+    Ok(Self {
+      client,
+      directory,
+      key,
+      jwk,
+      thumbprint,
+      kid: None,
+      nonce: None,
+    })
+  }
+
+  /// Generate the JSON Web Key of the account and its thumbprint (RFC 7638)
+  fn gen_jwk(key: &PKey<Private>) -> Result<(Value, String), ErrorStack> {
+    let key = key.ec_key()?;
+    let mut ctx = BigNumContext::new()?;
+    let mut x = BigNum::new()?;
+    let mut y = BigNum::new()?;
+    key.public_key().affine_coordinates_gfp(
+      key.group(),
+      &mut x,
+      &mut y,
+      &mut ctx,
+    )?;
+    let x = b64url(&x.to_vec_padded(32)?);
+    let y = b64url(&y.to_vec_padded(32)?);
+    // Members must be in lexicographic order without whitespace
+    let canonical =
+      format!(r#"{{"crv":"P-256","kty":"EC","x":"{x}","y":"{y}"}}"#);
+    let thumbprint = b64url(&sha256(canonical.as_bytes()));
+    let jwk = json!({ "crv": "P-256", "kty": "EC", "x": x, "y": y });
+    Ok((jwk, thumbprint))
+  }
+
+  /// Sign with ES256, the signature is the concatenation of r and s
+  fn sign(&self, data: &[u8]) -> Result<Vec<u8>, ErrorStack> {
+    let ec_key = self.key.ec_key()?;
+    let sig = EcdsaSig::sign(&sha256(data), &ec_key)?;
+    let mut signature = sig.r().to_vec_padded(32)?;
+    signature.extend(sig.s().to_vec_padded(32)?);
+    Ok(signature)
+  }
+
+  fn key_authorization(&self, token: &str) -> String {
+    format!("{token}.{}", self.thumbprint)
+  }
+
+  async fn new_nonce(&self) -> IoResult<String> {
+    let res = self
+      .client
+      .head(&self.directory.new_nonce)
+      .send()
+      .await
+      .map_err(|err| err.map_err_context(|| "Unable to get a nonce"))?;
+    get_header(&res, "replay-nonce")
+      .ok_or_else(|| IoError::invalid_data("Acme", "missing Replay-Nonce"))
+  }
+
+  /// Send a signed request, without payload it's a POST-as-GET
+  async fn post(
+    &mut self,
+    url: &str,
+    payload: Option<&Value>,
+  ) -> IoResult<AcmeResponse> {
+    let mut retry = true;
+    loop {
+      let nonce = match self.nonce.take() {
+        Some(nonce) => nonce,
+        None => self.new_nonce().await?,
+      };
+      let mut protected = json!({ "alg": "ES256", "nonce": nonce, "url": url });
+      match &self.kid {
+        Some(kid) => protected["kid"] = json!(kid),
+        None => protected["jwk"] = self.jwk.clone(),
+      }
+      let protected = b64url(protected.to_string().as_bytes());
+      let payload = payload
+        .map(|payload| b64url(payload.to_string().as_bytes()))
+        .unwrap_or_default();
+      let signature = self
+        .sign(format!("{protected}.{payload}").as_bytes())
+        .map_err(ssl_err)?;
+      let body = json!({
+        "protected": protected,
+        "payload": payload,
+        "signature": b64url(&signature),
+      });
+      let mut res = self
+        .client
+        .post(url)
+        .header("Content-Type", "application/jose+json")
+        .send_body(body.to_string())
+        .await
+        .map_err(|err| err.map_err_context(|| format!("Acme {url}")))?;
+      self.nonce = get_header(&res, "replay-nonce");
+      let location = get_header(&res, "location");
+      let status = res.status();
+      let body = res
+        .body()
+        .limit(MAX_BODY_SIZE)
+        .await
+        .map_err(|err| err.map_err_context(|| format!("Acme {url}")))?;
+      if status.is_success() {
+        return Ok(AcmeResponse { location, body });
+      }
+      let problem =
+        serde_json::from_slice::<Problem>(&body).unwrap_or_default();
+      if retry && problem.kind == "urn:ietf:params:acme:error:badNonce" {
+        retry = false;
+        continue;
+      }
+      return Err(IoError::other(
+        "Acme",
+        &format!("{url} {status} {}", problem.detail),
+      ));
+    }
+  }
+
+  async fn get<T>(&mut self, url: &str) -> IoResult<T>
+  where
+    T: DeserializeOwned,
+  {
+    let res = self.post(url, None).await?;
+    let item = serde_json::from_slice::<T>(&res.body)?;
+    Ok(item)
+  }
+
+  /// Register the account or retrieve the one bound to the key
+  async fn account(&mut self, email: &str) -> IoResult<()> {
+    let url = self.directory.new_account.clone();
+    let payload = json!({
+      "termsOfServiceAgreed": true,
+      "contact": [format!("mailto:{email}")],
+    });
+    let res = self.post(&url, Some(&payload)).await?;
+    let kid = res
+      .location
+      .ok_or_else(|| IoError::invalid_data("Acme", "missing account url"))?;
+    self.kid = Some(kid);
+    Ok(())
+  }
+
+  async fn new_order(&mut self, domain: &str) -> IoResult<(String, Order)> {
+    let url = self.directory.new_order.clone();
+    let payload = json!({
+      "identifiers": [{ "type": "dns", "value": domain }],
+    });
+    let res = self.post(&url, Some(&payload)).await?;
+    let order_url = res
+      .location
+      .ok_or_else(|| IoError::invalid_data("Acme", "missing order url"))?;
+    let order = serde_json::from_slice::<Order>(&res.body)?;
+    Ok((order_url, order))
+  }
+
+  /// Wait for the order to reach one of the given status
+  async fn poll_order(
+    &mut self,
+    url: &str,
+    status: &[&str],
+  ) -> IoResult<Order> {
+    for _ in 0..POLL_ATTEMPTS {
+      let order = self.get::<Order>(url).await?;
+      if status.contains(&order.status.as_str()) {
+        return Ok(order);
+      }
+      if order.status == "invalid" {
+        return Err(IoError::invalid_data(
+          "Acme",
+          &format!("order {url} is invalid"),
+        ));
+      }
+      ntex::time::sleep(Duration::from_secs(2)).await;
+    }
+    Err(IoError::interrupted(
+      "Acme",
+      &format!("order {url} timed out"),
+    ))
+  }
+
+  /// Wait for the authorization to be validated by the ACME server
+  async fn poll_authorization(&mut self, url: &str) -> IoResult<()> {
+    for _ in 0..POLL_ATTEMPTS {
+      let authorization = self.get::<Authorization>(url).await?;
+      match authorization.status.as_str() {
+        "valid" => return Ok(()),
+        "pending" | "processing" => {
+          ntex::time::sleep(Duration::from_secs(2)).await;
+        }
+        status => {
+          let errors = authorization
+            .challenges
+            .iter()
+            .filter_map(|challenge| challenge.error.clone())
+            .collect::<Vec<_>>();
+          return Err(IoError::invalid_data(
+            "Acme",
+            &format!(
+              "authorization of {} is {status} {errors:?}",
+              authorization.identifier.value
+            ),
+          ));
+        }
+      }
+    }
+    Err(IoError::interrupted(
+      "Acme",
+      &format!("authorization {url} timed out"),
+    ))
+  }
+}
+
+/// Answer a challenge until the ACME server validates it
+enum ChallengeResponder {
+  Http01(String),
+  TlsAlpn01(Arc<AtomicBool>),
+}
+
+impl ChallengeResponder {
+  async fn start(
+    kind: &AcmeChallenge,
+    domain: &str,
+    token: &str,
+    key_authorization: &str,
+    state_dir: &str,
+    acme: &AcmeState,
+  ) -> IoResult<Self> {
+    match kind {
+      AcmeChallenge::Http01 => {
+        let dir = http_challenge_dir(state_dir);
+        tokio::fs::create_dir_all(&dir).await?;
+        let path = format!("{dir}/{token}");
+        tokio::fs::write(&path, key_authorization)
+          .await
+          .map_err(|err| {
+            err.map_err_context(|| format!("Unable to write challenge {path}"))
+          })?;
+        Ok(Self::Http01(path))
+      }
+      AcmeChallenge::TlsAlpn01 => {
+        let (cert, key) =
+          gen_tls_alpn_cert(domain, key_authorization).map_err(ssl_err)?;
+        let mut builder =
+          SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())
+            .map_err(ssl_err)?;
+        builder.set_certificate(&cert).map_err(ssl_err)?;
+        builder.set_private_key(&key).map_err(ssl_err)?;
+        builder.set_alpn_select_callback(|_, client| {
+          select_next_proto(ACME_TLS_ALPN, client).ok_or(AlpnError::NOACK)
+        });
+        let acceptor = builder.build();
+        let listener =
+          TcpListener::bind(&acme.tls_alpn_addr).map_err(|err| {
+            err.map_err_context(|| {
+              format!("Unable to bind {}", acme.tls_alpn_addr)
+            })
+          })?;
+        listener.set_nonblocking(true)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+        std::thread::spawn(move || {
+          while !stopped.load(Ordering::Relaxed) {
+            match listener.accept() {
+              Ok((stream, _)) => {
+                let _ = stream.set_nonblocking(false);
+                let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+                // The validation only needs the handshake to complete
+                let _ = acceptor.accept(stream);
+              }
+              Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(100));
+              }
+              Err(err) => {
+                log::warn!("acme::tls_alpn: {err}");
+                break;
+              }
+            }
+          }
+        });
+        Ok(Self::TlsAlpn01(stop))
+      }
+    }
+  }
+
+  async fn stop(self) {
+    match self {
+      Self::Http01(path) => {
+        let _ = tokio::fs::remove_file(path).await;
+      }
+      Self::TlsAlpn01(stop) => stop.store(true, Ordering::Relaxed),
+    }
+  }
+}
+
+/// Order a certificate for a domain and return it with its private key
+pub async fn obtain(
+  domain: &str,
+  acme: &ProxySslAcme,
+  state_dir: &str,
+  acme_state: &AcmeState,
+) -> IoResult<ProxySslConfig> {
+  let directory = acme.directory.as_deref().unwrap_or(LETS_ENCRYPT_DIRECTORY);
+  tokio::fs::create_dir_all(format!("{state_dir}/acme")).await?;
+  let key_path = account_key_path(state_dir, directory, &acme.email);
+  let key = load_account_key(&key_path).await?;
+  let mut client =
+    AcmeClient::new(directory, key, acme_state.ca_file.as_deref()).await?;
+  client.account(&acme.email).await?;
+  let (order_url, order) = client.new_order(domain).await?;
+  let kind = acme.challenge.clone().unwrap_or_default();
+  let challenge_type = match kind {
+    AcmeChallenge::Http01 => "http-01",
+    AcmeChallenge::TlsAlpn01 => "tls-alpn-01",
+  };
+  for url in &order.authorizations {
+    let authorization = client.get::<Authorization>(url).await?;
+    if authorization.status == "valid" {
+      continue;
+    }
+    let challenge = authorization
+      .challenges
+      .iter()
+      .find(|challenge| challenge.kind == challenge_type)
+      .cloned()
+      .ok_or_else(|| {
+        IoError::not_found(
+          "Acme",
+          &format!("{challenge_type} challenge not offered for {domain}"),
+        )
+      })?;
+    let key_authorization = client.key_authorization(&challenge.token);
+    let responder = ChallengeResponder::start(
+      &kind,
+      &authorization.identifier.value,
+      &challenge.token,
+      &key_authorization,
+      state_dir,
+      acme_state,
+    )
+    .await?;
+    let res = match client.post(&challenge.url, Some(&json!({}))).await {
+      Ok(_) => client.poll_authorization(url).await,
+      Err(err) => Err(err),
+    };
+    responder.stop().await;
+    res?;
+  }
+  let order = client.poll_order(&order_url, &["ready", "valid"]).await?;
+  let (csr, key) = gen_csr(domain).map_err(ssl_err)?;
+  if order.status == "ready" {
+    let payload = json!({ "csr": b64url(&csr) });
+    client.post(&order.finalize, Some(&payload)).await?;
+  }
+  let order = client.poll_order(&order_url, &["valid"]).await?;
+  let certificate_url = order
+    .certificate
+    .ok_or_else(|| IoError::invalid_data("Acme", "missing certificate url"))?;
+  let res = client.post(&certificate_url, None).await?;
+  let certificate = String::from_utf8(res.body.to_vec())
+    .map_err(|err| err.map_err_context(|| "Acme certificate"))?;
+  let certificate_key = key.private_key_to_pem_pkcs8().map_err(ssl_err)?;
+  let certificate_key = String::from_utf8(certificate_key)
+    .map_err(|err| err.map_err_context(|| "Acme certificate key"))?;
+  Ok(ProxySslConfig {
+    certificate,
+    certificate_key,
+    certificate_client: None,
+    verify_client: None,
+    dhparam: None,
+  })
+}
+
+/// Create or update the secret of a domain with a new certificate
+async fn store_certificate(
+  domain: &str,
+  ssl: &ProxySslConfig,
+  state: &SystemStateRef,
+) -> IoResult<()> {
+  let name = secret_name(domain);
+  let data = serde_json::to_value(ssl)?;
+  if state.client.inspect_secret(&name, None).await.is_ok() {
+    let update = SecretUpdate {
+      data,
+      metadata: None,
+    };
+    state.client.patch_secret(&name, &update).await?;
+    return Ok(());
+  }
+  let secret = SecretPartial {
+    name,
+    kind: "nanocl.io/tls".to_owned(),
+    immutable: None,
+    data,
+    metadata: Some(json!({ "ManagedBy": "ncproxy", "Domain": domain })),
+  };
+  state.client.create_secret(&secret).await?;
+  Ok(())
+}
+
+/// Obtain the certificate of the domain if it's missing or about to expire
+/// then update the rules using it
+async fn renew(
+  domain: &str,
+  acme: &ProxySslAcme,
+  state: &SystemStateRef,
+) -> IoResult<()> {
+  let name = secret_name(domain);
+  let days = acme.renew_before_days.unwrap_or(RENEW_BEFORE_DAYS);
+  let query = SecretInspectQuery { reveal: Some(true) };
+  if let Ok(secret) = state.client.inspect_secret(&name, Some(&query)).await {
+    let ssl = serde_json::from_value::<ProxySslConfig>(secret.data)?;
+    if !needs_renewal(&ssl.certificate, days)? {
+      return Ok(());
+    }
+  }
+  log::info!("acme::renew: ordering a certificate for {domain}");
+  let ssl = obtain(domain, acme, &state.store.dir, &state.acme).await?;
+  store_certificate(domain, &ssl, state).await?;
+  log::info!("acme::renew: certificate of {domain} stored in secret {name}");
+  let resources =
+    super::resource::list_by_acme(Some(domain), &state.client).await?;
+  super::resource::update_rules(&resources, state).await?;
+  state.event_emitter.emit_reload().await;
+  Ok(())
+}
+
+/// Renew the certificate of a domain unless an order is already in progress
+pub async fn ensure(
+  domain: &str,
+  acme: &ProxySslAcme,
+  state: &SystemStateRef,
+) -> IoResult<()> {
+  if !state.acme.pending.lock()?.insert(domain.to_owned()) {
+    return Ok(());
+  }
+  let res = renew(domain, acme, state).await;
+  state.acme.pending.lock()?.remove(domain);
+  res
+}
+
+/// Spawn a task ensuring the certificate of a domain in background
+pub fn spawn_ensure(domain: &str, acme: &ProxySslAcme, state: &SystemStateRef) {
+  let domain = domain.to_owned();
+  let acme = acme.clone();
+  let state = Arc::clone(state);
+  rt::spawn(async move {
+    if let Err(err) = ensure(&domain, &acme, &state).await {
+      log::warn!("acme::ensure: {domain} {err}");
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn base64_url() {
+    assert_eq!(b64url(&[0xfb, 0xff]), "-_8");
+    assert_eq!(b64url(b"nanocl"), "bmFub2Ns");
+  }
+
+  #[test]
+  fn tls_alpn_cert() {
+    let (cert, _) = gen_tls_alpn_cert("example.com", "token.thumbprint")
+      .expect("Expect to generate the challenge certificate");
+    let der = cert.to_der().unwrap();
+    let mut digest = vec![0x04, 0x20];
+    digest.extend_from_slice(&sha256(b"token.thumbprint"));
+    assert!(der.windows(digest.len()).any(|window| window == digest));
+    assert!(!needs_renewal(
+      &String::from_utf8(cert.to_pem().unwrap()).unwrap(),
+      0
+    )
+    .unwrap());
+    assert!(needs_renewal(
+      &String::from_utf8(cert.to_pem().unwrap()).unwrap(),
+      30
+    )
+    .unwrap());
+  }
+
+  #[test]
+  fn jwk_thumbprint() {
+    let key = gen_key().unwrap();
+    let (jwk, thumbprint) = AcmeClient::gen_jwk(&key).unwrap();
+    assert_eq!(jwk["kty"], "EC");
+    // sha256 encoded in base64url without padding
+    assert_eq!(thumbprint.len(), 43);
+  }
+
+  /// Run against pebble with `PEBBLE_VA_ALWAYS_VALID=1`
+  /// `ACME_TEST_DIRECTORY=https://localhost:14000/dir ACME_TEST_CA=pebble.minica.pem`
+  #[ntex::test]
+  async fn pebble() {
+    let Ok(directory) = std::env::var("ACME_TEST_DIRECTORY") else {
+      return;
+    };
+    let state_dir = std::env::temp_dir().join("ncproxy-acme-test");
+    let state_dir = state_dir.display().to_string();
+    let acme_state =
+      AcmeState::new(std::env::var("ACME_TEST_CA").ok(), "0.0.0.0:5001");
+    let acme = ProxySslAcme {
+      email: "test@nanocl.io".to_owned(),
+      directory: Some(directory),
+      challenge: None,
+      renew_before_days: None,
+    };
+    let ssl = obtain("test.nanocl.io", &acme, &state_dir, &acme_state)
+      .await
+      .unwrap();
+    assert!(!needs_renewal(&ssl.certificate, 1).unwrap());
+  }
+}
@@ -0,0 +1,67 @@
+//! Dispatch the rules to the backend selected at startup
+use nanocl_error::io::IoResult;
+
+use nanocld_client::stubs::proxy::{ResourceProxyRule, ProxyRuleValidation};
+
+use crate::models::{SystemStateRef, ProxyBackend};
+
+/// Validate a rule for the backend in use
+pub fn validate(
+  rule: &ResourceProxyRule,
+  state: &SystemStateRef,
+) -> IoResult<()> {
+  super::rule::validate(rule)?;
+  super::rule::validate_acme(rule, &state.acme.tls_alpn_addr)?;
+  if state.backend == ProxyBackend::Native {
+    super::native::validate(rule)?;
+  }
+  Ok(())
+}
+
+/// Prepare the backend when nanocld is reachable
+pub async fn ensure_conf(state: &SystemStateRef) -> IoResult<()> {
+  match state.backend {
+    ProxyBackend::Nginx => super::nginx::ensure_conf(state).await,
+    ProxyBackend::Native => super::native::ensure_conf(state).await,
+  }
+}
+
+/// Create or replace a rule, it's served after the next reload
+pub async fn add_rule(
+  name: &str,
+  rule: &ResourceProxyRule,
+  state: &SystemStateRef,
+) -> IoResult<()> {
+  match state.backend {
+    ProxyBackend::Nginx => super::nginx::add_rule(name, rule, state).await,
+    ProxyBackend::Native => super::native::add_rule(name, rule, state).await,
+  }
+}
+
+/// Remove a rule, it stops being served after the next reload
+pub async fn del_rule(name: &str, state: &SystemStateRef) {
+  match state.backend {
+    ProxyBackend::Nginx => super::nginx::del_rule(name, state).await,
+    ProxyBackend::Native => super::native::del_rule(name, state),
+  }
+}
+
+/// Render a rule without applying it and return the errors found
+pub async fn validate_rule(
+  name: &str,
+  rule: &ResourceProxyRule,
+  state: &SystemStateRef,
+) -> IoResult<ProxyRuleValidation> {
+  if let Err(err) = validate(rule, state) {
+    return Ok(ProxyRuleValidation {
+      config: String::new(),
+      errors: vec![err.to_string()],
+    });
+  }
+  match state.backend {
+    ProxyBackend::Nginx => super::nginx::validate_rule(name, rule, state).await,
+    ProxyBackend::Native => {
+      super::native::validate_rule(name, rule, state).await
+    }
+  }
+}
@@ -2,6 +2,9 @@ pub mod server;
 pub mod rule;
 pub mod nginx;
 pub mod resource;
+pub mod acme;
+pub mod native;
+pub mod backend;
 
 #[cfg(test)]
 pub(crate) mod tests {
@@ -87,6 +90,9 @@ pub(crate) mod tests {
     let options = crate::cli::Cli {
       state_dir: format!("{home}/.nanocl_dev/state/proxy"),
       nginx_dir: "/etc/nginx".to_owned(),
+      acme_ca_file: None,
+      acme_tls_alpn_addr: "0.0.0.0:5001".to_owned(),
+      backend: crate::models::ProxyBackend::Nginx,
     };
     let system_state = crate::subsystem::init(&options).await.unwrap();
     // Create test server
@@ -0,0 +1,1695 @@
+//! In process reverse proxy used by `--backend native`.
+//! Rules are resolved when they are applied and swapped in memory,
+//! listeners are opened and closed when the rules are reloaded.
+//! Requests and connections are logged in the format of the nginx logs
+//! so the metric subsystem keeps producing the same records.
+use std::{
+  fmt::Write as _,
+  rc::Rc,
+  sync::Arc,
+  cell::{Cell, RefCell},
+  collections::{HashMap, HashSet},
+  net::{IpAddr, SocketAddr},
+  time::{Duration, Instant},
+};
+
+use ntex::{
+  rt, web,
+  time::Millis,
+  util::{Bytes, BytesMut},
+  http::{
+    header, StatusCode,
+    error::PayloadError,
+    client::{Client, Connector, error::SendRequestError},
+  },
+};
+use futures::{Stream, StreamExt};
+use serde_json::json;
+use openssl::{
+  error::ErrorStack,
+  ssl::{
+    AlpnError, NameType, SniError, SslAcceptor, SslAcceptorBuilder,
+    SslConnector, SslContext, SslFiletype, SslMethod, SslVerifyMode,
+    select_next_proto,
+  },
+};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+
+use nanocl_error::io::{IoError, IoResult, FromIo};
+
+use nanocld_client::stubs::{
+  generic::{GenericFilter, GenericClause},
+  resource::ResourcePartial,
+  proxy::{
+    ResourceProxyRule, ProxyRule, ProxyRuleHttp, ProxyRuleStream,
+    ProxyHttpLocation, ProxyHttpProtocol, ProxySslConfig, ProxyStreamProtocol,
+    LocationTarget, StreamTarget, UpstreamTarget, UpstreamBalancing,
+    SplitTarget, SplitSticky, ProxyRuleValidation,
+  },
+};
+
+use crate::{
+  vars,
+  models::{
+    Store, SystemState, SystemStateRef, NativeState, NativeRule,
+    NativeHttpRule, NativeStreamRule, NativeLocation, NativeTarget,
+    NativeUpstream, NativeBalancing, NativeIpRange, NativeListener,
+    NativeMetrics,
+  },
+};
+
+/// Protocols negotiated with the clients of the https listeners
+const HTTP_ALPN: &[u8] = b"\x02h2\x08http/1.1";
+/// Headers only meaningful for a single connection
+const HOP_BY_HOP_HEADERS: [&str; 8] = [
+  "connection",
+  "keep-alive",
+  "proxy-authenticate",
+  "proxy-authorization",
+  "te",
+  "trailer",
+  "transfer-encoding",
+  "upgrade",
+];
+/// Request bodies up to this size are buffered to keep their length
+const MAX_BUFFERED_BODY: u64 = 1024 * 1024;
+/// Maximum duration to wait for the response of an upstream
+const UPSTREAM_TIMEOUT: u32 = 90;
+/// Maximum duration to connect to the upstream of a stream
+const STREAM_CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Udp sessions are closed after this duration without response
+const UDP_SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn ssl_err(err: ErrorStack) -> IoError {
+  IoError::new("Native", err.into())
+}
+
+fn unsupported(name: &str, feature: &str) -> IoError {
+  IoError::invalid_input(
+    name,
+    &format!("{feature} isn't supported by the native backend"),
+  )
+}
+
+/// Format an ip and a port, ipv6 addresses are put in brackets
+fn socket_addr(ip: &str, port: u16) -> String {
+  if ip.contains(':') {
+    format!("[{ip}]:{port}")
+  } else {
+    format!("{ip}:{port}")
+  }
+}
+
+/// Nginx listens on all interfaces when the address is only a port
+fn listen_addr(addr: String) -> String {
+  if addr.contains(':') {
+    return addr;
+  }
+  format!("0.0.0.0:{addr}")
+}
+
+fn gen_balancing(
+  balancing: Option<&UpstreamBalancing>,
+) -> IoResult<NativeBalancing> {
+  match balancing {
+    None | Some(UpstreamBalancing::RoundRobin) => {
+      Ok(NativeBalancing::RoundRobin)
+    }
+    Some(UpstreamBalancing::IpHash) => Ok(NativeBalancing::IpHash),
+    Some(UpstreamBalancing::Header(header)) => {
+      Ok(NativeBalancing::Header(header.to_lowercase()))
+    }
+    Some(UpstreamBalancing::LeastConn) => {
+      Err(unsupported("UpstreamBalancing", "LeastConn"))
+    }
+    Some(UpstreamBalancing::Hash(_)) => {
+      Err(unsupported("UpstreamBalancing", "Hash"))
+    }
+  }
+}
+
+fn validate_upstream(upstream: &UpstreamTarget) -> IoResult<()> {
+  gen_balancing(upstream.balancing.as_ref())?;
+  if upstream.health.is_some() {
+    return Err(unsupported("UpstreamTarget", "Health"));
+  }
+  Ok(())
+}
+
+/// Parse the allowed ips of a location, `all` allows every address
+fn parse_allowed_ips(allowed_ips: &[String]) -> IoResult<Vec<NativeIpRange>> {
+  let mut ranges = vec![];
+  for allowed_ip in allowed_ips {
+    if allowed_ip == "all" {
+      ranges.push(NativeIpRange {
+        addr: IpAddr::from([0, 0, 0, 0]),
+        prefix: 0,
+      });
+      ranges.push(NativeIpRange {
+        addr: IpAddr::from([0u16; 8]),
+        prefix: 0,
+      });
+      continue;
+    }
+    let (addr, prefix) = match allowed_ip.split_once('/') {
+      Some((addr, prefix)) => (addr, Some(prefix)),
+      None => (allowed_ip.as_str(), None),
+    };
+    let invalid = || {
+      IoError::invalid_input(
+        "AllowedIps",
+        &format!("invalid address {allowed_ip}"),
+      )
+    };
+    let addr = addr.parse::<IpAddr>().map_err(|_| invalid())?;
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+      Some(prefix) => prefix.parse::<u8>().map_err(|_| invalid())?,
+      None => max,
+    };
+    if prefix > max {
+      return Err(invalid());
+    }
+    ranges.push(NativeIpRange { addr, prefix });
+  }
+  Ok(ranges)
+}
+
+/// Parse the headers of a location written like `Name value`,
+/// the value can be `$host`, `$scheme`, `$remote_addr` or `$http_{name}`
+fn parse_headers(headers: &[String]) -> IoResult<Vec<(String, String)>> {
+  headers
+    .iter()
+    .map(|header| {
+      let Some((name, value)) = header.trim().split_once(char::is_whitespace)
+      else {
+        return Err(IoError::invalid_input(
+          "Headers",
+          &format!("invalid header {header} expected a name and a value"),
+        ));
+      };
+      let value = value.trim().trim_matches('"');
+      if value.contains('$')
+        && !matches!(value, "$host" | "$scheme" | "$remote_addr")
+        && !value.starts_with("$http_")
+      {
+        return Err(unsupported("Headers", &format!("variable {value}")));
+      }
+      Ok((name.to_owned(), value.to_owned()))
+    })
+    .collect()
+}
+
+fn parse_body_size(size: &str) -> IoResult<u64> {
+  let digits = size.trim_end_matches(['k', 'K', 'm', 'M', 'g', 'G']);
+  let unit = match &size[digits.len()..] {
+    "" => 1,
+    "k" | "K" => 1024,
+    "m" | "M" => 1024 * 1024,
+    "g" | "G" => 1024 * 1024 * 1024,
+    _ => 0,
+  };
+  match digits.parse::<u64>() {
+    Ok(digits) if unit > 0 => Ok(digits * unit),
+    _ => Err(IoError::invalid_input(
+      "MaxBodySize",
+      &format!("invalid size {size}"),
+    )),
+  }
+}
+
+fn validate_location(location: &ProxyHttpLocation) -> IoResult<()> {
+  let name = "ProxyHttpLocation";
+  if location.path.starts_with('~') {
+    return Err(unsupported(name, "Regex path"));
+  }
+  if location.auth.is_some() {
+    return Err(unsupported(name, "Auth"));
+  }
+  if location.rate_limit.is_some() {
+    return Err(unsupported(name, "RateLimit"));
+  }
+  if location.connection_limit.is_some() {
+    return Err(unsupported(name, "ConnectionLimit"));
+  }
+  match location.protocol.clone().unwrap_or_default() {
+    ProxyHttpProtocol::Http => {}
+    protocol => {
+      return Err(unsupported(name, &format!("Protocol {protocol:?}")));
+    }
+  }
+  match &location.target {
+    LocationTarget::Upstream(upstream) => validate_upstream(upstream)?,
+    LocationTarget::Http(_) => {}
+    LocationTarget::Unix(_) => return Err(unsupported(name, "UnixPath")),
+    LocationTarget::Split(_) => {}
+  }
+  parse_allowed_ips(location.allowed_ips.as_deref().unwrap_or_default())?;
+  parse_headers(location.headers.as_deref().unwrap_or_default())?;
+  if let Some(max_body_size) = &location.max_body_size {
+    parse_body_size(max_body_size)?;
+  }
+  Ok(())
+}
+
+/// Ensure a rule only uses features supported by the native backend
+pub fn validate(rule: &ResourceProxyRule) -> IoResult<()> {
+  for rule in &rule.rules {
+    match rule {
+      ProxyRule::Http(http_rule) => {
+        if http_rule.includes.is_some() {
+          return Err(unsupported("ProxyRuleHttp", "Includes"));
+        }
+        for location in &http_rule.locations {
+          validate_location(location)?;
+        }
+      }
+      ProxyRule::Stream(stream_rule) => {
+        if stream_rule.ssl.is_some() {
+          return Err(unsupported("ProxyRuleStream", "Ssl"));
+        }
+        match &stream_rule.target {
+          StreamTarget::Upstream(upstream) => validate_upstream(upstream)?,
+          StreamTarget::Unix(_) => {
+            return Err(unsupported("ProxyRuleStream", "UnixPath"))
+          }
+          StreamTarget::Uri(_) => {
+            return Err(unsupported("ProxyRuleStream", "Uri"))
+          }
+        }
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Resolve the addresses of the processes of a cargo or a vm
+async fn gen_upstream(
+  target: &UpstreamTarget,
+  state: &SystemStateRef,
+) -> IoResult<NativeUpstream> {
+  let (processes, namespace) =
+    super::rule::get_target_processes(&target.key, state).await?;
+  let weight = target.weight.unwrap_or(1).max(1) as usize;
+  let addresses = super::rule::get_addresses(&processes, &namespace)
+    .await?
+    .into_iter()
+    .flat_map(|ip| {
+      std::iter::repeat(socket_addr(&ip, target.port)).take(weight)
+    })
+    .collect();
+  Ok(NativeUpstream::new(
+    addresses,
+    gen_balancing(target.balancing.as_ref())?,
+  ))
+}
+
+/// Resolve the addresses of every upstream of a split target,
+/// each address is repeated by the weight of its server
+async fn gen_split_upstream(
+  split: &SplitTarget,
+  state: &SystemStateRef,
+) -> IoResult<NativeUpstream> {
+  let mut addresses = vec![];
+  for upstream in &split.split {
+    if upstream.weight == 0 {
+      addresses.push(vec![]);
+      continue;
+    }
+    let upstream_addresses =
+      match super::rule::get_target_processes(&upstream.key, state).await {
+        Ok((processes, namespace)) => {
+          super::rule::get_addresses(&processes, &namespace).await
+        }
+        Err(err) => Err(err),
+      };
+    match upstream_addresses {
+      Err(err) => {
+        log::warn!("Split upstream {} skipped {err}", upstream.key);
+        addresses.push(vec![]);
+      }
+      Ok(upstream_addresses) => addresses.push(upstream_addresses),
+    }
+  }
+  let weights = super::rule::gen_split_weights(
+    &split
+      .split
+      .iter()
+      .zip(&addresses)
+      .map(|(upstream, addresses)| (upstream.weight, addresses.len()))
+      .collect::<Vec<_>>(),
+  );
+  let addresses = split
+    .split
+    .iter()
+    .zip(&addresses)
+    .zip(weights)
+    .flat_map(|((upstream, addresses), weight)| {
+      addresses.iter().flat_map(move |ip| {
+        std::iter::repeat(socket_addr(ip, upstream.port)).take(weight as usize)
+      })
+    })
+    .collect::<Vec<_>>();
+  if addresses.is_empty() {
+    return Err(IoError::invalid_data(
+      "SplitTarget",
+      "No address found for the split upstreams are processes running ?",
+    ));
+  }
+  let balancing = match &split.sticky {
+    None => NativeBalancing::RoundRobin,
+    Some(SplitSticky::Header(header)) => {
+      NativeBalancing::Header(header.to_lowercase())
+    }
+    Some(SplitSticky::Cookie(cookie)) => {
+      NativeBalancing::Cookie(cookie.clone())
+    }
+  };
+  Ok(NativeUpstream::new(addresses, balancing))
+}
+
+/// Build the tls context of a domain, clients select it with SNI
+fn gen_ssl_context(ssl: &ProxySslConfig) -> IoResult<SslContext> {
+  let mut builder =
+    SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).map_err(ssl_err)?;
+  builder
+    .set_certificate_chain_file(&ssl.certificate)
+    .map_err(ssl_err)?;
+  builder
+    .set_private_key_file(&ssl.certificate_key, SslFiletype::PEM)
+    .map_err(ssl_err)?;
+  builder.check_private_key().map_err(ssl_err)?;
+  if let Some(certificate_client) = &ssl.certificate_client {
+    builder.set_ca_file(certificate_client).map_err(ssl_err)?;
+    if ssl.verify_client.unwrap_or_default() {
+      builder
+        .set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    }
+  }
+  builder.set_alpn_select_callback(|_, client| {
+    select_next_proto(HTTP_ALPN, client).ok_or(AlpnError::NOACK)
+  });
+  Ok(builder.build().into_context())
+}
+
+async fn gen_location(
+  location: &ProxyHttpLocation,
+  state: &SystemStateRef,
+) -> IoResult<NativeLocation> {
+  let (exact, path) = match location.path.strip_prefix('=') {
+    Some(path) => (true, path.trim().to_owned()),
+    None => (false, location.path.clone()),
+  };
+  let target = match &location.target {
+    LocationTarget::Upstream(upstream) => NativeTarget::Upstream {
+      upstream: gen_upstream(upstream, state).await?,
+      path: upstream.path.clone().unwrap_or("/".to_owned()),
+    },
+    LocationTarget::Http(http) => match &http.redirect {
+      Some(redirect) => NativeTarget::Redirect {
+        url: http.url.clone(),
+        status: redirect.to_string().parse().unwrap_or(307),
+      },
+      None => NativeTarget::Url(http.url.clone()),
+    },
+    LocationTarget::Unix(_) => {
+      return Err(unsupported("ProxyHttpLocation", "UnixPath"))
+    }
+    LocationTarget::Split(split) => NativeTarget::Upstream {
+      upstream: gen_split_upstream(split, state).await?,
+      path: split.path.clone().unwrap_or("/".to_owned()),
+    },
+  };
+  let allowed_ips = match &location.allowed_ips {
+    Some(allowed_ips) => Some(parse_allowed_ips(allowed_ips)?),
+    None => None,
+  };
+  let max_body_size = match &location.max_body_size {
+    Some(size) => Some(parse_body_size(size)?),
+    None => None,
+  };
+  let logging = match &location.target {
+    LocationTarget::Upstream(upstream) => {
+      !upstream.disable_logging.unwrap_or_default()
+    }
+    _ => true,
+  };
+  Ok(NativeLocation {
+    path,
+    exact,
+    target,
+    allowed_ips,
+    headers: parse_headers(location.headers.as_deref().unwrap_or_default())?,
+    max_body_size,
+    logging,
+  })
+}
+
+async fn gen_http_rule(
+  name: &str,
+  http_rule: &ProxyRuleHttp,
+  state: &SystemStateRef,
+  warnings: &mut Vec<String>,
+) -> IoResult<NativeHttpRule> {
+  let listen =
+    super::rule::get_network_addr(&http_rule.network, 80, &state.client)
+      .await?;
+  let mut listen_https = None;
+  let mut ssl_context = None;
+  if let Some(ssl) = &http_rule.ssl {
+    let domain = http_rule.domain.as_deref();
+    match super::rule::gen_ssl_config(ssl, domain, state).await {
+      Err(err) => {
+        super::rule::skip(warnings, format!("Not ssl found for {name}: {err}"))
+      }
+      Ok(ssl) => {
+        ssl_context = Some(gen_ssl_context(&ssl)?);
+        listen_https = Some(listen_addr(
+          super::rule::get_network_addr(&http_rule.network, 443, &state.client)
+            .await?,
+        ));
+      }
+    }
+  }
+  let mut locations = vec![];
+  // Like nginx the locations are hidden until the certificate is available
+  if http_rule.ssl.is_none() || ssl_context.is_some() {
+    for location in &http_rule.locations {
+      match gen_location(location, state).await {
+        Err(err) => {
+          let path = &location.path;
+          super::rule::skip(
+            warnings,
+            format!("Location {path} of {name}: {err}"),
+          )
+        }
+        Ok(location) => locations.push(location),
+      }
+    }
+  }
+  Ok(NativeHttpRule {
+    listen: listen_addr(listen),
+    listen_https,
+    domain: http_rule.domain.clone(),
+    redirect_https: http_rule.ssl.is_some(),
+    ssl: ssl_context,
+    locations,
+  })
+}
+
+async fn gen_stream_rule(
+  stream_rule: &ProxyRuleStream,
+  state: &SystemStateRef,
+) -> IoResult<NativeStreamRule> {
+  let StreamTarget::Upstream(upstream) = &stream_rule.target else {
+    return Err(unsupported("ProxyRuleStream", "Target"));
+  };
+  let listen = super::rule::get_network_addr(
+    &stream_rule.network,
+    stream_rule.port,
+    &state.client,
+  )
+  .await?;
+  Ok(NativeStreamRule {
+    listen: listen_addr(listen),
+    protocol: stream_rule.protocol.clone(),
+    upstream: gen_upstream(upstream, state).await?,
+  })
+}
+
+/// Resolve a rule and return the parts of the rule that were skipped
+async fn gen_rule(
+  name: &str,
+  rule: &ResourceProxyRule,
+  state: &SystemStateRef,
+) -> IoResult<(NativeRule, Vec<String>)> {
+  validate(rule)?;
+  let mut warnings = vec![];
+  let mut native_rule = NativeRule::default();
+  for rule in &rule.rules {
+    match rule {
+      ProxyRule::Http(http_rule) => {
+        native_rule
+          .http
+          .push(gen_http_rule(name, http_rule, state, &mut warnings).await?);
+      }
+      ProxyRule::Stream(stream_rule) => {
+        match gen_stream_rule(stream_rule, state).await {
+          Err(err) => {
+            let port = stream_rule.port;
+            super::rule::skip(
+              &mut warnings,
+              format!("Stream {port} of {name}: {err}"),
+            );
+          }
+          Ok(stream_rule) => native_rule.streams.push(stream_rule),
+        }
+      }
+    }
+  }
+  Ok((native_rule, warnings))
+}
+
+/// Resolve a rule without applying it,
+/// there is no generated configuration with the native backend
+pub async fn validate_rule(
+  name: &str,
+  rule: &ResourceProxyRule,
+  state: &SystemStateRef,
+) -> IoResult<ProxyRuleValidation> {
+  // Keep the live directory for the secrets but don't request certificates
+  let sandbox = Arc::new(SystemState {
+    store: Store {
+      sandbox: true,
+      ..state.store.clone()
+    },
+    ..state.as_ref().clone()
+  });
+  let mut validation = ProxyRuleValidation::default();
+  match gen_rule(name, rule, &sandbox).await {
+    Err(err) => validation.errors.push(err.to_string()),
+    Ok((_, warnings)) => validation.errors = warnings,
+  }
+  Ok(validation)
+}
+
+/// Resolve a rule and replace the previous version in memory,
+/// requests in flight keep using the previous version
+pub async fn add_rule(
+  name: &str,
+  rule: &ResourceProxyRule,
+  state: &SystemStateRef,
+) -> IoResult<()> {
+  let (native_rule, _) = gen_rule(name, rule, state).await?;
+  state
+    .native
+    .rules
+    .write()
+    .map_err(|err| IoError::other("Native", &err.to_string()))?
+    .insert(name.to_owned(), Arc::new(native_rule));
+  Ok(())
+}
+
+pub fn del_rule(name: &str, state: &SystemStateRef) {
+  match state.native.rules.write() {
+    Ok(mut rules) => {
+      rules.remove(name);
+    }
+    Err(err) => log::warn!("native::del_rule: {err}"),
+  }
+}
+
+/// Create the state directories and apply the existing rules
+pub async fn ensure_conf(state: &SystemStateRef) -> IoResult<()> {
+  for name in ["log", "secrets", "acme/http-01"] {
+    tokio::fs::create_dir_all(format!("{}/{name}", state.store.dir)).await?;
+  }
+  let filter = GenericFilter::new()
+    .r#where("kind", GenericClause::Eq(vars::RULE_KEY.to_owned()));
+  let resources =
+    state
+      .client
+      .list_resource(Some(&filter))
+      .await
+      .map_err(|err| {
+        err.map_err_context(|| "Unable to list resources from nanocl daemon")
+      })?;
+  for resource in resources {
+    let resource: ResourcePartial = resource.into();
+    let rule = super::resource::serialize(&resource.data)?;
+    if let Err(err) = add_rule(&resource.name, &rule, state).await {
+      log::warn!("native::ensure_conf: {} {err}", resource.name);
+    }
+  }
+  state.event_emitter.emit_reload().await;
+  Ok(())
+}
+
+/// Append a metric formatted like the nginx logs read by the metric subsystem
+fn write_metric(
+  metrics: &NativeMetrics,
+  dir: &str,
+  file: &str,
+  metric: &serde_json::Value,
+) {
+  metrics.write(format!("{dir}/log/{file}"), format!("{metric}\n"));
+}
+
+fn date_gmt() -> String {
+  chrono::Local::now()
+    .format("%Y-%m-%dT%H:%M:%S%:z")
+    .to_string()
+}
+
+fn get_header<'a>(req: &'a web::HttpRequest, name: &str) -> &'a str {
+  req
+    .headers()
+    .get(name)
+    .and_then(|value| value.to_str().ok())
+    .unwrap_or_default()
+}
+
+fn get_host(req: &web::HttpRequest) -> Option<String> {
+  let host = match req.headers().get(header::HOST) {
+    Some(host) => host.to_str().ok()?,
+    None => req.uri().host()?,
+  };
+  let host = match host.rsplit_once(':') {
+    Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+    _ => host,
+  };
+  Some(host.to_lowercase())
+}
+
+/// State of the workers of an http or https listener
+struct NativeHttpState {
+  native: NativeState,
+  listen: String,
+  https: bool,
+  dir: String,
+  client: Client,
+}
+
+/// Data of a request logged once the response is known
+struct HttpLog<'a> {
+  req: &'a web::HttpRequest,
+  started: Instant,
+  upstream: Option<&'a str>,
+}
+
+impl HttpLog<'_> {
+  fn write(
+    &self,
+    state: &NativeHttpState,
+    status: StatusCode,
+    bytes_sent: u64,
+  ) {
+    let req = self.req;
+    let remote_addr = req
+      .peer_addr()
+      .map(|addr| addr.ip().to_string())
+      .unwrap_or_default();
+    let metric = json!({
+      "date_gmt": date_gmt(),
+      "remote_addr": remote_addr,
+      "realip_remote_addr": remote_addr,
+      "proxy_host": self.upstream.unwrap_or_default(),
+      "upstream_addr": self.upstream.unwrap_or_default(),
+      "server_protocol": format!("{:?}", req.version()),
+      "request_method": req.method().as_str(),
+      "host": get_host(req).unwrap_or_default(),
+      "uri": req.path(),
+      "query_string": req.query_string(),
+      "request_body": "",
+      "content_type": get_header(req, "content-type"),
+      "content_length": get_header(req, "content-length"),
+      "status": status.as_u16().to_string(),
+      "bytes_sent": bytes_sent.to_string(),
+      "request_time": format!("{:.3}", self.started.elapsed().as_secs_f64()),
+      "upstream_bytes_sent": "",
+      "upstream_bytes_received": "",
+      "upstream_response_time": "",
+      "upstream_connect_time": "",
+      "body_bytes_sent": bytes_sent.to_string(),
+      "http_referrer": get_header(req, "referer"),
+      "http_accept_language": get_header(req, "accept-language"),
+      "http_user_agent": get_header(req, "user-agent"),
+    });
+    write_metric(&state.native.metrics, &state.dir, "http.log", &metric);
+  }
+}
+
+/// Find the rule and the location matching a request on a listener,
+/// rules with the requested domain take precedence over the others
+fn find_location<'a>(
+  rules: &'a [Arc<NativeRule>],
+  listen: &str,
+  https: bool,
+  host: Option<&str>,
+  path: &str,
+) -> Option<(&'a NativeHttpRule, &'a NativeLocation, usize)> {
+  let candidates = rules
+    .iter()
+    .flat_map(|rule| rule.http.iter())
+    .filter(|rule| match https {
+      true => rule.listen_https.as_deref() == Some(listen),
+      false => rule.listen == listen,
+    })
+    .collect::<Vec<_>>();
+  let rule = candidates
+    .iter()
+    .find(|rule| rule.domain.is_some() && rule.domain.as_deref() == host)
+    .or_else(|| candidates.iter().find(|rule| rule.domain.is_none()))?;
+  let (location, len) = rule
+    .locations
+    .iter()
+    .filter_map(|location| location.matches(path).map(|len| (location, len)))
+    .max_by_key(|(location, len)| (location.exact, *len))?;
+  Some((rule, location, len))
+}
+
+/// Join the path of a target with the rest of the request path
+fn join_path(base: &str, rest: &str) -> String {
+  match (base.ends_with('/'), rest.starts_with('/')) {
+    (true, true) => format!("{base}{}", &rest[1..]),
+    (false, false) if !rest.is_empty() => format!("{base}/{rest}"),
+    _ => format!("{base}{rest}"),
+  }
+}
+
+/// Key used by the hash balancing to pick a server
+fn balancing_key(
+  balancing: &NativeBalancing,
+  req: &web::HttpRequest,
+) -> Option<String> {
+  match balancing {
+    NativeBalancing::RoundRobin => None,
+    NativeBalancing::IpHash => {
+      req.peer_addr().map(|addr| addr.ip().to_string())
+    }
+    NativeBalancing::Header(name) => {
+      Some(get_header(req, name).to_owned()).filter(|value| !value.is_empty())
+    }
+    NativeBalancing::Cookie(name) => get_cookie(req, name),
+  }
+}
+
+/// Value of a cookie sent by the client
+fn get_cookie(req: &web::HttpRequest, name: &str) -> Option<String> {
+  req
+    .headers()
+    .get_all(header::COOKIE)
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(';'))
+    .filter_map(|cookie| cookie.trim().split_once('='))
+    .find(|(cookie, value)| *cookie == name && !value.is_empty())
+    .map(|(_, value)| value.to_owned())
+}
+
+/// Random value of the sticky cookie of a new client
+fn gen_cookie_value() -> String {
+  let mut bytes = [0; 16];
+  if let Err(err) = openssl::rand::rand_bytes(&mut bytes) {
+    log::warn!("native::gen_cookie_value: {err}");
+  }
+  bytes.iter().fold(String::new(), |mut value, b| {
+    let _ = write!(value, "{b:02x}");
+    value
+  })
+}
+
+/// Serve the HTTP-01 challenges written by the acme client
+async fn acme_challenge(path: &str, dir: &str) -> Option<web::HttpResponse> {
+  let token = path.strip_prefix("/.well-known/acme-challenge/")?;
+  if token.is_empty()
+    || !token
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+  {
+    return None;
+  }
+  let path = format!("{}/{token}", super::acme::http_challenge_dir(dir));
+  let key_authorization = tokio::fs::read(path).await.ok()?;
+  Some(
+    web::HttpResponse::Ok()
+      .content_type("text/plain")
+      .body(key_authorization),
+  )
+}
+
+/// Count the bytes of a streamed body and fail once they exceed the limit,
+/// the flag is set so the request can be answered with a 413
+fn limit_body<S>(
+  payload: S,
+  max_body_size: Option<u64>,
+  too_large: Rc<Cell<bool>>,
+) -> impl Stream<Item = Result<Bytes, PayloadError>> + Unpin + 'static
+where
+  S: Stream<Item = Result<Bytes, PayloadError>> + Unpin + 'static,
+{
+  let mut received = 0;
+  payload.map(move |chunk| {
+    let chunk = chunk?;
+    received += chunk.len() as u64;
+    if max_body_size.is_some_and(|max| received > max) {
+      too_large.set(true);
+      return Err(PayloadError::Overflow);
+    }
+    Ok(chunk)
+  })
+}
+
+fn is_hop_by_hop(name: &str) -> bool {
+  HOP_BY_HOP_HEADERS.contains(&name)
+}
+
+async fn proxy(
+  req: web::HttpRequest,
+  mut payload: web::types::Payload,
+  state: web::types::State<NativeHttpState>,
+) -> web::HttpResponse {
+  let started = Instant::now();
+  let mut log = HttpLog {
+    req: &req,
+    started,
+    upstream: None,
+  };
+  let path = req.path();
+  if !state.https {
+    if let Some(res) = acme_challenge(path, &state.dir).await {
+      return res;
+    }
+  }
+  let host = get_host(&req);
+  let rules = state.native.rules();
+  let Some((rule, location, len)) =
+    find_location(&rules, &state.listen, state.https, host.as_deref(), path)
+  else {
+    log.write(&state, StatusCode::NOT_FOUND, 0);
+    return web::HttpResponse::NotFound().finish();
+  };
+  let write_log = |log: &HttpLog, status: StatusCode, bytes_sent: u64| {
+    if location.logging {
+      log.write(&state, status, bytes_sent);
+    }
+  };
+  if !state.https && rule.redirect_https {
+    let url = format!("https://{}{}", host.unwrap_or_default(), req.uri());
+    write_log(&log, StatusCode::MOVED_PERMANENTLY, 0);
+    return web::HttpResponse::MovedPermanently()
+      .header(header::LOCATION, url)
+      .finish();
+  }
+  let remote_ip = req.peer_addr().map(|addr| addr.ip());
+  if let Some(allowed_ips) = &location.allowed_ips {
+    let allowed = remote_ip.is_some_and(|ip| {
+      allowed_ips
+        .iter()
+        .any(|allowed_ip| allowed_ip.contains(&ip))
+    });
+    if !allowed {
+      write_log(&log, StatusCode::FORBIDDEN, 0);
+      return web::HttpResponse::Forbidden().finish();
+    }
+  }
+  let content_length = get_header(&req, "content-length").parse::<u64>().ok();
+  if let (Some(max), Some(length)) = (location.max_body_size, content_length) {
+    if length > max {
+      write_log(&log, StatusCode::PAYLOAD_TOO_LARGE, 0);
+      return web::HttpResponse::PayloadTooLarge().finish();
+    }
+  }
+  let rest = &path[len..];
+  let mut set_cookie = None;
+  let url = match &location.target {
+    NativeTarget::Redirect { url, status } => {
+      let status =
+        StatusCode::from_u16(*status).unwrap_or(StatusCode::TEMPORARY_REDIRECT);
+      write_log(&log, status, 0);
+      return web::HttpResponse::build(status)
+        .header(header::LOCATION, url.as_str())
+        .finish();
+    }
+    NativeTarget::Url(url) => join_path(url, rest),
+    NativeTarget::Upstream { upstream, path } => {
+      let mut key = balancing_key(&upstream.balancing, &req);
+      if let (NativeBalancing::Cookie(name), None) = (&upstream.balancing, &key)
+      {
+        let value = gen_cookie_value();
+        set_cookie = Some(format!("{name}={value}; Path=/; HttpOnly"));
+        key = Some(value);
+      }
+      let Some(addr) = upstream.pick(key.as_deref()) else {
+        write_log(&log, StatusCode::BAD_GATEWAY, 0);
+        return web::HttpResponse::BadGateway().finish();
+      };
+      log.upstream = Some(addr);
+      format!("http://{addr}{}", join_path(path, rest))
+    }
+  };
+  let url = match req.uri().query() {
+    Some(query) => format!("{url}?{query}"),
+    None => url,
+  };
+  let scheme = if state.https { "https" } else { "http" };
+  let remote_addr = remote_ip.map(|ip| ip.to_string()).unwrap_or_default();
+  let forwarded_for = match get_header(&req, "x-forwarded-for") {
+    "" => remote_addr.clone(),
+    forwarded_for => format!("{forwarded_for}, {remote_addr}"),
+  };
+  let mut upstream_req = state.client.request_from(url.as_str(), req.head());
+  for name in HOP_BY_HOP_HEADERS {
+    upstream_req.headers_mut().remove(name);
+  }
+  upstream_req = upstream_req
+    .set_header("x-forwarded-scheme", scheme)
+    .set_header("x-forwarded-proto", scheme)
+    .set_header("x-forwarded-for", forwarded_for)
+    .set_header("x-real-ip", remote_addr.as_str());
+  for (name, value) in &location.headers {
+    let value = match value.as_str() {
+      "$host" => host.clone().unwrap_or_default(),
+      "$scheme" => scheme.to_owned(),
+      "$remote_addr" => remote_addr.clone(),
+      value => match value.strip_prefix("$http_") {
+        Some(name) => get_header(&req, &name.replace('_', "-")).to_owned(),
+        None => value.to_owned(),
+      },
+    };
+    upstream_req = upstream_req.set_header(name.as_str(), value);
+  }
+  let body_too_large = Rc::new(Cell::new(false));
+  let res = match content_length {
+    None if !req.headers().contains_key(header::TRANSFER_ENCODING) => {
+      upstream_req.send().await
+    }
+    Some(length) if length <= MAX_BUFFERED_BODY => {
+      let mut body = BytesMut::with_capacity(length as usize);
+      while let Some(chunk) = payload.next().await {
+        match chunk {
+          Ok(chunk) => body.extend_from_slice(&chunk),
+          Err(err) => {
+            log::debug!("native::proxy: {err}");
+            write_log(&log, StatusCode::BAD_REQUEST, 0);
+            return web::HttpResponse::BadRequest().finish();
+          }
+        }
+      }
+      upstream_req.send_body(Bytes::from(body)).await
+    }
+    _ => {
+      upstream_req.headers_mut().remove(header::CONTENT_LENGTH);
+      let payload =
+        limit_body(payload, location.max_body_size, body_too_large.clone());
+      upstream_req.send_stream(payload).await
+    }
+  };
+  // The upstream may answer before reading the whole body
+  if body_too_large.get() {
+    write_log(&log, StatusCode::PAYLOAD_TOO_LARGE, 0);
+    return web::HttpResponse::PayloadTooLarge().finish();
+  }
+  let res = match res {
+    Ok(res) => res,
+    Err(err) => {
+      log::warn!("native::proxy: {url} {err}");
+      let status = match err {
+        SendRequestError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        _ => StatusCode::BAD_GATEWAY,
+      };
+      write_log(&log, status, 0);
+      return web::HttpResponse::build(status).finish();
+    }
+  };
+  let mut builder = web::HttpResponse::build(res.status());
+  for (name, value) in res.headers() {
+    if !is_hop_by_hop(name.as_str()) {
+      builder.header(name.clone(), value.clone());
+    }
+  }
+  if let Some(set_cookie) = set_cookie {
+    builder.header(header::SET_COOKIE, set_cookie);
+  }
+  let bytes_sent = res
+    .headers()
+    .get(header::CONTENT_LENGTH)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.parse::<u64>().ok())
+    .unwrap_or_default();
+  write_log(&log, res.status(), bytes_sent);
+  builder.streaming(res)
+}
+
+fn gen_client() -> Client {
+  let connector = match SslConnector::builder(SslMethod::tls()) {
+    Ok(builder) => Connector::default().openssl(builder.build()).finish(),
+    Err(err) => {
+      log::warn!("native::gen_client: {err}");
+      Connector::default().finish()
+    }
+  };
+  Client::build()
+    .connector(connector)
+    .timeout(Millis::from_secs(UPSTREAM_TIMEOUT))
+    .finish()
+}
+
+/// Tls acceptor selecting the certificate of the requested domain
+fn gen_acceptor(
+  native: &NativeState,
+  listen: &str,
+) -> IoResult<SslAcceptorBuilder> {
+  let mut builder =
+    SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).map_err(ssl_err)?;
+  let native = native.clone();
+  let listen = listen.to_owned();
+  builder.set_servername_callback(move |ssl, _| {
+    let domain = ssl
+      .servername(NameType::HOST_NAME)
+      .map(|domain| domain.to_lowercase());
+    let rules = native.rules();
+    let candidates = rules
+      .iter()
+      .flat_map(|rule| rule.http.iter())
+      .filter(|rule| rule.listen_https.as_deref() == Some(listen.as_str()))
+      .collect::<Vec<_>>();
+    let rule = candidates
+      .iter()
+      .find(|rule| rule.domain.is_some() && rule.domain == domain)
+      .or_else(|| candidates.iter().find(|rule| rule.domain.is_none()));
+    let Some(context) = rule.and_then(|rule| rule.ssl.as_ref()) else {
+      return Err(SniError::ALERT_FATAL);
+    };
+    ssl
+      .set_ssl_context(context)
+      .map_err(|_| SniError::ALERT_FATAL)
+  });
+  Ok(builder)
+}
+
+fn start_http(
+  listen: &str,
+  https: bool,
+  native: &NativeState,
+  dir: &str,
+) -> IoResult<ntex::server::Server> {
+  let native_ref = native.clone();
+  let listen_ref = listen.to_owned();
+  let dir = dir.to_owned();
+  let server = web::HttpServer::new(move || {
+    // The client isn't Send so each worker creates its own
+    web::App::new()
+      .state(NativeHttpState {
+        native: native_ref.clone(),
+        listen: listen_ref.clone(),
+        https,
+        dir: dir.clone(),
+        client: gen_client(),
+      })
+      .default_service(web::route().to(proxy))
+  });
+  let server = match https {
+    true => server.bind_openssl(listen, gen_acceptor(native, listen)?)?,
+    false => server.bind(listen)?,
+  };
+  Ok(server.run())
+}
+
+fn find_stream<'a>(
+  rules: &'a [Arc<NativeRule>],
+  listen: &str,
+  protocol: &ProxyStreamProtocol,
+) -> Option<&'a NativeStreamRule> {
+  rules
+    .iter()
+    .flat_map(|rule| rule.streams.iter())
+    .find(|stream| stream.listen == listen && &stream.protocol == protocol)
+}
+
+fn stream_key(upstream: &NativeUpstream, peer: &SocketAddr) -> Option<String> {
+  match upstream.balancing {
+    NativeBalancing::IpHash => Some(peer.ip().to_string()),
+    _ => None,
+  }
+}
+
+/// Data of a tcp connection or an udp session logged when it ends
+struct StreamLog {
+  protocol: &'static str,
+  peer: SocketAddr,
+  upstream: String,
+  started: Instant,
+  connect_time: Option<Duration>,
+  status: u16,
+  /// Bytes received from the client, sent to the upstream
+  received: u64,
+  /// Bytes received from the upstream, sent to the client
+  sent: u64,
+}
+
+impl StreamLog {
+  fn write(&self, metrics: &NativeMetrics, dir: &str) {
+    let connect_time = self
+      .connect_time
+      .map(|time| format!("{:.3}", time.as_secs_f64()))
+      .unwrap_or_default();
+    let metric = json!({
+      "date_gmt": date_gmt(),
+      "remote_addr": self.peer.ip().to_string(),
+      "upstream_addr": self.upstream,
+      "protocol": self.protocol,
+      "status": self.status.to_string(),
+      "session_time": format!("{:.3}", self.started.elapsed().as_secs_f64()),
+      "bytes_sent": self.sent.to_string(),
+      "bytes_received": self.received.to_string(),
+      "upstream_bytes_sent": self.received.to_string(),
+      "upstream_bytes_received": self.sent.to_string(),
+      "upstream_connect_time": connect_time,
+    });
+    write_metric(metrics, dir, "stream.log", &metric);
+  }
+}
+
+async fn proxy_tcp(
+  mut inbound: TcpStream,
+  peer: SocketAddr,
+  listen: &str,
+  native: &NativeState,
+  dir: &str,
+) {
+  let started = Instant::now();
+  let rules = native.rules();
+  let upstream = find_stream(&rules, listen, &ProxyStreamProtocol::Tcp)
+    .and_then(|rule| {
+      let key = stream_key(&rule.upstream, &peer);
+      rule
+        .upstream
+        .pick(key.as_deref())
+        .map(|addr| addr.to_owned())
+    });
+  let mut log = StreamLog {
+    protocol: "TCP",
+    peer,
+    upstream: upstream.clone().unwrap_or_default(),
+    started,
+    connect_time: None,
+    status: 502,
+    received: 0,
+    sent: 0,
+  };
+  let Some(upstream) = upstream else {
+    log.write(&native.metrics, dir);
+    return;
+  };
+  let mut outbound = match tokio::time::timeout(
+    STREAM_CONNECT_TIMEOUT,
+    TcpStream::connect(&upstream),
+  )
+  .await
+  {
+    Ok(Ok(outbound)) => outbound,
+    Ok(Err(err)) => {
+      log::debug!("native::proxy_tcp: {upstream} {err}");
+      log.write(&native.metrics, dir);
+      return;
+    }
+    Err(_) => {
+      log::debug!("native::proxy_tcp: {upstream} connect timeout");
+      log.write(&native.metrics, dir);
+      return;
+    }
+  };
+  log.connect_time = Some(started.elapsed());
+  match tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await {
+    Ok((received, sent)) => {
+      log.status = 200;
+      log.received = received;
+      log.sent = sent;
+    }
+    Err(err) => {
+      log::debug!("native::proxy_tcp: {upstream} {err}");
+      log.status = 500;
+    }
+  }
+  log.write(&native.metrics, dir);
+}
+
+async fn serve_tcp(
+  listener: TcpListener,
+  listen: String,
+  native: NativeState,
+  dir: String,
+) {
+  loop {
+    let (inbound, peer) = match listener.accept().await {
+      Ok(conn) => conn,
+      Err(err) => {
+        log::warn!("native::serve_tcp: {listen} {err}");
+        continue;
+      }
+    };
+    let listen = listen.clone();
+    let native = native.clone();
+    let dir = dir.clone();
+    rt::spawn(async move {
+      proxy_tcp(inbound, peer, &listen, &native, &dir).await;
+    });
+  }
+}
+
+/// Socket connected to the upstream of a client and its counters
+#[derive(Clone)]
+struct UdpSession {
+  socket: Rc<UdpSocket>,
+  received: Rc<Cell<u64>>,
+}
+
+async fn open_udp_session(
+  peer: SocketAddr,
+  upstream: &str,
+  socket: &Rc<UdpSocket>,
+  sessions: &Rc<RefCell<HashMap<SocketAddr, UdpSession>>>,
+  metrics: &NativeMetrics,
+  dir: &str,
+) -> IoResult<UdpSession> {
+  let started = Instant::now();
+  let bind = if upstream.starts_with('[') {
+    "[::]:0"
+  } else {
+    "0.0.0.0:0"
+  };
+  let outbound = UdpSocket::bind(bind).await?;
+  outbound.connect(upstream).await?;
+  let session = UdpSession {
+    socket: Rc::new(outbound),
+    received: Rc::new(Cell::new(0)),
+  };
+  sessions.borrow_mut().insert(peer, session.clone());
+  let mut log = StreamLog {
+    protocol: "UDP",
+    peer,
+    upstream: upstream.to_owned(),
+    started,
+    connect_time: Some(started.elapsed()),
+    status: 200,
+    received: 0,
+    sent: 0,
+  };
+  let socket = Rc::clone(socket);
+  let sessions = Rc::clone(sessions);
+  let reader = session.clone();
+  let metrics = metrics.clone();
+  let dir = dir.to_owned();
+  rt::spawn(async move {
+    let mut buf = vec![0; 65535];
+    while let Ok(Ok(len)) =
+      tokio::time::timeout(UDP_SESSION_TIMEOUT, reader.socket.recv(&mut buf))
+        .await
+    {
+      if socket.send_to(&buf[..len], peer).await.is_err() {
+        break;
+      }
+      log.sent += len as u64;
+    }
+    sessions.borrow_mut().remove(&peer);
+    log.received = reader.received.get();
+    log.write(&metrics, &dir);
+  });
+  Ok(session)
+}
+
+async fn serve_udp(
+  socket: UdpSocket,
+  listen: String,
+  native: NativeState,
+  dir: String,
+) {
+  let socket = Rc::new(socket);
+  let sessions = Rc::new(RefCell::new(HashMap::new()));
+  let mut buf = vec![0; 65535];
+  loop {
+    let (len, peer) = match socket.recv_from(&mut buf).await {
+      Ok(datagram) => datagram,
+      Err(err) => {
+        log::warn!("native::serve_udp: {listen} {err}");
+        continue;
+      }
+    };
+    let session = sessions.borrow().get(&peer).cloned();
+    let session = match session {
+      Some(session) => session,
+      None => {
+        let rules = native.rules();
+        let upstream = find_stream(&rules, &listen, &ProxyStreamProtocol::Udp)
+          .and_then(|rule| {
+            let key = stream_key(&rule.upstream, &peer);
+            rule
+              .upstream
+              .pick(key.as_deref())
+              .map(|addr| addr.to_owned())
+          });
+        let Some(upstream) = upstream else {
+          continue;
+        };
+        match open_udp_session(
+          peer,
+          &upstream,
+          &socket,
+          &sessions,
+          &native.metrics,
+          &dir,
+        )
+        .await
+        {
+          Ok(session) => session,
+          Err(err) => {
+            log::debug!("native::serve_udp: {upstream} {err}");
+            continue;
+          }
+        }
+      }
+    };
+    if session.socket.send(&buf[..len]).await.is_ok() {
+      session.received.set(session.received.get() + len as u64);
+    }
+  }
+}
+
+fn start_listener(
+  key: &str,
+  native: &NativeState,
+  dir: &str,
+) -> IoResult<NativeListener> {
+  let Some((kind, listen)) = key.split_once(':') else {
+    return Err(IoError::invalid_data("Listener", key));
+  };
+  let listener = match kind {
+    "http" => NativeListener::Http(start_http(listen, false, native, dir)?),
+    "https" => NativeListener::Http(start_http(listen, true, native, dir)?),
+    "tcp" => {
+      let listener = std::net::TcpListener::bind(listen)?;
+      listener.set_nonblocking(true)?;
+      let listener = TcpListener::from_std(listener)?;
+      NativeListener::Stream(rt::spawn(serve_tcp(
+        listener,
+        listen.to_owned(),
+        native.clone(),
+        dir.to_owned(),
+      )))
+    }
+    "udp" => {
+      let socket = std::net::UdpSocket::bind(listen)?;
+      socket.set_nonblocking(true)?;
+      let socket = UdpSocket::from_std(socket)?;
+      NativeListener::Stream(rt::spawn(serve_udp(
+        socket,
+        listen.to_owned(),
+        native.clone(),
+        dir.to_owned(),
+      )))
+    }
+    _ => return Err(IoError::invalid_data("Listener", key)),
+  };
+  Ok(listener)
+}
+
+/// Listeners needed by the rules by `{kind}:{addr}`
+fn gen_listener_keys(rules: &[Arc<NativeRule>]) -> HashSet<String> {
+  let mut keys = HashSet::new();
+  for rule in rules {
+    for http_rule in &rule.http {
+      keys.insert(format!("http:{}", http_rule.listen));
+      if let Some(listen_https) = &http_rule.listen_https {
+        keys.insert(format!("https:{listen_https}"));
+      }
+    }
+    for stream_rule in &rule.streams {
+      let kind = match stream_rule.protocol {
+        ProxyStreamProtocol::Tcp => "tcp",
+        ProxyStreamProtocol::Udp => "udp",
+      };
+      keys.insert(format!("{kind}:{}", stream_rule.listen));
+    }
+  }
+  keys
+}
+
+/// Open the listeners used by the rules and close the others,
+/// it replaces the nginx reload for the native backend
+pub async fn sync_listeners(native: &NativeState, dir: &str) -> IoResult<()> {
+  let keys = gen_listener_keys(&native.rules());
+  let (unused, missing) = {
+    let mut listeners = native
+      .listeners
+      .lock()
+      .map_err(|err| IoError::other("Native", &err.to_string()))?;
+    let unused = listeners
+      .keys()
+      .filter(|key| !keys.contains(*key))
+      .cloned()
+      .collect::<Vec<_>>();
+    let unused = unused
+      .into_iter()
+      .filter_map(|key| listeners.remove(&key).map(|listener| (key, listener)))
+      .collect::<Vec<_>>();
+    let missing = keys
+      .into_iter()
+      .filter(|key| !listeners.contains_key(key))
+      .collect::<Vec<_>>();
+    (unused, missing)
+  };
+  for (key, listener) in unused {
+    log::info!("native::sync_listeners: closing {key}");
+    match listener {
+      NativeListener::Http(server) => server.stop(true).await,
+      NativeListener::Stream(task) => task.abort(),
+    }
+  }
+  for key in missing {
+    log::info!("native::sync_listeners: opening {key}");
+    match start_listener(&key, native, dir) {
+      Err(err) => log::warn!("native::sync_listeners: {key} {err}"),
+      Ok(listener) => {
+        native
+          .listeners
+          .lock()
+          .map_err(|err| IoError::other("Native", &err.to_string()))?
+          .insert(key, listener);
+      }
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use nanocld_client::stubs::proxy::{HttpTarget, UnixTarget};
+
+  use super::*;
+
+  fn gen_native_http_rule(
+    domain: Option<&str>,
+    paths: &[&str],
+  ) -> NativeHttpRule {
+    NativeHttpRule {
+      listen: "0.0.0.0:80".to_owned(),
+      listen_https: None,
+      domain: domain.map(|domain| domain.to_owned()),
+      ssl: None,
+      redirect_https: false,
+      locations: paths
+        .iter()
+        .map(|path| NativeLocation {
+          path: path.trim_start_matches('=').to_owned(),
+          exact: path.starts_with('='),
+          target: NativeTarget::Url(format!("http://{}", path.len())),
+          allowed_ips: None,
+          headers: vec![],
+          max_body_size: None,
+          logging: true,
+        })
+        .collect(),
+    }
+  }
+
+  #[test]
+  fn location_matching() {
+    let rules = vec![Arc::new(NativeRule {
+      http: vec![
+        gen_native_http_rule(None, &["/"]),
+        gen_native_http_rule(Some("example.com"), &["/", "/api", "=/api"]),
+      ],
+      streams: vec![],
+    })];
+    let find = |host: Option<&str>, path: &str| {
+      find_location(&rules, "0.0.0.0:80", false, host, path)
+        .map(|(rule, location, len)| (rule.domain.clone(), location.exact, len))
+    };
+    assert_eq!(find(None, "/api"), Some((None, false, 1)));
+    assert_eq!(find(Some("other.com"), "/test"), Some((None, false, 1)));
+    assert_eq!(
+      find(Some("example.com"), "/api"),
+      Some((Some("example.com".to_owned()), true, 4))
+    );
+    assert_eq!(
+      find(Some("example.com"), "/api/v1"),
+      Some((Some("example.com".to_owned()), false, 4))
+    );
+    assert!(find_location(&rules, "0.0.0.0:8080", false, None, "/").is_none());
+    assert!(find_location(&rules, "0.0.0.0:80", true, None, "/").is_none());
+  }
+
+  #[test]
+  fn path_join() {
+    assert_eq!(join_path("/", "/test"), "/test");
+    assert_eq!(join_path("/", "test"), "/test");
+    assert_eq!(join_path("/api", ""), "/api");
+    assert_eq!(join_path("/api", "/v1"), "/api/v1");
+    assert_eq!(join_path("/api", "v1"), "/api/v1");
+    assert_eq!(
+      join_path("https://example.com", "/"),
+      "https://example.com/"
+    );
+  }
+
+  #[test]
+  fn parse_values() {
+    assert_eq!(parse_body_size("512").unwrap(), 512);
+    assert_eq!(parse_body_size("10k").unwrap(), 10 * 1024);
+    assert_eq!(parse_body_size("2M").unwrap(), 2 * 1024 * 1024);
+    for size in ["", "m", "10mb", "1.5m"] {
+      assert!(parse_body_size(size).is_err(), "{size}");
+    }
+    let ranges =
+      parse_allowed_ips(&["10.0.0.0/8".to_owned(), "::1".to_owned()]).unwrap();
+    assert_eq!(ranges[0].prefix, 8);
+    assert_eq!(ranges[1].prefix, 128);
+    assert_eq!(parse_allowed_ips(&["all".to_owned()]).unwrap().len(), 2);
+    for allowed_ip in ["10.0.0.0/33", "localhost", "10.0.0.1/a"] {
+      assert!(parse_allowed_ips(&[allowed_ip.to_owned()]).is_err());
+    }
+    let headers =
+      parse_headers(&["X-Custom value".to_owned(), "X-Host $host".to_owned()])
+        .unwrap();
+    assert_eq!(headers[0], ("X-Custom".to_owned(), "value".to_owned()));
+    assert_eq!(headers[1], ("X-Host".to_owned(), "$host".to_owned()));
+    assert!(parse_headers(&["X-Custom".to_owned()]).is_err());
+    assert!(parse_headers(&["X-Uri $request_uri".to_owned()]).is_err());
+    assert_eq!(listen_addr("80".to_owned()), "0.0.0.0:80");
+    assert_eq!(listen_addr("127.0.0.1:80".to_owned()), "127.0.0.1:80");
+  }
+
+  fn gen_chunks(
+    chunks: &[&'static [u8]],
+  ) -> impl Stream<Item = Result<Bytes, PayloadError>> + Unpin + 'static {
+    let chunks = chunks
+      .iter()
+      .map(|chunk| Ok(Bytes::from_static(chunk)))
+      .collect::<Vec<_>>();
+    futures::stream::iter(chunks)
+  }
+
+  #[ntex::test]
+  async fn body_limit() {
+    let too_large = Rc::new(Cell::new(false));
+    let body =
+      limit_body(gen_chunks(&[b"1234", b"5678"]), Some(8), too_large.clone())
+        .collect::<Vec<_>>()
+        .await;
+    assert!(body.iter().all(|chunk| chunk.is_ok()));
+    assert!(!too_large.get());
+    let body =
+      limit_body(gen_chunks(&[b"1234", b"5678"]), Some(6), too_large.clone())
+        .collect::<Vec<_>>()
+        .await;
+    assert!(body[0].is_ok());
+    assert!(matches!(body[1], Err(PayloadError::Overflow)));
+    assert!(too_large.get());
+  }
+
+  #[ntex::test]
+  async fn proxy_requests() {
+    let upstream = web::test::server(|| {
+      web::App::new().default_service(
+        web::route()
+          .to(|body: Bytes| async move { web::HttpResponse::Ok().body(body) }),
+      )
+    });
+    let mut rule = gen_native_http_rule(None, &["/private"]);
+    rule.locations[0].allowed_ips = Some(vec![]);
+    rule.locations.push(NativeLocation {
+      path: "/api".to_owned(),
+      exact: false,
+      target: NativeTarget::Upstream {
+        upstream: NativeUpstream::new(
+          vec![upstream.addr().to_string()],
+          NativeBalancing::RoundRobin,
+        ),
+        path: "/".to_owned(),
+      },
+      allowed_ips: None,
+      headers: vec![],
+      max_body_size: Some(8),
+      logging: false,
+    });
+    let native = NativeState::default();
+    native.rules.write().unwrap().insert(
+      "test".to_owned(),
+      Arc::new(NativeRule {
+        http: vec![rule],
+        streams: vec![],
+      }),
+    );
+    let srv = web::test::server(move || {
+      web::App::new()
+        .state(NativeHttpState {
+          native: native.clone(),
+          listen: "0.0.0.0:80".to_owned(),
+          https: false,
+          dir: std::env::temp_dir().display().to_string(),
+          client: gen_client(),
+        })
+        .default_service(web::route().to(proxy))
+    });
+    let mut res = srv.post("/api/echo").send_body("hello").await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.body().await.unwrap(), "hello");
+    let mut res = srv
+      .post("/api/echo")
+      .send_stream(gen_chunks(&[b"hel", b"lo"]))
+      .await
+      .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.body().await.unwrap(), "hello");
+    let res = srv
+      .post("/api/echo")
+      .send_body("hello world")
+      .await
+      .unwrap();
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let res = srv
+      .post("/api/echo")
+      .send_stream(gen_chunks(&[b"hello", b" world"]))
+      .await
+      .unwrap();
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let res = srv.get("/private").send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let res = srv.get("/unknown").send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+  }
+
+  #[test]
+  fn unsupported_features() {
+    let mut rule = crate::utils::tests::read_rule("tests/native.yml").unwrap();
+    assert!(validate(&rule).is_ok());
+    let ProxyRule::Http(http_rule) = &mut rule.rules[0] else {
+      panic!("Expect the first rule to be an http rule");
+    };
+    http_rule.locations[0].target = LocationTarget::Unix(UnixTarget {
+      unix_path: "/run/test.sock".to_owned(),
+    });
+    assert!(validate(&rule).is_err());
+    let ProxyRule::Http(http_rule) = &mut rule.rules[0] else {
+      panic!("Expect the first rule to be an http rule");
+    };
+    http_rule.locations[0].target = LocationTarget::Http(HttpTarget {
+      url: "http://example.com".to_owned(),
+      redirect: None,
+    });
+    http_rule.locations[0].protocol = Some(ProxyHttpProtocol::Grpc);
+    assert!(validate(&rule).is_err());
+    let gen_keys = |rule: NativeRule| gen_listener_keys(&[Arc::new(rule)]);
+    let keys = gen_keys(NativeRule {
+      http: vec![gen_native_http_rule(None, &["/"])],
+      streams: vec![NativeStreamRule {
+        listen: "127.0.0.1:9000".to_owned(),
+        protocol: ProxyStreamProtocol::Udp,
+        upstream: NativeUpstream::new(vec![], NativeBalancing::RoundRobin),
+      }],
+    });
+    assert!(keys.contains("http:0.0.0.0:80"));
+    assert!(keys.contains("udp:127.0.0.1:9000"));
+    assert_eq!(keys.len(), 2);
+  }
+}
@@ -6,17 +6,21 @@ use futures::StreamExt;
 use nanocl_error::io::{IoError, IoResult};
 
 use nanocld_client::{
-  stubs::proxy::{ResourceProxyRule, ProxyRule, LocationTarget},
+  stubs::proxy::{
+    ResourceProxyRule, ProxyRule, LocationTarget, ProxySsl, AcmeChallenge,
+    ProxyRuleValidation,
+  },
   bollard_next::exec::{CreateExecOptions, StartExecOptions},
   NanocldClient,
 };
 
 use crate::models::{
-  SystemStateRef, NginxRuleKind, LocationTemplate, STREAM_TEMPLATE,
-  HTTP_TEMPLATE, CONF_TEMPLATE,
+  Store, SystemState, SystemStateRef, NginxRuleKind, LocationTemplate,
+  STREAM_TEMPLATE, HTTP_TEMPLATE, CONF_TEMPLATE,
 };
 
-pub async fn ensure_conf(state: &SystemStateRef) -> IoResult<()> {
+/// Create the state directories and write the main nginx configuration
+async fn write_base_conf(state: &SystemStateRef) -> IoResult<()> {
   let state_ref = Arc::clone(state);
   let conf_path = format!("{}/nginx.conf", state_ref.store.dir);
   let default_conf = CONF_TEMPLATE.compile(&liquid::object!({
@@ -31,6 +35,7 @@ pub async fn ensure_conf(state: &SystemStateRef) -> IoResult<()> {
       "streams-enabled",
       "log",
       "secrets",
+      "acme/http-01",
     ]
     .into_iter()
     .map(|name| {
@@ -45,6 +50,11 @@ pub async fn ensure_conf(state: &SystemStateRef) -> IoResult<()> {
     "NginxManager: writing default conf to {conf_path}:\n{default_conf}"
   );
   std::fs::write(conf_path, default_conf)?;
+  Ok(())
+}
+
+pub async fn ensure_conf(state: &SystemStateRef) -> IoResult<()> {
+  write_base_conf(state).await?;
   self::test(&state.client).await?;
   Ok(())
 }
@@ -95,14 +105,37 @@ pub async fn reload(client: &NanocldClient) -> IoResult<()> {
   Ok(())
 }
 
-pub async fn add_rule(
+/// Generate a location answering 503 to every request
+fn gen_unavailable_location(path: &str) -> LocationTemplate {
+  LocationTemplate {
+    path: path.to_owned(),
+    upstream_key: String::new(),
+    upstream_path: String::new(),
+    redirect: Some("503".to_owned()),
+    allowed_ips: None,
+    version: None,
+    headers: None,
+    rate_limit: None,
+    connection_limit: None,
+    max_body_size: None,
+    basic_auth: None,
+    forward_auth: None,
+    protocol: "http".to_owned(),
+    split_cookie: None,
+  }
+}
+
+/// Write the configuration files of a rule in the store of the state
+/// and return the parts of the rule that were skipped
+async fn write_rule(
   name: &str,
   rule: &ResourceProxyRule,
   state: &SystemStateRef,
-) -> IoResult<()> {
+) -> IoResult<Vec<String>> {
+  let mut warnings = vec![];
   let mut stream_conf = String::new();
   let mut http_conf = String::new();
-  for rule in &rule.rules {
+  for (rule_index, rule) in rule.rules.iter().enumerate() {
     match rule {
       ProxyRule::Stream(stream_rule) => {
         let listen = super::rule::get_network_addr(
@@ -118,23 +151,31 @@ pub async fn add_rule(
         .await
         {
           Err(err) => {
-            log::warn!("{err} {:#?}", stream_rule.target);
+            let port = stream_rule.port;
+            super::rule::skip(
+              &mut warnings,
+              format!("Stream {port} of {name}: {err}"),
+            );
             continue;
           }
           Ok(upstream_key) => upstream_key,
         };
         let ssl = match &stream_rule.ssl {
-          Some(ssl) => match super::rule::gen_ssl_config(ssl, state).await {
-            Err(err) => {
-              log::warn!("Not ssl found for {name} {ssl:#?} {err}");
-              None
+          Some(ssl) => {
+            match super::rule::gen_ssl_config(ssl, None, state).await {
+              Err(err) => {
+                super::rule::skip(
+                  &mut warnings,
+                  format!("Not ssl found for {name}: {err}"),
+                );
+                None
+              }
+              Ok(ssl) => Some(ssl),
             }
-            Ok(ssl) => Some(ssl),
-          },
+          }
           None => None,
         };
         if stream_rule.ssl.is_some() && ssl.is_none() {
-          log::warn!("Not ssl found for {name} {ssl:#?}");
           continue;
         }
         let data = STREAM_TEMPLATE.compile(&liquid::object!({
@@ -146,23 +187,73 @@ pub async fn add_rule(
       }
       ProxyRule::Http(http_rule) => {
         let mut locations = vec![];
+        let mut zones = vec![];
+        let mut split_cookies = vec![];
+        let mut split_upstreams = String::new();
         let listen =
           super::rule::get_network_addr(&http_rule.network, 80, &state.client)
             .await?;
         let listen_https =
           super::rule::get_network_addr(&http_rule.network, 443, &state.client)
             .await?;
+        let domain = http_rule.domain.as_deref();
         let ssl = match &http_rule.ssl {
-          Some(ssl) => match super::rule::gen_ssl_config(ssl, state).await {
-            Err(err) => {
-              log::warn!("Not ssl found for {name} {ssl:#?} {err}");
-              None
+          Some(ssl) => {
+            match super::rule::gen_ssl_config(ssl, domain, state).await {
+              Err(err) => {
+                super::rule::skip(
+                  &mut warnings,
+                  format!("Not ssl found for {name}: {err}"),
+                );
+                None
+              }
+              Ok(ssl) => Some(ssl),
             }
-            Ok(ssl) => Some(ssl),
-          },
+          }
           None => None,
         };
-        for location in &http_rule.locations {
+        let acme_challenge = match &http_rule.ssl {
+          Some(ProxySsl::Acme { acme })
+            if acme.challenge.clone().unwrap_or_default()
+              == AcmeChallenge::Http01 =>
+          {
+            Some(super::acme::http_challenge_dir(&state.store.dir))
+          }
+          _ => None,
+        };
+        for (index, location) in http_rule.locations.iter().enumerate() {
+          let zone = format!("{name}_{rule_index}_{index}");
+          let limits = super::rule::gen_location_limits(&zone, location)?;
+          zones.extend(limits.zones);
+          let protocol = match super::rule::gen_location_protocol(location) {
+            Err(err) => {
+              let path = &location.path;
+              super::rule::skip(
+                &mut warnings,
+                format!("Location {path} of {name}: {err}"),
+              );
+              continue;
+            }
+            Ok(protocol) => protocol,
+          };
+          let auth = match super::rule::gen_location_auth(
+            &zone, location, state,
+          )
+          .await
+          {
+            Err(err) => {
+              let path = &location.path;
+              super::rule::skip(
+                &mut warnings,
+                format!("Location {path} of {name}: {err}"),
+              );
+              // Skipping the location would let a parent location serve it
+              // without authentication
+              locations.push(gen_unavailable_location(&location.path));
+              continue;
+            }
+            Ok(auth) => auth,
+          };
           match &location.target {
             LocationTarget::Upstream(upstream) => {
               let upstream_key = match super::rule::gen_upstream(
@@ -173,19 +264,30 @@ pub async fn add_rule(
               .await
               {
                 Err(err) => {
-                  log::warn!("{err} {:#?}", upstream);
+                  let path = &location.path;
+                  super::rule::skip(
+                    &mut warnings,
+                    format!("Location {path} of {name}: {err}"),
+                  );
                   continue;
                 }
                 Ok(upstream_key) => upstream_key,
               };
               let location = LocationTemplate {
                 path: location.path.clone(),
-                upstream_key: format!("http://{upstream_key}"),
+                upstream_key: format!("{}://{upstream_key}", protocol.scheme),
                 redirect: None,
                 upstream_path: upstream.path.clone().unwrap_or("/".to_owned()),
                 version: location.version,
                 allowed_ips: location.allowed_ips.clone(),
                 headers: location.headers.clone(),
+                rate_limit: limits.rate_limit,
+                connection_limit: limits.connection_limit,
+                max_body_size: limits.max_body_size,
+                basic_auth: auth.basic,
+                forward_auth: auth.forward,
+                protocol: protocol.name.to_owned(),
+                split_cookie: None,
               };
               locations.push(location);
             }
@@ -198,12 +300,19 @@ pub async fn add_rule(
               .await?;
               let location = LocationTemplate {
                 path: location.path.clone(),
-                upstream_key: format!("http://{upstream_key}"),
+                upstream_key: format!("{}://{upstream_key}", protocol.scheme),
                 redirect: None,
                 upstream_path: "/".to_owned(),
                 version: location.version,
                 allowed_ips: location.allowed_ips.clone(),
                 headers: location.headers.clone(),
+                rate_limit: limits.rate_limit,
+                connection_limit: limits.connection_limit,
+                max_body_size: limits.max_body_size,
+                basic_auth: auth.basic,
+                forward_auth: auth.forward,
+                protocol: protocol.name.to_owned(),
+                split_cookie: None,
               };
               locations.push(location);
             }
@@ -216,7 +325,56 @@ pub async fn add_rule(
                 allowed_ips: location.allowed_ips.clone(),
                 headers: location.headers.clone(),
                 redirect: http.redirect.clone().map(|r| format!("{r}")),
+                rate_limit: limits.rate_limit,
+                connection_limit: limits.connection_limit,
+                max_body_size: limits.max_body_size,
+                basic_auth: auth.basic,
+                forward_auth: auth.forward,
+                protocol: protocol.name.to_owned(),
+                split_cookie: None,
+              };
+              locations.push(location);
+            }
+            LocationTarget::Split(split) => {
+              let split_location = match super::rule::gen_location_split(
+                &zone, split, state,
+              )
+              .await
+              {
+                Err(err) => {
+                  let path = &location.path;
+                  super::rule::skip(
+                    &mut warnings,
+                    format!("Location {path} of {name}: {err}"),
+                  );
+                  continue;
+                }
+                Ok(split_location) => split_location,
+              };
+              let location = LocationTemplate {
+                path: location.path.clone(),
+                upstream_key: format!(
+                  "{}://{}",
+                  protocol.scheme, split_location.upstream_key
+                ),
+                redirect: None,
+                upstream_path: split.path.clone().unwrap_or("/".to_owned()),
+                version: location.version,
+                allowed_ips: location.allowed_ips.clone(),
+                headers: location.headers.clone(),
+                rate_limit: limits.rate_limit,
+                connection_limit: limits.connection_limit,
+                max_body_size: limits.max_body_size,
+                basic_auth: auth.basic,
+                forward_auth: auth.forward,
+                protocol: protocol.name.to_owned(),
+                split_cookie: split_location
+                  .cookie
+                  .as_ref()
+                  .map(|cookie| cookie.set_cookie.clone()),
               };
+              split_cookies.extend(split_location.cookie);
+              split_upstreams += &split_location.upstream;
               locations.push(location);
             }
           }
@@ -226,9 +384,13 @@ pub async fn add_rule(
           "listen_https": listen_https,
           "domain": http_rule.domain,
           "locations": locations,
+          "zones": zones,
+          "split_cookies": split_cookies,
           "ssl": ssl,
+          "acme_challenge": acme_challenge,
           "hide_upstream": http_rule.ssl.is_some() && ssl.is_none(),
         }))?;
+        http_conf += &split_upstreams;
         http_conf += &data;
       }
     }
@@ -245,6 +407,15 @@ pub async fn add_rule(
       .write_conf_file(name, &http_conf, &NginxRuleKind::Site)
       .await?;
   }
+  Ok(warnings)
+}
+
+pub async fn add_rule(
+  name: &str,
+  rule: &ResourceProxyRule,
+  state: &SystemStateRef,
+) -> IoResult<()> {
+  write_rule(name, rule, state).await?;
   if let Err(err) = self::test(&state.client).await {
     let _ = del_rule(name, state).await;
     return Err(err);
@@ -252,6 +423,64 @@ pub async fn add_rule(
   Ok(())
 }
 
+/// Read the configuration files written in a sandbox directory
+async fn read_sandbox_conf(dir: &str) -> IoResult<String> {
+  let mut config = String::new();
+  for kind in ["sites-available", "streams-available"] {
+    let mut paths = vec![];
+    let mut entries = tokio::fs::read_dir(format!("{dir}/{kind}")).await?;
+    while let Some(entry) = entries.next_entry().await? {
+      paths.push(entry.path());
+    }
+    paths.sort();
+    for path in paths {
+      let data = tokio::fs::read_to_string(&path).await?;
+      let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+      config += &format!("# {kind}/{file_name}\n{data}\n");
+    }
+  }
+  Ok(config)
+}
+
+/// Render a rule in a sandbox directory and test it with nginx
+/// without touching the live configuration
+pub async fn validate_rule(
+  name: &str,
+  rule: &ResourceProxyRule,
+  state: &SystemStateRef,
+) -> IoResult<ProxyRuleValidation> {
+  let id = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_nanos();
+  let dir = format!("{}/sandbox/{name}-{id}", state.store.dir);
+  let sandbox = Arc::new(SystemState {
+    store: Store::new_sandbox(&dir),
+    ..state.as_ref().clone()
+  });
+  let validation = async {
+    let mut validation = ProxyRuleValidation::default();
+    write_base_conf(&sandbox).await?;
+    match write_rule(name, rule, &sandbox).await {
+      Err(err) => validation.errors.push(err.to_string()),
+      Ok(warnings) => {
+        validation.errors.extend(warnings);
+        let cmd = format!("nginx -t -c {dir}/nginx.conf");
+        if let Err(err) = exec_nginx_cmd(&cmd, &state.client).await {
+          validation.errors.push(err.to_string());
+        }
+      }
+    }
+    validation.config = read_sandbox_conf(&dir).await?;
+    Ok::<_, IoError>(validation)
+  }
+  .await;
+  if let Err(err) = tokio::fs::remove_dir_all(&dir).await {
+    log::warn!("nginx::validate_rule: {dir} {err}");
+  }
+  validation
+}
+
 pub async fn del_rule(name: &str, state: &SystemStateRef) {
   let _ = state
     .store
@@ -262,3 +491,25 @@ pub async fn del_rule(name: &str, state: &SystemStateRef) {
     .delete_conf_file(name, &NginxRuleKind::Stream)
     .await;
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn unavailable_location() {
+    let data = HTTP_TEMPLATE
+      .compile(&liquid::object!({
+        "listen": "0.0.0.0:80",
+        "locations": vec![gen_unavailable_location("/admin")],
+        "zones": Vec::<String>::new(),
+        "split_cookies": Vec::<String>::new(),
+        "hide_upstream": false,
+      }))
+      .unwrap();
+    let location = data.split("location /admin {").nth(1).unwrap();
+    let location = location.split('}').next().unwrap();
+    assert!(location.contains("return 503"), "{location}");
+    assert!(!location.contains("proxy_pass"), "{location}");
+  }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use futures::{stream::FuturesUnordered, StreamExt};
 use nanocl_error::io::{IoError, IoResult, FromIo};
 
@@ -12,11 +14,12 @@ use nanocld_client::{
 
 use crate::{vars, models::SystemStateRef};
 
+/// List the rules using a secret for their ssl or their basic auth
 pub async fn list_by_secret(
   name: &str,
   client: &NanocldClient,
 ) -> IoResult<Vec<Resource>> {
-  let filter = GenericFilter::new()
+  let ssl_filter = GenericFilter::new()
     .r#where("kind", GenericClause::Eq(vars::RULE_KEY.to_owned()))
     .r#where(
       "data",
@@ -24,9 +27,35 @@ pub async fn list_by_secret(
         serde_json::json!({ "Rules": [ { "Ssl": name }  ] }),
       ),
     );
-  let resources = client.list_resource(Some(&filter)).await.map_err(|err| {
-    err.map_err_context(|| "Unable to list resources from nanocl daemon")
-  })?;
+  let ssl_resources =
+    client
+      .list_resource(Some(&ssl_filter))
+      .await
+      .map_err(|err| {
+        err.map_err_context(|| "Unable to list resources from nanocl daemon")
+      })?;
+  let auth_filter = GenericFilter::new()
+    .r#where("kind", GenericClause::Eq(vars::RULE_KEY.to_owned()))
+    .r#where(
+      "data",
+      GenericClause::Contains(serde_json::json!({
+        "Rules": [ { "Locations": [ { "Auth": { "Basic": { "Secret": name } } } ] } ]
+      })),
+    );
+  let auth_resources =
+    client
+      .list_resource(Some(&auth_filter))
+      .await
+      .map_err(|err| {
+        err.map_err_context(|| "Unable to list resources from nanocl daemon")
+      })?;
+  // A rule using the secret for both its ssl and its auth is listed twice
+  let mut keys = HashSet::new();
+  let resources = ssl_resources
+    .into_iter()
+    .chain(auth_resources.into_iter())
+    .filter(|resource| keys.insert(resource.spec.resource_key.clone()))
+    .collect::<Vec<Resource>>();
   if resources.is_empty() {
     return Err(IoError::not_found(
       "Resource",
@@ -57,6 +86,18 @@ pub(crate) async fn list_by_cargo(
     })?;
   let filter = GenericFilter::new()
   .r#where("kind", GenericClause::Eq(vars::RULE_KEY.to_owned()))
+  .r#where(
+    "data",
+    GenericClause::Contains(
+      serde_json::json!({ "Rules": [ { "Locations": [ { "Target": { "Split": [ { "Key": target_key } ] } } ] }  ] }),
+    ),
+  );
+  let split_resources =
+    client.list_resource(Some(&filter)).await.map_err(|err| {
+      err.map_err_context(|| "Unable to list resources from nanocl daemon")
+    })?;
+  let filter = GenericFilter::new()
+  .r#where("kind", GenericClause::Eq(vars::RULE_KEY.to_owned()))
   .r#where(
     "data",
     GenericClause::Contains(
@@ -69,6 +110,7 @@ pub(crate) async fn list_by_cargo(
     })?;
   let resources = http_resources
     .into_iter()
+    .chain(split_resources.into_iter())
     .chain(stream_resources.into_iter())
     .collect::<Vec<nanocld_client::stubs::resource::Resource>>();
   if resources.is_empty() {
@@ -80,6 +122,27 @@ pub(crate) async fn list_by_cargo(
   Ok(resources)
 }
 
+/// List the rules using `ProxySsl::Acme`, optionally for a single domain
+pub(crate) async fn list_by_acme(
+  domain: Option<&str>,
+  client: &NanocldClient,
+) -> IoResult<Vec<Resource>> {
+  let mut rule = serde_json::json!({ "Ssl": { "Acme": {} } });
+  if let Some(domain) = domain {
+    rule["Domain"] = serde_json::json!(domain);
+  }
+  let filter = GenericFilter::new()
+    .r#where("kind", GenericClause::Eq(vars::RULE_KEY.to_owned()))
+    .r#where(
+      "data",
+      GenericClause::Contains(serde_json::json!({ "Rules": [rule] })),
+    );
+  let resources = client.list_resource(Some(&filter)).await.map_err(|err| {
+    err.map_err_context(|| "Unable to list resources from nanocl daemon")
+  })?;
+  Ok(resources)
+}
+
 pub(crate) fn serialize(
   data: &serde_json::Value,
 ) -> IoResult<ResourceProxyRule> {
@@ -99,7 +162,7 @@ pub(crate) async fn update_rules(
     .map(|resource| async move {
       let resource: ResourcePartial = resource.clone().into();
       let rule = serialize(&resource.data)?;
-      super::nginx::add_rule(&resource.name, &rule, state).await?;
+      super::backend::add_rule(&resource.name, &rule, state).await?;
       Ok::<_, IoError>(())
     })
     .collect::<FuturesUnordered<_>>()
@@ -1,19 +1,30 @@
+use std::fmt::Write;
+
 use nanocl_error::io::{IoResult, IoError, FromIo};
 
 use nanocld_client::{
   NanocldClient,
   stubs::{
     process::Process,
+    secret::SecretInspectQuery,
     proxy::{
       UpstreamTarget, StreamTarget, UnixTarget, ProxySsl, ProxySslConfig,
+      UpstreamBalancing, ProxyHttpLocation, ResourceProxyRule, ProxyRule,
+      LocationTarget, ProxyAuth, ProxyHttpProtocol, SplitTarget, SplitSticky,
+      AcmeChallenge,
     },
   },
 };
 
 use crate::models::{
-  SystemStateRef, NginxRuleKind, UPSTREAM_TEMPLATE, UNIX_UPSTREAM_TEMPLATE,
+  SystemStateRef, NginxRuleKind, BasicAuthTemplate, ForwardAuthTemplate,
+  ForwardAuthHeaderTemplate, SplitCookieTemplate, UPSTREAM_TEMPLATE,
+  UNIX_UPSTREAM_TEMPLATE, SPLIT_UPSTREAM_TEMPLATE,
 };
 
+/// Kind of the secrets holding the users of a basic auth
+const KIND_HTPASSWD: &str = "nanocl.io/htpasswd";
+
 /// Get public address of host
 async fn get_host_addr(client: &NanocldClient) -> IoResult<String> {
   let info = client
@@ -42,7 +53,9 @@ async fn get_namespace_addr(
   Ok(ip_address)
 }
 
-fn parse_upstream_target(key: &str) -> IoResult<(String, String, String)> {
+pub(crate) fn parse_upstream_target(
+  key: &str,
+) -> IoResult<(String, String, String)> {
   let info = key.split('.').collect::<Vec<&str>>();
   if info.len() < 3 {
     return Err(IoError::invalid_data(
@@ -56,6 +69,50 @@ fn parse_upstream_target(key: &str) -> IoResult<(String, String, String)> {
   Ok((name, namespace, kind))
 }
 
+/// Log a part of a rule skipped because it can't be served yet,
+/// the warnings are returned by the validation of the rule
+pub(crate) fn skip(warnings: &mut Vec<String>, warning: String) {
+  log::warn!("{warning}");
+  warnings.push(warning);
+}
+
+/// Get the processes of a cargo or a vm and the namespace of their network
+pub(crate) async fn get_target_processes(
+  key: &str,
+  state: &SystemStateRef,
+) -> IoResult<(Vec<Process>, String)> {
+  let (name, namespace, kind) = parse_upstream_target(key)?;
+  let processes = match kind.as_str() {
+    "c" => {
+      let cargo = state
+        .client
+        .inspect_cargo(&name, Some(&namespace))
+        .await
+        .map_err(|err| {
+          err.map_err_context(|| format!("Unable to inspect cargo {name}"))
+        })?;
+      cargo.instances
+    }
+    "v" => {
+      let vm = state
+        .client
+        .inspect_vm(&name, Some(&namespace))
+        .await
+        .map_err(|err| {
+          err.map_err_context(|| format!("Unable to inspect vm {name}"))
+        })?;
+      vm.instances
+    }
+    _ => {
+      return Err(IoError::invalid_data(
+        "UpstreamTarget",
+        &format!("Unknown Kind {kind}"),
+      ))
+    }
+  };
+  Ok((processes, namespace))
+}
+
 pub async fn get_addresses(
   processes: &[Process],
   network: &str,
@@ -114,38 +171,681 @@ pub async fn get_network_addr(
   }
 }
 
+/// Write the certificate stored in a `nanocl.io/tls` secret to the state directory
+async fn write_ssl_secret(
+  name: &str,
+  state: &SystemStateRef,
+) -> IoResult<ProxySslConfig> {
+  let query = SecretInspectQuery { reveal: Some(true) };
+  let secret = state.client.inspect_secret(name, Some(&query)).await?;
+  let mut ssl_config = serde_json::from_value::<ProxySslConfig>(secret.data)
+    .map_err(|err| {
+      err.map_err_context(|| "Unable to deserialize ProxySslConfig")
+    })?;
+  let secret_path = format!("{}/secrets/{}", state.store.dir, secret.name);
+  let cert_path = format!("{secret_path}.cert");
+  tokio::fs::write(&cert_path, ssl_config.certificate.clone()).await?;
+  let key_path = format!("{secret_path}.key");
+  tokio::fs::write(&key_path, ssl_config.certificate_key.clone()).await?;
+  if let Some(certificate_client) = ssl_config.certificate_client {
+    let certificate_client_path = format!("{secret_path}.ca");
+    tokio::fs::write(&certificate_client_path, certificate_client).await?;
+    ssl_config.certificate_client = Some(certificate_client_path);
+  }
+  if let Some(dh_param) = ssl_config.dhparam {
+    let dh_param_path = format!("{secret_path}.pem");
+    tokio::fs::write(&dh_param_path, dh_param).await?;
+    ssl_config.dhparam = Some(dh_param_path);
+  }
+  ssl_config.certificate = cert_path;
+  ssl_config.certificate_key = key_path;
+  Ok(ssl_config)
+}
+
+/// Generate the ssl config of a rule,
+/// for `ProxySsl::Acme` the certificate is ordered in background when missing
 pub async fn gen_ssl_config(
   ssl: &ProxySsl,
+  domain: Option<&str>,
   state: &SystemStateRef,
 ) -> IoResult<ProxySslConfig> {
   match ssl {
     ProxySsl::Config(ssl_config) => Ok(ssl_config.clone()),
-    ProxySsl::Secret(secret) => {
-      let secret = state.client.inspect_secret(secret).await?;
-      let mut ssl_config =
-        serde_json::from_value::<ProxySslConfig>(secret.data).map_err(
-          |err| err.map_err_context(|| "Unable to deserialize ProxySslConfig"),
-        )?;
-      let secret_path = format!("{}/secrets/{}", state.store.dir, secret.name);
-      let cert_path = format!("{secret_path}.cert");
-      tokio::fs::write(&cert_path, ssl_config.certificate.clone()).await?;
-      let key_path = format!("{secret_path}.key");
-      tokio::fs::write(&key_path, ssl_config.certificate_key.clone()).await?;
-      if let Some(certificate_client) = ssl_config.certificate_client {
-        let certificate_client_path = format!("{secret_path}.ca");
-        tokio::fs::write(&certificate_client_path, certificate_client).await?;
-        ssl_config.certificate_client = Some(certificate_client_path);
+    ProxySsl::Secret(secret) => write_ssl_secret(secret, state).await,
+    ProxySsl::Acme { acme } => {
+      let Some(domain) = domain else {
+        return Err(IoError::invalid_input(
+          "ProxySsl",
+          "Acme requires the rule to have a domain",
+        ));
+      };
+      if !state.store.sandbox {
+        super::acme::spawn_ensure(domain, acme, state);
       }
-      if let Some(dh_param) = ssl_config.dhparam {
-        let dh_param_path = format!("{secret_path}.pem");
-        tokio::fs::write(&dh_param_path, dh_param).await?;
-        ssl_config.dhparam = Some(dh_param_path);
+      write_ssl_secret(&super::acme::secret_name(domain), state).await
+    }
+  }
+}
+
+/// Ensure a value rendered in an nginx directive can't escape it
+fn ensure_directive_value(name: &str, value: &str) -> IoResult<()> {
+  if value.is_empty()
+    || value
+      .chars()
+      .any(|c| c.is_whitespace() || matches!(c, ';' | '{' | '}' | '\'' | '"'))
+  {
+    return Err(IoError::invalid_input(
+      name,
+      &format!("invalid value {value}"),
+    ));
+  }
+  Ok(())
+}
+
+/// Ensure a header name only contains letters, digits and dashes
+fn ensure_header_name(name: &str, header: &str) -> IoResult<()> {
+  if header.is_empty()
+    || !header
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-')
+  {
+    return Err(IoError::invalid_input(
+      name,
+      &format!("invalid header {header}"),
+    ));
+  }
+  Ok(())
+}
+
+/// Generate the balancing directive of an upstream
+fn gen_balancing(
+  balancing: Option<&UpstreamBalancing>,
+  kind: &NginxRuleKind,
+) -> IoResult<Option<String>> {
+  let directive = match (balancing, kind) {
+    (None, _) | (Some(UpstreamBalancing::RoundRobin), _) => return Ok(None),
+    (Some(UpstreamBalancing::LeastConn), _) => "least_conn".to_owned(),
+    (Some(UpstreamBalancing::IpHash), NginxRuleKind::Site) => {
+      "ip_hash".to_owned()
+    }
+    (Some(UpstreamBalancing::IpHash), NginxRuleKind::Stream) => {
+      "hash $remote_addr consistent".to_owned()
+    }
+    (Some(UpstreamBalancing::Header(header)), NginxRuleKind::Site) => {
+      ensure_header_name("UpstreamBalancing", header)?;
+      let header = header.to_lowercase().replace('-', "_");
+      format!("hash $http_{header} consistent")
+    }
+    (Some(UpstreamBalancing::Header(_)), NginxRuleKind::Stream) => {
+      return Err(IoError::invalid_input(
+        "UpstreamBalancing",
+        "Header is only available for http rules",
+      ));
+    }
+    (Some(UpstreamBalancing::Hash(key)), _) => {
+      ensure_directive_value("UpstreamBalancing", key)?;
+      format!("hash {key} consistent")
+    }
+  };
+  Ok(Some(directive))
+}
+
+/// Limits of a location rendered in the generated http config
+#[derive(Debug, Default)]
+pub struct LocationLimits {
+  /// Zones declared in the http context
+  pub zones: Vec<String>,
+  pub rate_limit: Option<String>,
+  pub connection_limit: Option<String>,
+  pub max_body_size: Option<String>,
+}
+
+/// Generate the rate, connection and body size limits of a location
+/// The zone is used as prefix of the shared memory zones of the location
+pub fn gen_location_limits(
+  zone: &str,
+  location: &ProxyHttpLocation,
+) -> IoResult<LocationLimits> {
+  let zone = gen_zone_name(zone);
+  let mut limits = LocationLimits::default();
+  if let Some(rate_limit) = &location.rate_limit {
+    if rate_limit.rate == 0 {
+      return Err(IoError::invalid_input(
+        "RateLimit",
+        &format!("rate of {} must be greater than 0", location.path),
+      ));
+    }
+    let zone = format!("{zone}_req");
+    limits.zones.push(format!(
+      "limit_req_zone $binary_remote_addr zone={zone}:10m rate={}r/s",
+      rate_limit.rate
+    ));
+    let mut directive = format!("limit_req zone={zone}");
+    if let Some(burst) = rate_limit.burst {
+      directive += &format!(" burst={burst}");
+    }
+    if rate_limit.no_delay.unwrap_or_default() {
+      directive += " nodelay";
+    }
+    limits.rate_limit = Some(directive);
+  }
+  if let Some(connection_limit) = location.connection_limit {
+    if connection_limit == 0 {
+      return Err(IoError::invalid_input(
+        "ConnectionLimit",
+        &format!("limit of {} must be greater than 0", location.path),
+      ));
+    }
+    let zone = format!("{zone}_conn");
+    limits.zones.push(format!(
+      "limit_conn_zone $binary_remote_addr zone={zone}:10m"
+    ));
+    limits.connection_limit =
+      Some(format!("limit_conn {zone} {connection_limit}"));
+  }
+  if let Some(max_body_size) = &location.max_body_size {
+    let size = max_body_size.trim_end_matches(['k', 'K', 'm', 'M', 'g', 'G']);
+    let unit_len = max_body_size.len() - size.len();
+    if size.is_empty()
+      || unit_len > 1
+      || !size.chars().all(|c| c.is_ascii_digit())
+    {
+      return Err(IoError::invalid_input(
+        "MaxBodySize",
+        &format!("invalid size {max_body_size} expected a number with an optional unit k, m or g"),
+      ));
+    }
+    limits.max_body_size = Some(max_body_size.clone());
+  }
+  Ok(limits)
+}
+
+/// Protocol of a location rendered in the generated http config
+#[derive(Debug)]
+pub struct LocationProtocol {
+  /// Name used by the template: `http`, `websocket` or `grpc`
+  pub name: &'static str,
+  /// Scheme of the upstream
+  pub scheme: &'static str,
+}
+
+/// Generate the protocol of a location and ensure its target supports it
+pub fn gen_location_protocol(
+  location: &ProxyHttpLocation,
+) -> IoResult<LocationProtocol> {
+  let protocol = location.protocol.clone().unwrap_or_default();
+  let (name, scheme) = match protocol {
+    ProxyHttpProtocol::Http => ("http", "http"),
+    ProxyHttpProtocol::WebSocket => ("websocket", "http"),
+    ProxyHttpProtocol::Grpc => ("grpc", "grpc"),
+    ProxyHttpProtocol::Grpcs => ("grpc", "grpcs"),
+  };
+  if name == "websocket" && location.version.is_some_and(|v| v != 1.1) {
+    return Err(IoError::invalid_input(
+      "Protocol",
+      &format!("WebSocket of {} requires Version 1.1", location.path),
+    ));
+  }
+  if name == "grpc" {
+    match &location.target {
+      LocationTarget::Upstream(upstream) if upstream.path.is_some() => {
+        return Err(IoError::invalid_input(
+          "Protocol",
+          &format!("Path of {} isn't supported by gRPC", location.path),
+        ));
+      }
+      LocationTarget::Split(split) if split.path.is_some() => {
+        return Err(IoError::invalid_input(
+          "Protocol",
+          &format!("Path of {} isn't supported by gRPC", location.path),
+        ));
+      }
+      LocationTarget::Http(http)
+        if http.redirect.is_some()
+          || !http.url.starts_with(&format!("{scheme}://")) =>
+      {
+        return Err(IoError::invalid_input(
+          "Protocol",
+          &format!("Url of {} must start with {scheme}://", location.path),
+        ));
+      }
+      _ => {}
+    }
+  }
+  Ok(LocationProtocol { name, scheme })
+}
+
+/// Validate the authentication of a location
+fn validate_location_auth(location: &ProxyHttpLocation) -> IoResult<()> {
+  match &location.auth {
+    None => {}
+    Some(ProxyAuth::Basic(basic)) => {
+      ensure_directive_value("BasicAuth", &basic.secret)?;
+      if basic.secret.contains('/') {
+        return Err(IoError::invalid_input(
+          "BasicAuth",
+          &format!("invalid secret {}", basic.secret),
+        ));
+      }
+      if let Some(realm) = &basic.realm {
+        if realm
+          .chars()
+          .any(|c| c.is_control() || matches!(c, '"' | '\\'))
+        {
+          return Err(IoError::invalid_input(
+            "BasicAuth",
+            &format!("invalid realm {realm}"),
+          ));
+        }
+      }
+    }
+    Some(ProxyAuth::Forward(forward)) => {
+      match &forward.target {
+        LocationTarget::Http(http) => {
+          if http.redirect.is_some() {
+            return Err(IoError::invalid_input(
+              "ForwardAuth",
+              "target can't be a redirect",
+            ));
+          }
+          ensure_directive_value("ForwardAuth", &http.url)?;
+        }
+        LocationTarget::Split(_) => {
+          return Err(IoError::invalid_input(
+            "ForwardAuth",
+            "target can't be a split",
+          ));
+        }
+        _ => {}
+      }
+      for header in forward.response_headers.iter().flatten() {
+        ensure_header_name("ForwardAuth", header)?;
       }
-      ssl_config.certificate = cert_path;
-      ssl_config.certificate_key = key_path;
-      Ok(ssl_config)
     }
   }
+  Ok(())
+}
+
+/// Write the users of a `nanocl.io/htpasswd` secret in a file read by nginx
+async fn write_htpasswd_secret(
+  name: &str,
+  state: &SystemStateRef,
+) -> IoResult<String> {
+  let query = SecretInspectQuery { reveal: Some(true) };
+  let secret = state.client.inspect_secret(name, Some(&query)).await?;
+  if secret.kind != KIND_HTPASSWD {
+    return Err(IoError::invalid_input(
+      "BasicAuth",
+      &format!("secret {name} is not of kind {KIND_HTPASSWD}"),
+    ));
+  }
+  let users = serde_json::from_value::<Vec<String>>(secret.data)
+    .map_err(|err| err.map_err_context(|| "Unable to deserialize htpasswd"))?;
+  let path = format!("{}/secrets/{}.htpasswd", state.store.dir, secret.name);
+  tokio::fs::write(&path, users.join("\n") + "\n").await?;
+  Ok(path)
+}
+
+/// Generate the headers copied from the forward auth response
+fn gen_forward_auth_headers(
+  zone: &str,
+  headers: &[String],
+) -> Vec<ForwardAuthHeaderTemplate> {
+  headers
+    .iter()
+    .enumerate()
+    .map(|(index, header)| ForwardAuthHeaderTemplate {
+      name: header.clone(),
+      variable: format!("$auth_{zone}_{index}"),
+      upstream_variable: format!(
+        "$upstream_http_{}",
+        header.to_lowercase().replace('-', "_")
+      ),
+    })
+    .collect()
+}
+
+/// Authentication of a location rendered in the generated http config
+#[derive(Debug, Default)]
+pub struct LocationAuth {
+  pub basic: Option<BasicAuthTemplate>,
+  pub forward: Option<ForwardAuthTemplate>,
+}
+
+/// Generate the basic or forward authentication of a location
+/// The zone is used to name the internal location of the forward auth
+pub async fn gen_location_auth(
+  zone: &str,
+  location: &ProxyHttpLocation,
+  state: &SystemStateRef,
+) -> IoResult<LocationAuth> {
+  validate_location_auth(location)?;
+  let zone = gen_zone_name(zone);
+  let mut auth = LocationAuth::default();
+  match &location.auth {
+    None => {}
+    Some(ProxyAuth::Basic(basic)) => {
+      let user_file = write_htpasswd_secret(&basic.secret, state).await?;
+      auth.basic = Some(BasicAuthTemplate {
+        realm: basic.realm.clone().unwrap_or(location.path.clone()),
+        user_file,
+      });
+    }
+    Some(ProxyAuth::Forward(forward)) => {
+      let upstream = match &forward.target {
+        LocationTarget::Upstream(upstream) => {
+          let key = gen_upstream(upstream, &NginxRuleKind::Site, state).await?;
+          let path = upstream.path.clone().unwrap_or("/".to_owned());
+          format!("http://{key}{path}")
+        }
+        LocationTarget::Unix(unix) => {
+          let key =
+            gen_unix_target_key(unix, &NginxRuleKind::Site, state).await?;
+          format!("http://{key}/")
+        }
+        LocationTarget::Http(http) => http.url.clone(),
+        LocationTarget::Split(_) => {
+          return Err(IoError::invalid_input(
+            "ForwardAuth",
+            "target can't be a split",
+          ));
+        }
+      };
+      auth.forward = Some(ForwardAuthTemplate {
+        path: format!("/_ncproxy_auth_{zone}"),
+        upstream,
+        headers: gen_forward_auth_headers(
+          &zone,
+          forward.response_headers.as_deref().unwrap_or_default(),
+        ),
+      });
+    }
+  }
+  Ok(auth)
+}
+
+/// Validate the weights and the sticky routing of a split target
+fn validate_split(split: &SplitTarget) -> IoResult<()> {
+  if split.split.iter().all(|upstream| upstream.weight == 0) {
+    return Err(IoError::invalid_input(
+      "SplitTarget",
+      "at least one upstream must have a weight greater than 0",
+    ));
+  }
+  match &split.sticky {
+    None => {}
+    Some(SplitSticky::Header(header)) => {
+      ensure_header_name("SplitSticky", header)?;
+    }
+    Some(SplitSticky::Cookie(cookie)) => {
+      // The cookie is read with the nginx variable $cookie_{name}
+      if cookie.is_empty()
+        || !cookie
+          .chars()
+          .all(|c| c.is_ascii_alphanumeric() || c == '_')
+      {
+        return Err(IoError::invalid_input(
+          "SplitSticky",
+          &format!("invalid cookie {cookie}"),
+        ));
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Weight of the servers of each upstream of a split target from the weight
+/// of the upstream and its number of servers, so the share of an upstream
+/// doesn't depend on its number of instances.
+/// Upstreams without weight or servers get 0
+pub(crate) fn gen_split_weights(upstreams: &[(u32, usize)]) -> Vec<u64> {
+  fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+      a
+    } else {
+      gcd(b, a % b)
+    }
+  }
+  let lcm = upstreams
+    .iter()
+    .filter(|(weight, servers)| *weight > 0 && *servers > 0)
+    .fold(1, |lcm, (_, servers)| {
+      let servers = *servers as u64;
+      lcm / gcd(lcm, servers) * servers
+    });
+  let weights = upstreams
+    .iter()
+    .map(|(weight, servers)| match (*weight, *servers) {
+      (0, _) | (_, 0) => 0,
+      (weight, servers) => weight as u64 * lcm / servers as u64,
+    })
+    .collect::<Vec<_>>();
+  let divisor = weights
+    .iter()
+    .fold(0, |divisor, weight| gcd(divisor, *weight));
+  weights
+    .into_iter()
+    .map(|weight| weight / divisor.max(1))
+    .collect()
+}
+
+/// Split target rendered in the generated http config
+#[derive(Debug)]
+pub struct LocationSplit {
+  /// Key of the upstream holding the servers of every split upstream
+  pub upstream_key: String,
+  /// Cookie keeping the clients on the same server for `SplitSticky::Cookie`
+  pub cookie: Option<SplitCookieTemplate>,
+  /// Upstream block written in the configuration of the rule,
+  /// so it's removed with the rule
+  pub upstream: String,
+}
+
+/// Generate a weighted upstream with the servers of every upstream of a split target
+/// The zone is used to name the upstream and the variables of the sticky cookie
+pub async fn gen_location_split(
+  zone: &str,
+  split: &SplitTarget,
+  state: &SystemStateRef,
+) -> IoResult<LocationSplit> {
+  validate_split(split)?;
+  let zone = gen_zone_name(zone);
+  let mut addresses = vec![];
+  for upstream in &split.split {
+    if upstream.weight == 0 {
+      addresses.push(vec![]);
+      continue;
+    }
+    let upstream_addresses = match get_target_processes(&upstream.key, state)
+      .await
+    {
+      Ok((processes, namespace)) => get_addresses(&processes, &namespace).await,
+      Err(err) => Err(err),
+    };
+    match upstream_addresses {
+      Err(err) => {
+        // A stopped upstream doesn't stop the others from receiving traffic
+        log::warn!("Split upstream {} skipped {err}", upstream.key);
+        addresses.push(vec![]);
+      }
+      Ok(upstream_addresses) => addresses.push(upstream_addresses),
+    }
+  }
+  let weights = gen_split_weights(
+    &split
+      .split
+      .iter()
+      .zip(&addresses)
+      .map(|(upstream, addresses)| (upstream.weight, addresses.len()))
+      .collect::<Vec<_>>(),
+  );
+  let servers = split
+    .split
+    .iter()
+    .zip(&addresses)
+    .zip(weights)
+    .filter(|(_, weight)| *weight > 0)
+    .flat_map(|((upstream, addresses), weight)| {
+      addresses
+        .iter()
+        .map(move |addr| format!("{addr}:{} weight={weight}", upstream.port))
+    })
+    .collect::<Vec<_>>();
+  if servers.is_empty() {
+    return Err(IoError::invalid_data(
+      "SplitTarget",
+      "No address found for the split upstreams are processes running ?",
+    ));
+  }
+  let (balancing, cookie) = match &split.sticky {
+    None => (None, None),
+    Some(SplitSticky::Header(header)) => {
+      let header = header.to_lowercase().replace('-', "_");
+      (Some(format!("hash $http_{header} consistent")), None)
+    }
+    Some(SplitSticky::Cookie(name)) => {
+      let variable = format!("$ncproxy_split_{zone}");
+      let cookie = SplitCookieTemplate {
+        name: name.clone(),
+        set_cookie: format!("{variable}_cookie"),
+        variable: variable.clone(),
+      };
+      (Some(format!("hash {variable} consistent")), Some(cookie))
+    }
+  };
+  let upstream_key = format!("split-{zone}");
+  let upstream = SPLIT_UPSTREAM_TEMPLATE.compile(&liquid::object!({
+    "key": upstream_key,
+    "balancing": balancing,
+    "servers": servers,
+  }))?;
+  Ok(LocationSplit {
+    upstream_key,
+    cookie,
+    upstream,
+  })
+}
+
+/// Validate the values of a rule before generating its configuration
+pub fn validate(rule: &ResourceProxyRule) -> IoResult<()> {
+  for rule in &rule.rules {
+    match rule {
+      ProxyRule::Http(http_rule) => {
+        for location in &http_rule.locations {
+          gen_location_limits("validate", location)?;
+          validate_location_auth(location)?;
+          let protocol = gen_location_protocol(location)?;
+          // clients can only speak HTTP/2 to the tls listener
+          if protocol.name == "grpc" && http_rule.ssl.is_none() {
+            return Err(IoError::invalid_input(
+              "Protocol",
+              &format!("gRPC location {} requires Ssl", location.path),
+            ));
+          }
+          match &location.target {
+            LocationTarget::Upstream(upstream) => {
+              gen_balancing(upstream.balancing.as_ref(), &NginxRuleKind::Site)?;
+            }
+            LocationTarget::Split(split) => validate_split(split)?,
+            _ => {}
+          }
+        }
+      }
+      ProxyRule::Stream(stream_rule) => {
+        if let StreamTarget::Upstream(upstream) = &stream_rule.target {
+          gen_balancing(upstream.balancing.as_ref(), &NginxRuleKind::Stream)?;
+        }
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Ensure the TLS-ALPN-01 challenges of a rule can reach the responder.
+/// The backends serve port 443 and don't route the `acme-tls/1` protocol,
+/// so the responder must listen on port 443 itself.
+pub fn validate_acme(
+  rule: &ResourceProxyRule,
+  tls_alpn_addr: &str,
+) -> IoResult<()> {
+  if tls_alpn_addr.rsplit(':').next() == Some("443") {
+    return Ok(());
+  }
+  for rule in &rule.rules {
+    let ProxyRule::Http(http_rule) = rule else {
+      continue;
+    };
+    let Some(ProxySsl::Acme { acme }) = &http_rule.ssl else {
+      continue;
+    };
+    if acme.challenge == Some(AcmeChallenge::TlsAlpn01) {
+      return Err(IoError::invalid_input(
+        "AcmeChallenge",
+        &format!(
+          "TlsAlpn01 requires --acme-tls-alpn-addr on port 443, got {tls_alpn_addr}"
+        ),
+      ));
+    }
+  }
+  Ok(())
+}
+
+/// First 4 bytes of the sha256 of a value as hex
+fn short_hash(value: &str) -> String {
+  let hash = openssl::sha::sha256(value.as_bytes());
+  hash.iter().take(4).fold(String::new(), |mut hash, b| {
+    let _ = write!(hash, "{b:02x}");
+    hash
+  })
+}
+
+/// Generate a name usable as nginx zone or variable from a zone.
+/// The hash of the zone is appended because sanitizing it can make two zones
+/// collide like `my-rule_0_0` and `my_rule_0_0`.
+fn gen_zone_name(zone: &str) -> String {
+  let name = zone.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+  format!("{name}_{}", short_hash(zone))
+}
+
+/// Generate the parameters added to each server of an upstream
+fn gen_server_params(target: &UpstreamTarget) -> String {
+  let mut params = String::new();
+  if let Some(weight) = target.weight {
+    params += &format!(" weight={weight}");
+  }
+  if let Some(health) = &target.health {
+    if let Some(max_fails) = health.max_fails {
+      params += &format!(" max_fails={max_fails}");
+    }
+    if let Some(fail_timeout) = health.fail_timeout {
+      params += &format!(" fail_timeout={fail_timeout}s");
+    }
+  }
+  params
+}
+
+/// Generate the key of an upstream,
+/// targets with custom balancing or health get their own upstream
+fn gen_upstream_key(
+  target_key: &str,
+  port: u16,
+  suffix: &str,
+  target: &UpstreamTarget,
+) -> IoResult<String> {
+  let key = format!("{target_key}-{port}-{suffix}");
+  if target.balancing.is_none()
+    && target.weight.is_none()
+    && target.health.is_none()
+  {
+    return Ok(key);
+  }
+  let policy = serde_json::to_string(&(
+    &target.balancing,
+    &target.weight,
+    &target.health,
+  ))?;
+  let hash = short_hash(&policy);
+  Ok(format!("{key}-{hash}"))
 }
 
 pub async fn gen_upstream(
@@ -156,6 +856,8 @@ pub async fn gen_upstream(
   let (target_name, target_namespace, target_kind) =
     parse_upstream_target(&target.key)?;
   let port = target.port;
+  let balancing = gen_balancing(target.balancing.as_ref(), kind)?;
+  let server_params = gen_server_params(target);
   let (key, content) = match target_kind.as_str() {
     "c" => {
       let cargo = state
@@ -169,11 +871,13 @@ pub async fn gen_upstream(
         })?;
       let addresses =
         get_addresses(&cargo.instances, &target_namespace).await?;
-      let key = format!("{}-{}-cargo", cargo.spec.cargo_key, port);
+      let key = gen_upstream_key(&cargo.spec.cargo_key, port, "cargo", target)?;
       let data = UPSTREAM_TEMPLATE.compile(&liquid::object!({
         "key": key,
         "port": port,
         "addresses": addresses,
+        "balancing": balancing,
+        "server_params": server_params,
       }))?;
       (key, data)
     }
@@ -186,11 +890,13 @@ pub async fn gen_upstream(
           err.map_err_context(|| format!("Unable to inspect vm {target_name}"))
         })?;
       let addresses = get_addresses(&vm.instances, &target_namespace).await?;
-      let key = format!("{}-{}-vm", vm.spec.vm_key, port);
+      let key = gen_upstream_key(&vm.spec.vm_key, port, "vm", target)?;
       let data = UPSTREAM_TEMPLATE.compile(&liquid::object!({
         "key": key,
         "port": port,
         "addresses": addresses,
+        "balancing": balancing,
+        "server_params": server_params,
       }))?;
       (key, data)
     }
@@ -238,3 +944,293 @@ pub async fn gen_stream_upstream_key(
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use nanocld_client::stubs::proxy::{
+    UpstreamHealth, ProxyRateLimit, ProxyBasicAuth, ProxyForwardAuth,
+    HttpTarget, SplitUpstream, ProxyRuleHttp, ProxySslAcme,
+  };
+
+  use super::*;
+
+  fn gen_target() -> UpstreamTarget {
+    UpstreamTarget {
+      key: "test.global.c".to_owned(),
+      port: 9000,
+      path: None,
+      disable_logging: None,
+      balancing: None,
+      weight: None,
+      health: None,
+    }
+  }
+
+  #[test]
+  fn balancing() {
+    let site = NginxRuleKind::Site;
+    let stream = NginxRuleKind::Stream;
+    assert_eq!(gen_balancing(None, &site).unwrap(), None);
+    let balancing = UpstreamBalancing::LeastConn;
+    assert_eq!(
+      gen_balancing(Some(&balancing), &stream).unwrap().as_deref(),
+      Some("least_conn")
+    );
+    let balancing = UpstreamBalancing::IpHash;
+    assert_eq!(
+      gen_balancing(Some(&balancing), &site).unwrap().as_deref(),
+      Some("ip_hash")
+    );
+    assert_eq!(
+      gen_balancing(Some(&balancing), &stream).unwrap().as_deref(),
+      Some("hash $remote_addr consistent")
+    );
+    let balancing = UpstreamBalancing::Header("X-Tenant-Id".to_owned());
+    assert_eq!(
+      gen_balancing(Some(&balancing), &site).unwrap().as_deref(),
+      Some("hash $http_x_tenant_id consistent")
+    );
+    assert!(gen_balancing(Some(&balancing), &stream).is_err());
+    let balancing = UpstreamBalancing::Header("X-Tenant;".to_owned());
+    assert!(gen_balancing(Some(&balancing), &site).is_err());
+    let balancing = UpstreamBalancing::Hash("$request_uri".to_owned());
+    assert_eq!(
+      gen_balancing(Some(&balancing), &site).unwrap().as_deref(),
+      Some("hash $request_uri consistent")
+    );
+    let balancing = UpstreamBalancing::Hash("$uri; }".to_owned());
+    assert!(gen_balancing(Some(&balancing), &site).is_err());
+  }
+
+  #[test]
+  fn acme_challenge() {
+    let mut acme = ProxySslAcme {
+      email: "admin@example.com".to_owned(),
+      directory: None,
+      challenge: None,
+      renew_before_days: None,
+    };
+    let gen_rule = |acme: &ProxySslAcme| ResourceProxyRule {
+      rules: vec![ProxyRule::Http(ProxyRuleHttp {
+        domain: Some("example.com".to_owned()),
+        network: "Public".to_owned(),
+        locations: vec![],
+        ssl: Some(ProxySsl::Acme { acme: acme.clone() }),
+        includes: None,
+      })],
+    };
+    assert!(validate_acme(&gen_rule(&acme), "0.0.0.0:5001").is_ok());
+    acme.challenge = Some(AcmeChallenge::TlsAlpn01);
+    assert!(validate_acme(&gen_rule(&acme), "0.0.0.0:5001").is_err());
+    assert!(validate_acme(&gen_rule(&acme), "0.0.0.0:443").is_ok());
+  }
+
+  fn gen_location() -> ProxyHttpLocation {
+    ProxyHttpLocation {
+      path: "/".to_owned(),
+      target: LocationTarget::Upstream(gen_target()),
+      allowed_ips: None,
+      headers: None,
+      version: None,
+      rate_limit: None,
+      connection_limit: None,
+      max_body_size: None,
+      auth: None,
+      protocol: None,
+    }
+  }
+
+  #[test]
+  fn location_limits() {
+    let mut location = gen_location();
+    let limits = gen_location_limits("test-rule_0_0", &location).unwrap();
+    assert!(limits.zones.is_empty());
+    location.rate_limit = Some(ProxyRateLimit {
+      rate: 10,
+      burst: Some(20),
+      no_delay: Some(true),
+    });
+    location.connection_limit = Some(5);
+    location.max_body_size = Some("10m".to_owned());
+    let limits = gen_location_limits("test-rule_0_0", &location).unwrap();
+    let zone = gen_zone_name("test-rule_0_0");
+    assert_eq!(
+      limits.zones,
+      vec![
+        format!(
+          "limit_req_zone $binary_remote_addr zone={zone}_req:10m rate=10r/s"
+        ),
+        format!("limit_conn_zone $binary_remote_addr zone={zone}_conn:10m"),
+      ]
+    );
+    assert_eq!(
+      limits.rate_limit,
+      Some(format!("limit_req zone={zone}_req burst=20 nodelay"))
+    );
+    assert_eq!(
+      limits.connection_limit,
+      Some(format!("limit_conn {zone}_conn 5"))
+    );
+    assert_eq!(limits.max_body_size.as_deref(), Some("10m"));
+    for size in ["", "m", "10mb", "1.5m", "10m;"] {
+      location.max_body_size = Some(size.to_owned());
+      assert!(gen_location_limits("test", &location).is_err(), "{size}");
+    }
+    location.max_body_size = None;
+    location.connection_limit = Some(0);
+    assert!(gen_location_limits("test", &location).is_err());
+    location.connection_limit = None;
+    location.rate_limit = Some(ProxyRateLimit {
+      rate: 0,
+      burst: None,
+      no_delay: None,
+    });
+    assert!(gen_location_limits("test", &location).is_err());
+  }
+
+  #[test]
+  fn zone_name() {
+    let zone = gen_zone_name("test-rule_0_0");
+    assert!(zone.starts_with("test_rule_0_0_"));
+    assert!(zone.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+    assert_ne!(zone, gen_zone_name("test_rule_0_0"));
+    assert_ne!(gen_zone_name("a_1_0"), gen_zone_name("a-1_0"));
+  }
+
+  #[test]
+  fn location_auth() {
+    let mut location = gen_location();
+    assert!(validate_location_auth(&location).is_ok());
+    location.auth = Some(ProxyAuth::Basic(ProxyBasicAuth {
+      secret: "test-users".to_owned(),
+      realm: Some("Restricted area".to_owned()),
+    }));
+    assert!(validate_location_auth(&location).is_ok());
+    for (secret, realm) in
+      [("", None), ("../users", None), ("users", Some("a\"b"))]
+    {
+      location.auth = Some(ProxyAuth::Basic(ProxyBasicAuth {
+        secret: secret.to_owned(),
+        realm: realm.map(|r| r.to_owned()),
+      }));
+      assert!(validate_location_auth(&location).is_err(), "{secret}");
+    }
+    let mut forward = ProxyForwardAuth {
+      target: LocationTarget::Http(HttpTarget {
+        url: "http://auth.example.com/verify".to_owned(),
+        redirect: None,
+      }),
+      response_headers: Some(vec!["X-User".to_owned()]),
+    };
+    location.auth = Some(ProxyAuth::Forward(forward.clone()));
+    assert!(validate_location_auth(&location).is_ok());
+    forward.response_headers = Some(vec!["X-User: admin".to_owned()]);
+    location.auth = Some(ProxyAuth::Forward(forward.clone()));
+    assert!(validate_location_auth(&location).is_err());
+    let headers = gen_forward_auth_headers(
+      "test_rule_0_0",
+      &["X-User".to_owned(), "X-Email".to_owned()],
+    );
+    assert_eq!(headers[0].name, "X-User");
+    assert_eq!(headers[0].variable, "$auth_test_rule_0_0_0");
+    assert_eq!(headers[0].upstream_variable, "$upstream_http_x_user");
+    assert_eq!(headers[1].variable, "$auth_test_rule_0_0_1");
+  }
+
+  #[test]
+  fn location_protocol() {
+    let mut location = gen_location();
+    let protocol = gen_location_protocol(&location).unwrap();
+    assert_eq!((protocol.name, protocol.scheme), ("http", "http"));
+    location.protocol = Some(ProxyHttpProtocol::WebSocket);
+    let protocol = gen_location_protocol(&location).unwrap();
+    assert_eq!((protocol.name, protocol.scheme), ("websocket", "http"));
+    location.version = Some(1.0);
+    assert!(gen_location_protocol(&location).is_err());
+    location.version = None;
+    location.protocol = Some(ProxyHttpProtocol::Grpcs);
+    let protocol = gen_location_protocol(&location).unwrap();
+    assert_eq!((protocol.name, protocol.scheme), ("grpc", "grpcs"));
+    let mut target = gen_target();
+    target.path = Some("/api".to_owned());
+    location.target = LocationTarget::Upstream(target);
+    assert!(gen_location_protocol(&location).is_err());
+    location.protocol = Some(ProxyHttpProtocol::Grpc);
+    for (url, valid) in [
+      ("grpc://api.example.com:50051", true),
+      ("grpcs://api.example.com:50051", false),
+      ("http://api.example.com", false),
+    ] {
+      location.target = LocationTarget::Http(HttpTarget {
+        url: url.to_owned(),
+        redirect: None,
+      });
+      assert_eq!(gen_location_protocol(&location).is_ok(), valid, "{url}");
+    }
+  }
+
+  #[test]
+  fn location_split() {
+    let mut split = SplitTarget {
+      split: vec![
+        SplitUpstream {
+          key: "api-v1.global.c".to_owned(),
+          port: 9000,
+          weight: 95,
+        },
+        SplitUpstream {
+          key: "api-v2.global.c".to_owned(),
+          port: 9000,
+          weight: 5,
+        },
+      ],
+      path: None,
+      sticky: None,
+    };
+    assert!(validate_split(&split).is_ok());
+    split.sticky = Some(SplitSticky::Header("X-User-Id".to_owned()));
+    assert!(validate_split(&split).is_ok());
+    split.sticky = Some(SplitSticky::Cookie("ncproxy_canary".to_owned()));
+    assert!(validate_split(&split).is_ok());
+    split.sticky = Some(SplitSticky::Cookie("canary;".to_owned()));
+    assert!(validate_split(&split).is_err());
+    split.sticky = None;
+    split
+      .split
+      .iter_mut()
+      .for_each(|upstream| upstream.weight = 0);
+    assert!(validate_split(&split).is_err());
+    let mut location = gen_location();
+    location.protocol = Some(ProxyHttpProtocol::Grpc);
+    split.split[0].weight = 1;
+    split.path = Some("/api".to_owned());
+    location.target = LocationTarget::Split(split);
+    assert!(gen_location_protocol(&location).is_err());
+    // 95% over 1 instance and 5% over 2 instances
+    assert_eq!(gen_split_weights(&[(95, 1), (5, 2)]), vec![38, 1]);
+    assert_eq!(gen_split_weights(&[(50, 2), (50, 2)]), vec![1, 1]);
+    assert_eq!(gen_split_weights(&[(1, 3), (0, 2), (1, 0)]), vec![1, 0, 0]);
+    assert_eq!(gen_split_weights(&[(0, 1)]), vec![0]);
+  }
+
+  #[test]
+  fn server_params() {
+    let mut target = gen_target();
+    assert_eq!(gen_server_params(&target), "");
+    assert_eq!(
+      gen_upstream_key("test.global", 9000, "cargo", &target).unwrap(),
+      "test.global-9000-cargo"
+    );
+    target.weight = Some(2);
+    target.health = Some(UpstreamHealth {
+      max_fails: Some(3),
+      fail_timeout: Some(30),
+    });
+    assert_eq!(
+      gen_server_params(&target),
+      " weight=2 max_fails=3 fail_timeout=30s"
+    );
+    let key = gen_upstream_key("test.global", 9000, "cargo", &target).unwrap();
+    assert!(key.starts_with("test.global-9000-cargo-"));
+  }
+}
@@ -198,7 +198,7 @@ async fn main() -> std::io::Result<()> {
   };
   loop {
     log::info!("Subscribing to nanocl daemon events..");
-    match nanocl_client.watch_events(None).await {
+    match nanocl_client.watch_events(None, None).await {
       Err(err) => {
         log::warn!("Unable to Subscribe to nanocl daemon events: {err}");
       }
@@ -20,8 +20,8 @@ use crate::generic::ImagePullPolicy;
 )]
 pub enum ReplicationMode {
   /// Auto is used to automatically define that the number of replicas in the cluster
-  /// This will ensure at least 1 replica exists in the cluster
-  /// And automatically add more replicas in the cluster if needed for redundancy
+  /// This will run 1 replica on the first 2 nodes of the cluster sorted by name
+  /// A single node cluster runs a single replica
   Auto,
   /// Unique is used to ensure that only one replica exists in the cluster
   Unique,
@@ -53,6 +53,172 @@ pub struct ReplicationStatic {
   pub number: usize,
 }
 
+/// Horizontal autoscaling policy of a cargo
+/// The number of instances is adjusted on each node between `min_replicas`
+/// and `max_replicas` to keep the average usage close to the targets
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct CargoAutoscale {
+  /// Minimum number of instances
+  pub min_replicas: usize,
+  /// Maximum number of instances
+  pub max_replicas: usize,
+  /// Target average cpu usage in percent
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub target_cpu_percent: Option<u8>,
+  /// Target average memory usage in percent of the memory limit
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub target_memory_percent: Option<u8>,
+  /// Minimum delay in seconds between two scaling decisions (default 60)
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub cooldown: Option<u64>,
+}
+
+/// Kind of strategy used to replace the instances of a cargo on update
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub enum UpdateStrategyKind {
+  /// Replace the instances progressively by batches
+  #[default]
+  Rolling,
+  /// Remove all the instances before creating the new ones
+  Recreate,
+  /// Create all the new instances and remove the old ones once they are healthy
+  BlueGreen,
+}
+
+/// Update strategy of a cargo
+/// Define how the instances are replaced when the cargo is updated
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct UpdateStrategy {
+  /// Kind of strategy (default Rolling)
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub kind: UpdateStrategyKind,
+  /// Maximum number of instances created above the wanted number (Rolling only, default 1)
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub max_surge: Option<usize>,
+  /// Maximum number of instances unavailable during the update (Rolling only, default 0)
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub max_unavailable: Option<usize>,
+  /// Delay in seconds for new instances to become healthy (default 60)
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub health_deadline: Option<u64>,
+  /// Revert to the previous spec when new instances are not healthy in time
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub auto_rollback: Option<bool>,
+}
+
+/// A file written from a key of the secret data
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct SecretMountItem {
+  /// Key of the secret data to write
+  pub key: String,
+  /// Path of the file relative to the mount target (default to the key)
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub path: Option<String>,
+  /// Mode of the file (default to the mount mode)
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub mode: Option<u32>,
+}
+
+/// Mount the data of a secret as files inside the cargo containers
+/// The files are written in a tmpfs volume mounted read only
+/// each time an instance start, they are never stored on the disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct SecretMount {
+  /// Key of the secret to mount
+  pub secret: String,
+  /// Directory where the files are mounted inside the container
+  pub target: String,
+  /// Files to write (default to one file per key of the secret data)
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub items: Option<Vec<SecretMountItem>>,
+  /// Default mode of the files (default 0o400)
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub mode: Option<u32>,
+  /// User id owning the files (default 0)
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub uid: Option<u32>,
+  /// Group id owning the files (default 0)
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub gid: Option<u32>,
+  /// Restart the instances when the secret is updated
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub restart_on_update: Option<bool>,
+}
+
 /// A cargo spec partial is used to create a Cargo
 #[derive(Debug, Default, Clone, PartialEq)]
 #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
@@ -84,6 +250,12 @@ pub struct CargoSpecPartial {
     serde(skip_serializing_if = "Option::is_none")
   )]
   pub secrets: Option<Vec<String>>,
+  /// List of secrets to mount as files
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub secret_mounts: Option<Vec<SecretMount>>,
   /// Secret to use when pulling the image
   #[cfg_attr(
     feature = "serde",
@@ -104,6 +276,18 @@ pub struct CargoSpecPartial {
     serde(skip_serializing_if = "Option::is_none")
   )]
   pub replication: Option<ReplicationMode>,
+  /// Autoscaling policy of the cargo
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub autoscale: Option<CargoAutoscale>,
+  /// Update strategy of the cargo
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub update_strategy: Option<UpdateStrategy>,
 }
 
 /// Payload used to patch a cargo
@@ -143,6 +327,12 @@ pub struct CargoSpecUpdate {
     serde(skip_serializing_if = "Option::is_none")
   )]
   pub secrets: Option<Vec<String>>,
+  /// List of secrets to mount as files
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub secret_mounts: Option<Vec<SecretMount>>,
   /// Secret to use when pulling the image
   #[cfg_attr(
     feature = "serde",
@@ -167,6 +357,18 @@ pub struct CargoSpecUpdate {
     serde(skip_serializing_if = "Option::is_none")
   )]
   pub replication: Option<ReplicationMode>,
+  /// Autoscaling policy of the cargo
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub autoscale: Option<CargoAutoscale>,
+  /// Update strategy of the cargo
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub update_strategy: Option<UpdateStrategy>,
 }
 
 impl From<CargoSpecPartial> for CargoSpecUpdate {
@@ -176,8 +378,11 @@ impl From<CargoSpecPartial> for CargoSpecUpdate {
       init_container: spec.init_container,
       container: Some(spec.container),
       replication: spec.replication,
+      autoscale: spec.autoscale,
+      update_strategy: spec.update_strategy,
       metadata: spec.metadata,
       secrets: spec.secrets,
+      secret_mounts: spec.secret_mounts,
       image_pull_secret: spec.image_pull_secret,
       image_pull_policy: spec.image_pull_policy,
     }
@@ -222,6 +427,12 @@ pub struct CargoSpec {
     serde(skip_serializing_if = "Option::is_none")
   )]
   pub secrets: Option<Vec<String>>,
+  /// List of secrets to mount as files
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub secret_mounts: Option<Vec<SecretMount>>,
   /// Secret to use when pulling the image
   #[cfg_attr(
     feature = "serde",
@@ -242,6 +453,18 @@ pub struct CargoSpec {
     serde(skip_serializing_if = "Option::is_none")
   )]
   pub replication: Option<ReplicationMode>,
+  /// Autoscaling policy of the cargo
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub autoscale: Option<CargoAutoscale>,
+  /// Update strategy of the cargo
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub update_strategy: Option<UpdateStrategy>,
 }
 
 impl From<CargoSpec> for CargoSpecPartial {
@@ -250,9 +473,12 @@ impl From<CargoSpec> for CargoSpecPartial {
       init_container: spec.init_container,
       name: spec.name,
       replication: spec.replication,
+      autoscale: spec.autoscale,
+      update_strategy: spec.update_strategy,
       container: spec.container,
       metadata: spec.metadata,
       secrets: spec.secrets,
+      secret_mounts: spec.secret_mounts,
       image_pull_secret: spec.image_pull_secret,
       image_pull_policy: spec.image_pull_policy,
     }
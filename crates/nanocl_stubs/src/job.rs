@@ -1,3 +1,5 @@
+use std::str::FromStr;
+
 use serde::{Serialize, Deserialize};
 
 use bollard_next::container::Config;
@@ -6,6 +8,198 @@ use crate::generic::ImagePullPolicy;
 use crate::process::Process;
 use crate::system::{EventActor, EventActorKind, ObjPsStatus};
 
+/// Kind of object a job can depend on
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub enum JobDependencyKind {
+  /// The job must succeed a run ended after the run of the dependent job
+  /// was created, it's started when it isn't already pending or running
+  Job,
+  /// The cargo must reach `Start`
+  Cargo,
+}
+
+/// An object that must be ready before a job start
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct JobDependency {
+  /// Kind of the dependency
+  pub kind: JobDependencyKind,
+  /// Name of the job or the cargo
+  pub name: String,
+  /// Namespace of the cargo, default to `global`
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub namespace: Option<String>,
+  /// Seconds to wait for the dependency to be ready, default to 600
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub timeout_seconds: Option<u64>,
+}
+
+/// What to do when a scheduled run is due while the job is still running
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum JobConcurrencyPolicy {
+  /// Queue the run, it starts once the running one ended.
+  /// The runs of a job never overlap since they share its processes.
+  #[default]
+  Allow,
+  /// Skip the run
+  Forbid,
+  /// Stop the running one and start the run
+  Replace,
+}
+
+/// Status of a run of a job
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum JobRunStatus {
+  /// The run is waiting to be started
+  #[default]
+  Pending,
+  /// The steps of the job are running
+  Running,
+  /// Every step succeeded
+  Succeeded,
+  /// A step or a dependency failed, or the job have been stopped
+  Failed,
+  /// The run was due while the job was running with the `Forbid` policy
+  Skipped,
+  /// The run was not started before its starting deadline
+  Missed,
+  /// The run was stopped by a new one with the `Replace` policy
+  Replaced,
+}
+
+impl FromStr for JobRunStatus {
+  type Err = std::io::Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "pending" => Ok(Self::Pending),
+      "running" => Ok(Self::Running),
+      "succeeded" => Ok(Self::Succeeded),
+      "failed" => Ok(Self::Failed),
+      "skipped" => Ok(Self::Skipped),
+      "missed" => Ok(Self::Missed),
+      "replaced" => Ok(Self::Replaced),
+      _ => Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("Invalid job run status {s}"),
+      )),
+    }
+  }
+}
+
+impl std::fmt::Display for JobRunStatus {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let data = match self {
+      Self::Pending => "pending",
+      Self::Running => "running",
+      Self::Succeeded => "succeeded",
+      Self::Failed => "failed",
+      Self::Skipped => "skipped",
+      Self::Missed => "missed",
+      Self::Replaced => "replaced",
+    };
+    write!(f, "{data}")
+  }
+}
+
+/// A run of a job, started manually or by its schedule
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct JobRun {
+  /// Unique identifier of the run
+  pub key: uuid::Uuid,
+  /// When the run have been created
+  pub created_at: chrono::NaiveDateTime,
+  /// When the run have been updated
+  pub updated_at: chrono::NaiveDateTime,
+  /// Name of the job
+  pub job_name: String,
+  /// When the run was due, none when started manually
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub scheduled_at: Option<chrono::NaiveDateTime>,
+  /// When the steps started
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub started_at: Option<chrono::NaiveDateTime>,
+  /// When the run ended
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub ended_at: Option<chrono::NaiveDateTime>,
+  /// Attempt of the run, starting at 1
+  pub attempt: u32,
+  /// Status of the run
+  pub status: JobRunStatus,
+  /// Why the run failed or was not started
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub reason: Option<String>,
+}
+
+/// A step of a job, the steps are run as a DAG.
+/// A step start once all the steps it depends on succeeded,
+/// the independent steps are run in parallel.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct JobStep {
+  /// Name of the step, unique in the job
+  pub name: String,
+  /// Name of the steps that must succeed before this one start
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub depends_on: Option<Vec<String>>,
+  /// Number of times the step is restarted when it fail
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub retry: Option<usize>,
+  /// Container to run
+  pub container: Config,
+}
+
 /// Job partial is used to create a new job
 #[derive(Debug, Default, Clone, PartialEq)]
 #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
@@ -37,6 +231,52 @@ pub struct JobPartial {
     serde(skip_serializing_if = "Option::is_none")
   )]
   pub schedule: Option<String>,
+  /// Time zone of the schedule (ex: Europe/Paris), default to `UTC`
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub time_zone: Option<String>,
+  /// What to do when a scheduled run is due while the job is running
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub concurrency_policy: Option<JobConcurrencyPolicy>,
+  /// Seconds after its due date a scheduled run can still be started,
+  /// the later ones are recorded as missed
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub starting_deadline_seconds: Option<u64>,
+  /// Number of times the job is retried when it fail, default to 0.
+  /// A retry resume from the steps that did not succeed.
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub backoff_limit: Option<u32>,
+  /// Seconds to wait before the first retry, default to 10.
+  /// The delay is doubled after each failed attempt.
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub backoff_seconds: Option<u64>,
+  /// Maximum seconds to wait between two attempts, default to 360
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub max_backoff_seconds: Option<u64>,
+  /// Seconds the job can run across all its attempts
+  /// before its processes are killed and it is marked as failed
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub active_deadline_seconds: Option<u64>,
   /// Remove the job after (x) seconds after execution
   #[cfg_attr(
     feature = "serde",
@@ -54,7 +294,20 @@ pub struct JobPartial {
     serde(skip_serializing_if = "Option::is_none")
   )]
   pub image_pull_policy: Option<ImagePullPolicy>,
-  /// List of container to run
+  /// Jobs and cargoes that must be ready before the job start
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub depends_on: Option<Vec<JobDependency>>,
+  /// Steps to run as a DAG after the containers
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub steps: Option<Vec<JobStep>>,
+  /// List of container to run in sequence
+  #[cfg_attr(feature = "serde", serde(default))]
   pub containers: Vec<Config>,
 }
 
@@ -66,15 +319,25 @@ impl From<Job> for JobPartial {
       secrets: job.secrets,
       metadata: job.metadata,
       schedule: job.schedule,
+      time_zone: job.time_zone,
+      concurrency_policy: job.concurrency_policy,
+      starting_deadline_seconds: job.starting_deadline_seconds,
+      backoff_limit: job.backoff_limit,
+      backoff_seconds: job.backoff_seconds,
+      max_backoff_seconds: job.max_backoff_seconds,
+      active_deadline_seconds: job.active_deadline_seconds,
       ttl: job.ttl,
       containers: job.containers,
       image_pull_secret: job.image_pull_secret,
       image_pull_policy: job.image_pull_policy,
+      depends_on: job.depends_on,
+      steps: job.steps,
     }
   }
 }
 
-/// A job is a collection of containers to run in sequence as a single unit to act like a command
+/// A job is a collection of containers to run in sequence as a single unit to act like a command.
+/// Its steps are run as a DAG once its dependencies are ready.
 #[derive(Debug, Default, Clone, PartialEq)]
 #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
@@ -108,6 +371,52 @@ pub struct Job {
     serde(skip_serializing_if = "Option::is_none")
   )]
   pub schedule: Option<String>,
+  /// Time zone of the schedule (ex: Europe/Paris), default to `UTC`
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub time_zone: Option<String>,
+  /// What to do when a scheduled run is due while the job is running
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub concurrency_policy: Option<JobConcurrencyPolicy>,
+  /// Seconds after its due date a scheduled run can still be started,
+  /// the later ones are recorded as missed
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub starting_deadline_seconds: Option<u64>,
+  /// Number of times the job is retried when it fail, default to 0.
+  /// A retry resume from the steps that did not succeed.
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub backoff_limit: Option<u32>,
+  /// Seconds to wait before the first retry, default to 10.
+  /// The delay is doubled after each failed attempt.
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub backoff_seconds: Option<u64>,
+  /// Maximum seconds to wait between two attempts, default to 360
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub max_backoff_seconds: Option<u64>,
+  /// Seconds the job can run across all its attempts
+  /// before its processes are killed and it is marked as failed
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub active_deadline_seconds: Option<u64>,
   /// Remove the job after (x) seconds after execution
   #[cfg_attr(
     feature = "serde",
@@ -126,7 +435,20 @@ pub struct Job {
     serde(skip_serializing_if = "Option::is_none")
   )]
   pub image_pull_policy: Option<ImagePullPolicy>,
-  /// Containers to run
+  /// Jobs and cargoes that must be ready before the job start
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub depends_on: Option<Vec<JobDependency>>,
+  /// Steps to run as a DAG after the containers
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub steps: Option<Vec<JobStep>>,
+  /// Containers to run in sequence
+  #[cfg_attr(feature = "serde", serde(default))]
   pub containers: Vec<Config>,
 }
 
@@ -184,6 +506,8 @@ pub struct JobInspect {
   pub spec: Job,
   /// List of instances
   pub instances: Vec<Process>,
+  /// Latest runs of the job, each attempt is a run
+  pub runs: Vec<JobRun>,
 }
 
 /// Convert a job inspect into a job partial
@@ -14,7 +14,9 @@ pub mod vm_spec;
 pub mod vm_image;
 pub mod metric;
 pub mod secret;
+pub mod secret_kind;
 pub mod job;
 pub mod process;
 pub mod resource;
 pub mod resource_kind;
+pub mod webhook;
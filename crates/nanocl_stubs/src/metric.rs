@@ -40,6 +40,179 @@ pub struct MetricPartial {
   pub note: Option<String>,
 }
 
+/// Aggregate function applied to the metrics of a group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum MetricAggregateFn {
+  /// Number of metrics
+  Count,
+  /// Average of the field
+  Avg,
+  /// 95th percentile of the field
+  P95,
+  /// Sum of the field
+  Sum,
+}
+
+impl std::str::FromStr for MetricAggregateFn {
+  type Err = std::io::Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "count" => Ok(Self::Count),
+      "avg" => Ok(Self::Avg),
+      "p95" => Ok(Self::P95),
+      "sum" => Ok(Self::Sum),
+      _ => Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("Invalid aggregate function {s}"),
+      )),
+    }
+  }
+}
+
+/// Query string of the metric list, the metrics are aggregated
+/// when one of `group_by`, `interval` or `aggregate` is set
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MetricListQuery {
+  /// A json as string as GenericFilter
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub filter: Option<String>,
+  /// Only the metrics created at or after this date
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub since: Option<DateTime<FixedOffset>>,
+  /// Only the metrics created before this date
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub until: Option<DateTime<FixedOffset>>,
+  /// Comma separated fields to group by: kind, node_name or proxy_host
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub group_by: Option<String>,
+  /// Size of the time buckets in seconds
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub interval: Option<u64>,
+  /// Comma separated aggregate functions: count, avg, p95 or sum, default to count
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub aggregate: Option<String>,
+  /// Field of the data used by avg, p95 and sum, default to request_time
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub field: Option<String>,
+}
+
+impl MetricListQuery {
+  /// Whether the metrics are aggregated instead of listed
+  pub fn is_aggregate(&self) -> bool {
+    self.group_by.is_some()
+      || self.interval.is_some()
+      || self.aggregate.is_some()
+  }
+}
+
+/// Aggregated values of a group of metrics
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct MetricAggregate {
+  /// Start of the time bucket when an interval is set
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub bucket: Option<chrono::NaiveDateTime>,
+  /// Kind of the metrics when grouped by kind
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub kind: Option<String>,
+  /// Node of the metrics when grouped by node_name
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub node_name: Option<String>,
+  /// Upstream of the http metrics when grouped by proxy_host
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub proxy_host: Option<String>,
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub count: Option<u64>,
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub avg: Option<f64>,
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub p95: Option<f64>,
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub sum: Option<f64>,
+}
+
+/// Hourly summary of the http metrics of an upstream,
+/// saved with the kind ncproxy.io/http-hourly when the raw metrics are downsampled
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct HttpMetricSummary {
+  /// Start of the hour
+  pub bucket: chrono::NaiveDateTime,
+  /// Upstream of the requests
+  pub proxy_host: String,
+  /// Number of requests
+  pub count: u64,
+  /// Number of requests by status code
+  pub statuses: std::collections::BTreeMap<String, u64>,
+  /// Average request time in seconds
+  pub avg: f64,
+  /// 95th percentile of the request time in seconds
+  pub p95: f64,
+  /// Sum of the request times in seconds
+  pub sum: f64,
+  /// Bytes sent to the clients
+  pub bytes_sent: i64,
+}
+
 /// ## deserialize empty string
 ///
 /// Serde helper to deserialize string that can be empty to `Option<String>`.
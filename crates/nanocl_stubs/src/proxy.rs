@@ -47,6 +47,51 @@ pub struct ProxySslConfig {
   pub dhparam: Option<String>,
 }
 
+/// Challenge used to prove the ownership of a domain to the ACME server
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AcmeChallenge {
+  /// Serve the key authorization under `/.well-known/acme-challenge/`
+  #[default]
+  Http01,
+  /// Answer a TLS handshake using the `acme-tls/1` protocol
+  TlsAlpn01,
+}
+
+/// Obtain and renew a certificate automatically using the ACME protocol
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct ProxySslAcme {
+  /// Email used to register the ACME account
+  pub email: String,
+  /// Url of the ACME directory, default to Let's Encrypt
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub directory: Option<String>,
+  /// Challenge used to validate the domain, default to Http01
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub challenge: Option<AcmeChallenge>,
+  /// Number of days before the expiration to renew the certificate, default to 30
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub renew_before_days: Option<u32>,
+}
+
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
@@ -55,6 +100,57 @@ pub struct ProxySslConfig {
 pub enum ProxySsl {
   Config(ProxySslConfig),
   Secret(String),
+  /// Certificate managed by ncproxy, stored as a `nanocl.io/tls` secret
+  Acme {
+    #[cfg_attr(feature = "serde", serde(rename = "Acme"))]
+    acme: ProxySslAcme,
+  },
+}
+
+/// Load balancing strategy between the instances of a target
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum UpstreamBalancing {
+  /// Distribute the requests in turn
+  #[default]
+  RoundRobin,
+  /// Send the request to the instance with the least active connections
+  LeastConn,
+  /// Keep a client on the same instance based on its ip address
+  IpHash,
+  /// Keep the requests with the same header value on the same instance
+  /// Only available for http rules
+  Header(String),
+  /// Keep the requests with the same key on the same instance
+  /// The key can contain nginx variables like `$request_uri`
+  Hash(String),
+}
+
+/// Passive health check, instances failing too often are ejected for a while
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct UpstreamHealth {
+  /// Number of failed attempts before the instance is ejected, default to 1
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub max_fails: Option<u32>,
+  /// Time in seconds during which the failed attempts are counted
+  /// and the instance stays ejected, default to 10
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub fail_timeout: Option<u32>,
 }
 
 /// Config for targeting a cargo or a vm
@@ -83,6 +179,24 @@ pub struct UpstreamTarget {
     serde(skip_serializing_if = "Option::is_none")
   )]
   pub disable_logging: Option<bool>,
+  /// Load balancing strategy between the instances, default to RoundRobin
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub balancing: Option<UpstreamBalancing>,
+  /// Weight of each instance of the target
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub weight: Option<u32>,
+  /// Passive health check of the instances
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub health: Option<UpstreamHealth>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -143,6 +257,68 @@ pub enum LocationTarget {
   Http(HttpTarget),
   /// Target a specific unix socket
   Unix(UnixTarget),
+  /// Split the traffic between several cargoes or vms
+  Split(SplitTarget),
+}
+
+/// A cargo or a vm receiving a share of the traffic of a split target
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct SplitUpstream {
+  /// The key of the cargo or the vm to target
+  pub key: String,
+  /// The port of the cargo or the vm to target
+  pub port: u16,
+  /// Share of the traffic relative to the other upstreams, 0 drains it
+  pub weight: u32,
+}
+
+/// Keep a client on the same upstream of a split target
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub enum SplitSticky {
+  /// Name of a cookie set on the first response when the client doesn't have it
+  Cookie(String),
+  /// Name of a header sent by the clients
+  Header(String),
+}
+
+/// Config for splitting the traffic of a location between several upstreams
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct SplitTarget {
+  /// Upstreams receiving the traffic
+  pub split: Vec<SplitUpstream>,
+  /// The http path to target
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub path: Option<String>,
+  /// Keep a client on the same upstream, default to a new pick per request
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub sticky: Option<SplitSticky>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -237,6 +413,99 @@ pub struct ProxyRuleStream {
   pub target: StreamTarget,
 }
 
+/// Limit the rate of requests of each client ip address on a location
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct ProxyRateLimit {
+  /// Number of requests per second allowed for a client ip address
+  pub rate: u32,
+  /// Number of requests above the rate queued before being rejected
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub burst: Option<u32>,
+  /// Serve the queued requests without delaying them
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub no_delay: Option<bool>,
+}
+
+/// Protocol spoken between the proxy and the target of a location
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ProxyHttpProtocol {
+  #[default]
+  Http,
+  /// Upgrade the connection and keep it open for an hour without traffic
+  WebSocket,
+  /// Proxy gRPC calls in plain text using `grpc_pass`
+  Grpc,
+  /// Proxy gRPC calls over TLS using `grpc_pass`
+  Grpcs,
+}
+
+/// Basic authentication with the users of a `nanocl.io/htpasswd` secret
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct ProxyBasicAuth {
+  /// Name of the secret containing the users
+  pub secret: String,
+  /// Realm displayed by the browser, default to the location path
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub realm: Option<String>,
+}
+
+/// Ask another service to authorize each request before proxying it.
+/// A 2xx response allows the request, a 401 or 403 is returned to the client.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct ProxyForwardAuth {
+  /// The cargo, vm or url authorizing the requests
+  pub target: LocationTarget,
+  /// Headers of the authorization response copied to the proxied request
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub response_headers: Option<Vec<String>>,
+}
+
+/// Authentication required to access a location
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ProxyAuth {
+  Basic(ProxyBasicAuth),
+  Forward(ProxyForwardAuth),
+}
+
 /// Defines a proxy rule location
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
@@ -251,6 +520,12 @@ pub struct ProxyHttpLocation {
   pub path: String,
   /// The target cargo
   pub target: LocationTarget,
+  /// Protocol used to proxy the requests, default to Http
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub protocol: Option<ProxyHttpProtocol>,
   /// Allowed ip addr
   pub allowed_ips: Option<Vec<String>>,
   /// Extras header to add
@@ -265,6 +540,30 @@ pub struct ProxyHttpLocation {
     serde(skip_serializing_if = "Option::is_none")
   )]
   pub version: Option<f64>,
+  /// Limit the rate of requests of each client ip address
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub rate_limit: Option<ProxyRateLimit>,
+  /// Maximum number of simultaneous connections of a client ip address
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub connection_limit: Option<u32>,
+  /// Maximum size of the request body like `512k` or `10m`
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub max_body_size: Option<String>,
+  /// Authentication required to access the location
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub auth: Option<ProxyAuth>,
 }
 
 /// Defines a proxy rule http config
@@ -314,3 +613,19 @@ pub struct ResourceProxyRule {
   /// The rules to apply
   pub rules: Vec<ProxyRule>,
 }
+
+/// Result of the validation of a `ResourceProxyRule` without applying it
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct ProxyRuleValidation {
+  /// The configuration generated for the rule
+  pub config: String,
+  /// The errors and the skipped parts of the rule, empty when it's valid
+  pub errors: Vec<String>,
+}
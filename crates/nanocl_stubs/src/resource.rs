@@ -136,3 +136,20 @@ impl From<Resource> for ResourcePartial {
     }
   }
 }
+
+/// Validation of a resource by its schema and its controller
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ResourceValidation {
+  /// The configuration generated by the controller if any
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub config: Option<String>,
+  /// The errors of the resource, empty when it's valid
+  pub errors: Vec<String>,
+}
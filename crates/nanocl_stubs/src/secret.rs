@@ -121,3 +121,36 @@ impl From<SecretPartial> for SecretUpdate {
     }
   }
 }
+
+/// A previous version of a secret data
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct SecretHistory {
+  /// Unique identifier of the history entry
+  pub key: uuid::Uuid,
+  /// The key of the secret
+  pub secret_key: String,
+  /// When the version have been created
+  pub created_at: chrono::NaiveDateTime,
+  // The metadata (user defined)
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  #[cfg_attr(feature = "utoipa", schema(value_type = HashMap<String, Any>))]
+  pub metadata: Option<serde_json::Value>,
+  /// The secret data
+  #[cfg_attr(feature = "utoipa", schema(value_type = HashMap<String, Any>))]
+  pub data: serde_json::Value,
+}
+
+/// Inspect secret query
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SecretInspectQuery {
+  /// Return the data of the secret instead of redacting it
+  pub reveal: Option<bool>,
+}
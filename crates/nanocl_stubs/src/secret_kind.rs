@@ -0,0 +1,50 @@
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+/// A partial secret kind. This is used to register a custom secret kind.
+/// The data of the secrets of this kind are validated against its JSONSchema.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct SecretKindPartial {
+  /// The name of the secret kind in the form `domain/name`
+  pub name: String,
+  /// The JSONSchema of the data of the secrets of this kind
+  #[cfg_attr(feature = "utoipa", schema(value_type = HashMap<String, Any>))]
+  pub schema: serde_json::Value,
+  /// Metadata (user defined) of the secret kind
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  #[cfg_attr(feature = "utoipa", schema(value_type = HashMap<String, Any>))]
+  pub metadata: Option<serde_json::Value>,
+}
+
+/// A registered secret kind
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct SecretKind {
+  /// The name of the secret kind in the form `domain/name`
+  pub name: String,
+  /// When the secret kind have been created
+  pub created_at: chrono::NaiveDateTime,
+  /// The JSONSchema of the data of the secrets of this kind
+  #[cfg_attr(feature = "utoipa", schema(value_type = HashMap<String, Any>))]
+  pub schema: serde_json::Value,
+  /// Metadata (user defined) of the secret kind
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  #[cfg_attr(feature = "utoipa", schema(value_type = HashMap<String, Any>))]
+  pub metadata: Option<serde_json::Value>,
+}
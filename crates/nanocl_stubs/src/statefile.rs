@@ -193,3 +193,49 @@ pub struct Statefile {
   )]
   pub jobs: Option<Vec<JobPartial>>,
 }
+
+/// Status of an object of a Statefile applied or removed by the daemon
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub enum StateStreamStatus {
+  /// The object is being processed
+  Pending,
+  /// The object has been created
+  Created,
+  /// The object has been updated
+  Updated,
+  /// The object is already up to date
+  Unchanged,
+  /// The object has been removed
+  Removed,
+  /// The object doesn't exist
+  NotFound,
+  /// The object failed to be processed
+  Failed,
+  /// The change made on the object has been reverted
+  RolledBack,
+}
+
+/// Progress of a Statefile apply or remove streamed by the daemon
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct StateStream {
+  /// Kind of the object (Cargo, Vm, Job, Secret, Resource)
+  pub kind: String,
+  /// Key of the object
+  pub key: String,
+  /// Status of the object
+  pub status: StateStreamStatus,
+  /// Additional information like the reason of a failure
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub context: Option<String>,
+}
@@ -206,6 +206,7 @@ pub enum NativeEventAction {
   Die,
   Downloading,
   Download,
+  Scale,
   Other(String),
 }
 
@@ -229,6 +230,7 @@ impl FromStr for NativeEventAction {
       "die" => Ok(NativeEventAction::Die),
       "downloading" => Ok(NativeEventAction::Downloading),
       "download" => Ok(NativeEventAction::Download),
+      "scale" => Ok(NativeEventAction::Scale),
       _ => Ok(NativeEventAction::Other(s.to_owned())),
     }
   }
@@ -252,6 +254,7 @@ impl std::fmt::Display for NativeEventAction {
       NativeEventAction::Die => write!(f, "die"),
       NativeEventAction::Downloading => write!(f, "downloading"),
       NativeEventAction::Download => write!(f, "download"),
+      NativeEventAction::Scale => write!(f, "scale"),
       NativeEventAction::Other(s) => write!(f, "{}", s),
     }
   }
@@ -471,3 +474,26 @@ impl std::cmp::PartialEq<Event> for EventCondition {
       && self.action.clone().into_iter().any(|a| a == action)
   }
 }
+
+/// Query string of the event watch
+#[derive(Default, Clone, Debug)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EventWatchQuery {
+  /// Key of the last event received or a RFC 3339 date.
+  /// The persisted events after it are replayed before the live ones.
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub since: Option<String>,
+}
+
+impl EventWatchQuery {
+  /// Resume after the event with the given key
+  pub fn since_key(key: &uuid::Uuid) -> Self {
+    Self {
+      since: Some(key.to_string()),
+    }
+  }
+}
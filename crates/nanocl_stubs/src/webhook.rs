@@ -0,0 +1,199 @@
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+use crate::system::EventCondition;
+
+/// Retry policy of a webhook.
+/// The delay between two attempts starts at `Backoff` seconds
+/// and is doubled after each failure, up to `MaxBackoff` seconds.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct WebhookRetry {
+  /// Number of attempts before the delivery is dead-lettered
+  pub max_attempts: u32,
+  /// Delay in seconds before the first retry
+  pub backoff: u64,
+  /// Maximum delay in seconds between two attempts
+  pub max_backoff: u64,
+}
+
+impl Default for WebhookRetry {
+  fn default() -> Self {
+    Self {
+      max_attempts: 5,
+      backoff: 2,
+      max_backoff: 300,
+    }
+  }
+}
+
+/// A partial webhook. This is used to subscribe an external url to the events.
+/// The matching events are delivered as json with a `POST` request.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(deny_unknown_fields, rename_all = "PascalCase")
+)]
+pub struct WebhookPartial {
+  /// The name of the webhook
+  pub name: String,
+  /// The http or https url where the events are posted
+  pub url: String,
+  /// Only the events matching one of the conditions are delivered.
+  /// An unset field of a condition match any value, every event is delivered
+  /// when there is no condition.
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub conditions: Option<Vec<EventCondition>>,
+  /// Secret used to sign the body of the requests with HMAC-SHA256.
+  /// The signature is sent in the `X-Nanocl-Signature` header.
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub secret: Option<String>,
+  /// Retry policy of the failed deliveries
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub retry: Option<WebhookRetry>,
+  /// Metadata (user defined) of the webhook
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  #[cfg_attr(feature = "utoipa", schema(value_type = HashMap<String, Any>))]
+  pub metadata: Option<serde_json::Value>,
+}
+
+/// A registered webhook, the signing secret is never returned
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct Webhook {
+  /// The name of the webhook
+  pub name: String,
+  /// When the webhook have been created
+  pub created_at: chrono::NaiveDateTime,
+  /// When the webhook have been updated
+  pub updated_at: chrono::NaiveDateTime,
+  /// The http or https url where the events are posted
+  pub url: String,
+  /// Only the events matching one of the conditions are delivered
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub conditions: Option<Vec<EventCondition>>,
+  /// Whether the requests are signed
+  pub signed: bool,
+  /// Retry policy of the failed deliveries
+  pub retry: WebhookRetry,
+  /// Metadata (user defined) of the webhook
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  #[cfg_attr(feature = "utoipa", schema(value_type = HashMap<String, Any>))]
+  pub metadata: Option<serde_json::Value>,
+}
+
+/// Status of a webhook delivery
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum WebhookDeliveryStatus {
+  /// The delivery is waiting for its next attempt
+  #[default]
+  Pending,
+  /// The endpoint answered with a success status code
+  Delivered,
+  /// Every attempt failed, the delivery will not be retried
+  DeadLetter,
+}
+
+impl FromStr for WebhookDeliveryStatus {
+  type Err = std::io::Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "pending" => Ok(Self::Pending),
+      "delivered" => Ok(Self::Delivered),
+      "deadletter" => Ok(Self::DeadLetter),
+      _ => Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("Invalid webhook delivery status {s}"),
+      )),
+    }
+  }
+}
+
+impl std::fmt::Display for WebhookDeliveryStatus {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let data = match self {
+      Self::Pending => "pending",
+      Self::Delivered => "delivered",
+      Self::DeadLetter => "deadletter",
+    };
+    write!(f, "{data}")
+  }
+}
+
+/// The delivery of an event to a webhook
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct WebhookDelivery {
+  /// Unique identifier of the delivery
+  pub key: uuid::Uuid,
+  /// When the delivery have been created
+  pub created_at: chrono::NaiveDateTime,
+  /// When the delivery have been updated
+  pub updated_at: chrono::NaiveDateTime,
+  /// Name of the webhook
+  pub webhook_name: String,
+  /// Key of the delivered event
+  pub event_key: uuid::Uuid,
+  /// Name of the node delivering the event
+  pub node_name: String,
+  /// Status of the delivery
+  pub status: WebhookDeliveryStatus,
+  /// Number of attempts made
+  pub attempts: u32,
+  /// Status code of the last response
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub status_code: Option<u16>,
+  /// Error of the last failed attempt
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub error: Option<String>,
+  /// When the next attempt is scheduled
+  #[cfg_attr(
+    feature = "serde",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub next_attempt_at: Option<chrono::NaiveDateTime>,
+}
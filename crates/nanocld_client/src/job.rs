@@ -1,6 +1,12 @@
-use nanocl_error::http_client::HttpClientResult;
+use nanocl_error::{
+  io::IoError,
+  http_client::{HttpClientError, HttpClientResult},
+};
 
-use nanocl_stubs::job::{Job, JobPartial, JobInspect, JobSummary};
+use nanocl_stubs::{
+  generic::{GenericFilter, GenericListQuery},
+  job::{Job, JobPartial, JobInspect, JobRun, JobSummary},
+};
 
 use super::http_client::NanocldClient;
 
@@ -85,6 +91,34 @@ impl NanocldClient {
       .await?;
     Ok(())
   }
+
+  /// List the runs of a job, the newest first
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_to("http://localhost:8585", None);
+  /// let runs = client.list_job_run("my_job", None).await?;
+  /// ```
+  pub async fn list_job_run(
+    &self,
+    name: &str,
+    query: Option<&GenericFilter>,
+  ) -> HttpClientResult<Vec<JobRun>> {
+    let query = query.cloned().unwrap_or_default();
+    let query = GenericListQuery::try_from(query).map_err(|err| {
+      HttpClientError::IoError(IoError::invalid_data(
+        "Query".to_owned(),
+        err.to_string(),
+      ))
+    })?;
+    let res = self
+      .send_get(&format!("{}/{name}/runs", Self::JOB_PATH), Some(&query))
+      .await?;
+    Self::res_json(res).await
+  }
 }
 
 #[cfg(test)]
@@ -120,11 +154,20 @@ mod tests {
           ..Default::default()
         }],
         schedule: None,
+        time_zone: None,
+        concurrency_policy: None,
+        starting_deadline_seconds: None,
+        backoff_limit: None,
+        backoff_seconds: None,
+        max_backoff_seconds: None,
+        active_deadline_seconds: None,
         secrets: None,
         metadata: None,
         ttl: None,
         image_pull_secret: None,
         image_pull_policy: None,
+        depends_on: None,
+        steps: None,
       })
       .await
       .unwrap();
@@ -133,6 +176,8 @@ mod tests {
     client.start_process("job", &job.name, None).await.unwrap();
     while let Some(Ok(_)) = stream.next().await {}
     let job = client.inspect_job(&job.name).await.unwrap();
+    let runs = client.list_job_run(&job.spec.name, None).await.unwrap();
+    assert!(!runs.is_empty());
     client.delete_job(&job.spec.name).await.unwrap();
   }
 }
@@ -9,10 +9,13 @@ pub(crate) mod vm;
 pub(crate) mod vm_image;
 pub(crate) mod node;
 pub(crate) mod secret;
+pub(crate) mod secret_kind;
 pub(crate) mod job;
 pub(crate) mod process;
 pub(crate) mod metric;
 pub(crate) mod resource_kind;
+pub(crate) mod state;
+pub(crate) mod webhook;
 
 pub use bollard_next;
 pub mod error;
@@ -4,7 +4,7 @@ use nanocl_error::{
 };
 
 use nanocl_stubs::{
-  metric::{Metric, MetricPartial},
+  metric::{Metric, MetricPartial, MetricAggregate, MetricListQuery},
   generic::{GenericFilter, GenericListQuery},
 };
 
@@ -65,6 +65,34 @@ impl NanocldClient {
       .await?;
     Self::res_json(res).await
   }
+
+  /// Aggregate the metrics in the system by time bucket, kind, node or upstream
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  /// use nanocld_client::stubs::metric::MetricListQuery;
+  ///
+  /// let client = NanocldClient::connect_to("http://localhost:8585", None);
+  /// let res = client.aggregate_metric(&MetricListQuery {
+  ///   group_by: Some("proxy_host".to_owned()),
+  ///   interval: Some(3600),
+  ///   aggregate: Some("count,p95".to_owned()),
+  ///   ..Default::default()
+  /// }).await;
+  /// ```
+  pub async fn aggregate_metric(
+    &self,
+    query: &MetricListQuery,
+  ) -> HttpClientResult<Vec<MetricAggregate>> {
+    let mut query = query.clone();
+    if !query.is_aggregate() {
+      query.aggregate = Some("count".to_owned());
+    }
+    let res = self.send_get(Self::METRIC_PATH, Some(&query)).await?;
+    Self::res_json(res).await
+  }
 }
 
 #[cfg(test)]
@@ -93,5 +121,15 @@ mod tests {
     assert_eq!(metric.kind, "my-source.io/type");
     let metrics = client.list_metric(None).await.unwrap();
     assert!(!metrics.is_empty());
+    let aggregates = client
+      .aggregate_metric(&MetricListQuery {
+        group_by: Some("kind".to_owned()),
+        ..Default::default()
+      })
+      .await
+      .unwrap();
+    assert!(aggregates
+      .iter()
+      .any(|item| item.kind.as_deref() == Some("my-source.io/type")));
   }
 }
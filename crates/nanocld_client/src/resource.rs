@@ -3,7 +3,7 @@ use nanocl_error::http_client::{HttpClientResult, HttpClientError};
 use nanocl_error::io::IoError;
 use nanocl_stubs::generic::{GenericFilter, GenericListQuery};
 use nanocl_stubs::resource::{
-  Resource, ResourcePartial, ResourceSpec, ResourceUpdate,
+  Resource, ResourcePartial, ResourceSpec, ResourceUpdate, ResourceValidation,
 };
 
 use super::http_client::NanocldClient;
@@ -64,6 +64,36 @@ impl NanocldClient {
     Self::res_json(res).await
   }
 
+  /// Validate a resource without creating it,
+  /// the config is generated by the controller of the resource kind
+  /// and is empty when the kind has no controller
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_to("http://localhost:8585", None);
+  /// let res = client.validate_resource(&ResourcePartial {
+  ///   name: "my-resource".into(),
+  ///   kind: String::from("Custom"),
+  ///   data: serde_json::json!({}),
+  /// }).await;
+  /// ```
+  pub async fn validate_resource(
+    &self,
+    data: &ResourcePartial,
+  ) -> HttpClientResult<ResourceValidation> {
+    let res = self
+      .send_post(
+        &format!("{}/validate", Self::RESOURCE_PATH),
+        Some(data),
+        None::<String>,
+      )
+      .await?;
+    Self::res_json(res).await
+  }
+
   /// Inspect an existing resource
   ///
   /// ## Example
@@ -2,7 +2,9 @@ use nanocl_error::io::IoError;
 use nanocl_error::http_client::{HttpClientError, HttpClientResult};
 
 use nanocl_stubs::generic::{GenericFilter, GenericListQuery};
-use nanocl_stubs::secret::{Secret, SecretPartial, SecretUpdate};
+use nanocl_stubs::secret::{
+  Secret, SecretPartial, SecretUpdate, SecretHistory, SecretInspectQuery,
+};
 
 use super::http_client::NanocldClient;
 
@@ -63,6 +65,7 @@ impl NanocldClient {
   }
 
   /// Inspect a secret by it's key to get more information about it
+  /// The data is redacted unless `reveal` is set in the query
   ///
   /// ## Example
   ///
@@ -70,12 +73,60 @@ impl NanocldClient {
   /// use nanocld_client::NanocldClient;
   ///
   /// let client = NanocldClient::connect_to("http://localhost:8585", None);
-  /// let secret = client.inspect_secret("my-secret").await?;
+  /// let secret = client.inspect_secret("my-secret", None).await?;
   /// ```
-  pub async fn inspect_secret(&self, key: &str) -> HttpClientResult<Secret> {
+  pub async fn inspect_secret(
+    &self,
+    key: &str,
+    query: Option<&SecretInspectQuery>,
+  ) -> HttpClientResult<Secret> {
+    let res = self
+      .send_get(&format!("{}/{key}/inspect", Self::SECRET_PATH), query)
+      .await?;
+    Self::res_json(res).await
+  }
+
+  /// List the history of a secret by it's key
+  /// The data is redacted unless `reveal` is set in the query
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_to("http://localhost:8585", None);
+  /// let histories = client.list_history_secret("my-secret", None).await?;
+  /// ```
+  pub async fn list_history_secret(
+    &self,
+    key: &str,
+    query: Option<&SecretInspectQuery>,
+  ) -> HttpClientResult<Vec<SecretHistory>> {
     let res = self
-      .send_get(
-        &format!("{}/{key}/inspect", Self::SECRET_PATH),
+      .send_get(&format!("{}/{key}/histories", Self::SECRET_PATH), query)
+      .await?;
+    Self::res_json(res).await
+  }
+
+  /// Revert a secret to a specific history
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_to("http://localhost:8585", None);
+  /// let secret = client.revert_secret("my-secret", "my-history-id").await?;
+  /// ```
+  pub async fn revert_secret(
+    &self,
+    key: &str,
+    id: &str,
+  ) -> HttpClientResult<Secret> {
+    let res = self
+      .send_patch(
+        &format!("{}/{key}/histories/{id}/revert", Self::SECRET_PATH),
+        None::<String>,
         None::<String>,
       )
       .await?;
@@ -123,8 +174,22 @@ mod tests {
     };
     let secret = client.create_secret(&secret).await.unwrap();
     assert_eq!(secret.name, SECRET_NAME);
-    let secret = client.inspect_secret(SECRET_NAME).await.unwrap();
+    let secret = client.inspect_secret(SECRET_NAME, None).await.unwrap();
     assert_eq!(secret.name, SECRET_NAME);
+    assert_eq!(secret.data, serde_json::json!({"key": "<redacted>"}));
+    let query = SecretInspectQuery { reveal: Some(true) };
+    let secret = client
+      .inspect_secret(SECRET_NAME, Some(&query))
+      .await
+      .unwrap();
+    assert_eq!(secret.data, serde_json::json!({"key": "value"}));
+    let histories =
+      client.list_history_secret(SECRET_NAME, None).await.unwrap();
+    let history = histories.first().unwrap();
+    client
+      .revert_secret(SECRET_NAME, &history.key.to_string())
+      .await
+      .unwrap();
     client.delete_secret(SECRET_NAME).await.unwrap();
   }
 }
@@ -0,0 +1,92 @@
+use nanocl_error::{
+  io::IoError,
+  http_client::{HttpClientError, HttpClientResult},
+};
+
+use nanocl_stubs::{
+  generic::{GenericFilter, GenericListQuery},
+  secret_kind::{SecretKind, SecretKindPartial},
+};
+
+use super::http_client::NanocldClient;
+
+impl NanocldClient {
+  /// ## Default path for secret kinds
+  const SECRET_KIND_PATH: &'static str = "/secret/kinds";
+
+  /// List the custom secret kinds registered in the system.
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_to("http://localhost:8585", None);
+  /// let res = client.list_secret_kind(None).await;
+  /// ```
+  pub async fn list_secret_kind(
+    &self,
+    query: Option<&GenericFilter>,
+  ) -> HttpClientResult<Vec<SecretKind>> {
+    let query = query.cloned().unwrap_or_default();
+    let query = GenericListQuery::try_from(query).map_err(|err| {
+      HttpClientError::IoError(IoError::invalid_data(
+        "Query".to_owned(),
+        err.to_string(),
+      ))
+    })?;
+    let res = self.send_get(Self::SECRET_KIND_PATH, Some(&query)).await?;
+    Self::res_json(res).await
+  }
+
+  /// Register a custom secret kind with the JSONSchema of its data
+  pub async fn create_secret_kind(
+    &self,
+    item: &SecretKindPartial,
+  ) -> HttpClientResult<SecretKind> {
+    let res = self
+      .send_post(Self::SECRET_KIND_PATH, Some(item), None::<String>)
+      .await?;
+    Self::res_json(res).await
+  }
+
+  /// Inspect a secret kind by it's name
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_to("http://localhost:8585", None);
+  /// let kind = client.inspect_secret_kind("my-domain.io/ssh-key").await?;
+  /// ```
+  pub async fn inspect_secret_kind(
+    &self,
+    key: &str,
+  ) -> HttpClientResult<SecretKind> {
+    let res = self
+      .send_get(
+        &format!("{}/{key}/inspect", Self::SECRET_KIND_PATH),
+        None::<String>,
+      )
+      .await?;
+    Self::res_json(res).await
+  }
+
+  /// Delete a secret kind by it's name
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_to("http://localhost:8585", None);
+  /// client.delete_secret_kind("my-domain.io/ssh-key").await?;
+  /// ```
+  pub async fn delete_secret_kind(&self, key: &str) -> HttpClientResult<()> {
+    self
+      .send_delete(&format!("{}/{key}", Self::SECRET_KIND_PATH), None::<String>)
+      .await?;
+    Ok(())
+  }
+}
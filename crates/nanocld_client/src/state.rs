@@ -0,0 +1,70 @@
+use ntex::channel::mpsc::Receiver;
+
+use nanocl_error::http::HttpResult;
+use nanocl_error::http_client::HttpClientResult;
+
+use nanocl_stubs::statefile::{Statefile, StateStream};
+
+use crate::NanocldClient;
+
+impl NanocldClient {
+  /// ## Default path for states
+  const STATE_PATH: &'static str = "/states";
+
+  /// Apply a rendered Statefile and stream the progress of each object
+  /// If an object fails to be applied the previous changes are reverted
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use futures::StreamExt;
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_to("http://localhost:8585", None);
+  /// let mut stream = client.apply_state(&statefile).await.unwrap();
+  /// while let Some(progress) = stream.next().await {
+  ///   println!("{:?}", progress);
+  /// }
+  /// ```
+  pub async fn apply_state(
+    &self,
+    statefile: &Statefile,
+  ) -> HttpClientResult<Receiver<HttpResult<StateStream>>> {
+    let res = self
+      .send_post(
+        &format!("{}/apply", Self::STATE_PATH),
+        Some(statefile),
+        None::<String>,
+      )
+      .await?;
+    Ok(Self::res_stream(res).await)
+  }
+
+  /// Remove the objects of a rendered Statefile and stream their progress
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use futures::StreamExt;
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_to("http://localhost:8585", None);
+  /// let mut stream = client.remove_state(&statefile).await.unwrap();
+  /// while let Some(progress) = stream.next().await {
+  ///   println!("{:?}", progress);
+  /// }
+  /// ```
+  pub async fn remove_state(
+    &self,
+    statefile: &Statefile,
+  ) -> HttpClientResult<Receiver<HttpResult<StateStream>>> {
+    let res = self
+      .send_post(
+        &format!("{}/remove", Self::STATE_PATH),
+        Some(statefile),
+        None::<String>,
+      )
+      .await?;
+    Ok(Self::res_stream(res).await)
+  }
+}
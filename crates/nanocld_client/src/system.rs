@@ -3,7 +3,9 @@ use ntex::channel::mpsc::Receiver;
 use nanocl_error::http::HttpResult;
 use nanocl_error::http_client::HttpClientResult;
 
-use nanocl_stubs::system::{BinaryInfo, Event, EventCondition, HostInfo};
+use nanocl_stubs::system::{
+  BinaryInfo, Event, EventCondition, EventWatchQuery, HostInfo,
+};
 
 use super::http_client::NanocldClient;
 
@@ -24,7 +26,9 @@ impl NanocldClient {
   }
 
   /// Watch daemon events
-  /// It will emit an event when the daemon state change
+  /// It will emit an event when the daemon state change.
+  /// With a `since` cursor the events missed after it are replayed first,
+  /// use it with the key of the last event received to resume a watch.
   ///
   /// ## Example
   ///
@@ -32,7 +36,7 @@ impl NanocldClient {
   /// use nanocld_client::NanocldClient;
   ///
   /// let client = NanocldClient::connect_to("http://localhost:8585", None);
-  /// let mut stream = client.watch_events(None).await?;
+  /// let mut stream = client.watch_events(None, None).await?;
   /// while let Some(event) = stream.next().await {
   ///  println!("{:?}", event);
   /// }
@@ -40,10 +44,9 @@ impl NanocldClient {
   pub async fn watch_events(
     &self,
     conditions: Option<Vec<EventCondition>>,
+    query: Option<&EventWatchQuery>,
   ) -> HttpClientResult<Receiver<HttpResult<Event>>> {
-    let res = self
-      .send_post("/events/watch", conditions, None::<String>)
-      .await?;
+    let res = self.send_post("/events/watch", conditions, query).await?;
     Ok(Self::res_stream(res).await)
   }
 
@@ -100,7 +103,7 @@ mod tests {
       url: "http://nanocl.internal:8585".into(),
       ..Default::default()
     });
-    let _stream = client.watch_events(None).await.unwrap();
+    let _stream = client.watch_events(None, None).await.unwrap();
     // Todo : find a way to test this on CI because it's limited to 2 threads
     // let _event = stream.next().await.unwrap();
   }
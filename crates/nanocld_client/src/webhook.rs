@@ -0,0 +1,156 @@
+use nanocl_error::{
+  io::IoError,
+  http_client::{HttpClientError, HttpClientResult},
+};
+
+use nanocl_stubs::{
+  generic::{GenericFilter, GenericListQuery},
+  webhook::{Webhook, WebhookDelivery, WebhookPartial},
+};
+
+use super::http_client::NanocldClient;
+
+impl NanocldClient {
+  /// ## Default path for webhooks
+  const WEBHOOK_PATH: &'static str = "/webhooks";
+
+  /// Convert a generic filter into a list query
+  fn webhook_query(
+    query: Option<&GenericFilter>,
+  ) -> HttpClientResult<GenericListQuery> {
+    let query = query.cloned().unwrap_or_default();
+    GenericListQuery::try_from(query).map_err(|err| {
+      HttpClientError::IoError(IoError::invalid_data(
+        "Query".to_owned(),
+        err.to_string(),
+      ))
+    })
+  }
+
+  /// List the webhooks registered in the system.
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_to("http://localhost:8585", None);
+  /// let res = client.list_webhook(None).await;
+  /// ```
+  pub async fn list_webhook(
+    &self,
+    query: Option<&GenericFilter>,
+  ) -> HttpClientResult<Vec<Webhook>> {
+    let query = Self::webhook_query(query)?;
+    let res = self.send_get(Self::WEBHOOK_PATH, Some(&query)).await?;
+    Self::res_json(res).await
+  }
+
+  /// Create a webhook, the matching events will be posted to its url
+  pub async fn create_webhook(
+    &self,
+    item: &WebhookPartial,
+  ) -> HttpClientResult<Webhook> {
+    let res = self
+      .send_post(Self::WEBHOOK_PATH, Some(item), None::<String>)
+      .await?;
+    Self::res_json(res).await
+  }
+
+  /// Inspect a webhook by it's name
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_to("http://localhost:8585", None);
+  /// let webhook = client.inspect_webhook("deploy-bot").await?;
+  /// ```
+  pub async fn inspect_webhook(&self, name: &str) -> HttpClientResult<Webhook> {
+    let res = self
+      .send_get(
+        &format!("{}/{name}/inspect", Self::WEBHOOK_PATH),
+        None::<String>,
+      )
+      .await?;
+    Self::res_json(res).await
+  }
+
+  /// Delete a webhook and its deliveries by it's name
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_to("http://localhost:8585", None);
+  /// client.delete_webhook("deploy-bot").await?;
+  /// ```
+  pub async fn delete_webhook(&self, name: &str) -> HttpClientResult<()> {
+    self
+      .send_delete(&format!("{}/{name}", Self::WEBHOOK_PATH), None::<String>)
+      .await?;
+    Ok(())
+  }
+
+  /// List the deliveries of a webhook to inspect the failed ones
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_to("http://localhost:8585", None);
+  /// let deliveries = client.list_webhook_delivery("deploy-bot", None).await?;
+  /// ```
+  pub async fn list_webhook_delivery(
+    &self,
+    name: &str,
+    query: Option<&GenericFilter>,
+  ) -> HttpClientResult<Vec<WebhookDelivery>> {
+    let query = Self::webhook_query(query)?;
+    let res = self
+      .send_get(
+        &format!("{}/{name}/deliveries", Self::WEBHOOK_PATH),
+        Some(&query),
+      )
+      .await?;
+    Self::res_json(res).await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::ConnectOpts;
+
+  use super::*;
+
+  #[ntex::test]
+  async fn basic() {
+    const WEBHOOK_NAME: &str = "webhook-client-test";
+    let client = NanocldClient::connect_to(&ConnectOpts {
+      url: "http://nanocl.internal:8585".into(),
+      ..Default::default()
+    });
+    client.list_webhook(None).await.unwrap();
+    let webhook = WebhookPartial {
+      name: WEBHOOK_NAME.to_owned(),
+      url: "http://127.0.0.1:9/hook".to_owned(),
+      conditions: None,
+      secret: None,
+      retry: None,
+      metadata: None,
+    };
+    let webhook = client.create_webhook(&webhook).await.unwrap();
+    assert_eq!(webhook.name, WEBHOOK_NAME);
+    assert!(!webhook.signed);
+    let webhook = client.inspect_webhook(WEBHOOK_NAME).await.unwrap();
+    assert_eq!(webhook.name, WEBHOOK_NAME);
+    client
+      .list_webhook_delivery(WEBHOOK_NAME, None)
+      .await
+      .unwrap();
+    client.delete_webhook(WEBHOOK_NAME).await.unwrap();
+  }
+}